@@ -0,0 +1,20 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D background;
+
+layout(push_constant) uniform PushConstants {
+    vec2 uv_scale;
+    float opacity;
+} consts;
+
+void main() {
+    // The image covers the whole frame, so it is cropped around the center
+    vec2 uv = (v_uv - 0.5) * consts.uv_scale + 0.5;
+    vec3 color = texture(background, uv).rgb;
+
+    out_color = vec4(color * consts.opacity, consts.opacity);
+}
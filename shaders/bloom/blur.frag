@@ -0,0 +1,37 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 direction;
+    float radius;
+} consts;
+
+const int max_taps = 32;
+
+void main() {
+    vec2 texel = consts.direction / vec2(textureSize(source, 0));
+
+    // Spread the taps out if the radius is bigger than the tap count
+    float step_size = max(1.0, consts.radius / float(max_taps));
+    int taps = int(min(consts.radius, float(max_taps)));
+    float sigma = max(consts.radius / 3.0, 0.001);
+
+    vec3 color = texture(source, v_uv).rgb;
+    float total = 1;
+
+    for (int i = 1; i <= taps; i++) {
+        float offset = float(i) * step_size;
+        float weight = exp(-(offset * offset) / (2 * sigma * sigma));
+
+        color += texture(source, v_uv + texel * offset).rgb * weight;
+        color += texture(source, v_uv - texel * offset).rgb * weight;
+        total += weight * 2;
+    }
+
+    out_color = vec4(color / total, 1.0);
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform sampler2D bloom;
+
+void main() {
+    vec3 color = texture(bloom, v_uv).rgb;
+    float alpha = clamp(max(color.r, max(color.g, color.b)), 0, 1);
+
+    out_color = vec4(color, alpha);
+}
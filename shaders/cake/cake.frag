@@ -14,6 +14,8 @@ layout(push_constant) uniform PushConstants {
     int end_time;
     int screen_width;
     int screen_height;
+    float brightness;
+    int waterfall;
 } consts;
 
 layout(set = 0, binding = 0) readonly buffer BufferArray
@@ -99,5 +101,5 @@ void main()
         color = frag_color * 0.034;
     }
 
-    fsout_Color = vec4(color, 1);
+    fsout_Color = vec4(color * consts.brightness, 1);
 }
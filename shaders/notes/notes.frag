@@ -4,10 +4,47 @@ layout(location = 0) in vec3 frag_color;
 layout(location = 1) in vec2 frag_tex_coord;
 layout(location = 2) in vec2 v_note_size;
 layout(location = 3) in vec2 win_size;
+layout(location = 4) in float frag_bloom;
+layout(location = 5) flat in uint frag_channel;
+layout(location = 6) flat in uint frag_echo;
+layout(location = 7) flat in uint frag_chord;
+layout(location = 8) flat in uint frag_playing;
+layout(location = 9) flat in float frag_length;
+layout(location = 10) flat in float frag_fadein;
 
 layout(location = 0) out vec4 out_color;
+layout(location = 1) out vec4 out_bloom;
+
+layout(push_constant) uniform PushConstants {
+    vec4 inner_glow_color;
+    float height_time;
+    float win_width;
+    float win_height;
+    float brightness;
+    int waterfall;
+    float inner_glow_intensity;
+    int note_shape;
+    int tone_map;
+    float note_gap;
+    uint inactive_channels;
+    float inactive_alpha;
+    float min_note_height;
+    int spotlight;
+    uint key_offset;
+    int length_opacity;
+    float length_opacity_min;
+    float length_opacity_max;
+    float note_fadein;
+} consts;
+
+const int SHAPE_CIRCLE = 1;
+const int SHAPE_DIAMOND = 2;
 
 const float border = 2;
+const float tone_map_white = 2;
+const float inner_glow_width = 8;
+const float echo_alpha = 0.25;
+const float spotlight_alpha = 0.2;
 
 void main() {
     vec2 v_uv = frag_tex_coord;
@@ -29,16 +66,88 @@ void main() {
     float horiz_margin = 1 / horiz_width_pixels * border;
     float vert_margin = 1 / vert_width_pixels * border;
 
+    // The notes inside of a chord leave out their sides, so the border goes around
+    // the whole chord
+    bool in_chord = (frag_chord & 1) != 0;
+    bool left_border = !in_chord || (frag_chord & 2) != 0;
+    bool right_border = !in_chord || (frag_chord & 4) != 0;
+
     bool border =
-        v_uv.x < horiz_margin ||
-        v_uv.x > 1 - horiz_margin ||
+        (left_border && v_uv.x < horiz_margin) ||
+        (right_border && v_uv.x > 1 - horiz_margin) ||
         v_uv.y < vert_margin ||
         v_uv.y > 1 - vert_margin;
 
+    // Round and diamond notes measure the border from their own outline instead
+    vec2 centered = abs(v_uv - 0.5) * 2;
+    vec2 margin = vec2(horiz_margin, vert_margin) * 2;
+    if(consts.note_shape == SHAPE_CIRCLE)
+    {
+        border = length(centered / (1 - margin)) > 1;
+    }
+    else if(consts.note_shape == SHAPE_DIAMOND)
+    {
+        border = dot(centered, vec2(1)) > 1 - max(margin.x, margin.y) * 1.5;
+    }
+
+    // The glow is strongest right inside the border and fades out towards the center
+    if(consts.inner_glow_intensity > 0)
+    {
+        float edge_dist = min(
+            min(v_uv.x, 1 - v_uv.x) * horiz_width_pixels,
+            min(v_uv.y, 1 - v_uv.y) * vert_width_pixels
+        ) - border;
+        float glow_width = min(inner_glow_width, min(horiz_width_pixels, vert_width_pixels) / 2);
+        float glow = 1 - smoothstep(0, max(glow_width, 1), edge_dist);
+        color = mix(
+            color,
+            consts.inner_glow_color.rgb,
+            glow * consts.inner_glow_intensity * consts.inner_glow_color.a
+        );
+    }
+
     if(border)
     {
         color = vec3(frag_color * 0.034);
     }
 
-    out_color = vec4(color, 1.0);
+    color *= consts.brightness;
+
+    // The HDR surface is linear and unclamped, so the highlights from the gradient
+    // are compressed with extended Reinhard before converting from sRGB
+    if(consts.tone_map == 1)
+    {
+        color = color * (1 + color / (tone_map_white * tone_map_white)) / (1 + color);
+        color = pow(color, vec3(2.2));
+    }
+
+    // Notes on inactive channels are faded out, the colors are premultiplied
+    // so the background still shows through them
+    float alpha = 1.0;
+    if(((consts.inactive_channels >> frag_channel) & 1) != 0)
+    {
+        alpha = consts.inactive_alpha;
+    }
+    if(frag_echo != 0)
+    {
+        alpha *= echo_alpha;
+    }
+    if(consts.spotlight != 0 && frag_playing == 0)
+    {
+        alpha *= spotlight_alpha;
+    }
+    // Short notes fade out, from transparent at the min length to opaque at the max length
+    if(consts.length_opacity != 0)
+    {
+        float range = max(consts.length_opacity_max - consts.length_opacity_min, 0.001);
+        alpha *= clamp((frag_length - consts.length_opacity_min) / range, 0, 1);
+    }
+    // The start of the note fades in like the attack of the voice
+    if(frag_fadein > 0)
+    {
+        alpha *= clamp(frag_tex_coord.y / frag_fadein, 0, 1);
+    }
+
+    out_color = vec4(color * alpha, alpha);
+    out_bloom = vec4(color * frag_bloom * alpha, 1.0);
 }
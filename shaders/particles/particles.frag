@@ -0,0 +1,31 @@
+#version 450
+
+layout(location = 0) in vec3 frag_color;
+layout(location = 1) in vec2 frag_tex_coord;
+layout(location = 2) in float frag_alpha;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants {
+    float win_width;
+    float win_height;
+    float size;
+    int waterfall;
+    int tone_map;
+} consts;
+
+void main() {
+    // A soft round dot that is brightest in the middle
+    float dist = length(frag_tex_coord - 0.5) * 2;
+    float alpha = frag_alpha * (1 - smoothstep(0.5, 1, dist));
+
+    vec3 color = frag_color;
+
+    // The HDR surface is linear, while the note colors are in sRGB
+    if(consts.tone_map == 1)
+    {
+        color = pow(color, vec3(2.2));
+    }
+
+    out_color = vec4(color * alpha, alpha);
+}
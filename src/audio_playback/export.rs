@@ -0,0 +1,190 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use midi_toolkit::{
+    io::MIDIFile as TKMIDIFile,
+    pipe,
+    sequence::{
+        event::{cancel_tempo_events, scale_event_time},
+        unwrap_items, TimeCaster,
+    },
+};
+use xsynth_core::{
+    channel::{ChannelConfigEvent, ChannelEvent},
+    channel_group::{ChannelGroup, ChannelGroupConfig, SynthEvent},
+    AudioPipe, AudioStreamParams, ChannelCount,
+};
+
+use crate::{
+    midi::{decompress_gzip_midi, is_gzip_midi, CompressedAudio},
+    settings::WasabiSettings,
+};
+
+use super::{
+    channel_tuning_events, resets_controllers,
+    sfz::{RegionFilter, SfzInstrument},
+    to_synth_event,
+    xsynth::{
+        convert_to_channel_init, convert_to_sf_init, load_soundfonts, soundfont_path,
+        SharedSoundfont,
+    },
+};
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// The most frames rendered at once between two MIDI events
+const MAX_RENDER_FRAMES: u64 = 4096;
+
+/// How long to keep rendering after the last event, so the released notes can fade out
+pub const TAIL_SECONDS: f64 = 2.0;
+
+/// Renders a MIDI file to a WAV file with XSynth as fast as possible,
+/// without opening a window or an audio device
+pub fn export_audio(
+    settings: &WasabiSettings,
+    midi_path: &Path,
+    output: &Path,
+) -> Result<(), String> {
+    // Nothing cancels the export, it runs until it is done
+    let (_cancel, listener) = triggered::trigger();
+    // The decompressed copy is deleted when this is dropped, once the file was read
+    let decompressed = if is_gzip_midi(midi_path) {
+        Some(
+            decompress_gzip_midi(midi_path.to_path_buf(), listener)
+                .recv()
+                .ok()
+                .flatten()
+                .ok_or_else(|| format!("Failed to decompress {}", midi_path.display()))?,
+        )
+    } else {
+        None
+    };
+    let midi_path = decompressed.as_deref().unwrap_or(midi_path);
+
+    let file = File::open(midi_path).map_err(|e| format!("Failed to open MIDI: {}", e))?;
+    let midi = TKMIDIFile::open_from_stream(file, None)
+        .map_err(|e| format!("Failed to parse MIDI: {:?}", e))?;
+
+    let ppq = midi.ppq();
+    let merged = pipe!(
+        midi.iter_all_track_events_merged_batches()
+        |>TimeCaster::<f64>::cast_event_delta()
+        |>cancel_tempo_events(250000)
+        |>scale_event_time(1.0 / ppq as f64)
+        |>unwrap_items()
+    );
+    let blocks: Vec<_> = CompressedAudio::build_blocks(merged.map(Arc::new)).collect();
+    let length = blocks.last().map(|block| block.time).unwrap_or(0.0);
+
+    let stream_params = AudioStreamParams::new(SAMPLE_RATE, ChannelCount::Stereo);
+    let mut group = ChannelGroup::new(ChannelGroupConfig {
+        channel_init_options: convert_to_channel_init(settings),
+        channel_count: 16,
+        audio_params: stream_params,
+        use_threadpool: true,
+    });
+
+    load_soundfonts(
+        &soundfont_path(settings),
+        true,
+        &SharedSoundfont::default(),
+        stream_params,
+        convert_to_sf_init(settings),
+        |event| group.send_event(event),
+    )?;
+    let layers = match settings.synth.layer_count {
+        0 => None,
+        layers => Some(layers),
+    };
+    let config = ChannelConfigEvent::SetLayerCount(layers);
+    group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(config)));
+
+    // The channels are tuned the same way as when the MIDI is played, and again
+    // whenever the MIDI resets their controllers
+    let send_tuning = |group: &mut ChannelGroup, channel: usize| {
+        let cents = settings.synth.channel_tuning[channel];
+        if cents == 0.0 {
+            return;
+        }
+        for event in channel_tuning_events(channel as u8, cents) {
+            if let Some(event) = to_synth_event(event, &settings.synth.vel_ignore) {
+                group.send_event(event);
+            }
+        }
+    };
+    for channel in 0..16 {
+        send_tuning(&mut group, channel);
+    }
+
+    let region_filter = SfzInstrument::parse(&settings.synth.sfz_path)
+        .and_then(|sfz| RegionFilter::new(&sfz, &settings.synth.disabled_regions));
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer =
+        WavWriter::create(output, spec).map_err(|e| format!("Failed to create WAV: {}", e))?;
+
+    let mut buffer = Vec::new();
+    let mut rendered_frames = 0;
+    let mut last_percent = None;
+
+    let mut render_until = |group: &mut ChannelGroup, time: f64| -> Result<(), String> {
+        let target_frames = (time * SAMPLE_RATE as f64) as u64;
+        while rendered_frames < target_frames {
+            let frames = (target_frames - rendered_frames).min(MAX_RENDER_FRAMES);
+            buffer.resize(frames as usize * 2, 0.0);
+            buffer.fill(0.0);
+            group.read_samples(&mut buffer);
+
+            for sample in buffer.iter() {
+                writer
+                    .write_sample(*sample)
+                    .map_err(|e| format!("Failed to write WAV: {}", e))?;
+            }
+            rendered_frames += frames;
+        }
+        Ok(())
+    };
+
+    for block in blocks.iter() {
+        render_until(&mut group, block.time)?;
+        for event in block.iter_events() {
+            if region_filter
+                .as_ref()
+                .is_some_and(|filter| filter.blocks(event))
+            {
+                continue;
+            }
+            if let Some(event) = to_synth_event(event, &settings.synth.vel_ignore) {
+                group.send_event(event);
+            }
+            if let Some(channel) = resets_controllers(event) {
+                send_tuning(&mut group, channel);
+            }
+        }
+
+        if length > 0.0 {
+            let percent = (block.time / length * 100.0) as u32;
+            if last_percent != Some(percent) {
+                last_percent = Some(percent);
+                print!("\rExporting audio: {}%", percent);
+                io::stdout().flush().ok();
+            }
+        }
+    }
+    render_until(&mut group, length + TAIL_SECONDS)?;
+    println!("\rExporting audio: 100%");
+
+    writer
+        .finalize()
+        .map_err(|e| format!("Failed to finish WAV: {}", e))
+}
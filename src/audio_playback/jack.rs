@@ -0,0 +1,305 @@
+use crate::WasabiSettings;
+
+use super::{xsynth::convert_to_channel_init, AudioPlayerType};
+
+pub use self::client::JackPlayer;
+
+#[cfg(feature = "jack")]
+mod client {
+    use std::{
+        ops::RangeInclusive,
+        sync::{atomic::AtomicBool, Arc},
+    };
+
+    use super::super::{
+        pcm::PcmRingBuffer,
+        to_synth_event,
+        xsynth::{load_soundfonts, load_soundfonts_in_background, SharedSoundfont, SoundfontPath},
+    };
+    use crate::notifications;
+
+    use crossbeam_channel::{unbounded, Sender};
+    use jack::{
+        AsyncClient, AudioOut, Client, ClientOptions, ClosureProcessHandler, Control, PortFlags,
+        ProcessScope,
+    };
+    use xsynth_core::{
+        channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ChannelInitOptions},
+        channel_group::{ChannelGroup, ChannelGroupConfig, SynthEvent},
+        soundfont::SoundfontInitOptions,
+        AudioPipe, AudioStreamParams, ChannelCount,
+    };
+
+    type ProcessCallback = Box<dyn FnMut(&Client, &ProcessScope) -> Control + Send>;
+
+    pub struct JackPlayer {
+        sender: Sender<SynthEvent>,
+        ignore_range: RangeInclusive<u8>,
+        stream_params: AudioStreamParams,
+        soundfont: SharedSoundfont,
+        port_names: [String; 2],
+        connected: [Option<String>; 2],
+        pcm: Arc<PcmRingBuffer>,
+        client: AsyncClient<(), ClosureProcessHandler<ProcessCallback>>,
+    }
+
+    impl JackPlayer {
+        pub fn new(
+            client_name: &str,
+            ignore_range: RangeInclusive<u8>,
+            options: ChannelInitOptions,
+        ) -> Result<Self, jack::Error> {
+            // Don't try to start a server, just fail if there isn't one running
+            let (client, _) = Client::new(client_name, ClientOptions::NO_START_SERVER)?;
+
+            let mut out_left = client.register_port("out_left", AudioOut::default())?;
+            let mut out_right = client.register_port("out_right", AudioOut::default())?;
+            let port_names = [out_left.name()?, out_right.name()?];
+
+            let stream_params =
+                AudioStreamParams::new(client.sample_rate() as u32, ChannelCount::Stereo);
+            let mut group = ChannelGroup::new(ChannelGroupConfig {
+                channel_init_options: options,
+                channel_count: 16,
+                audio_params: stream_params,
+                use_threadpool: false,
+            });
+
+            let (sender, receiver) = unbounded();
+            let mut buffer = Vec::new();
+
+            let pcm = Arc::new(PcmRingBuffer::new(stream_params.sample_rate));
+            let pcm_writer = pcm.clone();
+
+            let process: ProcessCallback = Box::new(move |_, ps| {
+                for event in receiver.try_iter() {
+                    group.send_event(event);
+                }
+
+                let left = out_left.as_mut_slice(ps);
+                let right = out_right.as_mut_slice(ps);
+
+                buffer.resize(left.len() * 2, 0.0);
+                buffer.fill(0.0);
+                group.read_samples(&mut buffer);
+                pcm_writer.push_stereo(&buffer);
+
+                for (i, frame) in buffer.chunks_exact(2).enumerate() {
+                    left[i] = frame[0];
+                    right[i] = frame[1];
+                }
+
+                Control::Continue
+            });
+
+            let client = client.activate_async((), ClosureProcessHandler::new(process))?;
+
+            let mut player = JackPlayer {
+                sender,
+                ignore_range,
+                stream_params,
+                soundfont: Default::default(),
+                port_names,
+                connected: [None, None],
+                pcm,
+                client,
+            };
+
+            player.connect_ports("", "");
+
+            Ok(player)
+        }
+
+        /// The samples that were sent to the outputs
+        pub fn pcm(&self) -> Arc<PcmRingBuffer> {
+            self.pcm.clone()
+        }
+
+        /// Lists the ports that the left and right outputs can be connected to
+        pub fn available_ports(&self) -> Vec<String> {
+            self.client.as_client().ports(
+                None,
+                Some("32 bit float mono audio"),
+                PortFlags::IS_INPUT | PortFlags::IS_PHYSICAL,
+            )
+        }
+
+        /// Connects the left and right outputs to the given ports, an empty
+        /// name picks the matching physical output
+        pub fn connect_ports(&mut self, left: &str, right: &str) {
+            let defaults = self.available_ports();
+            let client = self.client.as_client();
+
+            for (i, ((source, connected), target)) in self
+                .port_names
+                .iter()
+                .zip(self.connected.iter_mut())
+                .zip([left, right])
+                .enumerate()
+            {
+                if let Some(previous) = connected.take() {
+                    client.disconnect_ports_by_name(source, &previous).ok();
+                }
+
+                let target = if target.is_empty() {
+                    defaults.get(i).cloned()
+                } else {
+                    Some(target.to_owned())
+                };
+
+                if let Some(target) = target {
+                    match client.connect_ports_by_name(source, &target) {
+                        Ok(()) => *connected = Some(target),
+                        Err(e) => notifications::error(format!(
+                            "Failed to connect JACK port {}: {}",
+                            target, e
+                        )),
+                    }
+                }
+            }
+        }
+
+        pub fn push_event(&mut self, data: u32) {
+            if let Some(event) = to_synth_event(data, &self.ignore_range) {
+                self.sender.send(event).ok();
+            }
+        }
+
+        pub fn reset(&mut self) {
+            for event in [
+                ChannelAudioEvent::AllNotesKilled,
+                ChannelAudioEvent::ResetControl,
+            ] {
+                self.sender
+                    .send(SynthEvent::AllChannels(ChannelEvent::Audio(event)))
+                    .ok();
+            }
+        }
+
+        pub fn set_layer_count(&mut self, layers: Option<usize>) {
+            self.send_config(ChannelConfigEvent::SetLayerCount(layers));
+        }
+
+        pub fn set_soundfont(&mut self, sfz: &SoundfontPath, options: SoundfontInitOptions) {
+            if sfz.path.is_empty() {
+                return;
+            }
+            let sender = &self.sender;
+            let result = load_soundfonts(
+                sfz,
+                true,
+                &self.soundfont,
+                self.stream_params,
+                options,
+                |event| {
+                    sender.send(event).ok();
+                },
+            );
+            if let Err(e) = result {
+                notifications::error(e);
+            }
+        }
+
+        /// Like `set_soundfont`, but the SFZ is loaded on another thread and the old one
+        /// keeps playing until the new one is swapped in. Only the copies of the channels
+        /// are loaded again without `reload_main`.
+        pub fn reload_soundfont(
+            &self,
+            sfz: SoundfontPath,
+            reload_main: bool,
+            options: SoundfontInitOptions,
+            loading: Arc<AtomicBool>,
+        ) {
+            let sender = self.sender.clone();
+            load_soundfonts_in_background(
+                sfz,
+                reload_main,
+                self.soundfont.clone(),
+                self.stream_params,
+                options,
+                loading,
+                move |event| {
+                    sender.send(event).ok();
+                },
+            );
+        }
+
+        fn send_config(&mut self, config: ChannelConfigEvent) {
+            self.sender
+                .send(SynthEvent::AllChannels(ChannelEvent::Config(config)))
+                .ok();
+        }
+    }
+}
+
+#[cfg(not(feature = "jack"))]
+mod client {
+    use std::{
+        ops::RangeInclusive,
+        sync::{atomic::AtomicBool, Arc},
+    };
+
+    use xsynth_core::{channel::ChannelInitOptions, soundfont::SoundfontInitOptions};
+
+    use super::super::{pcm::PcmRingBuffer, xsynth::SoundfontPath};
+
+    /// Stands in for the JACK player when Wasabi is built without the `jack` feature.
+    /// It can't be made, so picking JACK fails the same way as without a JACK server.
+    pub enum JackPlayer {}
+
+    impl JackPlayer {
+        pub fn new(
+            _client_name: &str,
+            _ignore_range: RangeInclusive<u8>,
+            _options: ChannelInitOptions,
+        ) -> Result<Self, &'static str> {
+            Err("JACK needs Wasabi to be built with the `jack` feature")
+        }
+
+        pub fn pcm(&self) -> Arc<PcmRingBuffer> {
+            match *self {}
+        }
+
+        pub fn available_ports(&self) -> Vec<String> {
+            match *self {}
+        }
+
+        pub fn connect_ports(&mut self, _left: &str, _right: &str) {
+            match *self {}
+        }
+
+        pub fn push_event(&mut self, _data: u32) {
+            match *self {}
+        }
+
+        pub fn reset(&mut self) {
+            match *self {}
+        }
+
+        pub fn set_layer_count(&mut self, _layers: Option<usize>) {
+            match *self {}
+        }
+
+        pub fn set_soundfont(&mut self, _sfz: &SoundfontPath, _options: SoundfontInitOptions) {
+            match *self {}
+        }
+
+        pub fn reload_soundfont(
+            &self,
+            _sfz: SoundfontPath,
+            _reload_main: bool,
+            _options: SoundfontInitOptions,
+            _loading: Arc<AtomicBool>,
+        ) {
+            match *self {}
+        }
+    }
+}
+
+pub fn convert_to_jack_player(settings: &WasabiSettings) -> AudioPlayerType {
+    AudioPlayerType::Jack {
+        client_name: settings.synth.jack_client_name.clone(),
+        ignore_range: settings.synth.vel_ignore.clone(),
+        options: convert_to_channel_init(settings),
+    }
+}
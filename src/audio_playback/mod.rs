@@ -1,8 +1,35 @@
 use kdmapi::{KDMAPIStream, KDMAPI};
-use std::ops::RangeInclusive;
-use xsynth_core::{channel::ChannelInitOptions, soundfont::SoundfontInitOptions};
+use std::{
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc,
+    },
+};
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelEvent, ChannelInitOptions, ControlEvent},
+    channel_group::SynthEvent,
+    soundfont::SoundfontInitOptions,
+};
+pub mod export;
+pub mod jack;
+pub mod pcm;
+pub mod sfz;
+pub mod timbre;
 pub mod xsynth;
 
+use crate::{
+    notifications,
+    settings::{EventFilterFlags, Synth, WasabiSettings},
+};
+
+use self::{
+    jack::convert_to_jack_player,
+    pcm::PcmRingBuffer,
+    sfz::RegionFilter,
+    xsynth::{convert_to_channel_init, convert_to_sf_init, soundfont_path, SoundfontPath},
+};
+
 #[derive(Clone)]
 pub enum AudioPlayerType {
     XSynth {
@@ -11,34 +38,71 @@ pub enum AudioPlayerType {
         options: ChannelInitOptions,
     },
     Kdmapi,
+    Jack {
+        client_name: String,
+        ignore_range: RangeInclusive<u8>,
+        options: ChannelInitOptions,
+    },
+    Silent,
 }
 
 pub struct SimpleTemporaryPlayer {
     player_type: AudioPlayerType,
     xsynth: Option<xsynth::XSynthPlayer>,
     kdmapi: Option<KDMAPIStream>,
+    jack: Option<jack::JackPlayer>,
+    transpose: i8,
+    channel_peaks: [AtomicU8; 16],
+    channel_tuning: [f32; 16],
+    region_filter: Option<RegionFilter>,
+    event_filter: EventFilterFlags,
+    muted_channels: u16,
+    soundfont_loading: Arc<AtomicBool>,
+    /// The fade in that was written into the SFZ given to the synth, in seconds
+    note_fadein: f32,
 }
 
 impl SimpleTemporaryPlayer {
     pub fn new(player_type: AudioPlayerType) -> Self {
-        let (xsynth, kdmapi) = match player_type.clone() {
+        let (xsynth, kdmapi, jack) = match player_type.clone() {
             AudioPlayerType::XSynth {
                 buffer,
                 ignore_range,
                 options,
             } => {
                 let xsynth = xsynth::XSynthPlayer::new(buffer, ignore_range, options);
-                (Some(xsynth), None)
+                (Some(xsynth), None, None)
             }
             AudioPlayerType::Kdmapi => {
                 let kdmapi = KDMAPI.open_stream();
-                (None, Some(kdmapi))
+                (None, Some(kdmapi), None)
             }
+            AudioPlayerType::Jack {
+                client_name,
+                ignore_range,
+                options,
+            } => match jack::JackPlayer::new(&client_name, ignore_range, options) {
+                Ok(jack) => (None, None, Some(jack)),
+                Err(e) => {
+                    notifications::error(format!("Failed to connect to the JACK server: {}", e));
+                    (None, None, None)
+                }
+            },
+            AudioPlayerType::Silent => (None, None, None),
         };
         Self {
             player_type,
             xsynth,
             kdmapi,
+            jack,
+            transpose: 0,
+            channel_peaks: Default::default(),
+            channel_tuning: [0.0; 16],
+            region_filter: None,
+            event_filter: EventFilterFlags::all(),
+            muted_channels: 0,
+            soundfont_loading: Arc::new(AtomicBool::new(false)),
+            note_fadein: 0.0,
         }
     }
 
@@ -46,11 +110,41 @@ impl SimpleTemporaryPlayer {
         self.reset();
         self.xsynth = None;
         self.kdmapi = None;
+        self.jack = None;
         let new_player = Self::new(player_type);
 
         self.player_type = new_player.player_type;
         self.xsynth = new_player.xsynth;
         self.kdmapi = new_player.kdmapi;
+        self.jack = new_player.jack;
+        self.note_fadein = 0.0;
+        self.apply_channel_tuning(|_, cents| cents != 0.0);
+    }
+
+    /// Switches to the synth picked in the settings and sets it up with the
+    /// SoundFont, layer limit, JACK ports and tuning from the settings
+    pub fn start_synth(&mut self, settings: &WasabiSettings) {
+        self.switch_player(match settings.synth.synth {
+            _ if settings.no_audio => AudioPlayerType::Silent,
+            Synth::Kdmapi => AudioPlayerType::Kdmapi,
+            Synth::XSynth => AudioPlayerType::XSynth {
+                buffer: settings.synth.buffer_ms,
+                ignore_range: settings.synth.vel_ignore.clone(),
+                options: convert_to_channel_init(settings),
+            },
+            Synth::Jack => convert_to_jack_player(settings),
+        });
+        self.set_soundfont(&soundfont_path(settings), convert_to_sf_init(settings));
+        self.set_layer_count(if settings.synth.limit_layers {
+            Some(settings.synth.layer_count)
+        } else {
+            None
+        });
+        self.connect_jack_ports(
+            &settings.synth.jack_port_left,
+            &settings.synth.jack_port_right,
+        );
+        self.set_channel_tuning(settings.synth.channel_tuning);
     }
 
     pub fn get_voice_count(&self) -> u64 {
@@ -62,7 +156,7 @@ impl SimpleTemporaryPlayer {
                     0
                 }
             }
-            AudioPlayerType::Kdmapi => 0,
+            AudioPlayerType::Kdmapi | AudioPlayerType::Jack { .. } | AudioPlayerType::Silent => 0,
         }
     }
 
@@ -73,6 +167,43 @@ impl SimpleTemporaryPlayer {
     }
 
     pub fn push_event(&mut self, data: u32) {
+        // Only the note ons of muted channels are dropped, so their notes still end
+        let muted_note_on = data & 0xF0 == 0x90
+            && (data >> 16) & 0xFF != 0
+            && self.muted_channels & (1 << (data & 0xF)) != 0;
+        if self.event_filter.allows(data) && !muted_note_on {
+            self.send_event(data);
+
+            // Resetting the controllers of a channel also resets its tuning
+            if let Some(channel) = resets_controllers(data) {
+                self.apply_channel_tuning(|tuned, cents| tuned == channel && cents != 0.0);
+            }
+        }
+    }
+
+    /// Sends an event to the synth without the event filter, for the events
+    /// that the player itself needs, like the tuning
+    fn send_event(&mut self, data: u32) {
+        let data = match self.transpose_event(data) {
+            Some(data) => data,
+            None => return,
+        };
+
+        // KDMAPI plays its own soundfonts, so the SFZ regions don't apply to it
+        if !matches!(self.player_type, AudioPlayerType::Kdmapi)
+            && self
+                .region_filter
+                .as_ref()
+                .is_some_and(|filter| filter.blocks(data))
+        {
+            return;
+        }
+
+        let velocity = ((data >> 16) & 0xFF) as u8;
+        if data & 0xF0 == 0x90 && velocity > 0 {
+            self.channel_peaks[(data & 0x0F) as usize].fetch_max(velocity, Ordering::Relaxed);
+        }
+
         match self.player_type {
             AudioPlayerType::XSynth { .. } => {
                 if let Some(xsynth) = self.xsynth.as_mut() {
@@ -84,10 +215,94 @@ impl SimpleTemporaryPlayer {
                     kdmapi.send_direct_data(data);
                 }
             }
+            AudioPlayerType::Jack { .. } => {
+                if let Some(jack) = self.jack.as_mut() {
+                    jack.push_event(data);
+                }
+            }
+            AudioPlayerType::Silent => {}
+        }
+    }
+
+    fn transpose_event(&self, data: u32) -> Option<u32> {
+        if self.transpose == 0 {
+            return Some(data);
+        }
+
+        match data & 0xF0 {
+            // Note off, note on and polyphonic aftertouch carry a key
+            0x80 | 0x90 | 0xA0 => {
+                let key = ((data >> 8) & 0xFF) as i32 + self.transpose as i32;
+                if (0..=127).contains(&key) {
+                    Some((data & !0xFF00) | ((key as u32) << 8))
+                } else {
+                    None
+                }
+            }
+            _ => Some(data),
+        }
+    }
+
+    /// The highest note on velocity per channel since the last call
+    pub fn take_channel_peaks(&self) -> [u8; 16] {
+        let mut peaks = [0; 16];
+        for (peak, channel) in peaks.iter_mut().zip(self.channel_peaks.iter()) {
+            *peak = channel.swap(0, Ordering::Relaxed);
+        }
+        peaks
+    }
+
+    pub fn set_transpose(&mut self, semitones: i8) {
+        if self.transpose != semitones {
+            // Held notes would otherwise never receive a matching note off
+            self.reset();
+            self.transpose = semitones;
+        }
+    }
+
+    /// Sets which notes are dropped because they would only play disabled SFZ regions
+    pub fn set_region_filter(&mut self, filter: Option<RegionFilter>) {
+        self.region_filter = filter;
+    }
+
+    /// Sets which kinds of events are dropped before they reach the synth
+    pub fn set_event_filter(&mut self, filter: EventFilterFlags) {
+        self.event_filter = filter;
+    }
+
+    /// Sets the bitmask of the channels whose notes are dropped before they reach the synth
+    pub fn set_muted_channels(&mut self, channels: u16) {
+        self.muted_channels = channels;
+    }
+
+    /// Sets the fine tuning of every channel in cents, between -100 and 100
+    pub fn set_channel_tuning(&mut self, tuning: [f32; 16]) {
+        let previous = self.channel_tuning;
+        self.channel_tuning = tuning;
+
+        self.apply_channel_tuning(|channel, cents| cents != previous[channel]);
+    }
+
+    /// Sends the channel fine tuning RPN to the channels matching the filter
+    fn apply_channel_tuning(&mut self, filter: impl Fn(usize, f32) -> bool) {
+        for (channel, cents) in self.channel_tuning.into_iter().enumerate() {
+            if !filter(channel, cents) {
+                continue;
+            }
+
+            for event in channel_tuning_events(channel as u8, cents) {
+                self.send_event(event);
+            }
         }
     }
 
     pub fn reset(&mut self) {
+        self.reset_player();
+        // Resetting the controllers also resets the tuning, so it is sent again
+        self.apply_channel_tuning(|_, cents| cents != 0.0);
+    }
+
+    fn reset_player(&mut self) {
         match self.player_type {
             AudioPlayerType::XSynth { .. } => {
                 if let Some(xsynth) = self.xsynth.as_mut() {
@@ -99,22 +314,167 @@ impl SimpleTemporaryPlayer {
                     kdmapi.reset();
                 }
             }
+            AudioPlayerType::Jack { .. } => {
+                if let Some(jack) = self.jack.as_mut() {
+                    jack.reset();
+                }
+            }
+            AudioPlayerType::Silent => {}
         }
     }
 
     pub fn set_layer_count(&mut self, layers: Option<usize>) {
-        if let AudioPlayerType::XSynth { .. } = self.player_type {
-            if let Some(xsynth) = self.xsynth.as_mut() {
-                xsynth.set_layer_count(layers);
+        match self.player_type {
+            AudioPlayerType::XSynth { .. } => {
+                if let Some(xsynth) = self.xsynth.as_mut() {
+                    xsynth.set_layer_count(layers);
+                }
             }
+            AudioPlayerType::Jack { .. } => {
+                if let Some(jack) = self.jack.as_mut() {
+                    jack.set_layer_count(layers);
+                }
+            }
+            _ => {}
         }
     }
 
-    pub fn set_soundfont(&mut self, path: &str, options: SoundfontInitOptions) {
-        if let AudioPlayerType::XSynth { .. } = self.player_type {
-            if let Some(xsynth) = self.xsynth.as_mut() {
-                xsynth.set_soundfont(path, options);
+    pub fn set_soundfont(&mut self, sfz: &SoundfontPath, options: SoundfontInitOptions) {
+        match self.player_type {
+            AudioPlayerType::XSynth { .. } => {
+                if let Some(xsynth) = self.xsynth.as_mut() {
+                    xsynth.set_soundfont(sfz, options);
+                    self.note_fadein = sfz.note_fadein;
+                }
             }
+            AudioPlayerType::Jack { .. } => {
+                if let Some(jack) = self.jack.as_mut() {
+                    jack.set_soundfont(sfz, options);
+                    self.note_fadein = sfz.note_fadein;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Loads a SoundFont in the background and swaps it in once it is ready, so the
+    /// audio keeps playing with the old one in the meantime. Nothing happens while
+    /// another SoundFont is still loading.
+    pub fn reload_soundfont(&mut self, sfz: &SoundfontPath, options: SoundfontInitOptions) {
+        if self.is_loading_soundfont() {
+            return;
         }
+        self.load_in_background(sfz, true, options);
     }
+
+    /// Gives the channels the copies of the SFZ without their disabled regions, or the
+    /// SFZ itself when they don't need one anymore, after the disabled regions changed
+    pub fn set_channel_soundfonts(&mut self, sfz: &SoundfontPath, options: SoundfontInitOptions) {
+        self.load_in_background(sfz, false, options);
+    }
+
+    fn load_in_background(
+        &mut self,
+        sfz: &SoundfontPath,
+        reload_main: bool,
+        options: SoundfontInitOptions,
+    ) {
+        let loading = self.soundfont_loading.clone();
+        match self.player_type {
+            AudioPlayerType::XSynth { .. } => {
+                if let Some(xsynth) = self.xsynth.as_ref() {
+                    xsynth.reload_soundfont(sfz.clone(), reload_main, options, loading);
+                    self.note_fadein = sfz.note_fadein;
+                }
+            }
+            AudioPlayerType::Jack { .. } => {
+                if let Some(jack) = self.jack.as_ref() {
+                    jack.reload_soundfont(sfz.clone(), reload_main, options, loading);
+                    self.note_fadein = sfz.note_fadein;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// How long the voices fade in, in seconds, from the SFZ that was given to the
+    /// synth. It is 0 for the players that don't load the SFZ, like KDMAPI.
+    pub fn note_fadein(&self) -> f32 {
+        self.note_fadein
+    }
+
+    pub fn is_loading_soundfont(&self) -> bool {
+        self.soundfont_loading.load(Ordering::Relaxed)
+    }
+
+    /// The ports the JACK outputs can be connected to, empty for other players
+    pub fn get_jack_ports(&self) -> Vec<String> {
+        if let Some(jack) = &self.jack {
+            jack.available_ports()
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn connect_jack_ports(&mut self, left: &str, right: &str) {
+        if let Some(jack) = self.jack.as_mut() {
+            jack.connect_ports(left, right);
+        }
+    }
+
+    /// The rendered samples of the player, only JACK renders them inside of Wasabi.
+    /// XSynth realtime sends its samples straight to the audio device.
+    pub fn pcm_buffer(&self) -> Option<Arc<PcmRingBuffer>> {
+        self.jack.as_ref().map(|jack| jack.pcm())
+    }
+}
+
+/// The channel of a Reset All Controllers event, which resets the channel tuning too
+fn resets_controllers(data: u32) -> Option<usize> {
+    (data & 0xF0 == 0xB0 && (data >> 8) & 0xFF == 121).then_some((data & 0x0F) as usize)
+}
+
+/// The channel fine tuning RPN of a channel, in cents between -100 and 100
+fn channel_tuning_events(channel: u8, cents: f32) -> [u32; 6] {
+    // 8192 is in tune, and the full 14 bit range covers +-100 cents
+    let value = (8192.0 + cents.clamp(-100.0, 100.0) / 100.0 * 8192.0)
+        .round()
+        .clamp(0.0, 16383.0) as u32;
+
+    let control = |cc: u32, value: u32| 0xB0 | channel as u32 | (cc << 8) | (value << 16);
+    [
+        control(101, 0),
+        control(100, 1),
+        control(6, value >> 7),
+        control(38, value & 0x7F),
+        control(101, 127),
+        control(100, 127),
+    ]
+}
+
+/// Converts a raw MIDI event into an event for an XSynth channel group,
+/// dropping note ons with a velocity inside of the ignored range
+fn to_synth_event(data: u32, ignore_range: &RangeInclusive<u8>) -> Option<SynthEvent> {
+    let channel = data & 0x0F;
+    let key = ((data >> 8) & 0xFF) as u8;
+    let value = ((data >> 16) & 0xFF) as u8;
+
+    let event = match data & 0xF0 {
+        0x80 => ChannelAudioEvent::NoteOff { key },
+        0x90 if value == 0 => ChannelAudioEvent::NoteOff { key },
+        0x90 => {
+            if ignore_range.contains(&value) {
+                return None;
+            }
+            ChannelAudioEvent::NoteOn { key, vel: value }
+        }
+        0xB0 => ChannelAudioEvent::Control(ControlEvent::Raw(key, value)),
+        0xE0 => {
+            let bend = (((value as i32) << 7) | key as i32) - 8192;
+            ChannelAudioEvent::Control(ControlEvent::PitchBendValue(bend as f32 / 8192.0))
+        }
+        _ => return None,
+    };
+
+    Some(SynthEvent::Channel(channel, ChannelEvent::Audio(event)))
 }
@@ -0,0 +1,46 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Enough samples for the largest FFT of the spectrogram, with some room for frames that take longer
+const CAPACITY: usize = 32768;
+
+/// The most recent samples a player rendered, mixed down to mono, so they can be visualized
+pub struct PcmRingBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    sample_rate: u32,
+}
+
+impl PcmRingBuffer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(CAPACITY)),
+            sample_rate,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Adds interleaved stereo samples. This runs on the audio thread, so the
+    /// samples are skipped instead of waiting while they are being read.
+    pub fn push_stereo(&self, frames: &[f32]) {
+        let Ok(mut samples) = self.samples.try_lock() else {
+            return;
+        };
+
+        samples.extend(
+            frames
+                .chunks_exact(2)
+                .map(|frame| (frame[0] + frame[1]) * 0.5),
+        );
+
+        let overflow = samples.len().saturating_sub(CAPACITY);
+        samples.drain(..overflow);
+    }
+
+    /// Moves the samples that were added since the last call into `out`
+    pub fn take_into(&self, out: &mut Vec<f32>) {
+        let mut samples = self.samples.lock().unwrap();
+        out.extend(samples.drain(..));
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    fs,
+    hash::{Hash, Hasher},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use crate::notifications;
+
+/// How deep `#include`s can be nested, so files that include each other don't loop forever
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A region of an SFZ instrument, the notes it plays on are used to filter it
+#[derive(Debug, Clone)]
+pub struct SfzRegion {
+    pub name: String,
+    pub keys: RangeInclusive<u8>,
+    pub velocities: RangeInclusive<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SfzGroup {
+    pub name: String,
+    pub regions: Vec<SfzRegion>,
+}
+
+/// The groups and regions of an SFZ file, only the opcodes needed to
+/// tell the regions apart are read
+#[derive(Debug, Clone)]
+pub struct SfzInstrument {
+    pub path: String,
+    pub groups: Vec<SfzGroup>,
+}
+
+impl SfzInstrument {
+    pub fn parse(path: &str) -> Option<Self> {
+        let text = read_sfz(Path::new(path))?;
+
+        let mut groups: Vec<SfzGroup> = Vec::new();
+        let mut inherited: Vec<(String, String)> = Vec::new();
+        let mut group_opcodes: Vec<(String, String)> = Vec::new();
+
+        for (header, opcodes) in split_headers(&text) {
+            match header.as_str() {
+                "global" | "master" => {
+                    inherited = opcodes;
+                    group_opcodes.clear();
+                }
+                "group" => {
+                    let name = find_opcode(&opcodes, "group_label")
+                        .map(|label| label.to_owned())
+                        .unwrap_or_else(|| format!("Group {}", groups.len() + 1));
+                    groups.push(SfzGroup {
+                        name,
+                        regions: Vec::new(),
+                    });
+                    group_opcodes = opcodes;
+                }
+                "region" => {
+                    if groups.is_empty() {
+                        groups.push(SfzGroup {
+                            name: String::from("Ungrouped"),
+                            regions: Vec::new(),
+                        });
+                    }
+
+                    // Opcodes of the region take priority over the group's, then the global ones
+                    let lookup = |name: &str| {
+                        find_opcode(&opcodes, name)
+                            .or_else(|| find_opcode(&group_opcodes, name))
+                            .or_else(|| find_opcode(&inherited, name))
+                    };
+                    groups
+                        .last_mut()
+                        .unwrap()
+                        .regions
+                        .push(parse_region(lookup));
+                }
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+            groups,
+        })
+    }
+
+    /// Iterates over the regions of every group, the index of a region in
+    /// this order is how it is identified in the settings
+    pub fn regions(&self) -> impl Iterator<Item = &SfzRegion> {
+        self.groups.iter().flat_map(|group| group.regions.iter())
+    }
+
+    /// The velocities that the disabled and the enabled regions play on each key
+    fn velocity_masks(&self, disabled: &BTreeSet<usize>) -> ([u128; 128], [u128; 128]) {
+        let mut disabled_masks = [0u128; 128];
+        let mut enabled_masks = [0u128; 128];
+        for (i, region) in self.regions().enumerate() {
+            let velocities = velocity_mask(&region.velocities);
+            let masks = if disabled.contains(&i) {
+                &mut disabled_masks
+            } else {
+                &mut enabled_masks
+            };
+            for key in region.keys.clone() {
+                masks[key as usize] |= velocities;
+            }
+        }
+        (disabled_masks, enabled_masks)
+    }
+
+    /// Whether a disabled region shares a note with an enabled one. That note can't be
+    /// dropped, so the disabled regions have to be left out of the SFZ instead.
+    pub fn disabled_overlap_enabled(&self, disabled: &BTreeSet<usize>) -> bool {
+        let (disabled, enabled) = self.velocity_masks(disabled);
+        disabled
+            .iter()
+            .zip(enabled.iter())
+            .any(|(disabled, enabled)| disabled & enabled != 0)
+    }
+}
+
+/// The index of a channel from its number in the settings, which counts from 1
+pub fn channel_index(channel: &str) -> Option<usize> {
+    channel
+        .parse::<usize>()
+        .ok()
+        .and_then(|channel| channel.checked_sub(1))
+        .filter(|channel| *channel < 16)
+}
+
+/// Reads an SFZ with the files it `#include`s pasted in and the variables it `#define`s
+/// replaced, like the synth reads it. The included files are found from the folder of
+/// the SFZ that was opened, the same as the samples.
+pub fn read_sfz(path: &Path) -> Option<String> {
+    let folder = path.parent()?;
+    let mut defines = Vec::new();
+    let mut output = String::new();
+    read_sfz_into(path, folder, &mut defines, &mut output, 0)?;
+    Some(output)
+}
+
+fn read_sfz_into(
+    path: &Path,
+    folder: &Path,
+    defines: &mut Vec<(String, String)>,
+    output: &mut String,
+    depth: usize,
+) -> Option<()> {
+    let text = fs::read_to_string(path).ok()?;
+
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or("");
+
+        if let Some(define) = line.trim_start().strip_prefix("#define") {
+            let mut parts = define.trim().splitn(2, char::is_whitespace);
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.retain(|(defined, _)| defined != name);
+                defines.push((name.to_owned(), value.trim().to_owned()));
+                // Longer names go first, so `$A` doesn't replace the start of `$AB`
+                defines.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+            }
+            continue;
+        }
+
+        let line = defines.iter().fold(line.to_owned(), |line, (name, value)| {
+            line.replace(name, value)
+        });
+
+        if let Some(include) = line.trim_start().strip_prefix("#include") {
+            let include = folder.join(include.trim().trim_matches('"').replace('\\', "/"));
+            if depth >= MAX_INCLUDE_DEPTH {
+                notifications::error(format!(
+                    "{} isn't read, the #includes of {} are nested too deep",
+                    include.display(),
+                    path.display()
+                ));
+            } else if read_sfz_into(&include, folder, defines, output, depth + 1).is_none() {
+                notifications::error(format!(
+                    "Failed to read {}, which {} includes",
+                    include.display(),
+                    path.display()
+                ));
+            }
+            continue;
+        }
+
+        output.push_str(&line);
+        output.push('\n');
+    }
+
+    Some(())
+}
+
+/// How the envelopes of the regions are changed in the copies of an SFZ, since
+/// XSynth has no setting for them
+#[derive(Debug, Clone, Copy)]
+pub struct EnvelopeOverride {
+    pub release_multiplier: f32,
+    pub release_cutoff_secs: Option<f32>,
+    /// The attack time of every region is raised to at least this
+    pub min_attack_secs: f32,
+}
+
+/// Writes a copy of an SFZ with the envelopes changed and the `disabled_regions` left
+/// out, with its `#include`s and `#define`s already resolved. The copy goes in the temp
+/// folder, so the sample paths are made absolute to still lead to the same files. The
+/// copies of one SFZ are told apart by `suffix`. Returns `None` if the SFZ can't be read.
+pub fn write_sfz_copy(
+    path: &str,
+    suffix: &str,
+    envelope: Option<EnvelopeOverride>,
+    disabled_regions: &BTreeSet<usize>,
+) -> Option<PathBuf> {
+    let text = read_sfz(Path::new(path))?;
+
+    let original = Path::new(path);
+    let folder = original.canonicalize().ok()?.parent()?.to_path_buf();
+
+    // The samples are found from `default_path` when the SFZ has one, otherwise from
+    // the folder of the SFZ
+    let absolute = |value: &str| folder.join(value.replace('\\', "/"));
+    let mut has_default_path = false;
+
+    let mut output = String::new();
+    let mut inherited: Vec<(String, String)> = Vec::new();
+    let mut group_opcodes: Vec<(String, String)> = Vec::new();
+    let mut region_index = 0;
+
+    for (header, opcodes) in split_headers(&text) {
+        match header.as_str() {
+            "global" | "master" => {
+                inherited = opcodes.clone();
+                group_opcodes.clear();
+            }
+            "group" => group_opcodes = opcodes.clone(),
+            "region" => {
+                let index = region_index;
+                region_index += 1;
+                if disabled_regions.contains(&index) {
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        output.push('<');
+        output.push_str(&header);
+        output.push('>');
+        for (name, value) in opcodes.iter() {
+            let value = match name.as_str() {
+                "default_path" => {
+                    has_default_path = true;
+                    // The sample is added to the end of it as text
+                    let mut path = absolute(value).to_string_lossy().into_owned();
+                    if !path.ends_with('/') {
+                        path.push('/');
+                    }
+                    path
+                }
+                // Names starting with `*` are generated sounds instead of files
+                "sample" if !has_default_path && !value.starts_with('*') => {
+                    absolute(value).to_string_lossy().into_owned()
+                }
+                _ => value.clone(),
+            };
+            output.push_str(&format!(" {}={}", name, value));
+        }
+
+        // The last value of an opcode wins, so this overrides the region's own envelope
+        if let Some(envelope) = envelope.filter(|_| header == "region") {
+            let lookup = |name: &str| {
+                find_opcode(&opcodes, name)
+                    .or_else(|| find_opcode(&group_opcodes, name))
+                    .or_else(|| find_opcode(&inherited, name))
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .unwrap_or(0.0)
+            };
+
+            let mut release = lookup("ampeg_release") * envelope.release_multiplier;
+            if let Some(cutoff) = envelope.release_cutoff_secs {
+                release = release.min(cutoff);
+            }
+            output.push_str(&format!(" ampeg_release={}", release));
+
+            if envelope.min_attack_secs > 0.0 {
+                let attack = lookup("ampeg_attack").max(envelope.min_attack_secs);
+                output.push_str(&format!(" ampeg_attack={}", attack));
+            }
+        }
+        output.push('\n');
+    }
+
+    // The full path is hashed, so SFZs with the same name in other folders don't share
+    // the copy
+    let mut hasher = DefaultHasher::new();
+    folder.hash(&mut hasher);
+    let stem = original.file_stem()?.to_string_lossy();
+    let mut dir = std::env::temp_dir();
+    dir.push("wasabi");
+    if let Err(e) = fs::create_dir_all(&dir) {
+        notifications::error(format!("Failed to create {}: {}", dir.display(), e));
+        return None;
+    }
+    let copy = dir.join(format!("{}-{:016x}{}.sfz", stem, hasher.finish(), suffix));
+    match fs::write(&copy, output) {
+        Ok(()) => Some(copy),
+        Err(e) => {
+            notifications::error(format!("Failed to write {}: {}", copy.display(), e));
+            None
+        }
+    }
+}
+
+fn parse_region<'a>(lookup: impl Fn(&str) -> Option<&'a str>) -> SfzRegion {
+    let key = lookup("key").and_then(parse_key);
+    let lokey = lookup("lokey").and_then(parse_key).or(key).unwrap_or(0);
+    let hikey = lookup("hikey").and_then(parse_key).or(key).unwrap_or(127);
+    let lovel = lookup("lovel").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let hivel = lookup("hivel").and_then(|v| v.parse().ok()).unwrap_or(127);
+
+    let name = lookup("region_label")
+        .map(|label| label.to_owned())
+        .or_else(|| {
+            lookup("sample").map(|sample| {
+                let sample = sample.replace('\\', "/");
+                Path::new(&sample)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or(sample)
+            })
+        })
+        .unwrap_or_default();
+
+    SfzRegion {
+        name,
+        keys: lokey.min(127)..=hikey.min(127),
+        velocities: lovel.min(127)..=hivel.min(127),
+    }
+}
+
+/// Reads a key either as a number or as a note name like `c#4`, where `c4` is 60
+fn parse_key(value: &str) -> Option<u8> {
+    if let Ok(key) = value.parse::<i32>() {
+        return u8::try_from(key).ok();
+    }
+
+    let value = value.to_lowercase();
+    let mut chars = value.chars();
+    let mut key = match chars.next()? {
+        'c' => 0,
+        'd' => 2,
+        'e' => 4,
+        'f' => 5,
+        'g' => 7,
+        'a' => 9,
+        'b' => 11,
+        _ => return None,
+    };
+    let mut rest = chars.as_str();
+    if let Some(stripped) = rest.strip_prefix('#') {
+        key += 1;
+        rest = stripped;
+    } else if let Some(stripped) = rest.strip_prefix('b') {
+        key -= 1;
+        rest = stripped;
+    }
+
+    let octave: i32 = rest.parse().ok()?;
+    u8::try_from((octave + 1) * 12 + key).ok()
+}
+
+fn find_opcode<'a>(opcodes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    opcodes
+        .iter()
+        .rev()
+        .find(|(opcode, _)| opcode == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Splits an SFZ file into its headers and the opcodes that follow each of them
+fn split_headers(text: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let text = text
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut headers = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let header = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let body = &rest[..rest.find('<').unwrap_or(rest.len())];
+        headers.push((header.trim().to_owned(), parse_opcodes(body)));
+    }
+    headers
+}
+
+/// Values can contain spaces, like sample paths, so a value continues until the next
+/// word that has an `=` in it. The value is taken from the text as it is, so the spaces
+/// in it are kept.
+fn parse_opcodes(body: &str) -> Vec<(String, String)> {
+    // The name of each opcode, where its value starts and where its word starts
+    let mut starts: Vec<(&str, usize, usize)> = Vec::new();
+    for word in body.split_whitespace() {
+        let offset = word.as_ptr() as usize - body.as_ptr() as usize;
+        if let Some((name, _)) = word.split_once('=') {
+            if !name.starts_with('#') {
+                starts.push((name, offset + name.len() + 1, offset));
+            }
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, (name, value_start, _))| {
+            let value_end = starts.get(i + 1).map_or(body.len(), |(_, _, next)| *next);
+            (
+                (*name).to_owned(),
+                body[*value_start..value_end].trim().to_owned(),
+            )
+        })
+        .collect()
+}
+
+/// The notes that would only play disabled regions, per channel. XSynth can't
+/// leave out regions by itself, so these notes are dropped before reaching it. The
+/// disabled regions that share notes with enabled ones are left out of a copy of
+/// the SFZ that the channel plays instead.
+pub struct RegionFilter {
+    /// A bit for each velocity, for every key of every channel
+    blocked: Box<[[u128; 128]; 16]>,
+}
+
+impl RegionFilter {
+    /// Returns `None` if every region is enabled on every channel
+    pub fn new(
+        instrument: &SfzInstrument,
+        disabled_regions: &BTreeMap<String, BTreeSet<usize>>,
+    ) -> Option<Self> {
+        let mut blocked = Box::new([[0u128; 128]; 16]);
+        let mut any_blocked = false;
+
+        for (channel, disabled) in disabled_regions.iter() {
+            let Some(channel) = channel_index(channel) else {
+                continue;
+            };
+            if disabled.is_empty() {
+                continue;
+            }
+
+            // The notes that an enabled region plays too are left to the channel's own
+            // copy of the SFZ without the disabled regions
+            let (disabled, enabled) = instrument.velocity_masks(disabled);
+            for ((blocked, disabled), enabled) in blocked[channel]
+                .iter_mut()
+                .zip(disabled.iter())
+                .zip(enabled.iter())
+            {
+                *blocked = disabled & !enabled;
+                any_blocked |= *blocked != 0;
+            }
+        }
+
+        any_blocked.then_some(Self { blocked })
+    }
+
+    /// Whether a raw MIDI event is a note on that only disabled regions would play
+    pub fn blocks(&self, data: u32) -> bool {
+        let channel = (data & 0x0F) as usize;
+        let key = ((data >> 8) & 0x7F) as usize;
+        let velocity = (data >> 16) & 0x7F;
+
+        data & 0xF0 == 0x90 && velocity > 0 && (self.blocked[channel][key] >> velocity) & 1 == 1
+    }
+}
+
+fn velocity_mask(velocities: &RangeInclusive<u8>) -> u128 {
+    (*velocities.start()..=*velocities.end())
+        .filter(|velocity| *velocity < 128)
+        .fold(0, |mask, velocity| mask | 1 << velocity)
+}
@@ -0,0 +1,213 @@
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use rustfft::{num_complex::Complex, FftPlanner};
+use xsynth_core::{
+    channel::{ChannelAudioEvent, ChannelConfigEvent, ChannelEvent, ChannelInitOptions},
+    channel_group::{ChannelGroup, ChannelGroupConfig, SynthEvent},
+    soundfont::{SampleSoundfont, SoundfontBase, SoundfontInitOptions},
+    AudioPipe, AudioStreamParams, ChannelCount,
+};
+
+use crate::{midi::MIDIColor, notifications, settings::WasabiSettings};
+
+use super::xsynth::convert_to_sf_init;
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// How much of each note is rendered, long enough for the attack to settle
+const NOTE_SAMPLES: usize = SAMPLE_RATE as usize / 2;
+
+const FFT_SIZE: usize = 4096;
+
+/// The hue of the lowest centroid, the highest one is red
+const COOL_HUE: f32 = 0.66;
+
+/// The key each General MIDI family usually plays around, indexed by `program / 8`
+const FAMILY_KEYS: [u8; 16] = [
+    60, // Piano
+    72, // Chromatic percussion
+    60, // Organ
+    55, // Guitar
+    36, // Bass
+    67, // Strings
+    60, // Ensemble
+    58, // Brass
+    62, // Reed
+    79, // Pipe
+    72, // Synth lead
+    55, // Synth pad
+    64, // Synth effects
+    62, // Ethnic
+    60, // Percussive
+    60, // Sound effects
+];
+
+/// What the cached colors were analyzed from, any change analyzes the SFZ again
+#[derive(PartialEq)]
+struct CacheKey {
+    sfz_path: String,
+    linear_release: bool,
+    use_effects: bool,
+    modified: Option<SystemTime>,
+}
+
+/// The colors of the last SFZ that was analyzed
+static CACHE: Mutex<Option<(CacheKey, [u32; 128])>> = Mutex::new(None);
+
+/// Colors the programs by the spectral centroid of a note played through the SFZ,
+/// warm for bright sounds and cool for dull ones.
+///
+/// The synth plays the same SFZ for every program, so the programs can't be told
+/// apart by their own sound. Instead, each General MIDI family is played in the
+/// register it usually plays in, and the programs of a family share its hue. The
+/// colors mostly follow that register table, and only the brightness of the SFZ
+/// in each register comes from the analysis.
+pub struct TimbreColors {
+    sfz_path: String,
+    options: SoundfontInitOptions,
+}
+
+impl TimbreColors {
+    /// The analysis for the current SFZ, if the smart colors are turned on
+    pub fn from_settings(settings: &WasabiSettings) -> Option<Self> {
+        settings.midi.smart_colors.then(|| Self {
+            sfz_path: settings.synth.sfz_path.clone(),
+            options: convert_to_sf_init(settings),
+        })
+    }
+
+    /// The colors of the 128 programs, analyzed once for each SFZ. Errors are
+    /// printed and give `None`, so the MIDI still loads with its usual colors.
+    pub fn program_colors(&self) -> Option<Vec<MIDIColor>> {
+        let key = CacheKey {
+            sfz_path: self.sfz_path.clone(),
+            linear_release: self.options.linear_release,
+            use_effects: self.options.use_effects,
+            modified: fs::metadata(&self.sfz_path)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+        };
+
+        let mut cache = CACHE.lock().unwrap();
+        let colors = match cache.as_ref() {
+            Some((cached, colors)) if *cached == key => *colors,
+            _ => match self.analyze() {
+                Ok(colors) => {
+                    *cache = Some((key, colors));
+                    colors
+                }
+                Err(e) => {
+                    notifications::error(format!("Failed to analyze the SFZ for colors: {}", e));
+                    return None;
+                }
+            },
+        };
+        Some(
+            colors
+                .iter()
+                .map(|&color| MIDIColor::from_u32(color))
+                .collect(),
+        )
+    }
+
+    fn analyze(&self) -> Result<[u32; 128], String> {
+        let stream_params = AudioStreamParams::new(SAMPLE_RATE, ChannelCount::Stereo);
+        let soundfont = SampleSoundfont::new(&self.sfz_path, stream_params, self.options)
+            .map_err(|e| format!("Failed to load soundfont: {:?}", e))?;
+        let soundfont: Arc<dyn SoundfontBase> = Arc::new(soundfont);
+
+        let mut group = ChannelGroup::new(ChannelGroupConfig {
+            // The notes are cut off right away, so they don't bleed into the next one
+            channel_init_options: ChannelInitOptions {
+                fade_out_killing: false,
+            },
+            channel_count: 1,
+            audio_params: stream_params,
+            use_threadpool: false,
+        });
+        group.send_event(SynthEvent::AllChannels(ChannelEvent::Config(
+            ChannelConfigEvent::SetSoundfonts(vec![soundfont]),
+        )));
+
+        // The programs of a family share a key, so each family is only rendered once
+        let centroids: Vec<Option<f32>> = FAMILY_KEYS
+            .iter()
+            .map(|&key| render_centroid(&mut group, key))
+            .collect();
+
+        let log_centroids = centroids.iter().flatten().map(|centroid| centroid.log2());
+        let (low, high) = log_centroids.fold((f32::MAX, f32::MIN), |(low, high), centroid| {
+            (low.min(centroid), high.max(centroid))
+        });
+        if low > high {
+            return Err("no key made a sound".to_string());
+        }
+
+        let mut colors = [0; 128];
+        for (program, color) in colors.iter_mut().enumerate() {
+            let family = program / 8;
+            let index = program % 8;
+
+            // A key without a region keeps the hue of the middle
+            let t = match centroids[family] {
+                Some(centroid) if high > low => (centroid.log2() - low) / (high - low),
+                _ => 0.5,
+            };
+            let hsva =
+                egui::ecolor::Hsva::new((1.0 - t) * COOL_HUE, 0.8, 1.0 - index as f32 * 0.06, 1.0);
+            let [r, g, b, _] = egui::Color32::from(hsva).to_array();
+            *color = MIDIColor::new(r, g, b).as_u32();
+        }
+        Ok(colors)
+    }
+}
+
+/// Plays a key and measures the spectral centroid of the sound in Hz, or `None` if
+/// the SFZ has nothing on the key
+fn render_centroid(group: &mut ChannelGroup, key: u8) -> Option<f32> {
+    let note = |event| SynthEvent::Channel(0, ChannelEvent::Audio(event));
+    group.send_event(note(ChannelAudioEvent::NoteOn { key, vel: 100 }));
+
+    let mut samples = vec![0.0; NOTE_SAMPLES * 2];
+    group.read_samples(&mut samples);
+    group.send_event(note(ChannelAudioEvent::AllNotesKilled));
+
+    let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (i as f32 / FFT_SIZE as f32 * std::f32::consts::TAU).cos())
+        .collect();
+
+    // The magnitudes of all windows are summed, the stereo channels are mixed down
+    let mono: Vec<f32> = samples
+        .chunks_exact(2)
+        .map(|frame| (frame[0] + frame[1]) * 0.5)
+        .collect();
+    let mut magnitudes = vec![0.0; FFT_SIZE / 2];
+    let mut buffer = Vec::with_capacity(FFT_SIZE);
+    for chunk in mono.chunks_exact(FFT_SIZE) {
+        buffer.clear();
+        buffer.extend(
+            chunk
+                .iter()
+                .zip(window.iter())
+                .map(|(sample, window)| Complex::new(sample * window, 0.0)),
+        );
+        fft.process(&mut buffer);
+        for (magnitude, bin) in magnitudes.iter_mut().zip(buffer.iter()) {
+            *magnitude += bin.norm();
+        }
+    }
+
+    let bin_hz = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+    let total: f32 = magnitudes.iter().sum();
+    let weighted: f32 = magnitudes
+        .iter()
+        .enumerate()
+        .map(|(bin, magnitude)| bin as f32 * bin_hz * magnitude)
+        .sum();
+    (total > f32::EPSILON).then(|| weighted / total)
+}
@@ -1,13 +1,20 @@
 use std::{
+    collections::BTreeSet,
     ops::{Deref, DerefMut, RangeInclusive},
     path::Path,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
-use crate::WasabiSettings;
+use crate::{notifications, WasabiSettings};
+
+use super::sfz::{channel_index, write_sfz_copy, EnvelopeOverride, SfzInstrument};
 
 use xsynth_core::{
-    channel::{ChannelConfigEvent, ChannelInitOptions},
+    channel::{ChannelConfigEvent, ChannelEvent, ChannelInitOptions},
+    channel_group::SynthEvent,
     soundfont::{SampleSoundfont, SoundfontBase, SoundfontInitOptions},
     AudioStreamParams,
 };
@@ -39,6 +46,7 @@ pub struct XSynthPlayer {
     sender: RealtimeEventSender,
     pub stats: RealtimeSynthStatsReader,
     stream_params: AudioStreamParams,
+    soundfont: SharedSoundfont,
     _synth: FuckYouImSend<RealtimeSynth>,
 }
 
@@ -61,6 +69,7 @@ impl XSynthPlayer {
             sender,
             stats,
             stream_params,
+            soundfont: Default::default(),
             _synth: synth,
         }
     }
@@ -82,16 +91,198 @@ impl XSynthPlayer {
             .send_config(ChannelConfigEvent::SetLayerCount(layers));
     }
 
-    pub fn set_soundfont(&mut self, path: &str, options: SoundfontInitOptions) {
-        if !path.is_empty() && Path::new(path).exists() {
-            let samplesf = SampleSoundfont::new(path, self.stream_params, options);
-            if let Ok(sf) = samplesf {
-                let soundfont: Arc<dyn SoundfontBase> = Arc::new(sf);
-                self.sender
-                    .send_config(ChannelConfigEvent::SetSoundfonts(vec![soundfont]));
+    pub fn set_soundfont(&mut self, sfz: &SoundfontPath, options: SoundfontInitOptions) {
+        if sfz.path.is_empty() {
+            return;
+        }
+        let sender = &mut self.sender;
+        let result = load_soundfonts(
+            sfz,
+            true,
+            &self.soundfont,
+            self.stream_params,
+            options,
+            |event| sender.send_event(event),
+        );
+        if let Err(e) = result {
+            notifications::error(e);
+        }
+    }
+
+    /// Like `set_soundfont`, but the SFZ is loaded on another thread and the old one
+    /// keeps playing until the new one is swapped in. Only the copies of the channels
+    /// are loaded again without `reload_main`.
+    pub fn reload_soundfont(
+        &self,
+        sfz: SoundfontPath,
+        reload_main: bool,
+        options: SoundfontInitOptions,
+        loading: Arc<AtomicBool>,
+    ) {
+        let mut sender = self.sender.clone();
+        load_soundfonts_in_background(
+            sfz,
+            reload_main,
+            self.soundfont.clone(),
+            self.stream_params,
+            options,
+            loading,
+            move |event| sender.send_event(event),
+        );
+    }
+}
+
+/// The SFZ that the synth plays, kept so the channels that stop playing their own
+/// copy of it can be given it back
+pub type SharedSoundfont = Arc<Mutex<Option<Arc<dyn SoundfontBase>>>>;
+
+fn load_soundfont(
+    path: &str,
+    stream_params: AudioStreamParams,
+    options: SoundfontInitOptions,
+) -> Result<Arc<dyn SoundfontBase>, String> {
+    if path.is_empty() || !Path::new(path).exists() {
+        return Err(format!("The SoundFont {} doesn't exist", path));
+    }
+    match SampleSoundfont::new(path, stream_params, options) {
+        Ok(sf) => Ok(Arc::new(sf)),
+        Err(e) => Err(format!("Failed to load the SoundFont {}: {:?}", path, e)),
+    }
+}
+
+/// Loads the SFZ, or takes the one that is already loaded without `reload_main`, and
+/// sends it to the channels that don't have their own copy of it. The copies are
+/// loaded and sent to their channels, a channel whose copy fails gets the SFZ instead.
+pub fn load_soundfonts(
+    sfz: &SoundfontPath,
+    reload_main: bool,
+    shared: &SharedSoundfont,
+    stream_params: AudioStreamParams,
+    options: SoundfontInitOptions,
+    mut send: impl FnMut(SynthEvent),
+) -> Result<(), String> {
+    let main = if reload_main {
+        let main = load_soundfont(&sfz.path, stream_params, options)?;
+        *shared.lock().unwrap() = Some(main.clone());
+        Some(main)
+    } else {
+        shared.lock().unwrap().clone()
+    };
+
+    let set_soundfont = |soundfont: Arc<dyn SoundfontBase>| {
+        ChannelEvent::Config(ChannelConfigEvent::SetSoundfonts(vec![soundfont]))
+    };
+    if sfz.channels.is_empty() {
+        if let Some(main) = main {
+            send(SynthEvent::AllChannels(set_soundfont(main)));
+        }
+        return Ok(());
+    }
+
+    let mut result = Ok(());
+    for channel in 0..16 {
+        let soundfont = match sfz.channels.iter().find(|(c, _)| *c == channel) {
+            Some((_, path)) => match load_soundfont(path, stream_params, options) {
+                Ok(soundfont) => Some(soundfont),
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                    main.clone()
+                }
+            },
+            None => main.clone(),
+        };
+        if let Some(soundfont) = soundfont {
+            send(SynthEvent::Channel(channel, set_soundfont(soundfont)));
+        }
+    }
+    result
+}
+
+/// Runs `load_soundfonts` on a new thread, `loading` is set for as long as it runs
+pub fn load_soundfonts_in_background(
+    sfz: SoundfontPath,
+    reload_main: bool,
+    shared: SharedSoundfont,
+    stream_params: AudioStreamParams,
+    options: SoundfontInitOptions,
+    loading: Arc<AtomicBool>,
+    send: impl 'static + Send + FnMut(SynthEvent),
+) {
+    loading.store(true, Ordering::Relaxed);
+    std::thread::spawn(move || {
+        if let Err(e) = load_soundfonts(&sfz, reload_main, &shared, stream_params, options, send) {
+            notifications::error(e);
+        }
+        loading.store(false, Ordering::Relaxed);
+    });
+}
+
+/// The SFZ to load into the synth, with the fade in in seconds that was written into it
+#[derive(Debug, Clone)]
+pub struct SoundfontPath {
+    pub path: String,
+    pub note_fadein: f32,
+    /// The copies of the SFZ for the channels whose disabled regions share notes with
+    /// enabled ones, without the disabled regions
+    pub channels: Vec<(u32, String)>,
+}
+
+/// The SFZ that is loaded into the synth, which is a copy with the envelopes
+/// changed when the release or fade in settings aren't left at their defaults.
+/// XSynth has no options for either, so they are written into the regions. The
+/// original is used without a fade in when the copy can't be written.
+pub fn soundfont_path(settings: &WasabiSettings) -> SoundfontPath {
+    let path = &settings.synth.sfz_path;
+    let mut envelope = (settings.synth.release_multiplier != 1.0
+        || settings.synth.release_cutoff_ms.is_some()
+        || settings.synth.note_fadein_ms > 0.0)
+        .then(|| EnvelopeOverride {
+            release_multiplier: settings.synth.release_multiplier,
+            release_cutoff_secs: settings.synth.release_cutoff_ms.map(|ms| ms / 1000.0),
+            min_attack_secs: settings.synth.note_fadein_ms.max(0.0) / 1000.0,
+        });
+
+    let mut sfz = SoundfontPath {
+        path: path.clone(),
+        note_fadein: 0.0,
+        channels: Vec::new(),
+    };
+    if let Some(override_envelope) = envelope {
+        match write_sfz_copy(path, "", Some(override_envelope), &BTreeSet::new()) {
+            Some(copy) => {
+                sfz.path = copy.to_string_lossy().into_owned();
+                sfz.note_fadein = override_envelope.min_attack_secs;
+            }
+            // The channel copies would fade in when the rest doesn't
+            None => envelope = None,
+        }
+    }
+
+    let channels = settings
+        .synth
+        .disabled_regions
+        .iter()
+        .filter_map(|(channel, disabled)| Some((channel_index(channel)?, disabled)))
+        .filter(|(_, disabled)| !disabled.is_empty())
+        .collect::<Vec<_>>();
+    if !channels.is_empty() {
+        if let Some(instrument) = SfzInstrument::parse(path) {
+            for (channel, disabled) in channels {
+                if !instrument.disabled_overlap_enabled(disabled) {
+                    continue;
+                }
+                let suffix = format!("-ch{}", channel + 1);
+                if let Some(copy) = write_sfz_copy(path, &suffix, envelope, disabled) {
+                    sfz.channels
+                        .push((channel as u32, copy.to_string_lossy().into_owned()));
+                }
             }
         }
     }
+
+    sfz
 }
 
 pub fn convert_to_sf_init(settings: &WasabiSettings) -> SoundfontInitOptions {
@@ -1,7 +1,10 @@
 use std::sync::Arc;
 
 use egui_winit_vulkano::Gui;
-use vulkano::device::{Device, Queue};
+use vulkano::{
+    device::{Device, Queue},
+    pipeline::cache::PipelineCache,
+};
 
 use crate::renderer::swapchain::SwapchainFrame;
 
@@ -18,4 +21,5 @@ pub struct GuiRenderer<'a> {
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
     pub format: vulkano::format::Format,
+    pub pipeline_cache: Arc<PipelineCache>,
 }
@@ -1,38 +1,112 @@
+mod cc_lanes;
+mod channel_activity;
+mod chord_names;
+mod comparison;
+mod drum_pads;
+mod fonts;
 mod fps;
+mod instrument_labels;
 mod keyboard;
 mod keyboard_layout;
+mod live_chat;
+mod lyrics;
+mod midi_info;
+mod note_editor;
+mod note_inspector;
+mod remote_control;
+mod resume_prompt;
 mod scene;
+mod sop_report;
 mod stats;
+mod svg_export;
+mod time_ruler;
+mod toasts;
+mod tracks_window;
+mod video_export;
 
 mod settings_window;
+mod spectrogram;
 mod top_panel;
+mod velocity_histogram;
+mod vu_meter;
+mod watermark;
 mod xsynth_settings;
+mod zip_picker;
 
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
+    thread,
     time::Duration,
 };
 
-use egui::{style::Margin, Frame, Visuals};
+use crossbeam_channel::{Receiver, TryRecvError};
+use egui::{style::Margin, Frame, TextureHandle, Visuals};
+use rand::Rng;
+use tempfile::TempPath;
+use triggered::Trigger;
 
 use crate::{
     audio_playback::{
-        xsynth::{convert_to_channel_init, convert_to_sf_init},
+        sfz::{RegionFilter, SfzInstrument},
+        timbre::TimbreColors,
         AudioPlayerType, SimpleTemporaryPlayer,
     },
-    gui::window::{keyboard::GuiKeyboard, scene::GuiRenderScene},
-    midi::{CakeMIDIFile, InRamMIDIFile, LiveLoadMIDIFile, MIDIFileBase, MIDIFileUnion},
-    settings::{MidiLoading, Synth, WasabiSettings},
+    gui::window::{
+        keyboard::{GuiKeyboard, KeyboardTheme},
+        keyboard_layout::KeyboardParams,
+        remote_control::{RemoteStatus, WasabiRemoteCommand},
+        scene::GuiRenderScene,
+    },
+    midi::{
+        decompress_gzip_midi, export_musicxml, extract_zip_midi, is_gzip_midi, is_zip_archive,
+        list_zip_midis, validate_sop, CakeMIDIFile, InRamMIDIFile, LiveLoadMIDIFile, LoopStation,
+        MIDIClockSync, MIDIColor, MIDIFileBase, MIDIFileUnion, MIDIOpenError, MIDISidecar,
+    },
+    notifications,
+    renderer::describe_gpu_devices,
+    settings::{
+        EventFilterFlags, MidiLoading, MidiRepairMode, PercussionDisplayMode, SavedSession,
+        SettingsOverride, WasabiAction, WasabiSettings,
+    },
     state::WasabiState,
     GuiRenderer, GuiState,
 };
 
 use egui_file::FileDialog;
+use image::RgbaImage;
 
 pub struct WasabiFileDialogs {
     midi_file_dialog: Option<FileDialog>,
     sf_file_dialog: Option<FileDialog>,
+    zip_picker: Option<zip_picker::ZipPicker>,
+    comparison_file_dialog: Option<FileDialog>,
+    musicxml_file_dialog: Option<FileDialog>,
+    svg_file_dialog: Option<FileDialog>,
+}
+
+/// The MIDI that was open before the last one was loaded, kept so the load can be undone
+struct PreviousMIDI {
+    midi_file: MIDIFileUnion,
+    extracted_midi: Option<TempPath>,
+    path: PathBuf,
+    position: Duration,
+    track_order: Vec<usize>,
+}
+
+/// A MIDI that is being decompressed or extracted on a separate thread
+struct PendingExtract {
+    receiver: Receiver<Option<TempPath>>,
+    cancel: Trigger,
+}
+
+/// A MIDI file that is being loaded on a separate thread
+struct PendingMIDILoad {
+    receiver: Receiver<Result<MIDIFileUnion, MIDIOpenError>>,
+    cancel: Trigger,
+    sidecar: MIDISidecar,
+    /// Where to continue playing from, when the MIDI is loaded again
+    position: Option<Duration>,
 }
 
 pub struct GuiWasabiWindow {
@@ -43,40 +117,91 @@ pub struct GuiWasabiWindow {
     synth: Arc<RwLock<SimpleTemporaryPlayer>>,
     fps: fps::Fps,
     file_dialogs: WasabiFileDialogs,
+    ui_font_size: f32,
+    /// The MIDI files waiting to be rendered to videos
+    video_export: video_export::VideoExportQueue,
+    transpose: i8,
+    event_filter: EventFilterFlags,
+    muted_channels: u16,
+    follow_start: Option<f32>,
+    /// The first and last key the key range is zooming to with Ctrl + scroll
+    key_zoom_target: Option<(f32, f32)>,
+    /// Where the zoom animation is between whole keys, like `follow_start`
+    key_zoom_current: Option<(f32, f32)>,
+    key_scroll: f32,
+    touch_key_zoom: f32,
+    vu_meter: vu_meter::VuMeter,
+    spectrogram: spectrogram::SpectrogramPanel,
+    midi_clock: Option<MIDIClockSync>,
+    midi_clock_port: Option<String>,
+    live_chat: Option<live_chat::LiveChat>,
+    /// The Twitch channel the chat was last connected to, so it isn't retried every frame
+    live_chat_channel: Option<String>,
+    remote_control: Option<remote_control::RemoteControl>,
+    /// The port the remote control was last started on, so it isn't retried every frame
+    remote_port: Option<u16>,
+    note_editor: note_editor::NoteEditor,
+    note_inspector: note_inspector::NoteInspector,
+    pending_extract: Option<PendingExtract>,
+    /// The temporary file that the open MIDI was decompressed or extracted to, which is
+    /// deleted once the MIDI is closed
+    extracted_midi: Option<TempPath>,
+    pending_load: Option<PendingMIDILoad>,
+    /// Stops the metadata scan of the loaded MIDI, once another one is loaded
+    metadata_scan: Option<Trigger>,
+    comparison: Option<comparison::ComparisonView>,
+    loop_station: Option<LoopStation>,
+    pending_loop_layers: Option<Receiver<Result<InRamMIDIFile, MIDIOpenError>>>,
+    settings_override: Option<SettingsOverride>,
+    brightness: f32,
+    dragged_track: Option<usize>,
+    /// Set by the track list when the order changed, which the cake renderer only
+    /// applies when loading
+    track_order_changed: bool,
+    paused_on_focus_loss: bool,
+    sfz_instrument: Option<SfzInstrument>,
+    region_channel: usize,
+    /// How many notes each key has in the MIDI, for the keyboard heatmap
+    key_counts: Option<Vec<u64>>,
+    drum_pads: drum_pads::DrumPads,
+    /// The album art of the MIDI and its texture, once it was uploaded
+    album_art: Option<(Arc<RgbaImage>, TextureHandle)>,
+    previous_midi: Option<PreviousMIDI>,
+    sop_report: Option<sop_report::SopReport>,
+    toasts: toasts::Toasts,
+    /// The validation running on its own thread, since it reads the whole file again
+    pending_sop: Option<Receiver<sop_report::SopReport>>,
+    /// The tempo the MIDI is played at instead of the one it starts with
+    tempo_override: Option<f64>,
+    /// The names of the GPUs for the GPU setting, listed once at startup
+    gpu_devices: Vec<String>,
+    /// Where to save the notes on screen as SVG, which happens when the notes are drawn next
+    pending_svg_export: Option<PathBuf>,
+    /// Where the MIDI was when Wasabi was last closed, until the user
+    /// answered whether to continue from there
+    resume_position: Option<Duration>,
 }
 
 impl GuiWasabiWindow {
     pub fn new(renderer: &mut GuiRenderer, settings: &mut WasabiSettings) -> GuiWasabiWindow {
-        let synth = match settings.synth.synth {
-            Synth::Kdmapi => Arc::new(RwLock::new(SimpleTemporaryPlayer::new(
-                AudioPlayerType::Kdmapi,
-            ))),
-            Synth::XSynth => {
-                let synth = Arc::new(RwLock::new(SimpleTemporaryPlayer::new(
-                    AudioPlayerType::XSynth {
-                        buffer: settings.synth.buffer_ms,
-                        ignore_range: settings.synth.vel_ignore.clone(),
-                        options: convert_to_channel_init(settings),
-                    },
-                )));
-                synth
-                    .write()
-                    .unwrap()
-                    .set_soundfont(&settings.synth.sfz_path, convert_to_sf_init(settings));
-                synth
-                    .write()
-                    .unwrap()
-                    .set_layer_count(match settings.synth.layer_count {
-                        0 => None,
-                        _ => Some(settings.synth.layer_count),
-                    });
-                synth
-            }
-        };
+        let mut player = SimpleTemporaryPlayer::new(AudioPlayerType::Silent);
+        player.start_synth(settings);
+        player.set_transpose(settings.midi.transpose_semitones);
+        player.set_event_filter(settings.midi.event_filter);
+        player.set_muted_channels(settings.midi.muted_channels);
+        let synth = Arc::new(RwLock::new(player));
 
-        GuiWasabiWindow {
+        let ctx = renderer.gui.context();
+        if let Some(path) = &settings.visual.ui_font_path {
+            fonts::load_ui_font(&ctx, path);
+        }
+        fonts::set_ui_font_size(&ctx, settings.visual.ui_font_size);
+
+        let mut window = GuiWasabiWindow {
             render_scene: GuiRenderScene::new(renderer),
-            keyboard_layout: keyboard_layout::KeyboardLayout::new(&Default::default()),
+            keyboard_layout: keyboard_layout::KeyboardLayout::new(&KeyboardParams::classic(
+                settings.visual.black_key_width_ratio,
+            )),
             keyboard: GuiKeyboard::new(),
             midi_file: None,
             synth,
@@ -84,8 +209,59 @@ impl GuiWasabiWindow {
             file_dialogs: WasabiFileDialogs {
                 midi_file_dialog: None,
                 sf_file_dialog: None,
+                zip_picker: None,
+                comparison_file_dialog: None,
+                musicxml_file_dialog: None,
+                svg_file_dialog: None,
             },
-        }
+            ui_font_size: settings.visual.ui_font_size,
+            video_export: Default::default(),
+            transpose: settings.midi.transpose_semitones,
+            event_filter: settings.midi.event_filter,
+            muted_channels: settings.midi.muted_channels,
+            follow_start: None,
+            key_zoom_target: None,
+            key_zoom_current: None,
+            key_scroll: 0.0,
+            touch_key_zoom: 0.0,
+            vu_meter: vu_meter::VuMeter::new(),
+            spectrogram: spectrogram::SpectrogramPanel::new(),
+            midi_clock: None,
+            midi_clock_port: None,
+            live_chat: None,
+            live_chat_channel: None,
+            remote_control: None,
+            remote_port: None,
+            note_editor: note_editor::NoteEditor::new(),
+            note_inspector: note_inspector::NoteInspector::new(),
+            pending_extract: None,
+            extracted_midi: None,
+            pending_sop: None,
+            pending_load: None,
+            metadata_scan: None,
+            comparison: None,
+            loop_station: None,
+            pending_loop_layers: None,
+            settings_override: None,
+            brightness: 1.0,
+            dragged_track: None,
+            track_order_changed: false,
+            paused_on_focus_loss: false,
+            sfz_instrument: None,
+            region_channel: 0,
+            key_counts: None,
+            drum_pads: drum_pads::DrumPads::new(),
+            album_art: None,
+            previous_midi: None,
+            sop_report: None,
+            toasts: Default::default(),
+            tempo_override: None,
+            gpu_devices: describe_gpu_devices(renderer.device.instance()),
+            pending_svg_export: None,
+            resume_position: None,
+        };
+        window.update_region_filter(settings);
+        window
     }
 
     /// Defines the layout of our UI
@@ -98,6 +274,73 @@ impl GuiWasabiWindow {
         let ctx = state.renderer.gui.context();
         self.fps.update();
         ctx.set_visuals(Visuals::dark());
+        if self.ui_font_size != settings.visual.ui_font_size {
+            fonts::set_ui_font_size(&ctx, settings.visual.ui_font_size);
+            self.ui_font_size = settings.visual.ui_font_size;
+        }
+        if self.transpose != settings.midi.transpose_semitones {
+            self.synth
+                .write()
+                .unwrap()
+                .set_transpose(settings.midi.transpose_semitones);
+            self.transpose = settings.midi.transpose_semitones;
+        }
+        if self.event_filter != settings.midi.event_filter {
+            self.synth
+                .write()
+                .unwrap()
+                .set_event_filter(settings.midi.event_filter);
+            self.event_filter = settings.midi.event_filter;
+        }
+        if self.muted_channels != settings.midi.muted_channels {
+            self.synth
+                .write()
+                .unwrap()
+                .set_muted_channels(settings.midi.muted_channels);
+            self.muted_channels = settings.midi.muted_channels;
+        }
+        self.sync_midi_clock(settings);
+        self.update_playback_speed(settings);
+        if let Some(midi_file) = self.midi_file.as_mut() {
+            let lead = settings.synth.latency_compensation_ms();
+            midi_file
+                .timer_mut()
+                .set_audio_lead(Duration::from_secs_f32(lead / 1000.0));
+        }
+        self.sync_live_chat(&ctx, settings);
+        self.poll_remote_control(settings, wasabi_state);
+        self.keyboard.key_labels = settings.visual.keyboard_key_labels;
+        self.keyboard.clickable = settings.midi.keyboard_clickable;
+        self.keyboard.black_key_length = settings.visual.black_key_height_ratio;
+        let layout_params = KeyboardParams::classic(settings.visual.black_key_width_ratio);
+        if *self.keyboard_layout.params() != layout_params {
+            self.keyboard_layout = keyboard_layout::KeyboardLayout::new(&layout_params);
+        }
+        self.poll_pending_extract(settings, wasabi_state);
+        self.poll_pending_load();
+        self.poll_pending_sop();
+        self.update_loop_station(settings);
+        self.update_night_dim(&ctx, settings, wasabi_state);
+
+        // Text fields use the same shortcut for their own undo
+        let undo_load = !ctx.wants_keyboard_input()
+            && ctx.input(|i| {
+                i.events.iter().any(|event| match event {
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                        ..
+                    } => {
+                        settings.input.key_bindings.action_for(*key, modifiers)
+                            == Some(WasabiAction::UndoMidiLoad)
+                    }
+                    _ => false,
+                })
+            });
+        if undo_load {
+            self.undo_midi_load(settings, wasabi_state);
+        }
 
         if wasabi_state.settings_visible {
             settings_window::draw_settings(self, settings, wasabi_state, &ctx);
@@ -105,36 +348,208 @@ impl GuiWasabiWindow {
         if wasabi_state.xsynth_settings_visible {
             xsynth_settings::draw_xsynth_settings(self, settings, wasabi_state, &ctx);
         }
+        if wasabi_state.batch_export_visible {
+            // Files dropped onto the window go into the queue while it is open
+            let dropped = ctx.input(|i| {
+                i.raw
+                    .dropped_files
+                    .iter()
+                    .filter_map(|file| file.path.clone())
+                    .collect::<Vec<_>>()
+            });
+            for path in dropped {
+                self.video_export.add_file(path);
+            }
+            self.video_export
+                .show(&ctx, &mut wasabi_state.batch_export_visible);
+        }
+        if self.video_export.is_running() {
+            // The videos show the whole key range, without the panning of the window
+            let key_view = self
+                .keyboard_layout
+                .get_view_for_keys(
+                    *settings.midi.key_range.start() as usize,
+                    *settings.midi.key_range.end() as usize,
+                )
+                .inverted(settings.visual.invert_pitch_axis);
+            self.video_export
+                .update(state.renderer, &key_view, settings);
+            ctx.request_repaint();
+        }
+        if wasabi_state.tracks_visible {
+            tracks_window::draw_tracks(self, wasabi_state, &ctx);
+        }
+        if std::mem::take(&mut self.track_order_changed) {
+            self.reload_cake_tracks(settings, wasabi_state);
+        }
+        if wasabi_state.midi_info_visible {
+            midi_info::draw_midi_info(self, wasabi_state, &ctx);
+        }
+        if let Some(report) = &self.sop_report {
+            if !report.show(&ctx) {
+                self.sop_report = None;
+            }
+        }
+        self.toasts.show(&ctx);
 
         if let Some(dialog) = &mut self.file_dialogs.midi_file_dialog {
             if dialog.show(&ctx).selected() {
                 if let Some(midi_path) = dialog.path() {
-                    wasabi_state.last_midi_file = Some(midi_path.clone());
-                    self.load_midi(settings, midi_path);
+                    self.load_midi(settings, wasabi_state, midi_path);
                 }
                 self.file_dialogs.midi_file_dialog = None;
             }
         }
 
+        if let Some(dialog) = &mut self.file_dialogs.comparison_file_dialog {
+            if dialog.show(&ctx).selected() {
+                if let Some(path) = dialog.path() {
+                    self.comparison = Some(comparison::ComparisonView::new(
+                        state.renderer,
+                        settings,
+                        path,
+                    ));
+                }
+                self.file_dialogs.comparison_file_dialog = None;
+            }
+        }
+
+        if let Some(dialog) = &mut self.file_dialogs.musicxml_file_dialog {
+            if dialog.show(&ctx).selected() {
+                if let (Some(path), Some(midi_file)) = (dialog.path(), &self.midi_file) {
+                    // The file is read again, so edits aren't included
+                    let midi_path = midi_file.signature().filepath.clone();
+                    let grid = settings.midi.musicxml_grid;
+                    let path = path.to_path_buf();
+                    thread::spawn(move || match export_musicxml(&midi_path, &path, grid) {
+                        Ok(()) => notifications::info(format!(
+                            "Wrote the sheet music to {}",
+                            path.display()
+                        )),
+                        Err(e) => notifications::error(e),
+                    });
+                }
+                self.file_dialogs.musicxml_file_dialog = None;
+            }
+        }
+
+        if let Some(dialog) = &mut self.file_dialogs.svg_file_dialog {
+            if dialog.show(&ctx).selected() {
+                self.pending_svg_export = dialog.path().map(|path| path.to_path_buf());
+                self.file_dialogs.svg_file_dialog = None;
+            }
+        }
+
+        if let Some(picker) = &self.file_dialogs.zip_picker {
+            match picker.show(&ctx) {
+                zip_picker::ZipPickerAction::Selected(entry) => {
+                    let path = picker.path.clone();
+                    self.file_dialogs.zip_picker = None;
+                    self.load_midi_from(settings, wasabi_state, path, Some(entry));
+                }
+                zip_picker::ZipPickerAction::Cancelled => self.file_dialogs.zip_picker = None,
+                zip_picker::ZipPickerAction::None => {}
+            }
+        }
+
+        // The prompt waits for the MIDI to finish loading, so it can seek right away
+        if let (Some(position), Some(midi_file)) = (self.resume_position, self.midi_file.as_mut()) {
+            match resume_prompt::show_resume_prompt(&ctx, position) {
+                resume_prompt::ResumeAction::Resume => {
+                    midi_file.timer_mut().seek(position);
+                    self.resume_position = None;
+                }
+                resume_prompt::ResumeAction::StartOver => self.resume_position = None,
+                resume_prompt::ResumeAction::None => {}
+            }
+        }
+
+        // Empty panels around the edges take up the safe zone margin before anything else,
+        // so the other panels and the scene image are all moved inwards
+        let margin = settings.visual.safe_zone_margin_px as f32;
+        if margin > 0.0 {
+            let empty = Frame::none();
+            egui::TopBottomPanel::top("Safe zone top")
+                .exact_height(margin)
+                .resizable(false)
+                .show_separator_line(false)
+                .frame(empty)
+                .show(&ctx, |_| {});
+            egui::TopBottomPanel::bottom("Safe zone bottom")
+                .exact_height(margin)
+                .resizable(false)
+                .show_separator_line(false)
+                .frame(empty)
+                .show(&ctx, |_| {});
+            egui::SidePanel::left("Safe zone left")
+                .exact_width(margin)
+                .resizable(false)
+                .show_separator_line(false)
+                .frame(empty)
+                .show(&ctx, |_| {});
+            egui::SidePanel::right("Safe zone right")
+                .exact_width(margin)
+                .resizable(false)
+                .show_separator_line(false)
+                .frame(empty)
+                .show(&ctx, |_| {});
+        }
+
         let height_prev = ctx.available_rect().height();
         if settings.visual.show_top_pannel {
             top_panel::draw_panel(self, settings, wasabi_state, &ctx);
         }
+        let panel_height = height_prev - ctx.available_rect().height();
+
+        // Only the JACK synth renders its audio inside of Wasabi, so the spectrogram
+        // isn't shown with the other synths
+        let pcm = settings
+            .visual
+            .show_spectrogram
+            .then(|| self.synth.read().unwrap().pcm_buffer())
+            .flatten();
+        if let Some(pcm) = pcm {
+            egui::TopBottomPanel::bottom("Spectrogram panel")
+                .exact_height(settings.visual.spectrogram_height)
+                .resizable(false)
+                .frame(Frame::default().inner_margin(Margin::same(0.0)))
+                .show(&ctx, |ui| self.spectrogram.draw(ui, &pcm, &settings.visual));
+        }
 
         // Calculate available space left for keyboard and notes
         // We must render notes before keyboard because the notes
         // renderer tells us the key colors
         let available = ctx.available_rect();
         let height = available.height();
-        let panel_height = height_prev - height;
-        let keyboard_height =
-            (11.6 / settings.midi.key_range.len() as f32 * available.width()).min(height / 2.0);
-        let notes_height = height - keyboard_height;
-
-        let key_view = self.keyboard_layout.get_view_for_keys(
-            *settings.midi.key_range.start() as usize,
-            *settings.midi.key_range.end() as usize,
-        );
+
+        // In waterfall mode the keyboard sits on the left edge, so it is sized
+        // along the width of the window instead of the height
+        let waterfall = settings.visual.waterfall_mode;
+        let (keys_length, depth_length) = if waterfall {
+            (height, available.width())
+        } else {
+            (available.width(), height)
+        };
+        let keyboard_size =
+            (11.6 / settings.midi.key_range.len() as f32 * keys_length).min(depth_length / 2.0);
+        let notes_size = depth_length - keyboard_size;
+
+        self.animate_key_zoom(&ctx, settings);
+
+        // Panning shifts the visible keys without changing the key range setting
+        let range_start = *settings.midi.key_range.start() as i32;
+        let range_end = *settings.midi.key_range.end() as i32;
+        wasabi_state.view_key_offset = wasabi_state
+            .view_key_offset
+            .min(MAX_VIEW_KEY - range_end)
+            .max(-range_start);
+        let first_key = (range_start + wasabi_state.view_key_offset) as usize;
+        let last_key = (range_end + wasabi_state.view_key_offset) as usize;
+
+        let key_view = self
+            .keyboard_layout
+            .get_view_for_keys(first_key, last_key)
+            .inverted(settings.visual.invert_pitch_axis);
 
         let no_frame = Frame::default()
             .inner_margin(Margin::same(0.0))
@@ -144,65 +559,121 @@ impl GuiWasabiWindow {
 
         let mut render_result_data = None;
 
+        // Pressed keys keep the color of their notes over the heatmap
+        let heatmap = if settings.visual.keyboard_heatmap {
+            self.heatmap_colors(settings)
+        } else {
+            None
+        };
+        let with_heatmap = |mut colors: Vec<Option<MIDIColor>>| {
+            if let Some(heatmap) = &heatmap {
+                for (color, heat) in colors.iter_mut().zip(heatmap) {
+                    *color = color.or(*heat);
+                }
+            }
+            colors
+        };
+
         // Render the notes
-        egui::TopBottomPanel::top("Note panel")
-            .height_range(notes_height..=notes_height)
-            .frame(no_frame)
-            .show_separator_line(false)
-            .show(&ctx, |ui| {
-                if let Some(midi_file) = self.midi_file.as_mut() {
-                    let one_sec = Duration::from_secs(1);
-                    let time = midi_file.timer().get_time();
-
-                    ui.input(|events| {
-                        for event in &events.events {
-                            if let egui::Event::Key { key, pressed, .. } = event {
-                                if pressed == &true {
-                                    match key {
-                                        egui::Key::ArrowRight => {
-                                            midi_file.timer_mut().seek(time + one_sec)
-                                        }
-                                        egui::Key::ArrowLeft => {
-                                            if midi_file.allows_seeking_backward() {
-                                                midi_file.timer_mut().seek(if time <= one_sec {
-                                                    Duration::from_secs(0)
-                                                } else {
-                                                    time - one_sec
-                                                })
-                                            }
-                                        }
-                                        egui::Key::ArrowUp => {
-                                            settings.midi.note_speed += 0.05;
-                                        }
-                                        egui::Key::ArrowDown => {
-                                            settings.midi.note_speed -= 0.05;
-                                        }
-                                        egui::Key::Space => midi_file.timer_mut().toggle_pause(),
-                                        _ => {}
-                                    }
-                                }
-                            }
-                        }
-                    });
+        let notes_contents = |ui: &mut egui::Ui| {
+            let panel_rect = ui.max_rect();
+            let keys_length = if waterfall {
+                panel_rect.height()
+            } else {
+                panel_rect.width()
+            };
 
-                    let result = self.render_scene.draw(
-                        state,
-                        ui,
-                        &key_view,
-                        midi_file,
-                        settings.midi.note_speed,
+            // Two finger gestures on a touchscreen, which aren't passed on as scrolling
+            let touch = ui.input(|i| i.multi_touch()).filter(|touch| {
+                settings.input.touch_enabled && panel_rect.contains(touch.start_pos)
+            });
+            if let Some(touch) = &touch {
+                Self::apply_pinch(
+                    settings,
+                    &mut self.touch_key_zoom,
+                    touch.zoom_delta_2d,
+                    waterfall,
+                );
+            }
+
+            // Shift + scroll pans the view across the keys, as does swiping vertically
+            let (scroll, shift) = ui.input(|i| (i.scroll_delta, i.modifiers.shift));
+            let key_pan = match &touch {
+                Some(touch) => touch.translation_delta.y * settings.input.touch_swipe_sensitivity,
+                None if shift && ui.rect_contains_pointer(panel_rect) => scroll.x + scroll.y,
+                None => 0.0,
+            };
+            // The keys run the other way when the pitch axis is inverted
+            let key_pan = if settings.visual.invert_pitch_axis {
+                -key_pan
+            } else {
+                key_pan
+            };
+            if key_pan != 0.0 {
+                let key_width = keys_length / (last_key - first_key + 1) as f32;
+                self.key_scroll += key_pan;
+                let keys = (self.key_scroll / key_width).trunc();
+                self.key_scroll -= keys * key_width;
+                wasabi_state.view_key_offset -= keys as i32;
+            }
+
+            // Ctrl + scroll zooms the keys around the hovered one, egui turns it into a zoom
+            let (zoom, ctrl) = ui.input(|i| (i.zoom_delta(), i.modifiers.command));
+            let hovered = ui.input(|i| i.pointer.hover_pos());
+            if let Some(pos) = hovered
+                .filter(|pos| touch.is_none() && ctrl && zoom != 1.0 && panel_rect.contains(*pos))
+            {
+                // In waterfall mode the keys run from the bottom to the top
+                let along = if waterfall {
+                    (panel_rect.bottom() - pos.y) / panel_rect.height()
+                } else {
+                    (pos.x - panel_rect.left()) / panel_rect.width()
+                };
+                let keys_along = if settings.visual.invert_pitch_axis {
+                    1.0 - along
+                } else {
+                    along
+                };
+                let hovered_key = key_view
+                    .iter_visible_notes()
+                    .find(|(_, note)| note.left <= along && along < note.right)
+                    .map_or(
+                        first_key as f32 + keys_along * (last_key - first_key + 1) as f32,
+                        |(key, _)| key as f32,
                     );
-                    stats.set_rendered_note_count(result.notes_rendered);
-                    render_result_data = Some(result);
+                Self::zoom_keys(
+                    settings,
+                    &mut self.key_zoom_target,
+                    hovered_key - wasabi_state.view_key_offset as f32,
+                    zoom,
+                );
+            }
+
+            if let Some(midi_file) = self.midi_file.as_mut() {
+                let time = midi_file.timer().get_time();
+
+                // Swiping horizontally seeks, moving the notes along with the fingers
+                if let Some(touch) = &touch {
+                    let depth_length = if waterfall {
+                        panel_rect.width()
+                    } else {
+                        panel_rect.height()
+                    };
+                    let secs_per_pixel = settings.midi.note_speed / depth_length as f64;
+                    let delta = -touch.translation_delta.x as f64
+                        * secs_per_pixel
+                        * settings.input.touch_swipe_sensitivity as f64;
+                    if delta > 0.0 {
+                        midi_file
+                            .timer_mut()
+                            .seek(time + Duration::from_secs_f64(delta));
+                    } else if delta < 0.0 && midi_file.allows_seeking_backward() {
+                        midi_file
+                            .timer_mut()
+                            .seek(time.saturating_sub(Duration::from_secs_f64(-delta)));
+                    }
                 }
-            });
 
-        // Render the keyboard
-        egui::TopBottomPanel::top("Keyboard panel")
-            .height_range(keyboard_height..=keyboard_height)
-            .frame(no_frame)
-            .show_separator_line(false)
-            .show(&ctx, |ui| {
                 ui.input(|events| {
                     for event in &events.events {
                         if let egui::Event::Key {
@@ -212,54 +683,728 @@ impl GuiWasabiWindow {
                             ..
                         } = event
                         {
-                            if *pressed && modifiers.ctrl {
-                                match key {
-                                    egui::Key::F => {
-                                        settings.visual.show_top_pannel =
-                                            !settings.visual.show_top_pannel
+                            if pressed == &true {
+                                // Shift seeks further and Ctrl seeks in finer steps
+                                let multiplier = if modifiers.shift {
+                                    settings.midi.seek_fast_multiplier
+                                } else if modifiers.ctrl {
+                                    settings.midi.seek_fine_multiplier
+                                } else {
+                                    1.0
+                                };
+                                let step = Duration::from_secs_f64(
+                                    (settings.midi.seek_step_secs * multiplier).max(0.0),
+                                );
+
+                                match settings.input.key_bindings.action_for(*key, modifiers) {
+                                    Some(WasabiAction::SeekForward) => {
+                                        midi_file.timer_mut().seek(time + step)
                                     }
-                                    egui::Key::G => {
-                                        settings.visual.show_statistics =
-                                            !settings.visual.show_statistics
+                                    Some(WasabiAction::SeekBackward) => {
+                                        if midi_file.allows_seeking_backward() {
+                                            midi_file.timer_mut().seek(if time <= step {
+                                                Duration::from_secs(0)
+                                            } else {
+                                                time - step
+                                            })
+                                        }
+                                    }
+                                    Some(WasabiAction::IncreaseNoteSpeed) => {
+                                        settings.midi.note_speed += 0.05;
+                                    }
+                                    Some(WasabiAction::DecreaseNoteSpeed) => {
+                                        settings.midi.note_speed -= 0.05;
+                                    }
+                                    Some(WasabiAction::TogglePause) => {
+                                        midi_file.timer_mut().toggle_pause()
                                     }
-                                    //egui::Key::O => self.open_midi_dialog(wasabi_state),
                                     _ => {}
                                 }
                             }
-                            if *pressed && modifiers.alt && key == &egui::Key::Enter {
-                                wasabi_state.fullscreen = !wasabi_state.fullscreen
-                            }
                         }
                     }
                 });
 
-                let colors = if let Some(data) = render_result_data {
-                    data.key_colors
+                // Right clicking a note shows its properties, unless the note editor uses the
+                // click, or it happened over a window that covers the notes
+                let inspect_pos = ui
+                    .input(|i| {
+                        i.pointer
+                            .button_clicked(egui::PointerButton::Secondary)
+                            .then(|| i.pointer.interact_pos())
+                            .flatten()
+                    })
+                    .filter(|pos| {
+                        !wasabi_state.note_editor
+                            && panel_rect.contains(*pos)
+                            && ui.ctx().layer_id_at(*pos) == Some(ui.layer_id())
+                    });
+                if let Some(pos) = inspect_pos {
+                    self.render_scene.pick_note_at(
+                        pos,
+                        panel_rect,
+                        &key_view,
+                        settings.midi.note_speed,
+                        waterfall,
+                    );
+                }
+
+                let svg_layout = match &self.pending_svg_export {
+                    Some(_) if matches!(midi_file, MIDIFileUnion::Cake(_)) => {
+                        self.pending_svg_export = None;
+                        notifications::error("The cake loading method can't be exported as SVG");
+                        None
+                    }
+                    Some(_) => {
+                        self.render_scene.capture_notes();
+                        // The image always has the keys along the bottom
+                        let size = if waterfall {
+                            egui::vec2(panel_rect.height(), panel_rect.width())
+                        } else {
+                            panel_rect.size()
+                        };
+                        Some(svg_export::SvgLayout::new(
+                            midi_file, &key_view, settings, size,
+                        ))
+                    }
+                    None => None,
+                };
+
+                self.render_scene.slot_colors = self
+                    .live_chat
+                    .as_ref()
+                    .map(|chat| chat.slot_colors())
+                    .unwrap_or_default();
+                self.render_scene.note_fadein = self.synth.read().unwrap().note_fadein();
+                let mut result = self.render_scene.draw(
+                    state,
+                    ui,
+                    &key_view,
+                    midi_file,
+                    settings,
+                    self.brightness,
+                    &wasabi_state.track_order,
+                );
+
+                // The renderer only keeps the notes once it exists, so the export can
+                // wait for the next frame
+                if let (Some(notes), Some(layout)) = (result.captured_notes.take(), svg_layout) {
+                    if let Some(path) = self.pending_svg_export.take() {
+                        thread::spawn(move || {
+                            match svg_export::write_svg(&path, &notes, &layout) {
+                                Ok(()) => notifications::info(format!(
+                                    "Wrote the notes to {}",
+                                    path.display()
+                                )),
+                                Err(e) => notifications::error(e),
+                            }
+                        });
+                    }
+                }
+
+                if let Some(pos) = inspect_pos {
+                    match result.picked_note {
+                        Some(note) => self.note_inspector.inspect(
+                            note,
+                            settings.midi.transpose_semitones,
+                            midi_file.timer().get_time().as_secs_f64(),
+                            pos,
+                        ),
+                        None => self.note_inspector.close(),
+                    }
+                }
+
+                stats.set_rendered_note_count(result.notes_rendered);
+                stats.set_frame_times(result.render_time_ms, result.cpu_fill_time_ms);
+                stats.set_velocity_histogram(result.velocity_histogram);
+                if settings.visual.percussion_display_mode == PercussionDisplayMode::DrumPad {
+                    self.drum_pads.update(&result.drum_colors);
+                    self.drum_pads.draw(ui, panel_rect);
+                }
+                if settings.visual.show_channel_activity {
+                    channel_activity::draw_channel_activity(
+                        ui,
+                        panel_rect,
+                        result.visible_channels,
+                        waterfall,
+                    );
+                }
+                if settings.visual.show_chord_names {
+                    chord_names::draw_chord_name(
+                        ui,
+                        panel_rect,
+                        &result.key_colors,
+                        &result.key_channels,
+                        waterfall,
+                    );
+                }
+                if !settings.visual.cc_lanes.is_empty() {
+                    let reversed_length = midi_file
+                        .is_reversed()
+                        .then(|| midi_file.midi_length())
+                        .flatten();
+                    cc_lanes::draw_cc_lanes(
+                        ui,
+                        panel_rect,
+                        &key_view,
+                        &result.key_channels,
+                        &midi_file.control_changes(),
+                        &settings.visual.cc_lanes,
+                        settings.visual.cc_lane_height,
+                        midi_file.timer().get_time().as_secs_f64(),
+                        settings.midi.note_speed,
+                        reversed_length,
+                        waterfall,
+                    );
+                }
+                render_result_data = Some(result);
+
+                if settings.visual.show_instrument_labels {
+                    let reversed_length = midi_file
+                        .is_reversed()
+                        .then(|| midi_file.midi_length())
+                        .flatten();
+                    instrument_labels::draw_instrument_labels(
+                        ui,
+                        panel_rect,
+                        &midi_file.program_changes(),
+                        midi_file.timer().get_time().as_secs_f64(),
+                        settings.midi.note_speed,
+                        reversed_length,
+                        waterfall,
+                    );
+                }
+
+                // Lyrics are sung forwards, so they aren't shown while playing backwards
+                if settings.visual.show_lyrics && !midi_file.is_reversed() {
+                    lyrics::draw_lyrics(
+                        ui,
+                        panel_rect,
+                        &midi_file.lyrics(),
+                        midi_file.timer().get_time().as_secs_f64(),
+                        &lyrics::LyricsStyle {
+                            font_size: settings.visual.lyrics_font_size,
+                            color: settings.visual.lyrics_color,
+                            highlight_color: settings.visual.lyrics_highlight_color,
+                            position: settings.visual.lyrics_position,
+                        },
+                    );
+                }
+
+                if settings.visual.show_time_ruler {
+                    time_ruler::draw_time_ruler(
+                        ui,
+                        panel_rect,
+                        midi_file,
+                        settings.midi.note_speed,
+                        settings.visual.time_ruler_mode,
+                        settings.visual.time_ruler_spacing_px,
+                        waterfall,
+                    );
+                }
+
+                if let Some(comparison) = self.comparison.as_mut() {
+                    comparison.draw(
+                        state,
+                        ui,
+                        panel_rect,
+                        &key_view,
+                        midi_file,
+                        settings,
+                        self.brightness,
+                    );
+                }
+
+                // The editor picks notes by their vertical position,
+                // so it isn't available when the view is rotated
+                if wasabi_state.note_editor && !waterfall {
+                    if let MIDIFileUnion::InRam(file) = midi_file {
+                        self.note_editor.update(
+                            ui,
+                            panel_rect,
+                            &key_view,
+                            file,
+                            settings,
+                            wasabi_state.last_midi_file.as_deref(),
+                        );
+                    }
+                }
+            }
+
+            if self.pending_load.is_some() {
+                let rect =
+                    egui::Rect::from_center_size(panel_rect.center(), egui::vec2(100.0, 20.0));
+                ui.allocate_ui_at_rect(rect, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Loading...");
+                    });
+                });
+            }
+
+            // A smaller copy of the keyboard over the top of the notes
+            if settings.visual.show_mini_keyboard {
+                let depth_length = if waterfall {
+                    panel_rect.width()
                 } else {
-                    vec![None; 256]
+                    panel_rect.height()
                 };
+                let mini_size =
+                    (4.0 / (last_key - first_key + 1) as f32 * keys_length).min(depth_length / 6.0);
+                let mini_rect = if waterfall {
+                    egui::Rect::from_min_max(
+                        egui::pos2(panel_rect.right() - mini_size, panel_rect.top()),
+                        panel_rect.max,
+                    )
+                } else {
+                    egui::Rect::from_min_size(
+                        panel_rect.min,
+                        egui::vec2(panel_rect.width(), mini_size),
+                    )
+                };
+                let colors = with_heatmap(match &render_result_data {
+                    Some(data) => data.key_colors.clone(),
+                    None => vec![None; 256],
+                });
+                self.keyboard.paint(
+                    ui,
+                    mini_rect,
+                    &key_view,
+                    &colors,
+                    &settings.visual.bar_color,
+                    &KeyboardTheme::from_settings(&settings.visual),
+                    waterfall,
+                );
+            }
+
+            if let Some(watermark) = &settings.visual.watermark {
+                watermark::draw_watermark(ui, panel_rect, watermark);
+            }
+
+            // The thumb is mirrored along with the keys
+            let (thumb_first, thumb_last) = if settings.visual.invert_pitch_axis {
+                (
+                    MAX_VIEW_KEY as usize - last_key,
+                    MAX_VIEW_KEY as usize - first_key,
+                )
+            } else {
+                (first_key, last_key)
+            };
+            draw_key_scrollbar(ui, panel_rect, thumb_first, thumb_last, waterfall);
+        };
+
+        // The notes panel goes first, so in waterfall mode it takes the right side
+        // and the keyboard fills the space left over on the left
+        if waterfall {
+            egui::SidePanel::right("Note panel")
+                .exact_width(notes_size)
+                .resizable(false)
+                .frame(no_frame)
+                .show_separator_line(false)
+                .show(&ctx, notes_contents);
+        } else {
+            egui::TopBottomPanel::top("Note panel")
+                .height_range(notes_size..=notes_size)
+                .frame(no_frame)
+                .show_separator_line(false)
+                .show(&ctx, notes_contents);
+        }
 
-                self.keyboard
-                    .draw(ui, &key_view, &colors, &settings.visual.bar_color);
+        // Render the keyboard
+        let keyboard_contents = |ui: &mut egui::Ui| {
+            ui.input(|events| {
+                for event in &events.events {
+                    if let egui::Event::Key {
+                        key,
+                        pressed,
+                        modifiers,
+                        ..
+                    } = event
+                    {
+                        if !*pressed {
+                            continue;
+                        }
+                        match settings.input.key_bindings.action_for(*key, modifiers) {
+                            Some(WasabiAction::ToggleTopPanel) => {
+                                settings.visual.show_top_pannel = !settings.visual.show_top_pannel
+                            }
+                            Some(WasabiAction::ToggleStatistics) => {
+                                settings.visual.show_statistics = !settings.visual.show_statistics
+                            }
+                            Some(WasabiAction::ToggleFullscreen) => {
+                                wasabi_state.fullscreen = !wasabi_state.fullscreen
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            });
+
+            let colors = with_heatmap(if let Some(data) = &render_result_data {
+                data.key_colors.clone()
+            } else {
+                vec![None; 256]
             });
 
+            let clicks = self.keyboard.draw(
+                ui,
+                &key_view,
+                &colors,
+                &settings.visual.bar_color,
+                &KeyboardTheme::from_settings(&settings.visual),
+                waterfall,
+            );
+            if !clicks.is_empty() {
+                // The synth transposes the notes it plays, so the clicked key is moved
+                // back to sound like the key on screen
+                let mut synth = self.synth.write().unwrap();
+                for click in clicks {
+                    let key = click.key as i32 - settings.midi.transpose_semitones as i32;
+                    if !(0..=127).contains(&key) {
+                        continue;
+                    }
+                    let event = if click.down {
+                        0x90 | ((key as u32) << 8) | ((KEY_CLICK_VELOCITY as u32) << 16)
+                    } else {
+                        0x80 | ((key as u32) << 8)
+                    };
+                    synth.push_event(event);
+                }
+            }
+        };
+
+        if waterfall {
+            egui::SidePanel::left("Keyboard panel")
+                .exact_width(keyboard_size)
+                .resizable(false)
+                .frame(no_frame)
+                .show_separator_line(false)
+                .show(&ctx, keyboard_contents);
+        } else {
+            egui::TopBottomPanel::top("Keyboard panel")
+                .height_range(keyboard_size..=keyboard_size)
+                .frame(no_frame)
+                .show_separator_line(false)
+                .show(&ctx, keyboard_contents);
+        }
+
+        if settings.midi.auto_note_speed {
+            if let Some(data) = &render_result_data {
+                Self::adjust_note_speed(settings, data.notes_rendered);
+            }
+        }
+
+        if settings.midi.follow_mode {
+            if let Some(key) = render_result_data.and_then(|data| data.most_active_key) {
+                self.follow_key(settings, key);
+            }
+        } else {
+            self.follow_start = None;
+        }
+
         // Render the stats
         if settings.visual.show_statistics {
             let voice_count = self.synth.read().unwrap().get_voice_count();
             stats.set_voice_count(voice_count);
 
-            let pos = egui::Pos2::new(10.0, panel_height + 10.0);
+            // The stats float over the notes, so they are kept inside of the safe zone too
+            let pos = egui::Pos2::new(margin + 10.0, margin + panel_height + 10.0);
             stats::draw_stats(self, &ctx, pos, stats);
         }
+
+        self.note_inspector.show(&ctx);
+    }
+
+    /// Pinching along the time axis zooms the note speed and along the keys zooms the
+    /// key range, around its center. Spreading the fingers apart zooms in.
+    fn apply_pinch(
+        settings: &mut WasabiSettings,
+        touch_key_zoom: &mut f32,
+        zoom: egui::Vec2,
+        waterfall: bool,
+    ) {
+        let sensitivity = settings.input.touch_zoom_sensitivity;
+        let (time_zoom, key_zoom) = if waterfall {
+            (zoom.x, zoom.y)
+        } else {
+            (zoom.y, zoom.x)
+        };
+
+        if time_zoom != 1.0 {
+            settings.midi.note_speed =
+                (settings.midi.note_speed / time_zoom.powf(sensitivity) as f64).max(0.001);
+        }
+
+        // The keys are removed or added a pair at a time, so partial keys are kept for later
+        let start = *settings.midi.key_range.start() as i32;
+        let end = *settings.midi.key_range.end() as i32;
+        let width = (end - start + 1) as f32;
+        *touch_key_zoom += width * (1.0 - 1.0 / key_zoom.powf(sensitivity));
+
+        let pairs = (*touch_key_zoom / 2.0).trunc() as i32;
+        if pairs != 0 {
+            *touch_key_zoom -= pairs as f32 * 2.0;
+
+            // At least an octave stays visible
+            let pairs = pairs.min((end - start + 1 - 12) / 2);
+            let start = (start + pairs).clamp(0, 127);
+            let end = (end - pairs).clamp(start, 127);
+            settings.midi.key_range = start as u8..=end as u8;
+        }
+    }
+
+    /// Sets the key range to zoom to, centered on the hovered key. Zooming in narrows the
+    /// range down to an octave at the least.
+    fn zoom_keys(
+        settings: &WasabiSettings,
+        key_zoom_target: &mut Option<(f32, f32)>,
+        hovered_key: f32,
+        zoom: f32,
+    ) {
+        const MIN_ZOOM_KEYS: f32 = 12.0;
+
+        // Zooming again before the last one finished continues from where it was going
+        let (start, end) = key_zoom_target.unwrap_or((
+            *settings.midi.key_range.start() as f32,
+            *settings.midi.key_range.end() as f32,
+        ));
+        let width = ((end - start + 1.0) / zoom).clamp(MIN_ZOOM_KEYS, 128.0);
+        let start = (hovered_key - width / 2.0).clamp(0.0, 128.0 - width);
+        *key_zoom_target = Some((start, start + width - 1.0));
+    }
+
+    /// Moves the key range a step closer to the one being zoomed to
+    fn animate_key_zoom(&mut self, ctx: &egui::Context, settings: &mut WasabiSettings) {
+        const ZOOM_SMOOTHING: f32 = 0.25;
+
+        let Some(target) = self.key_zoom_target else {
+            self.key_zoom_current = None;
+            return;
+        };
+
+        // Start from the current range again if it was changed elsewhere
+        let start = *settings.midi.key_range.start();
+        let end = *settings.midi.key_range.end();
+        let current = match self.key_zoom_current {
+            Some(current) if (current.0.round() as u8, current.1.round() as u8) == (start, end) => {
+                current
+            }
+            _ => (start as f32, end as f32),
+        };
+
+        let mut next = (
+            current.0 + (target.0 - current.0) * ZOOM_SMOOTHING,
+            current.1 + (target.1 - current.1) * ZOOM_SMOOTHING,
+        );
+        if (next.0 - target.0).abs() < 0.05 && (next.1 - target.1).abs() < 0.05 {
+            next = target;
+            self.key_zoom_target = None;
+        }
+        self.key_zoom_current = Some(next);
+
+        let next_start = next.0.round().clamp(0.0, 127.0) as u8;
+        let next_end = next.1.round().clamp(next_start as f32, 127.0) as u8;
+        settings.midi.key_range = next_start..=next_end;
+        ctx.request_repaint();
+    }
+
+    /// Moves the note speed a step closer to keeping the target amount of notes on screen
+    fn adjust_note_speed(settings: &mut WasabiSettings, notes_rendered: u64) {
+        // Empty parts of the MIDI don't say anything about its density
+        if notes_rendered == 0 {
+            return;
+        }
+
+        let notes_per_sec = notes_rendered as f64 / settings.midi.note_speed;
+        let target =
+            (settings.midi.auto_note_speed_target as f64 / notes_per_sec).clamp(0.001, 2.0);
+        settings.midi.note_speed +=
+            (target - settings.midi.note_speed) * settings.midi.auto_note_speed_smoothing;
+    }
+
+    /// Moves the visible key range a step closer to having `key` at its center
+    fn follow_key(&mut self, settings: &mut WasabiSettings, key: u8) {
+        const FOLLOW_SMOOTHING: f32 = 0.05;
+
+        let start = *settings.midi.key_range.start();
+        let width = *settings.midi.key_range.end() - start;
+
+        // Start from the current range again if it was changed elsewhere
+        let current = match self.follow_start {
+            Some(follow_start) if follow_start.round() as u8 == start => follow_start,
+            _ => start as f32,
+        };
+
+        let target = (key as f32 - width as f32 / 2.0).clamp(0.0, (u8::MAX - 1 - width) as f32);
+        let next = current + (target - current) * FOLLOW_SMOOTHING;
+        self.follow_start = Some(next);
+
+        let next_start = next.round() as u8;
+        settings.midi.key_range = next_start..=next_start + width;
+    }
+
+    /// Connects to the MIDI clock input when it's enabled, and disconnects from it when not
+    fn sync_midi_clock(&mut self, settings: &WasabiSettings) {
+        if !settings.synth.midi_clock_sync {
+            if self.midi_clock_port.take().is_some() {
+                self.midi_clock = None;
+            }
+            return;
+        }
+
+        // Only try to connect once per port, so a missing port isn't retried every frame
+        if self.midi_clock_port.as_ref() != Some(&settings.synth.midi_clock_port) {
+            self.midi_clock = None;
+            self.midi_clock = MIDIClockSync::new(&settings.synth.midi_clock_port);
+            self.midi_clock_port = Some(settings.synth.midi_clock_port.clone());
+        }
+    }
+
+    /// Sets the playback speed from the tempo of the MIDI clock while it's synced, so a beat
+    /// of the MIDI lasts as long as a beat of the clock, or else from the tempo override
+    fn update_playback_speed(&mut self, settings: &WasabiSettings) {
+        let clock_bpm = self
+            .midi_clock
+            .as_ref()
+            .filter(|_| settings.synth.midi_clock_sync)
+            .and_then(|clock| clock.bpm());
+        let Some(midi_file) = self.midi_file.as_mut() else {
+            return;
+        };
+
+        let speed = match (clock_bpm, self.tempo_override) {
+            (Some(bpm), _) => {
+                let time = midi_file.timer().get_time().as_secs_f64();
+                bpm / midi_file.bpm_at(time)
+            }
+            (None, Some(bpm)) => bpm / midi_file.bpm_at(0.0),
+            (None, None) => 1.0,
+        };
+        if (speed - midi_file.timer().get_speed()).abs() > 0.001 {
+            midi_file.timer_mut().set_speed(speed);
+        }
+    }
+
+    fn sync_live_chat(&mut self, ctx: &egui::Context, settings: &WasabiSettings) {
+        let channel = settings
+            .visual
+            .twitch_chat_channel
+            .as_ref()
+            .filter(|channel| !channel.is_empty());
+
+        // The channel name is only connected to once it's done being typed in
+        if self.live_chat_channel.as_ref() == channel || ctx.wants_keyboard_input() {
+            return;
+        }
+
+        self.live_chat = channel.and_then(|channel| live_chat::LiveChat::connect(channel));
+        self.live_chat_channel = channel.cloned();
+    }
+
+    /// Starts or stops the HTTP API, runs the command it received and tells it the status
+    fn poll_remote_control(&mut self, settings: &mut WasabiSettings, state: &mut WasabiState) {
+        // Without a token anyone on the machine could send commands
+        if settings.remote.enabled && settings.remote.token.is_empty() {
+            settings.remote.token = rand::thread_rng()
+                .sample_iter(&rand::distributions::Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+        }
+
+        let port = settings.remote.enabled.then_some(settings.remote.port);
+        if self.remote_port != port {
+            self.remote_control = port.and_then(remote_control::RemoteControl::start);
+            self.remote_port = port;
+        }
+
+        let Some(remote) = self.remote_control.as_ref() else {
+            return;
+        };
+        remote.set_token(&settings.remote.token);
+
+        match remote.take_command() {
+            WasabiRemoteCommand::Idle => {}
+            WasabiRemoteCommand::Play => {
+                if let Some(midi_file) = self.midi_file.as_mut() {
+                    midi_file.timer_mut().play();
+                    self.paused_on_focus_loss = false;
+                }
+            }
+            WasabiRemoteCommand::Pause => {
+                if let Some(midi_file) = self.midi_file.as_mut() {
+                    midi_file.timer_mut().pause();
+                }
+            }
+            WasabiRemoteCommand::Seek(time) => {
+                if let Some(midi_file) = self.midi_file.as_mut() {
+                    midi_file.timer_mut().seek(Duration::from_secs_f64(time));
+                }
+            }
+            WasabiRemoteCommand::Load(path) => self.load_midi(settings, state, path),
+        }
+
+        let status = match self.midi_file.as_ref() {
+            Some(midi_file) => RemoteStatus {
+                midi_file: state.last_midi_file.clone(),
+                playing: !midi_file.timer().is_paused(),
+                time: midi_file.timer().get_time().as_secs_f64(),
+                length: midi_file.midi_length(),
+            },
+            None => RemoteStatus::default(),
+        };
+        if let Some(remote) = self.remote_control.as_ref() {
+            remote.set_status(status);
+        }
+    }
+
+    /// Plays the MIDI as if it was written at `bpm` instead of the tempo it starts with,
+    /// by changing the playback speed. The MIDI clock sets the speed instead while it's
+    /// synced.
+    pub fn set_tempo_override(&mut self, bpm: Option<f64>) {
+        self.tempo_override = bpm;
+    }
+
+    /// Checks the open MIDI for events that break the usual rules and shows what was found
+    pub fn validate_sop(&mut self) {
+        let Some(midi_file) = self.midi_file.as_ref() else {
+            return;
+        };
+        let metadata = midi_file.metadata();
+        let path = midi_file.signature().filepath.clone();
+
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        thread::spawn(move || {
+            let violations = validate_sop(&path, metadata.format);
+            sender
+                .send(sop_report::SopReport::new(metadata.file_name, violations))
+                .ok();
+        });
+        self.pending_sop = Some(receiver);
+    }
+
+    fn poll_pending_sop(&mut self) {
+        if let Some(receiver) = &self.pending_sop {
+            match receiver.try_recv() {
+                Ok(report) => {
+                    self.pending_sop = None;
+                    self.sop_report = Some(report);
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.pending_sop = None,
+            }
+        }
     }
 
     pub fn open_midi_dialog(&mut self, state: &mut WasabiState) {
         fn filter(path: &std::path::Path) -> bool {
-            if let Some(path) = path.to_str() {
-                path.ends_with(".mid")
-            } else {
-                false
-            }
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ["mid", "kar", "gz", "zip"]
+                        .iter()
+                        .any(|known| ext.eq_ignore_ascii_case(known))
+                })
         }
 
         let mut dialog = FileDialog::open_file(state.last_midi_file.clone(), Some(filter))
@@ -271,43 +1416,662 @@ impl GuiWasabiWindow {
         self.file_dialogs.midi_file_dialog = Some(dialog);
     }
 
-    pub fn load_midi(&mut self, settings: &mut WasabiSettings, midi_path: PathBuf) {
+    /// Closes the MIDI that is playing and starts recording the first layer of a loop
+    pub fn start_loop_station(&mut self, settings: &mut WasabiSettings, state: &mut WasabiState) {
         if let Some(midi_file) = self.midi_file.as_mut() {
             midi_file.timer_mut().pause();
         }
         self.synth.write().unwrap().reset();
         self.midi_file = None;
+        self.extracted_midi = None;
+        self.note_editor.clear();
+        self.note_inspector.close();
+        self.cancel_loading();
+        self.restore_settings(settings);
+        state.view_key_offset = 0;
+        state.track_order.clear();
 
-        if let Some(midi_path) = midi_path.to_str() {
-            match settings.midi.midi_loading {
-                MidiLoading::Ram => {
-                    let mut midi_file = MIDIFileUnion::InRam(InRamMIDIFile::load_from_file(
-                        midi_path,
-                        self.synth.clone(),
-                        settings.midi.random_colors,
-                    ));
-                    midi_file.timer_mut().play();
-                    self.midi_file = Some(midi_file);
+        self.loop_station = LoopStation::new(
+            &settings.synth.loop_input_port,
+            settings.synth.loop_measures,
+            settings.synth.loop_bpm,
+            self.synth.clone(),
+        );
+        if self.loop_station.is_none() {
+            notifications::error("No MIDI input to record loops from");
+        }
+    }
+
+    /// Stops recording, the recorded layers stay loaded like any other MIDI
+    pub fn stop_loop_station(&mut self) {
+        self.loop_station = None;
+        self.pending_loop_layers = None;
+        if let Some(midi_file) = self.midi_file.as_mut() {
+            midi_file.timer_mut().pause();
+        }
+    }
+
+    /// Loads the layers of the loop whenever a new one was recorded,
+    /// and keeps their playback in time with the recording
+    fn update_loop_station(&mut self, settings: &WasabiSettings) {
+        /// How far the playback can drift from the loop before it is seeked back in line
+        const MAX_LOOP_DRIFT: Duration = Duration::from_millis(50);
+
+        let Some(station) = self.loop_station.as_mut() else {
+            return;
+        };
+
+        if let Some(path) = station.update() {
+            let (sender, receiver) = crossbeam_channel::bounded(1);
+            let synth = self.synth.clone();
+            let random_colors = settings.midi.random_colors;
+
+            thread::spawn(move || {
+                let (_cancel, listener) = triggered::trigger();
+                let midi_file = InRamMIDIFile::load_from_file(
+                    &path,
+                    synth,
+                    random_colors,
+                    None,
+                    false,
+                    MidiRepairMode::Off,
+                    &listener,
+                );
+                sender.send(midi_file).ok();
+            });
+            self.pending_loop_layers = Some(receiver);
+        }
+
+        if let Some(receiver) = &self.pending_loop_layers {
+            match receiver.try_recv() {
+                Ok(midi_file) => {
+                    self.pending_loop_layers = None;
+                    if let Ok(mut midi_file) = midi_file {
+                        if let Some(old) = self.midi_file.as_mut() {
+                            old.timer_mut().pause();
+                        }
+                        midi_file.timer_mut().play();
+                        self.midi_file = Some(MIDIFileUnion::InRam(midi_file));
+                        self.key_counts = None;
+                        self.drum_pads.reset();
+                        self.set_tempo_override(None);
+                    } else if let Err(e) = midi_file {
+                        notifications::error(format!(
+                            "Failed to load the recorded loop layers: {}",
+                            e
+                        ));
+                    }
                 }
-                MidiLoading::Live => {
-                    let mut midi_file = MIDIFileUnion::Live(LiveLoadMIDIFile::load_from_file(
-                        midi_path,
-                        self.synth.clone(),
-                        settings.midi.random_colors,
-                    ));
-                    midi_file.timer_mut().play();
-                    self.midi_file = Some(midi_file);
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.pending_loop_layers = None,
+            }
+        }
+
+        // Seeking back to the position of the loop also starts it over at the end
+        if let Some(midi_file) = self.midi_file.as_mut() {
+            let position = Duration::from_secs_f64(station.position());
+            let time = midi_file.timer().get_time();
+            let drift = if position > time {
+                position - time
+            } else {
+                time - position
+            };
+            if drift > MAX_LOOP_DRIFT {
+                midi_file.timer_mut().seek(position);
+            }
+        }
+    }
+
+    /// Picks a second MIDI to show next to the current one
+    pub fn open_comparison_dialog(&mut self, state: &mut WasabiState) {
+        fn filter(path: &std::path::Path) -> bool {
+            path.extension().is_some_and(|ext| ext == "mid")
+        }
+
+        let mut dialog = FileDialog::open_file(state.last_midi_file.clone(), Some(filter))
+            .show_rename(true)
+            .show_new_folder(true)
+            .resizable(true);
+
+        dialog.open();
+        self.file_dialogs.comparison_file_dialog = Some(dialog);
+    }
+
+    /// Asks where to save the notes that are on screen as an SVG image
+    pub fn open_svg_dialog(&mut self) {
+        let Some(midi_file) = &self.midi_file else {
+            return;
+        };
+        let path = midi_file.signature().filepath.with_extension("svg");
+
+        let mut dialog = FileDialog::save_file(Some(path))
+            .show_new_folder(true)
+            .resizable(true);
+
+        dialog.open();
+        self.file_dialogs.svg_file_dialog = Some(dialog);
+    }
+
+    /// Asks where to save the sheet music of the open MIDI, next to it by default
+    pub fn open_musicxml_dialog(&mut self) {
+        let Some(midi_file) = &self.midi_file else {
+            return;
+        };
+        let path = midi_file.signature().filepath.with_extension("musicxml");
+
+        let mut dialog = FileDialog::save_file(Some(path))
+            .show_new_folder(true)
+            .resizable(true);
+
+        dialog.open();
+        self.file_dialogs.musicxml_file_dialog = Some(dialog);
+    }
+
+    pub fn load_midi(
+        &mut self,
+        settings: &mut WasabiSettings,
+        state: &mut WasabiState,
+        midi_path: PathBuf,
+    ) {
+        if is_zip_archive(&midi_path) {
+            self.open_zip(settings, state, midi_path);
+        } else {
+            self.load_midi_from(settings, state, midi_path, None);
+        }
+    }
+
+    /// Opens the only MIDI in a ZIP archive right away, or lets the user pick one
+    fn open_zip(&mut self, settings: &mut WasabiSettings, state: &mut WasabiState, path: PathBuf) {
+        let mut entries = match list_zip_midis(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                notifications::error(format!("Failed to read {}: {}", path.display(), e));
+                return;
+            }
+        };
+
+        match entries.len() {
+            0 => notifications::error(format!("{} doesn't contain any MIDI files", path.display())),
+            1 => {
+                let entry = entries.remove(0);
+                self.load_midi_from(settings, state, path, Some(entry));
+            }
+            _ => {
+                self.file_dialogs.zip_picker = Some(zip_picker::ZipPicker::new(path, entries));
+            }
+        }
+    }
+
+    /// Loads a MIDI file, or the MIDI with the given name inside of a ZIP archive
+    fn load_midi_from(
+        &mut self,
+        settings: &mut WasabiSettings,
+        state: &mut WasabiState,
+        midi_path: PathBuf,
+        zip_entry: Option<String>,
+    ) {
+        self.keep_previous_midi(state);
+        self.synth.write().unwrap().reset();
+        self.note_editor.clear();
+        self.note_inspector.close();
+        self.loop_station = None;
+        self.pending_loop_layers = None;
+        self.resume_position = settings
+            .last_session
+            .as_ref()
+            .filter(|session| session.midi_file == midi_path && session.position >= MIN_RESUME)
+            .map(|session| session.position);
+
+        self.cancel_loading();
+        self.restore_settings(settings);
+
+        state.last_midi_file = Some(midi_path.clone());
+        state.view_key_offset = 0;
+        state.track_order.clear();
+
+        // The settings are overridden before loading, since they can change how the MIDI loads
+        let mut sidecar = MIDISidecar::load(&midi_path);
+        if let Some(overrides) = sidecar.settings.take() {
+            self.settings_override = settings.apply_override(overrides);
+            if let Some(settings_override) = &self.settings_override {
+                if settings_override.changes_section("synth") {
+                    self.reload_synth(settings);
                 }
-                MidiLoading::Cake => {
-                    let mut midi_file = MIDIFileUnion::Cake(CakeMIDIFile::load_from_file(
-                        midi_path,
-                        self.synth.clone(),
-                        settings.midi.random_colors,
-                    ));
-                    midi_file.timer_mut().play();
-                    self.midi_file = Some(midi_file);
+            }
+        }
+
+        // Compressed files are decompressed in the background and opened once done
+        if zip_entry.is_some() || is_gzip_midi(&midi_path) {
+            let (cancel, listener) = triggered::trigger();
+            let receiver = match zip_entry {
+                Some(entry) => extract_zip_midi(midi_path, entry, listener),
+                None => decompress_gzip_midi(midi_path, listener),
+            };
+            self.pending_extract = Some(PendingExtract { receiver, cancel });
+        } else {
+            state.track_order = sidecar.track_order.clone();
+            self.open_midi(settings, &midi_path, sidecar);
+        }
+    }
+
+    /// Pauses the open MIDI and keeps it, along with where it was, for undoing the load
+    fn keep_previous_midi(&mut self, state: &WasabiState) {
+        let extracted_midi = self.extracted_midi.take();
+        let Some(mut midi_file) = self.midi_file.take() else {
+            return;
+        };
+        midi_file.timer_mut().pause();
+
+        // The layers of the loop station aren't a file that can be gone back to
+        if self.loop_station.is_some() {
+            return;
+        }
+
+        if let Some(path) = state.last_midi_file.clone() {
+            self.previous_midi = Some(PreviousMIDI {
+                position: midi_file.timer().get_time(),
+                midi_file,
+                extracted_midi,
+                path,
+                track_order: state.track_order.clone(),
+            });
+        }
+    }
+
+    /// Goes back to the MIDI that was open before the last one was loaded, at the
+    /// position it was left at. Undoing again goes back to the newer MIDI.
+    fn undo_midi_load(&mut self, settings: &mut WasabiSettings, state: &mut WasabiState) {
+        let Some(previous) = self.previous_midi.take() else {
+            return;
+        };
+
+        self.cancel_loading();
+        self.keep_previous_midi(state);
+        self.synth.write().unwrap().reset();
+        self.note_editor.clear();
+        self.note_inspector.close();
+        self.loop_station = None;
+        self.pending_loop_layers = None;
+        self.resume_position = None;
+
+        self.restore_settings(settings);
+        if let Some(overrides) = MIDISidecar::load(&previous.path).settings {
+            self.settings_override = settings.apply_override(overrides);
+            if let Some(settings_override) = &self.settings_override {
+                if settings_override.changes_section("synth") {
+                    self.reload_synth(settings);
                 }
             }
         }
+
+        state.last_midi_file = Some(previous.path);
+        state.view_key_offset = 0;
+        state.track_order = previous.track_order;
+
+        let mut midi_file = previous.midi_file;
+        midi_file.timer_mut().seek(previous.position);
+        midi_file.timer_mut().play();
+        self.midi_file = Some(midi_file);
+        self.extracted_midi = previous.extracted_midi;
+        self.key_counts = None;
+        self.drum_pads.reset();
+        self.set_tempo_override(None);
+        self.paused_on_focus_loss = false;
+    }
+
+    /// Puts back the settings that the sidecar file of the last MIDI overrode
+    fn restore_settings(&mut self, settings: &mut WasabiSettings) {
+        if let Some(settings_override) = self.settings_override.take() {
+            let reload_synth = settings_override.changes_section("synth");
+            settings.restore_override(settings_override);
+            if reload_synth {
+                self.reload_synth(settings);
+            }
+        }
+    }
+
+    /// Recreates the audio player after the synth settings were changed by a sidecar file
+    fn reload_synth(&mut self, settings: &WasabiSettings) {
+        if settings.no_audio {
+            return;
+        }
+
+        self.synth.write().unwrap().start_synth(settings);
+        self.update_region_filter(settings);
+    }
+
+    /// Reads the regions of the SFZ if it changed, and sends the
+    /// notes of the disabled regions that should be dropped to the synth
+    fn update_region_filter(&mut self, settings: &WasabiSettings) {
+        let path = &settings.synth.sfz_path;
+        if self.sfz_instrument.as_ref().map(|sfz| &sfz.path) != Some(path) {
+            self.sfz_instrument = SfzInstrument::parse(path);
+        }
+
+        let filter = self
+            .sfz_instrument
+            .as_ref()
+            .and_then(|sfz| RegionFilter::new(sfz, &settings.synth.disabled_regions));
+        self.synth.write().unwrap().set_region_filter(filter);
     }
+
+    /// Slowly dims the notes once the MIDI has been playing without any input for
+    /// the configured time, any mouse movement or key press restores the brightness
+    fn update_night_dim(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &WasabiSettings,
+        state: &mut WasabiState,
+    ) {
+        const DIM_BRIGHTNESS: f32 = 0.3;
+        const DIM_FADE_SECS: f32 = 5.0;
+
+        let (active, dt) = ctx.input(|i| {
+            let active = i.pointer.is_moving()
+                || i.events
+                    .iter()
+                    .any(|event| matches!(event, egui::Event::Key { pressed: true, .. }));
+            (active, i.unstable_dt)
+        });
+
+        let playing = self
+            .midi_file
+            .as_ref()
+            .map(|midi_file| !midi_file.timer().is_paused())
+            .unwrap_or(false);
+
+        if active {
+            state.idle_playback_secs = 0.0;
+        } else if playing {
+            state.idle_playback_secs += dt as f64;
+        }
+
+        match settings.visual.dim_after_secs {
+            Some(secs) if state.idle_playback_secs >= secs as f64 => {
+                let step = (1.0 - DIM_BRIGHTNESS) * dt / DIM_FADE_SECS;
+                self.brightness = (self.brightness - step).max(DIM_BRIGHTNESS);
+            }
+            _ => self.brightness = 1.0,
+        }
+    }
+
+    /// Pauses the playback when the window loses focus, and resumes it when the focus
+    /// comes back, but only if it was playing and paused because of the focus loss
+    pub fn focus_changed(&mut self, settings: &WasabiSettings, focused: bool) {
+        let Some(midi_file) = self.midi_file.as_mut() else {
+            return;
+        };
+
+        if !focused && settings.midi.pause_on_focus_loss && !midi_file.timer().is_paused() {
+            midi_file.timer_mut().pause();
+            self.paused_on_focus_loss = true;
+        } else if focused && self.paused_on_focus_loss {
+            midi_file.timer_mut().play();
+            self.paused_on_focus_loss = false;
+        }
+    }
+
+    /// Opens a MIDI that was decompressed or extracted from a ZIP archive once it is ready
+    fn poll_pending_extract(&mut self, settings: &WasabiSettings, state: &mut WasabiState) {
+        let result = match &self.pending_extract {
+            Some(extract) => match extract.receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => None,
+            },
+            None => return,
+        };
+        self.pending_extract = None;
+
+        if let Some(path) = result {
+            // The sidecar belongs to the compressed file or archive, not the temporary one
+            let sidecar = match &state.last_midi_file {
+                Some(original) => MIDISidecar::load(original),
+                None => MIDISidecar::default(),
+            };
+            state.track_order = sidecar.track_order.clone();
+            self.open_midi(settings, &path, sidecar);
+            self.extracted_midi = Some(path);
+        }
+    }
+
+    /// The heatmap colors of the keys, once the events of the MIDI have been scanned
+    fn heatmap_colors(&mut self, settings: &WasabiSettings) -> Option<Vec<Option<MIDIColor>>> {
+        let midi_file = self.midi_file.as_ref()?;
+        if self.key_counts.is_none() {
+            let metadata = midi_file.metadata();
+            if !metadata.scanned {
+                return None;
+            }
+            self.key_counts = Some(metadata.key_counts.clone());
+        }
+
+        self.key_counts
+            .as_deref()
+            .map(|counts| keyboard::heatmap_colors(counts, &settings.visual.heatmap_gradient))
+    }
+
+    /// The MIDI that is open and how far into it the playback is, for continuing from
+    /// there the next time. A MIDI that finished playing starts over instead.
+    pub fn session(&self, state: &WasabiState) -> Option<SavedSession> {
+        let midi_file = self.midi_file.as_ref()?;
+        let midi_path = state.last_midi_file.clone()?;
+
+        let position = midi_file.timer().get_time();
+        let ended = midi_file
+            .midi_length()
+            .map_or(false, |length| position.as_secs_f64() >= length);
+
+        Some(SavedSession {
+            midi_file: midi_path,
+            position: if ended { Duration::ZERO } else { position },
+        })
+    }
+
+    /// Whether a MIDI file is being decompressed or loaded in the background
+    fn is_loading(&self) -> bool {
+        self.pending_extract.is_some() || self.pending_load.is_some()
+    }
+
+    /// Stops the MIDI file that is being decompressed or loaded, if any, and the
+    /// metadata scan of the MIDI that was loaded last
+    fn cancel_loading(&mut self) {
+        if let Some(extract) = self.pending_extract.take() {
+            extract.cancel.trigger();
+        }
+        if let Some(load) = self.pending_load.take() {
+            load.cancel.trigger();
+        }
+        if let Some(cancel) = self.metadata_scan.take() {
+            cancel.trigger();
+        }
+    }
+
+    /// Starts playing a MIDI once its background loading finished
+    fn poll_pending_load(&mut self) {
+        let result = match &self.pending_load {
+            Some(load) => match load.receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return,
+                // The loader panicked on something it couldn't read
+                Err(TryRecvError::Disconnected) => {
+                    notifications::error("Failed to load the MIDI, it might be corrupt");
+                    self.pending_load = None;
+                    return;
+                }
+            },
+            None => return,
+        };
+        let load = self.pending_load.take().unwrap();
+
+        if let Err(e) = &result {
+            notifications::error(format!("Failed to load the MIDI: {}", e));
+        }
+        if let Ok(mut midi_file) = result {
+            if let MIDIFileUnion::InRam(file) = &mut midi_file {
+                file.set_note_edits(load.sidecar.note_edits);
+            }
+            if let Some(position) = load.position {
+                midi_file.timer_mut().seek(position);
+            }
+            midi_file.timer_mut().play();
+            self.midi_file = Some(midi_file);
+            self.metadata_scan = Some(load.cancel);
+            self.key_counts = None;
+            self.drum_pads.reset();
+            self.set_tempo_override(None);
+            self.paused_on_focus_loss = false;
+        }
+    }
+
+    /// The cake renderer only keeps the note on top of each key, which is picked by the
+    /// track order while loading, so the MIDI is loaded again where it was left at
+    fn reload_cake_tracks(&mut self, settings: &WasabiSettings, state: &WasabiState) {
+        let Some(MIDIFileUnion::Cake(midi_file)) = self.midi_file.as_ref() else {
+            return;
+        };
+        let path = midi_file.signature().filepath.clone();
+        let position = midi_file.timer().get_time();
+
+        self.cancel_loading();
+        let sidecar = MIDISidecar {
+            track_order: state.track_order.clone(),
+            ..Default::default()
+        };
+        self.open_midi(settings, &path, sidecar);
+        if let Some(load) = self.pending_load.as_mut() {
+            load.position = Some(position);
+        }
+    }
+
+    /// Loads a MIDI on a separate thread, it starts playing once [`Self::poll_pending_load`]
+    /// receives it. Opening another MIDI in the meantime cancels the load.
+    fn open_midi(&mut self, settings: &WasabiSettings, midi_path: &Path, sidecar: MIDISidecar) {
+        let midi_path = midi_path.to_owned();
+
+        let (cancel, listener) = triggered::trigger();
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+
+        let synth = self.synth.clone();
+        let midi_loading = settings.midi.midi_loading;
+        let random_colors = settings.midi.random_colors;
+        let program_colors = settings.midi.program_colors.then(|| {
+            settings
+                .midi
+                .program_color_map
+                .iter()
+                .map(|c| MIDIColor::new(c.r(), c.g(), c.b()))
+                .collect::<Vec<_>>()
+        });
+        let timbre_colors = TimbreColors::from_settings(settings);
+        let reverse_playback = settings.midi.reverse_playback;
+        let repair_mode = settings.midi.repair_mode;
+        let use_mmap = settings.midi.use_mmap;
+        let track_order = sidecar.track_order.clone();
+
+        thread::spawn(move || {
+            // The SFZ is analyzed here, since it plays every program family through it
+            let program_colors = match timbre_colors {
+                Some(timbre) if midi_loading != MidiLoading::Cake => {
+                    timbre.program_colors().or(program_colors)
+                }
+                _ => program_colors,
+            };
+
+            let midi_file = match midi_loading {
+                MidiLoading::Ram => InRamMIDIFile::load_from_file(
+                    &midi_path,
+                    synth,
+                    random_colors,
+                    program_colors,
+                    reverse_playback,
+                    repair_mode,
+                    &listener,
+                )
+                .map(MIDIFileUnion::InRam),
+                MidiLoading::Live => LiveLoadMIDIFile::load_from_file(
+                    &midi_path,
+                    synth,
+                    random_colors,
+                    program_colors,
+                    use_mmap,
+                    &listener,
+                )
+                .map(MIDIFileUnion::Live),
+                MidiLoading::Cake => CakeMIDIFile::load_from_file(
+                    &midi_path,
+                    synth,
+                    random_colors,
+                    &track_order,
+                    &listener,
+                )
+                .map(MIDIFileUnion::Cake),
+            };
+
+            // A load that was cancelled, even right as it finished, is dropped here
+            if !listener.is_triggered() {
+                sender.send(midi_file).ok();
+            }
+        });
+
+        self.pending_load = Some(PendingMIDILoad {
+            receiver,
+            cancel,
+            sidecar,
+            position: None,
+        });
+    }
+}
+
+/// Sessions that ended closer to the start than this don't ask to be resumed
+const MIN_RESUME: Duration = Duration::from_secs(10);
+
+/// The highest key that can be shown on the keyboard
+const MAX_VIEW_KEY: i32 = 254;
+
+/// The velocity of the notes played by clicking the keyboard
+const KEY_CLICK_VELOCITY: u8 = 100;
+
+/// Draws a thin bar at the bottom of the notes showing which part
+/// of the keyboard is currently visible
+fn draw_key_scrollbar(
+    ui: &mut egui::Ui,
+    rect: egui::Rect,
+    first_key: usize,
+    last_key: usize,
+    vertical: bool,
+) {
+    let total_keys = MAX_VIEW_KEY as f32 + 1.0;
+    if (last_key - first_key + 1) as f32 >= total_keys {
+        return;
+    }
+
+    // A vertical scrollbar runs along the keyboard from the bottom up
+    let (track, thumb) = if vertical {
+        let track = egui::Rect::from_min_max(
+            rect.left_top(),
+            egui::pos2(rect.left() + 4.0, rect.bottom()),
+        );
+        let thumb = egui::Rect::from_x_y_ranges(
+            track.x_range(),
+            (track.bottom() - track.height() * (last_key + 1) as f32 / total_keys)
+                ..=(track.bottom() - track.height() * first_key as f32 / total_keys),
+        );
+        (track, thumb)
+    } else {
+        let track = egui::Rect::from_min_max(
+            egui::pos2(rect.left(), rect.bottom() - 4.0),
+            rect.right_bottom(),
+        );
+        let thumb = egui::Rect::from_x_y_ranges(
+            (track.left() + track.width() * first_key as f32 / total_keys)
+                ..=(track.left() + track.width() * (last_key + 1) as f32 / total_keys),
+            track.y_range(),
+        );
+        (track, thumb)
+    };
+
+    let painter = ui.painter();
+    painter.rect_filled(track, 0.0, egui::Color32::from_black_alpha(100));
+    painter.rect_filled(thumb, 2.0, egui::Color32::from_white_alpha(100));
 }
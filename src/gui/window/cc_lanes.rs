@@ -0,0 +1,145 @@
+use egui::{Align2, Color32, FontId, Rect, Ui};
+
+use crate::midi::{ControlChanges, MIDIColor};
+
+use super::keyboard_layout::KeyboardView;
+
+/// How many pixels apart the values of a ribbon are sampled along the time
+const SAMPLE_SPACING: f32 = 2.0;
+
+/// The names of the most common controllers
+pub fn controller_name(controller: u8) -> Option<&'static str> {
+    Some(match controller {
+        1 => "Modulation",
+        2 => "Breath",
+        7 => "Volume",
+        10 => "Pan",
+        11 => "Expression",
+        64 => "Sustain",
+        71 => "Resonance",
+        72 => "Release",
+        73 => "Attack",
+        74 => "Cutoff",
+        91 => "Reverb",
+        93 => "Chorus",
+        _ => return None,
+    })
+}
+
+pub fn controller_label(controller: u8) -> String {
+    match controller_name(controller) {
+        Some(name) => format!("CC {} {}", controller, name),
+        None => format!("CC {}", controller),
+    }
+}
+
+/// Draws a lane for each of the selected controllers at the bottom of every key column,
+/// the first one right above the keyboard. The lanes follow the notes, so each one shows
+/// the values at the times of the notes drawn over it, of the channel that is playing on
+/// the key. The cake renderer doesn't report the channels, so it doesn't get any lanes.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_cc_lanes(
+    ui: &Ui,
+    rect: Rect,
+    key_view: &KeyboardView,
+    key_channels: &[u16],
+    control_changes: &ControlChanges,
+    controllers: &[u8],
+    lane_height: f32,
+    time: f64,
+    range: f64,
+    reversed_length: Option<f64>,
+    waterfall: bool,
+) {
+    let painter = ui.painter().with_clip_rect(rect);
+
+    // The notes move from the top to the bottom, or from the right to the left in
+    // waterfall mode, where the lowest keys are at the bottom
+    let time_length = if waterfall {
+        rect.width()
+    } else {
+        rect.height()
+    };
+    let cell = |left: f32, right: f32, from: f32, to: f32| {
+        if waterfall {
+            Rect::from_min_max(
+                egui::pos2(rect.left() + from, rect.bottom() - right * rect.height()),
+                egui::pos2(rect.left() + to, rect.bottom() - left * rect.height()),
+            )
+        } else {
+            Rect::from_min_max(
+                egui::pos2(rect.left() + left * rect.width(), rect.bottom() - to),
+                egui::pos2(rect.left() + right * rect.width(), rect.bottom() - from),
+            )
+        }
+    };
+
+    for (i, controller) in controllers.iter().enumerate() {
+        let lane_start = i as f32 * lane_height;
+        if lane_start + lane_height > time_length {
+            break;
+        }
+
+        let channels = control_changes.channels_with(*controller);
+        for (key, position) in key_view.iter_visible_notes() {
+            let playing = key_channels.get(key).copied().unwrap_or(0);
+            let Some(channel) = channels
+                .iter()
+                .copied()
+                .find(|channel| playing & (1 << channel) != 0)
+            else {
+                continue;
+            };
+
+            painter.rect_filled(
+                cell(
+                    position.left,
+                    position.right,
+                    lane_start,
+                    lane_start + lane_height,
+                ),
+                0.0,
+                Color32::from_black_alpha(150),
+            );
+
+            let color = MIDIColor::new_from_hue(channel as f64 * 360.0 / 16.0);
+            let color = Color32::from_rgb(color.red(), color.green(), color.blue());
+            let center = (position.left + position.right) / 2.0;
+            let half_width = (position.right - position.left) / 2.0;
+
+            // The ribbon is as wide as the key at 127 and thins out to nothing at 0
+            let mut from = lane_start;
+            while from < lane_start + lane_height {
+                let to = (from + SAMPLE_SPACING).min(lane_start + lane_height);
+                let sample_time = time + from as f64 / time_length as f64 * range;
+                // The events are stored in the order of the file, not of the playback
+                let sample_time = match reversed_length {
+                    Some(length) => length - sample_time,
+                    None => sample_time,
+                };
+                if let Some(value) = control_changes.value_at(channel, *controller, sample_time) {
+                    let width = half_width * value as f32 / 127.0;
+                    painter.rect_filled(cell(center - width, center + width, from, to), 0.0, color);
+                }
+                from = to;
+            }
+        }
+
+        let label_pos = cell(0.0, 0.0, lane_start, lane_start + lane_height);
+        painter.text(
+            if waterfall {
+                label_pos.center_bottom() - egui::vec2(0.0, 2.0)
+            } else {
+                label_pos.left_top() + egui::vec2(4.0, 2.0)
+            },
+            if waterfall {
+                Align2::CENTER_BOTTOM
+            } else {
+                Align2::LEFT_TOP
+            },
+            controller_label(*controller),
+            FontId::proportional(10.0),
+            Color32::from_gray(200),
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use egui::{Color32, Rect, Ui};
+
+use crate::midi::MIDIColor;
+
+/// How thick the bar is in pixels
+const BAR_SIZE: f32 = 6.0;
+
+/// The space between the segments in pixels
+const SEGMENT_SPACING: f32 = 1.0;
+
+/// Draws a thin bar with a segment for each of the 16 channels along the edge of the
+/// notes next to the keyboard. A segment is bright while the channel has notes on
+/// screen and dim while it has none.
+pub fn draw_channel_activity(ui: &Ui, rect: Rect, visible_channels: u16, waterfall: bool) {
+    let painter = ui.painter().with_clip_rect(rect);
+
+    // In waterfall mode the keyboard is on the left, so the bar runs from the bottom up
+    let (bar, step) = if waterfall {
+        (
+            Rect::from_min_max(rect.min, egui::pos2(rect.left() + BAR_SIZE, rect.bottom())),
+            rect.height() / 16.0,
+        )
+    } else {
+        (
+            Rect::from_min_max(egui::pos2(rect.left(), rect.bottom() - BAR_SIZE), rect.max),
+            rect.width() / 16.0,
+        )
+    };
+
+    for channel in 0..16 {
+        let (min, max) = if waterfall {
+            let bottom = bar.bottom() - channel as f32 * step;
+            (
+                egui::pos2(bar.left(), bottom - step + SEGMENT_SPACING),
+                egui::pos2(bar.right(), bottom),
+            )
+        } else {
+            let left = bar.left() + channel as f32 * step;
+            (
+                egui::pos2(left, bar.top()),
+                egui::pos2(left + step - SEGMENT_SPACING, bar.bottom()),
+            )
+        };
+
+        let color = MIDIColor::new_from_hue(channel as f64 * 360.0 / 16.0);
+        let alpha = if visible_channels & (1 << channel) != 0 {
+            255
+        } else {
+            50
+        };
+        painter.rect_filled(
+            Rect::from_min_max(min, max),
+            1.0,
+            Color32::from_rgba_unmultiplied(color.red(), color.green(), color.blue(), alpha),
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use egui::{Align2, Color32, FontId, Rect, Ui};
+
+use crate::midi::MIDIColor;
+
+use super::note_inspector::NOTE_NAMES;
+
+/// The chords that are named, by the pitch classes above their root. The first match
+/// wins, so the chords with the same notes as another one come after it.
+const CHORD_TYPES: [(&[u8], &str); 21] = [
+    (&[0, 4, 7], "maj"),
+    (&[0, 3, 7], "m"),
+    (&[0, 3, 6], "dim"),
+    (&[0, 4, 8], "aug"),
+    (&[0, 2, 7], "sus2"),
+    (&[0, 5, 7], "sus4"),
+    (&[0, 7], "5"),
+    (&[0, 4, 7, 10], "7"),
+    (&[0, 4, 7, 11], "maj7"),
+    (&[0, 3, 7, 10], "m7"),
+    (&[0, 3, 7, 11], "mMaj7"),
+    (&[0, 3, 6, 10], "m7b5"),
+    (&[0, 3, 6, 9], "dim7"),
+    (&[0, 5, 7, 10], "7sus4"),
+    (&[0, 4, 7, 9], "6"),
+    (&[0, 3, 7, 9], "m6"),
+    (&[0, 2, 4, 7], "add9"),
+    (&[0, 2, 4, 7, 10], "9"),
+    (&[0, 2, 4, 7, 11], "maj9"),
+    (&[0, 2, 3, 7, 10], "m9"),
+    (&[0, 4, 10], "7(no5)"),
+];
+
+/// The drums play on channel 10, so its keys aren't pitches
+const PERCUSSION_CHANNEL: u8 = 9;
+
+/// The space between the name and the keyboard in pixels
+const MARGIN: f32 = 12.0;
+
+/// The pitch classes of a chord as bits, with the root as the lowest bit
+fn chord_mask(intervals: &[u8]) -> u16 {
+    intervals
+        .iter()
+        .fold(0, |mask, interval| mask | (1 << interval))
+}
+
+/// Names the chord that the pressed keys make up, like `Am7` or `Cmaj/E`. Every root in
+/// the chord is tried, starting with the lowest key, which is added after a slash when
+/// it isn't the root. Returns `None` when the keys don't make up a known chord.
+pub fn chord_name(keys: impl Iterator<Item = u8>) -> Option<String> {
+    let mut pitch_classes = 0u16;
+    let mut bass = None;
+    for key in keys {
+        pitch_classes |= 1 << (key % 12);
+        bass = Some(bass.map_or(key, |bass: u8| bass.min(key)));
+    }
+    let bass = bass? % 12;
+    if pitch_classes.count_ones() < 2 {
+        return None;
+    }
+
+    let roots = std::iter::once(bass).chain((0..12).filter(|root| *root != bass));
+    for root in roots.filter(|root| pitch_classes & (1 << root) != 0) {
+        // Turns the pitch classes so the root is the lowest bit
+        let relative = ((pitch_classes >> root) | (pitch_classes << (12 - root))) & 0xFFF;
+        let found = CHORD_TYPES
+            .iter()
+            .find(|(intervals, _)| chord_mask(intervals) == relative);
+        if let Some((_, suffix)) = found {
+            let name = format!("{}{}", NOTE_NAMES[root as usize], suffix);
+            return Some(if root == bass {
+                name
+            } else {
+                format!("{}/{}", name, NOTE_NAMES[bass as usize])
+            });
+        }
+    }
+    None
+}
+
+/// Draws the name of the chord that is being played over the edge of the notes next to
+/// the keyboard, which is the bottom edge or the left edge in waterfall mode. The keys
+/// only played on the percussion channel are left out when the channels are known.
+pub fn draw_chord_name(
+    ui: &Ui,
+    rect: Rect,
+    key_colors: &[Option<MIDIColor>],
+    key_channels: &[u16],
+    waterfall: bool,
+) {
+    let pitched = |key: usize| {
+        key_channels
+            .get(key)
+            .map_or(true, |channels| channels & !(1 << PERCUSSION_CHANNEL) != 0)
+    };
+    let pressed = key_colors
+        .iter()
+        .enumerate()
+        .filter(|(key, color)| color.is_some() && pitched(*key))
+        .filter_map(|(key, _)| u8::try_from(key).ok());
+    let Some(name) = chord_name(pressed) else {
+        return;
+    };
+
+    let painter = ui.painter().with_clip_rect(rect);
+    let galley = painter.layout_no_wrap(name, FontId::proportional(28.0), Color32::WHITE);
+    let (pos, align) = if waterfall {
+        (
+            egui::pos2(rect.left() + MARGIN, rect.center().y),
+            Align2::LEFT_CENTER,
+        )
+    } else {
+        (
+            egui::pos2(rect.center().x, rect.bottom() - MARGIN),
+            Align2::CENTER_BOTTOM,
+        )
+    };
+    let text_rect = align.anchor_rect(Rect::from_min_size(pos, galley.size()));
+
+    painter.rect_filled(
+        text_rect.expand2(egui::vec2(10.0, 4.0)),
+        6.0,
+        Color32::from_black_alpha(150),
+    );
+    painter.galley(text_rect.min, galley);
+}
@@ -0,0 +1,196 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, TryRecvError};
+use egui::{Color32, Rect, Stroke, Ui};
+use triggered::Trigger;
+
+use crate::{
+    audio_playback::{AudioPlayerType, SimpleTemporaryPlayer},
+    gui::{window::scene::GuiRenderScene, GuiRenderer, GuiState},
+    midi::{InRamMIDIFile, MIDIFileBase, MIDIFileUnion, MIDIOpenError},
+    notifications,
+    settings::WasabiSettings,
+};
+
+use super::keyboard_layout::KeyboardView;
+
+/// How far the second MIDI can drift from the main one before it is seeked back in line
+const MAX_DRIFT: Duration = Duration::from_millis(50);
+
+/// How far the colors of the second MIDI are turned around the color wheel
+const HUE_ROTATION: f64 = 180.0;
+
+/// The notes of the second MIDI are drawn in smaller batches than the main one,
+/// so its buffers take a tenth of the memory
+const NOTE_BUFFER_SIZE: u64 = 2500000;
+
+/// A second MIDI that is drawn on the right side of the notes panel, following the
+/// playhead of the main one, for comparing arrangements. It is loaded into RAM
+/// with a silent player, so only the main MIDI is heard.
+pub struct ComparisonView {
+    pub path: PathBuf,
+    /// Where the notes panel is split between the two MIDIs, from 0 to 1
+    pub split: f32,
+    render_scene: GuiRenderScene,
+    midi_file: Option<MIDIFileUnion>,
+    pending: Option<Receiver<Result<InRamMIDIFile, MIDIOpenError>>>,
+    /// Stops the load when the view is closed before it finished
+    cancel: Trigger,
+}
+
+impl ComparisonView {
+    pub fn new(renderer: &GuiRenderer, settings: &WasabiSettings, path: PathBuf) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let (cancel, listener) = triggered::trigger();
+
+        let midi_path = path.clone();
+        let reverse_playback = settings.midi.reverse_playback;
+        let repair_mode = settings.midi.repair_mode;
+        let random_colors = settings.midi.random_colors;
+
+        let scan_cancel = cancel.clone();
+        thread::spawn(move || {
+            let player = Arc::new(RwLock::new(SimpleTemporaryPlayer::new(
+                AudioPlayerType::Silent,
+            )));
+            let midi_file = InRamMIDIFile::load_from_file(
+                &midi_path,
+                player,
+                random_colors,
+                None,
+                reverse_playback,
+                repair_mode,
+                &listener,
+            );
+            // Only the notes of the second MIDI are drawn, so its events aren't
+            // scanned again for the metadata
+            scan_cancel.trigger();
+            sender.send(midi_file).ok();
+        });
+
+        Self {
+            path,
+            split: 0.5,
+            render_scene: GuiRenderScene::with_note_buffer_size(renderer, NOTE_BUFFER_SIZE),
+            midi_file: None,
+            pending: Some(receiver),
+            cancel,
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn poll_pending(&mut self) {
+        let result = match &self.pending {
+            Some(receiver) => match receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    notifications::error(format!(
+                        "Failed to load {} for comparison, it might be corrupt",
+                        self.path.display()
+                    ));
+                    self.pending = None;
+                    return;
+                }
+            },
+            None => return,
+        };
+        self.pending = None;
+
+        match result {
+            Ok(mut midi_file) => {
+                midi_file.rotate_hues(HUE_ROTATION);
+                self.midi_file = Some(MIDIFileUnion::InRam(midi_file));
+            }
+            Err(e) => notifications::error(format!(
+                "Failed to load {} for comparison: {}",
+                self.path.display(),
+                e
+            )),
+        }
+    }
+
+    /// Matches the time, speed and pause state of the second MIDI to the main one
+    fn sync_to(&mut self, main: &MIDIFileUnion) {
+        let Some(midi_file) = self.midi_file.as_mut() else {
+            return;
+        };
+        let main_timer = main.timer();
+        let timer = midi_file.timer_mut();
+
+        if timer.get_speed() != main_timer.get_speed() {
+            timer.set_speed(main_timer.get_speed());
+        }
+
+        let main_time = main_timer.get_time();
+        let time = timer.get_time();
+        let drift = if main_time > time {
+            main_time - time
+        } else {
+            time - main_time
+        };
+        if drift > MAX_DRIFT {
+            timer.seek(main_time);
+        }
+
+        if timer.is_paused() != main_timer.is_paused() {
+            if main_timer.is_paused() {
+                timer.pause();
+            } else {
+                timer.play();
+            }
+        }
+    }
+
+    /// Draws the second MIDI over the part of `rect` right of the split
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        state: &mut GuiState,
+        ui: &mut Ui,
+        rect: Rect,
+        key_view: &KeyboardView,
+        main: &MIDIFileUnion,
+        settings: &WasabiSettings,
+        brightness: f32,
+    ) {
+        self.poll_pending();
+        self.sync_to(main);
+
+        let split_x = rect.left() + rect.width() * self.split.clamp(0.0, 1.0);
+        let clip = Rect::from_min_max(egui::pos2(split_x, rect.top()), rect.max);
+
+        if let Some(midi_file) = self.midi_file.as_mut() {
+            // The main MIDI is already drawn there, and the scene is transparent behind the notes
+            ui.painter()
+                .with_clip_rect(clip)
+                .rect_filled(clip, 0.0, settings.visual.bg_color);
+
+            ui.allocate_ui_at_rect(rect, |ui| {
+                ui.set_clip_rect(clip.intersect(ui.clip_rect()));
+                self.render_scene
+                    .draw(state, ui, key_view, midi_file, settings, brightness, &[]);
+            });
+        }
+
+        ui.painter().vline(
+            split_x,
+            rect.y_range(),
+            Stroke::new(2.0, Color32::from_white_alpha(160)),
+        );
+    }
+}
+
+impl Drop for ComparisonView {
+    fn drop(&mut self) {
+        self.cancel.trigger();
+    }
+}
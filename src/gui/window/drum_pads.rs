@@ -0,0 +1,156 @@
+use std::{collections::BTreeSet, time::Instant};
+
+use egui::{Align2, Color32, FontId, Rect, Stroke, Ui};
+
+use crate::midi::MIDIColor;
+
+/// The size of a pad in pixels
+const PAD_WIDTH: f32 = 104.0;
+const PAD_HEIGHT: f32 = 40.0;
+
+/// The space between the pads and around them
+const PAD_SPACING: f32 = 4.0;
+
+/// The most pads in a row, more drums start a new row
+const PADS_PER_ROW: usize = 8;
+
+/// How long a pad keeps glowing after its drum stopped, in seconds
+const FADE_SECONDS: f32 = 0.3;
+
+/// The names of the drums of the General MIDI percussion channel
+pub fn drum_name(key: u8) -> Option<&'static str> {
+    Some(match key {
+        35 => "Acoustic Bass Drum",
+        36 => "Bass Drum",
+        37 => "Side Stick",
+        38 => "Snare",
+        39 => "Hand Clap",
+        40 => "Electric Snare",
+        41 => "Low Floor Tom",
+        42 => "Closed Hi-Hat",
+        43 => "High Floor Tom",
+        44 => "Pedal Hi-Hat",
+        45 => "Low Tom",
+        46 => "Open Hi-Hat",
+        47 => "Low-Mid Tom",
+        48 => "Hi-Mid Tom",
+        49 => "Crash Cymbal 1",
+        50 => "High Tom",
+        51 => "Ride Cymbal 1",
+        52 => "Chinese Cymbal",
+        53 => "Ride Bell",
+        54 => "Tambourine",
+        55 => "Splash Cymbal",
+        56 => "Cowbell",
+        57 => "Crash Cymbal 2",
+        58 => "Vibraslap",
+        59 => "Ride Cymbal 2",
+        60 => "Hi Bongo",
+        61 => "Low Bongo",
+        62 => "Mute Hi Conga",
+        63 => "Open Hi Conga",
+        64 => "Low Conga",
+        65 => "High Timbale",
+        66 => "Low Timbale",
+        67 => "High Agogo",
+        68 => "Low Agogo",
+        69 => "Cabasa",
+        70 => "Maracas",
+        71 => "Short Whistle",
+        72 => "Long Whistle",
+        73 => "Short Guiro",
+        74 => "Long Guiro",
+        75 => "Claves",
+        76 => "Hi Wood Block",
+        77 => "Low Wood Block",
+        78 => "Mute Cuica",
+        79 => "Open Cuica",
+        80 => "Mute Triangle",
+        81 => "Open Triangle",
+        _ => return None,
+    })
+}
+
+/// The pads of the drums that were played so far, which light up in the color of
+/// the note while their drum plays and fade out after
+pub struct DrumPads {
+    used: BTreeSet<u8>,
+    last_hits: Vec<Option<(MIDIColor, Instant)>>,
+}
+
+impl DrumPads {
+    pub fn new() -> Self {
+        Self {
+            used: BTreeSet::new(),
+            last_hits: vec![None; 256],
+        }
+    }
+
+    /// Forgets the drums, for when another MIDI is loaded
+    pub fn reset(&mut self) {
+        self.used.clear();
+        self.last_hits.fill(None);
+    }
+
+    /// Takes the drums playing in the last drawn frame, indexed by their note
+    pub fn update(&mut self, drum_colors: &[Option<MIDIColor>]) {
+        let now = Instant::now();
+        for (key, color) in drum_colors.iter().enumerate() {
+            if let Some(color) = color {
+                self.used.insert(key as u8);
+                self.last_hits[key] = Some((*color, now));
+            }
+        }
+    }
+
+    /// Draws the pads in rows at the top left of the notes
+    pub fn draw(&self, ui: &Ui, rect: Rect) {
+        let painter = ui.painter().with_clip_rect(rect);
+        let now = Instant::now();
+
+        for (i, key) in self.used.iter().enumerate() {
+            let left =
+                rect.left() + PAD_SPACING + (i % PADS_PER_ROW) as f32 * (PAD_WIDTH + PAD_SPACING);
+            let top =
+                rect.top() + PAD_SPACING + (i / PADS_PER_ROW) as f32 * (PAD_HEIGHT + PAD_SPACING);
+            let pad = Rect::from_min_size(egui::pos2(left, top), egui::vec2(PAD_WIDTH, PAD_HEIGHT));
+
+            let glow = self.last_hits[*key as usize].map(|(color, hit)| {
+                let faded = hit.elapsed().as_secs_f32() / FADE_SECONDS;
+                (color, (1.0 - faded).clamp(0.0, 1.0))
+            });
+            let fill = match glow {
+                Some((color, strength)) if strength > 0.0 => Color32::from_rgba_unmultiplied(
+                    color.red(),
+                    color.green(),
+                    color.blue(),
+                    (80.0 + strength * 175.0) as u8,
+                ),
+                _ => Color32::from_black_alpha(150),
+            };
+
+            painter.rect(pad, 4.0, fill, Stroke::new(1.0, Color32::from_gray(90)));
+            let label = match drum_name(*key) {
+                Some(name) => name.to_owned(),
+                None => format!("Note {}", key),
+            };
+            painter.text(
+                pad.center(),
+                Align2::CENTER_CENTER,
+                label,
+                FontId::proportional(11.0),
+                Color32::WHITE,
+            );
+        }
+
+        // The pads glow while they fade, so keep drawing until they went dark
+        let fading = self
+            .last_hits
+            .iter()
+            .flatten()
+            .any(|(_, hit)| now.duration_since(*hit).as_secs_f32() < FADE_SECONDS);
+        if fading {
+            ui.ctx().request_repaint();
+        }
+    }
+}
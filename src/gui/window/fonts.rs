@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use egui::{Context, FontData, FontDefinitions, FontFamily, TextStyle};
+
+use crate::notifications;
+
+/// The size of egui's default body text, which the other text styles are scaled from
+const DEFAULT_BODY_SIZE: f32 = 12.5;
+
+/// Loads a custom font and makes it the preferred font for all of the UI.
+/// The built-in fonts are kept as a fallback, and are used alone if the
+/// font file can't be read or parsed.
+pub fn load_ui_font(ctx: &Context, path: &Path) {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            notifications::error(format!(
+                "Failed to read the UI font {}: {err}",
+                path.display()
+            ));
+            return;
+        }
+    };
+
+    // egui panics on fonts it can't parse, so check them beforehand
+    if let Err(err) = ab_glyph::FontRef::try_from_slice(&bytes) {
+        notifications::error(format!(
+            "Failed to parse the UI font {}: {err}",
+            path.display()
+        ));
+        return;
+    }
+
+    let mut fonts = FontDefinitions::default();
+    fonts
+        .font_data
+        .insert("ui_font".to_owned(), FontData::from_owned(bytes));
+    for family in [FontFamily::Proportional, FontFamily::Monospace] {
+        fonts
+            .families
+            .entry(family)
+            .or_default()
+            .insert(0, "ui_font".to_owned());
+    }
+
+    ctx.set_fonts(fonts);
+}
+
+/// Scales all the text styles so that the body text has the given size
+pub fn set_ui_font_size(ctx: &Context, size: f32) {
+    let scale = size / DEFAULT_BODY_SIZE;
+
+    let mut style = (*ctx.style()).clone();
+    style.text_styles = egui::Style::default().text_styles;
+    for (text_style, font_id) in style.text_styles.iter_mut() {
+        font_id.size = match text_style {
+            TextStyle::Body => size,
+            _ => font_id.size * scale,
+        };
+    }
+    ctx.set_style(style);
+}
@@ -1,19 +1,50 @@
 use std::{collections::VecDeque, time::Instant};
 
-pub struct Fps(VecDeque<Instant>);
+use egui::{Color32, Rect, Sense, Ui, Vec2};
+
+pub struct Fps {
+    frames: VecDeque<Instant>,
+    /// The times between the last frames in milliseconds, oldest first
+    frame_times: VecDeque<f32>,
+}
 
 const FPS_WINDOW: f64 = 0.5;
 
+/// How many frame times are kept for the graph
+const FRAME_HISTORY: usize = 100;
+
+/// The frame time the graph is scaled to at least, so a steady frame rate draws low bars
+const MIN_GRAPH_MS: f32 = 1000.0 / 60.0;
+
+/// Frame times for the minimum, average and maximum in milliseconds
+pub struct FrameTimeSummary {
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+}
+
 impl Fps {
     pub fn new() -> Self {
-        Self(VecDeque::new())
+        Self {
+            frames: VecDeque::new(),
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+        }
     }
 
     pub fn update(&mut self) {
-        self.0.push_back(Instant::now());
-        while let Some(front) = self.0.front() {
+        let now = Instant::now();
+        if let Some(last) = self.frames.back() {
+            if self.frame_times.len() == FRAME_HISTORY {
+                self.frame_times.pop_front();
+            }
+            self.frame_times
+                .push_back((now - *last).as_secs_f32() * 1000.0);
+        }
+
+        self.frames.push_back(now);
+        while let Some(front) = self.frames.front() {
             if front.elapsed().as_secs_f64() > FPS_WINDOW {
-                self.0.pop_front();
+                self.frames.pop_front();
             } else {
                 break;
             }
@@ -21,10 +52,61 @@ impl Fps {
     }
 
     pub fn get_fps(&self) -> f64 {
-        if self.0.is_empty() {
+        if self.frames.is_empty() {
             0.0
         } else {
-            self.0.len() as f64 / self.0.front().unwrap().elapsed().as_secs_f64()
+            self.frames.len() as f64 / self.frames.front().unwrap().elapsed().as_secs_f64()
+        }
+    }
+
+    pub fn frame_time_summary(&self) -> Option<FrameTimeSummary> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+
+        let mut summary = FrameTimeSummary {
+            min: f32::MAX,
+            avg: 0.0,
+            max: 0.0,
+        };
+        for time in self.frame_times.iter() {
+            summary.min = summary.min.min(*time);
+            summary.max = summary.max.max(*time);
+            summary.avg += time;
+        }
+        summary.avg /= self.frame_times.len() as f32;
+        Some(summary)
+    }
+
+    /// A bar for each of the last frame times, newest on the right. Frames that took
+    /// more than twice the average are drawn in red, so stutters stand out.
+    pub fn draw_history(&self, ui: &mut Ui, height: f32) {
+        let size = Vec2::new(ui.available_width(), height);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter();
+
+        let Some(summary) = self.frame_time_summary() else {
+            return;
+        };
+        let scale = summary.max.max(MIN_GRAPH_MS);
+        let bar_width = rect.width() / FRAME_HISTORY as f32;
+
+        // The history fills up from the right while it has fewer frames than it can hold
+        let first = FRAME_HISTORY - self.frame_times.len();
+        for (i, time) in self.frame_times.iter().enumerate() {
+            let left = rect.left() + (first + i) as f32 * bar_width;
+            let top = rect.bottom() - rect.height() * (time / scale);
+            let bar = Rect::from_min_max(
+                egui::pos2(left, top),
+                egui::pos2(left + bar_width, rect.bottom()),
+            );
+
+            let color = if *time > summary.avg * 2.0 {
+                Color32::from_rgb(220, 60, 60)
+            } else {
+                Color32::from_gray(160)
+            };
+            painter.rect_filled(bar, 0.0, color);
         }
     }
 }
@@ -0,0 +1,220 @@
+use egui::{Color32, FontId, Rect, Stroke, Ui};
+
+use crate::midi::{MIDIColor, ProgramChange};
+
+/// The channel that General MIDI uses for percussion, where programs pick a drum kit
+const PERCUSSION_CHANNEL: u8 = 9;
+
+/// The names of the 128 General MIDI programs
+pub const GM_PROGRAM_NAMES: [&str; 128] = [
+    "Acoustic Grand Piano",
+    "Bright Acoustic Piano",
+    "Electric Grand Piano",
+    "Honky-tonk Piano",
+    "Electric Piano 1",
+    "Electric Piano 2",
+    "Harpsichord",
+    "Clavinet",
+    "Celesta",
+    "Glockenspiel",
+    "Music Box",
+    "Vibraphone",
+    "Marimba",
+    "Xylophone",
+    "Tubular Bells",
+    "Dulcimer",
+    "Drawbar Organ",
+    "Percussive Organ",
+    "Rock Organ",
+    "Church Organ",
+    "Reed Organ",
+    "Accordion",
+    "Harmonica",
+    "Tango Accordion",
+    "Acoustic Guitar (nylon)",
+    "Acoustic Guitar (steel)",
+    "Electric Guitar (jazz)",
+    "Electric Guitar (clean)",
+    "Electric Guitar (muted)",
+    "Overdriven Guitar",
+    "Distortion Guitar",
+    "Guitar Harmonics",
+    "Acoustic Bass",
+    "Electric Bass (finger)",
+    "Electric Bass (pick)",
+    "Fretless Bass",
+    "Slap Bass 1",
+    "Slap Bass 2",
+    "Synth Bass 1",
+    "Synth Bass 2",
+    "Violin",
+    "Viola",
+    "Cello",
+    "Contrabass",
+    "Tremolo Strings",
+    "Pizzicato Strings",
+    "Orchestral Harp",
+    "Timpani",
+    "String Ensemble 1",
+    "String Ensemble 2",
+    "Synth Strings 1",
+    "Synth Strings 2",
+    "Choir Aahs",
+    "Voice Oohs",
+    "Synth Voice",
+    "Orchestra Hit",
+    "Trumpet",
+    "Trombone",
+    "Tuba",
+    "Muted Trumpet",
+    "French Horn",
+    "Brass Section",
+    "Synth Brass 1",
+    "Synth Brass 2",
+    "Soprano Sax",
+    "Alto Sax",
+    "Tenor Sax",
+    "Baritone Sax",
+    "Oboe",
+    "English Horn",
+    "Bassoon",
+    "Clarinet",
+    "Piccolo",
+    "Flute",
+    "Recorder",
+    "Pan Flute",
+    "Blown Bottle",
+    "Shakuhachi",
+    "Whistle",
+    "Ocarina",
+    "Lead 1 (square)",
+    "Lead 2 (sawtooth)",
+    "Lead 3 (calliope)",
+    "Lead 4 (chiff)",
+    "Lead 5 (charang)",
+    "Lead 6 (voice)",
+    "Lead 7 (fifths)",
+    "Lead 8 (bass + lead)",
+    "Pad 1 (new age)",
+    "Pad 2 (warm)",
+    "Pad 3 (polysynth)",
+    "Pad 4 (choir)",
+    "Pad 5 (bowed)",
+    "Pad 6 (metallic)",
+    "Pad 7 (halo)",
+    "Pad 8 (sweep)",
+    "FX 1 (rain)",
+    "FX 2 (soundtrack)",
+    "FX 3 (crystal)",
+    "FX 4 (atmosphere)",
+    "FX 5 (brightness)",
+    "FX 6 (goblins)",
+    "FX 7 (echoes)",
+    "FX 8 (sci-fi)",
+    "Sitar",
+    "Banjo",
+    "Shamisen",
+    "Koto",
+    "Kalimba",
+    "Bagpipe",
+    "Fiddle",
+    "Shanai",
+    "Tinkle Bell",
+    "Agogo",
+    "Steel Drums",
+    "Woodblock",
+    "Taiko Drum",
+    "Melodic Tom",
+    "Synth Drum",
+    "Reverse Cymbal",
+    "Guitar Fret Noise",
+    "Breath Noise",
+    "Seashore",
+    "Bird Tweet",
+    "Telephone Ring",
+    "Helicopter",
+    "Applause",
+    "Gunshot",
+];
+
+/// The label of a program change, percussion programs pick a drum kit instead
+pub fn program_label(channel: u8, program: u8) -> String {
+    if channel == PERCUSSION_CHANNEL {
+        format!("Ch {}: Drum Kit {}", channel + 1, program + 1)
+    } else {
+        format!(
+            "Ch {}: {}",
+            channel + 1,
+            GM_PROGRAM_NAMES[program as usize & 0x7F]
+        )
+    }
+}
+
+/// Draws the name of the instrument of every program change on screen, at the edge
+/// where the notes come in from, lined up with where its notes would be. Labels that
+/// would overlap are moved next to each other.
+pub fn draw_instrument_labels(
+    ui: &Ui,
+    rect: Rect,
+    program_changes: &[ProgramChange],
+    time: f64,
+    range: f64,
+    reversed_length: Option<f64>,
+    waterfall: bool,
+) {
+    let painter = ui.painter().with_clip_rect(rect);
+    let font = FontId::proportional(11.0);
+
+    let mut last_label: Option<Rect> = None;
+    for change in program_changes {
+        // The events are stored in the order of the file, not of the playback
+        let change_time = match reversed_length {
+            Some(length) => length - change.time,
+            None => change.time,
+        };
+        let offset = ((change_time - time) / range) as f32;
+        if !(0.0..=1.0).contains(&offset) {
+            continue;
+        }
+
+        let color = MIDIColor::new_from_hue(change.channel as f64 * 360.0 / 16.0);
+        let color = Color32::from_rgb(color.red(), color.green(), color.blue());
+        let galley = painter.layout_no_wrap(
+            program_label(change.channel, change.program),
+            font.clone(),
+            Color32::WHITE,
+        );
+
+        // The notes move to the left in waterfall mode, so the labels sit along the bottom
+        let (anchor, line_start, line_end) = if waterfall {
+            let x = rect.left() + offset * rect.width();
+            (
+                egui::pos2(x + 2.0, rect.bottom() - galley.size().y - 2.0),
+                egui::pos2(x, rect.bottom() - galley.size().y - 4.0),
+                egui::pos2(x, rect.bottom()),
+            )
+        } else {
+            let y = rect.bottom() - offset * rect.height();
+            (
+                egui::pos2(rect.left() + 4.0, y - galley.size().y - 1.0),
+                egui::pos2(rect.left(), y),
+                egui::pos2(rect.left() + galley.size().x + 8.0, y),
+            )
+        };
+
+        let mut label = Rect::from_min_size(anchor, galley.size()).expand(2.0);
+        if let Some(last) = last_label.filter(|last| last.intersects(label)) {
+            label = label.translate(if waterfall {
+                egui::vec2(0.0, last.top() - label.bottom())
+            } else {
+                egui::vec2(last.right() - label.left() + 2.0, 0.0)
+            });
+        }
+
+        painter.line_segment([line_start, line_end], Stroke::new(1.0, color));
+        painter.rect_filled(label, 2.0, Color32::from_black_alpha(170));
+        painter.galley(label.min + egui::vec2(2.0, 2.0), galley);
+        painter.rect_stroke(label, 2.0, Stroke::new(1.0, color));
+        last_label = Some(label);
+    }
+}
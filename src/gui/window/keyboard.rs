@@ -1,44 +1,279 @@
-use egui::{Color32, Mesh, Pos2, Rect, Sense, Ui};
+use egui::{
+    vec2, Color32, Event, FontId, Mesh, PointerButton, Pos2, Rect, Sense, TouchId, TouchPhase, Ui,
+};
 
-use crate::midi::MIDIColor;
+use crate::{
+    midi::MIDIColor,
+    settings::{KeyLabelMode, VisualSettings},
+};
 
-use super::keyboard_layout::KeyboardView;
+use super::{keyboard_layout::KeyboardView, note_inspector::pitch_name};
 
-pub struct GuiKeyboard {}
+/// The largest size of the key labels, they are shrunk to fit narrower keys
+const LABEL_SIZE: f32 = 12.0;
+
+/// Labels that would have to be smaller than this to fit aren't drawn
+const MIN_LABEL_SIZE: f32 = 7.0;
+
+/// How far down the keys the colored bar reaches, clicks on it don't press a key
+const BAR_LENGTH: f32 = 0.06;
+
+/// The names of the notes with the black keys as flats
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+
+/// The label of a key, which only differs between the modes on the black keys
+fn key_label(mode: KeyLabelMode, key: usize) -> String {
+    match mode {
+        KeyLabelMode::All => format!("{}{}", FLAT_NAMES[key % 12], key as i32 / 12 - 1),
+        _ => pitch_name(key as i32),
+    }
+}
+
+/// A key that was pressed or released by clicking or touching the keyboard
+#[derive(Debug, Clone, Copy)]
+pub struct KeyClick {
+    pub key: u8,
+    pub down: bool,
+}
+
+/// The colors the keys are drawn with, the shading of the keys is derived from them
+#[derive(Debug, Clone, Copy)]
+pub struct KeyboardTheme {
+    pub white: Color32,
+    pub black: Color32,
+    /// Multiplied with the color of the note that presses a white key
+    pub white_pressed: Color32,
+    /// Multiplied with the color of the note that presses a black key
+    pub black_pressed: Color32,
+}
+
+impl KeyboardTheme {
+    pub fn from_settings(visual: &VisualSettings) -> Self {
+        Self {
+            white: visual.keyboard_white_key_color,
+            black: visual.keyboard_black_key_color,
+            white_pressed: visual.keyboard_white_key_pressed_color,
+            black_pressed: visual.keyboard_black_key_pressed_color,
+        }
+    }
+}
+
+pub struct GuiKeyboard {
+    /// Which keys get their note name written on them
+    pub key_labels: KeyLabelMode,
+    /// Whether the keys can be clicked to play them
+    pub clickable: bool,
+    /// How far down the keys the black keys reach, as a part of the keyboard height
+    pub black_key_length: f32,
+    /// The keys held by the mouse, which has no touch id, and by each finger
+    held: Vec<(Option<TouchId>, u8)>,
+}
 
 impl GuiKeyboard {
     pub fn new() -> GuiKeyboard {
-        GuiKeyboard {}
+        GuiKeyboard {
+            key_labels: KeyLabelMode::None,
+            clickable: false,
+            black_key_length: 0.66,
+            held: Vec::new(),
+        }
     }
 
+    /// Draws the keyboard into the space left in the ui, and returns the keys that were
+    /// pressed and released by clicking them since the last draw
     pub fn draw(
         &mut self,
         ui: &mut Ui,
         key_view: &KeyboardView,
         colors: &[Option<MIDIColor>],
         bar_color: &Color32,
-    ) {
+        theme: &KeyboardTheme,
+        vertical: bool,
+    ) -> Vec<KeyClick> {
         let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::click());
+
+        let mut clicks = Vec::new();
+        if self.clickable {
+            self.update_held_keys(ui, rect, key_view, vertical, &mut clicks);
+        } else {
+            for (_, key) in self.held.drain(..) {
+                clicks.push(KeyClick { key, down: false });
+            }
+        }
+
+        if self.held.is_empty() {
+            self.paint(ui, rect, key_view, colors, bar_color, theme, vertical);
+        } else {
+            // The held keys look pressed in the color of the bar
+            let mut colors = colors.to_vec();
+            for (_, key) in self.held.iter() {
+                colors[*key as usize] =
+                    Some(MIDIColor::new(bar_color.r(), bar_color.g(), bar_color.b()));
+            }
+            self.paint(ui, rect, key_view, &colors, bar_color, theme, vertical);
+        }
+
+        clicks
+    }
+
+    /// Presses the keys under the mouse and the fingers that went down on the keyboard,
+    /// and releases them again when they are lifted. Sliding onto another key releases
+    /// the old key and presses the new one.
+    fn update_held_keys(
+        &mut self,
+        ui: &Ui,
+        rect: Rect,
+        key_view: &KeyboardView,
+        vertical: bool,
+        clicks: &mut Vec<KeyClick>,
+    ) {
+        // egui also moves the mouse pointer along with the first finger, which is
+        // already handled by its touch events
+        let (events, touching) = ui.input(|i| (i.events.clone(), i.any_touches()));
+        let on_keyboard =
+            |pos: Pos2| rect.contains(pos) && ui.ctx().layer_id_at(pos) == Some(ui.layer_id());
+
+        for event in events {
+            let (pointer, pos, phase) = match event {
+                Event::PointerButton {
+                    pos,
+                    button: PointerButton::Primary,
+                    pressed,
+                    ..
+                } if !touching => (
+                    None,
+                    pos,
+                    if pressed {
+                        TouchPhase::Start
+                    } else {
+                        TouchPhase::End
+                    },
+                ),
+                Event::PointerMoved(pos) if !touching => (None, pos, TouchPhase::Move),
+                Event::Touch { id, phase, pos, .. } => (Some(id), pos, phase),
+                _ => continue,
+            };
+
+            let key = match phase {
+                TouchPhase::Start if on_keyboard(pos) => self.key_at(pos, rect, key_view, vertical),
+                TouchPhase::Move if self.held.iter().any(|(held, _)| *held == pointer) => {
+                    self.key_at(pos, rect, key_view, vertical)
+                }
+                TouchPhase::Start | TouchPhase::Move => continue,
+                TouchPhase::End | TouchPhase::Cancel => None,
+            };
+            self.hold(pointer, key, clicks);
+        }
+    }
+
+    /// Moves the mouse or a finger onto a key, releasing the key it held before
+    fn hold(&mut self, pointer: Option<TouchId>, key: Option<u8>, clicks: &mut Vec<KeyClick>) {
+        let held = self.held.iter().position(|(held, _)| *held == pointer);
+        if held.map(|index| self.held[index].1) == key {
+            return;
+        }
+
+        if let Some(index) = held {
+            let (_, key) = self.held.swap_remove(index);
+            clicks.push(KeyClick { key, down: false });
+        }
+        if let Some(key) = key {
+            self.held.push((pointer, key));
+            clicks.push(KeyClick { key, down: true });
+        }
+    }
+
+    /// The key under a position, with the black keys over the white ones
+    fn key_at(&self, pos: Pos2, rect: Rect, key_view: &KeyboardView, vertical: bool) -> Option<u8> {
+        // How far along the keys and how far down from their back the position is,
+        // a vertical keyboard has its lowest key at the bottom and its back on the right
+        let (along, depth) = if vertical {
+            (
+                (rect.bottom() - pos.y) / rect.height(),
+                (rect.right() - pos.x) / rect.width(),
+            )
+        } else {
+            (
+                (pos.x - rect.left()) / rect.width(),
+                (pos.y - rect.top()) / rect.height(),
+            )
+        };
+        if depth < BAR_LENGTH {
+            return None;
+        }
+
+        let find = |black: bool| {
+            key_view
+                .iter_visible_keys()
+                .find(|(_, key)| key.black == black && key.left <= along && along < key.right)
+                .and_then(|(i, _)| u8::try_from(i).ok())
+        };
+        if depth < self.black_key_length {
+            find(true).or_else(|| find(false))
+        } else {
+            find(false)
+        }
+    }
+
+    /// Paints the keyboard into a rect without allocating any space for it.
+    /// A vertical keyboard has its lowest key at the bottom and the top of its keys facing right.
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint(
+        &mut self,
+        ui: &Ui,
+        rect: Rect,
+        key_view: &KeyboardView,
+        colors: &[Option<MIDIColor>],
+        bar_color: &Color32,
+        theme: &KeyboardTheme,
+        vertical: bool,
+    ) {
+        // Vertical keyboards are laid out horizontally first and turned onto their side at the end
+        let target = rect;
+        let rect = if vertical {
+            Rect::from_min_size(Pos2::ZERO, vec2(rect.height(), rect.width()))
+        } else {
+            rect
+        };
+
         let mut mesh = Mesh::default();
         let key_density =
             ((rect.width() / key_view.visible_range.len() as f32) / 15.0).clamp(1.0, 5.0);
         let onepx = ui.painter().round_to_pixel(key_density);
 
         let md_height = rect.height() * 0.048;
-        let bar = rect.height() * 0.06;
+        let bar = rect.height() * BAR_LENGTH;
 
         let black_key_overlap = bar / 2.35;
         let top = rect.top() + bar;
         let bottom = rect.bottom();
-        let black_bottom = rect.bottom() - rect.height() * 0.34;
+        let black_bottom = rect.top() + rect.height() * self.black_key_length;
         let map_x = |num: f32| rect.left() + num * rect.width();
         fn map_color(col: MIDIColor) -> Color32 {
             Color32::from_rgb(col.red(), col.green(), col.blue())
         }
 
+        // The shades were picked for white keys, so they are scaled to the key color
+        let white_shade = |level: u8| scale(theme.white, level as f32 / 255.0);
+
+        // The shades were picked for black keys with a level of 20, and the lighter
+        // ones are mixed towards white by the same amount from the key color
+        let black_shade = |level: u8| {
+            let amount = level.saturating_sub(20) as f32 / 235.0;
+            let mix = |channel: u8| channel as f32 + (255.0 - channel as f32) * amount;
+            Color32::from_rgb(
+                mix(theme.black.r()) as u8,
+                mix(theme.black.g()) as u8,
+                mix(theme.black.b()) as u8,
+            )
+        };
+
         for (i, key) in key_view.iter_visible_keys() {
             if !key.black {
                 if let Some(color) = colors[i].map(map_color) {
+                    let color = tint(color, theme.white_pressed);
                     // Pressed
                     let darkened = Color32::from_rgb(
                         (color.r() as f32 * 0.6) as u8,
@@ -89,75 +324,63 @@ impl GuiKeyboard {
                 } else {
                     // Not pressed
                     add_rect_triangles(&mut mesh);
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.left), top),
-                        Color32::from_rgb(110, 110, 110),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.right), top),
-                        Color32::from_rgb(110, 110, 110),
-                    );
+                    mesh.colored_vertex(Pos2::new(map_x(key.left), top), white_shade(110));
+                    mesh.colored_vertex(Pos2::new(map_x(key.right), top), white_shade(110));
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left), top + black_key_overlap),
-                        Color32::from_rgb(210, 210, 210),
+                        white_shade(210),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.right), top + black_key_overlap),
-                        Color32::from_rgb(210, 210, 210),
+                        white_shade(210),
                     );
 
                     add_rect_triangles(&mut mesh);
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left), top + black_key_overlap),
-                        Color32::from_rgb(210, 210, 210),
+                        white_shade(210),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.right), top + black_key_overlap),
-                        Color32::from_rgb(210, 210, 210),
+                        white_shade(210),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left), bottom - md_height),
-                        Color32::WHITE,
+                        white_shade(255),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.right), bottom - md_height),
-                        Color32::WHITE,
+                        white_shade(255),
                     );
 
                     add_rect_triangles(&mut mesh);
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left), bottom - md_height),
-                        Color32::from_rgb(190, 190, 190),
+                        white_shade(190),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.right), bottom - md_height),
-                        Color32::from_rgb(190, 190, 190),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.left), bottom),
-                        Color32::from_rgb(120, 120, 120),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.right), bottom),
-                        Color32::from_rgb(120, 120, 120),
+                        white_shade(190),
                     );
+                    mesh.colored_vertex(Pos2::new(map_x(key.left), bottom), white_shade(120));
+                    mesh.colored_vertex(Pos2::new(map_x(key.right), bottom), white_shade(120));
 
                     add_rect_triangles(&mut mesh);
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left), bottom - md_height),
-                        Color32::from_rgb(70, 70, 70),
+                        white_shade(70),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.right), bottom - md_height),
-                        Color32::from_rgb(70, 70, 70),
+                        white_shade(70),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left), bottom - md_height + key_density * 2.0),
-                        Color32::from_rgb(140, 140, 140),
+                        white_shade(140),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.right), bottom - md_height + key_density * 2.0),
-                        Color32::from_rgb(140, 140, 140),
+                        white_shade(140),
                     );
                 }
                 // White key borders
@@ -209,6 +432,7 @@ impl GuiKeyboard {
         for (i, key) in key_view.iter_visible_keys() {
             if key.black {
                 if let Some(color) = colors[i].map(map_color) {
+                    let color = tint(color, theme.black_pressed);
                     // Pressed
                     let darkened = Color32::from_rgb(
                         (color.r() as f32 * 0.76) as u8,
@@ -301,40 +525,28 @@ impl GuiKeyboard {
                     add_rect_triangles(&mut mesh);
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left) + key_density, black_bottom - md_height),
-                        Color32::from_rgb(105, 105, 105),
+                        black_shade(105),
                     );
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.right) - key_density, black_bottom - md_height),
-                        Color32::from_rgb(105, 105, 105),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.left), black_bottom),
-                        Color32::from_rgb(20, 20, 20),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.right), black_bottom),
-                        Color32::from_rgb(20, 20, 20),
+                        black_shade(105),
                     );
+                    mesh.colored_vertex(Pos2::new(map_x(key.left), black_bottom), black_shade(20));
+                    mesh.colored_vertex(Pos2::new(map_x(key.right), black_bottom), black_shade(20));
 
                     add_rect_triangles(&mut mesh);
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.left), top),
-                        Color32::from_rgb(20, 20, 20),
-                    );
+                    mesh.colored_vertex(Pos2::new(map_x(key.left), top), black_shade(20));
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left) + 2.0 * key_density, top - black_key_overlap),
-                        Color32::from_rgb(105, 105, 105),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.left), black_bottom),
-                        Color32::from_rgb(20, 20, 20),
+                        black_shade(105),
                     );
+                    mesh.colored_vertex(Pos2::new(map_x(key.left), black_bottom), black_shade(20));
                     mesh.colored_vertex(
                         Pos2::new(
                             map_x(key.left) + 2.0 * key_density,
                             black_bottom - md_height,
                         ),
-                        Color32::from_rgb(105, 105, 105),
+                        black_shade(105),
                     );
 
                     add_rect_triangles(&mut mesh);
@@ -343,58 +555,172 @@ impl GuiKeyboard {
                             map_x(key.right) - 2.0 * key_density,
                             top - black_key_overlap,
                         ),
-                        Color32::from_rgb(105, 105, 105),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.right), top),
-                        Color32::from_rgb(20, 20, 20),
+                        black_shade(105),
                     );
+                    mesh.colored_vertex(Pos2::new(map_x(key.right), top), black_shade(20));
                     mesh.colored_vertex(
                         Pos2::new(
                             map_x(key.right) - 2.0 * key_density,
                             black_bottom - md_height,
                         ),
-                        Color32::from_rgb(105, 105, 105),
-                    );
-                    mesh.colored_vertex(
-                        Pos2::new(map_x(key.right), black_bottom),
-                        Color32::from_rgb(20, 20, 20),
+                        black_shade(105),
                     );
+                    mesh.colored_vertex(Pos2::new(map_x(key.right), black_bottom), black_shade(20));
 
                     add_rect_triangles(&mut mesh);
                     mesh.colored_vertex(
                         Pos2::new(map_x(key.left) + 2.0 * key_density, top - black_key_overlap),
-                        Color32::from_rgb(20, 20, 20),
+                        black_shade(20),
                     );
                     mesh.colored_vertex(
                         Pos2::new(
                             map_x(key.right) - 2.0 * key_density,
                             top - black_key_overlap,
                         ),
-                        Color32::from_rgb(20, 20, 20),
+                        black_shade(20),
                     );
                     mesh.colored_vertex(
                         Pos2::new(
                             map_x(key.left) + 2.0 * key_density,
                             black_bottom - md_height,
                         ),
-                        Color32::from_rgb(40, 40, 40),
+                        black_shade(40),
                     );
                     mesh.colored_vertex(
                         Pos2::new(
                             map_x(key.right) - 2.0 * key_density,
                             black_bottom - md_height,
                         ),
-                        Color32::from_rgb(40, 40, 40),
+                        black_shade(40),
                     );
                 }
             }
         }
 
+        if vertical {
+            for vertex in mesh.vertices.iter_mut() {
+                vertex.pos = Pos2::new(
+                    target.right() - vertex.pos.y,
+                    target.bottom() - vertex.pos.x,
+                );
+            }
+        }
+
         ui.painter().add(mesh);
+
+        // The labels stay upright on a vertical keyboard, where they are fit into the
+        // height of the keys instead of their width
+        for (i, key) in key_view.iter_visible_keys() {
+            if !self.key_labels.labels(i) {
+                continue;
+            }
+
+            let (color, label_bottom) = if key.black {
+                (Color32::from_gray(200), black_bottom - md_height)
+            } else {
+                (Color32::from_gray(60), bottom - md_height)
+            };
+            let galley = ui.painter().layout_no_wrap(
+                key_label(self.key_labels, i),
+                FontId::proportional(LABEL_SIZE),
+                color,
+            );
+            let fit = if vertical {
+                galley.size().y
+            } else {
+                galley.size().x
+            };
+            let key_width = (key.right - key.left) * rect.width() - 2.0;
+            let size = (LABEL_SIZE * key_width / fit).min(LABEL_SIZE);
+            if size < MIN_LABEL_SIZE {
+                continue;
+            }
+            let galley = if size < LABEL_SIZE {
+                ui.painter().layout_no_wrap(
+                    key_label(self.key_labels, i),
+                    FontId::proportional(size),
+                    color,
+                )
+            } else {
+                galley
+            };
+
+            let anchor = Pos2::new(map_x((key.left + key.right) / 2.0), label_bottom);
+            let pos = if vertical {
+                Pos2::new(
+                    target.right() - anchor.y + 2.0,
+                    target.bottom() - anchor.x - galley.size().y / 2.0,
+                )
+            } else {
+                Pos2::new(
+                    anchor.x - galley.size().x / 2.0,
+                    anchor.y - galley.size().y - 2.0,
+                )
+            };
+            ui.painter().galley(pos, galley);
+        }
     }
 }
 
+/// Colors every key by how many notes it has in the whole MIDI, along the gradient from
+/// the key with the fewest notes to the one with the most. The counts are compared on a
+/// log scale, since a few keys usually have far more notes than the rest.
+pub fn heatmap_colors(key_counts: &[u64], gradient: &[Color32]) -> Vec<Option<MIDIColor>> {
+    let max = key_counts.iter().copied().max().unwrap_or(0);
+    let scale = ((max + 1) as f32).ln();
+
+    let mut colors = vec![None; 256];
+    for (color, count) in colors.iter_mut().zip(key_counts) {
+        let heat = if max > 0 {
+            ((count + 1) as f32).ln() / scale
+        } else {
+            0.0
+        };
+        let heat_color = sample_gradient(gradient, heat);
+        *color = Some(MIDIColor::new(
+            heat_color.r(),
+            heat_color.g(),
+            heat_color.b(),
+        ));
+    }
+    colors
+}
+
+fn sample_gradient(gradient: &[Color32], position: f32) -> Color32 {
+    match gradient {
+        [] => Color32::WHITE,
+        [color] => *color,
+        _ => {
+            let position = position.clamp(0.0, 1.0) * (gradient.len() - 1) as f32;
+            let index = (position as usize).min(gradient.len() - 2);
+            let amount = position - index as f32;
+            let (from, to) = (gradient[index], gradient[index + 1]);
+            let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount) as u8;
+            Color32::from_rgb(
+                mix(from.r(), to.r()),
+                mix(from.g(), to.g()),
+                mix(from.b(), to.b()),
+            )
+        }
+    }
+}
+
+fn scale(color: Color32, factor: f32) -> Color32 {
+    Color32::from_rgb(
+        (color.r() as f32 * factor) as u8,
+        (color.g() as f32 * factor) as u8,
+        (color.b() as f32 * factor) as u8,
+    )
+}
+
+fn tint(color: Color32, tint: Color32) -> Color32 {
+    Color32::from_rgb(
+        (color.r() as u16 * tint.r() as u16 / 255) as u8,
+        (color.g() as u16 * tint.g() as u16 / 255) as u8,
+        (color.b() as u16 * tint.b() as u16 / 255) as u8,
+    )
+}
+
 fn add_rect_triangles(mesh: &mut Mesh) {
     let idx = mesh.vertices.len() as u32;
     mesh.add_triangle(idx, idx + 1, idx + 2);
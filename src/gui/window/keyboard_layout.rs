@@ -12,16 +12,23 @@ pub enum KeyboardParams {
     },
 }
 
-impl Default for KeyboardParams {
-    fn default() -> Self {
+impl KeyboardParams {
+    /// The classic layout with black keys of the given width relative to the white keys
+    pub fn classic(black_key_scale: f32) -> Self {
         KeyboardParams::Classic {
             black_key_2_set_offset: 0.35,
             black_key_3_set_offset: 0.45,
-            black_key_scale: 0.74,
+            black_key_scale,
         }
     }
 }
 
+impl Default for KeyboardParams {
+    fn default() -> Self {
+        KeyboardParams::classic(0.74)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct KeyPosition {
     pub black: bool,
@@ -40,6 +47,7 @@ impl KeyPosition {
 }
 
 pub struct KeyboardLayout {
+    params: KeyboardParams,
     keys: [KeyPosition; 257],
     notes: [KeyPosition; 257],
 }
@@ -155,7 +163,16 @@ impl KeyboardLayout {
             }
         }
 
-        KeyboardLayout { keys, notes }
+        KeyboardLayout {
+            params: params.clone(),
+            keys,
+            notes,
+        }
+    }
+
+    /// The params the layout was made with
+    pub fn params(&self) -> &KeyboardParams {
+        &self.params
     }
 
     pub fn get_range_for_keys(&self, first_key: usize, last_key: usize) -> KeyboardRange {
@@ -182,6 +199,7 @@ impl KeyboardLayout {
             layout: self,
             range,
             visible_range: left_key..right_key,
+            inverted: false,
         }
     }
 
@@ -212,6 +230,7 @@ impl KeyboardLayout {
             layout: self,
             range,
             visible_range: left_key..right_key,
+            inverted: false,
         }
     }
 }
@@ -236,25 +255,42 @@ pub struct KeyboardView<'a> {
     layout: &'a KeyboardLayout,
     pub range: KeyboardRange,
     pub visible_range: Range<usize>,
+    /// Whether the keys are mirrored, so the lowest one is on the right
+    inverted: bool,
 }
 
 impl<'a> KeyboardView<'a> {
-    pub fn key(&self, key: usize) -> KeyPosition {
-        let key = self.layout.keys[key];
-        KeyPosition {
-            black: key.black,
-            left: self.range.transform(key.left),
-            right: self.range.transform(key.right),
+    /// Mirrors the keys, which flips the keyboard and the notes together since they
+    /// are all placed by the positions of the view
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    fn position(&self, position: KeyPosition) -> KeyPosition {
+        let left = self.range.transform(position.left);
+        let right = self.range.transform(position.right);
+        if self.inverted {
+            KeyPosition {
+                black: position.black,
+                left: 1.0 - right,
+                right: 1.0 - left,
+            }
+        } else {
+            KeyPosition {
+                black: position.black,
+                left,
+                right,
+            }
         }
     }
 
+    pub fn key(&self, key: usize) -> KeyPosition {
+        self.position(self.layout.keys[key])
+    }
+
     pub fn note(&self, key: usize) -> KeyPosition {
-        let note = self.layout.notes[key];
-        KeyPosition {
-            black: note.black,
-            left: self.range.transform(note.left),
-            right: self.range.transform(note.right),
-        }
+        self.position(self.layout.notes[key])
     }
 
     pub fn iter_visible_keys(&self) -> impl '_ + Iterator<Item = (usize, KeyPosition)> {
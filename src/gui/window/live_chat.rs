@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use crate::{midi::MIDIColor, notifications};
+
+/// The most viewers that get a color, the ones after that are ignored
+const MAX_VIEWERS: usize = 4096;
+
+/// The colors that viewers picked with `!color <hex>`. Every viewer gets a slot in the
+/// order they first picked a color, and slot N colors the notes of the N-th channel of
+/// the MIDI, counting 16 channels per track like the track colors do.
+#[derive(Default)]
+pub struct ViewerColors {
+    colors: HashMap<String, u32>,
+    slots: Vec<String>,
+}
+
+impl ViewerColors {
+    #[cfg_attr(not(feature = "live-chat"), allow(dead_code))]
+    fn set(&mut self, viewer: &str, color: u32) {
+        if !self.colors.contains_key(viewer) {
+            if self.slots.len() >= MAX_VIEWERS {
+                return;
+            }
+            self.slots.push(viewer.to_owned());
+        }
+        self.colors.insert(viewer.to_owned(), color);
+    }
+
+    /// The color of every slot, indexed like the track colors
+    pub fn slot_colors(&self) -> Vec<Option<MIDIColor>> {
+        self.slots
+            .iter()
+            .map(|viewer| self.colors.get(viewer).copied().map(MIDIColor::from_u32))
+            .collect()
+    }
+}
+
+/// Reads the chat of a Twitch channel on a separate thread and keeps the colors the
+/// viewers picked. The thread disconnects once this is dropped.
+#[cfg_attr(not(feature = "live-chat"), allow(dead_code))]
+pub struct LiveChat {
+    colors: Arc<RwLock<ViewerColors>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl LiveChat {
+    #[cfg(feature = "live-chat")]
+    pub fn connect(channel: &str) -> Option<Self> {
+        let colors = Arc::new(RwLock::new(ViewerColors::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let channel = channel.trim_start_matches('#').to_lowercase();
+        let colors_clone = colors.clone();
+        let stop_clone = stop.clone();
+        std::thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    notifications::error(format!("Failed to start the live chat: {}", e));
+                    return;
+                }
+            };
+            runtime.block_on(twitch::read_chat(channel, colors_clone, stop_clone));
+        });
+
+        Some(Self { colors, stop })
+    }
+
+    #[cfg(not(feature = "live-chat"))]
+    pub fn connect(_channel: &str) -> Option<Self> {
+        notifications::error(
+            "Live chat colors need Wasabi to be built with the `live-chat` feature",
+        );
+        None
+    }
+
+    pub fn slot_colors(&self) -> Vec<Option<MIDIColor>> {
+        self.colors.read().unwrap().slot_colors()
+    }
+}
+
+impl Drop for LiveChat {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The viewer and the color of a `!color <hex>` command in an IRC line, the `#` in
+/// front of the color is optional
+#[cfg_attr(not(feature = "live-chat"), allow(dead_code))]
+fn parse_color_command(line: &str) -> Option<(&str, u32)> {
+    // :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #channel :!color #ff8000
+    let line = line.strip_prefix(':')?;
+    let (viewer, rest) = line.split_once('!')?;
+    let (_, message) = rest.split_once(" PRIVMSG ")?.1.split_once(" :")?;
+
+    let color = message.trim().strip_prefix("!color ")?.trim();
+    let color = color.strip_prefix('#').unwrap_or(color);
+    if color.len() != 6 {
+        return None;
+    }
+    let color = u32::from_str_radix(color, 16).ok()?;
+
+    Some((viewer, color))
+}
+
+#[cfg(feature = "live-chat")]
+mod twitch {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, RwLock,
+        },
+        time::Duration,
+    };
+
+    use futures_util::{SinkExt, StreamExt};
+    use rand::Rng;
+    use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+    use crate::notifications;
+
+    use super::{parse_color_command, ViewerColors};
+
+    /// The IRC interface of the Twitch chat over a WebSocket
+    const TWITCH_CHAT_URL: &str = "wss://irc-ws.chat.twitch.tv:443";
+
+    /// How often the stop flag is checked while no messages arrive
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// How long to wait before connecting again after the connection was lost
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+    /// Reads the chat until the stop flag is set, connecting again whenever the
+    /// connection is lost
+    pub async fn read_chat(
+        channel: String,
+        colors: Arc<RwLock<ViewerColors>>,
+        stop: Arc<AtomicBool>,
+    ) {
+        while !stop.load(Ordering::Relaxed) {
+            if let Err(e) = read_until_closed(&channel, &colors, &stop).await {
+                notifications::error(format!("Lost the connection to the Twitch chat: {}", e));
+            }
+
+            let mut waited = Duration::ZERO;
+            while waited < RECONNECT_DELAY && !stop.load(Ordering::Relaxed) {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                waited += POLL_INTERVAL;
+            }
+        }
+    }
+
+    async fn read_until_closed(
+        channel: &str,
+        colors: &RwLock<ViewerColors>,
+        stop: &AtomicBool,
+    ) -> Result<(), String> {
+        let (mut socket, _) = connect_async(TWITCH_CHAT_URL)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Anonymous users can read the chat with any "justinfan" name
+        let nick = format!("justinfan{}", rand::thread_rng().gen_range(10000..99999));
+        for command in [format!("NICK {}", nick), format!("JOIN #{}", channel)] {
+            socket
+                .send(Message::Text(command))
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        while !stop.load(Ordering::Relaxed) {
+            let message = match tokio::time::timeout(POLL_INTERVAL, socket.next()).await {
+                Ok(Some(message)) => message.map_err(|e| e.to_string())?,
+                Ok(None) => return Err("The server closed the connection".to_owned()),
+                Err(_) => continue,
+            };
+
+            let Message::Text(text) = message else {
+                continue;
+            };
+
+            // A message can hold several IRC lines
+            for line in text.lines() {
+                if let Some(server) = line.strip_prefix("PING ") {
+                    socket
+                        .send(Message::Text(format!("PONG {}", server)))
+                        .await
+                        .map_err(|e| e.to_string())?;
+                } else if let Some((viewer, color)) = parse_color_command(line) {
+                    colors.write().unwrap().set(viewer, color);
+                }
+            }
+        }
+
+        socket.close(None).await.ok();
+        Ok(())
+    }
+}
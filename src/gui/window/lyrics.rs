@@ -0,0 +1,95 @@
+use egui::{Color32, FontId, Rect, Ui};
+
+use crate::{midi::LyricLine, settings::LyricsPosition};
+
+/// How long before it is sung a line is shown, in seconds
+const LINE_PREVIEW: f64 = 3.0;
+
+/// The space around the lines in pixels
+const MARGIN: f32 = 12.0;
+
+/// The colors and size of the lyrics
+pub struct LyricsStyle {
+    pub font_size: f32,
+    pub color: Color32,
+    pub highlight_color: Color32,
+    pub position: LyricsPosition,
+}
+
+/// Draws the line of the lyrics being sung with the line after it below, dimmed. The
+/// syllables fill up with the highlight color from left to right while they are sung.
+pub fn draw_lyrics(ui: &Ui, rect: Rect, lines: &[LyricLine], time: f64, style: &LyricsStyle) {
+    let index = lines.partition_point(|line| line.end <= time);
+    let Some(current) = lines
+        .get(index)
+        .filter(|line| line.start - time < LINE_PREVIEW)
+    else {
+        return;
+    };
+    let next = lines
+        .get(index + 1)
+        .filter(|line| line.start - time < LINE_PREVIEW * 2.0);
+
+    let painter = ui.painter().with_clip_rect(rect);
+    let font = FontId::proportional(style.font_size);
+    let small_font = FontId::proportional(style.font_size * 0.75);
+
+    let line_height = style.font_size * 1.3;
+    let height = line_height + next.map_or(0.0, |_| style.font_size * 0.75 * 1.3);
+    let top = match style.position {
+        LyricsPosition::Top => rect.top() + MARGIN,
+        LyricsPosition::Center => rect.center().y - height / 2.0,
+        LyricsPosition::Bottom => rect.bottom() - MARGIN - height,
+    };
+
+    // Each syllable is laid out on its own, so it can be highlighted on its own
+    let galleys: Vec<_> = current
+        .syllables
+        .iter()
+        .map(|syllable| {
+            let base = painter.layout_no_wrap(syllable.text.clone(), font.clone(), style.color);
+            let sung =
+                painter.layout_no_wrap(syllable.text.clone(), font.clone(), style.highlight_color);
+            (syllable, base, sung)
+        })
+        .collect();
+    let width: f32 = galleys.iter().map(|(_, base, _)| base.size().x).sum();
+
+    let next = next.map(|next| {
+        let text: String = next
+            .syllables
+            .iter()
+            .map(|syllable| syllable.text.as_str())
+            .collect();
+        painter.layout_no_wrap(text, small_font, style.color.linear_multiply(0.6))
+    });
+    let background_width = next.as_ref().map_or(width, |next| width.max(next.size().x));
+
+    let background = Rect::from_center_size(
+        egui::pos2(rect.center().x, top + height / 2.0),
+        egui::vec2(background_width + MARGIN * 2.0, height + MARGIN),
+    );
+    painter.rect_filled(background, 6.0, Color32::from_black_alpha(150));
+
+    let mut left = rect.center().x - width / 2.0;
+    for (syllable, base, sung) in galleys {
+        let pos = egui::pos2(left, top);
+        let size = base.size();
+        painter.galley(pos, base);
+
+        let progress =
+            ((time - syllable.start) / (syllable.end - syllable.start)).clamp(0.0, 1.0) as f32;
+        if progress > 0.0 {
+            let sung_rect = Rect::from_min_size(pos, egui::vec2(size.x * progress, size.y));
+            painter
+                .with_clip_rect(sung_rect.intersect(rect))
+                .galley(pos, sung);
+        }
+        left += size.x;
+    }
+
+    if let Some(next) = next {
+        let pos = egui::pos2(rect.center().x - next.size().x / 2.0, top + line_height);
+        painter.galley(pos, next);
+    }
+}
@@ -0,0 +1,220 @@
+use std::ops::RangeInclusive;
+
+use egui::{Align2, Context};
+
+use crate::{
+    gui::window::GuiWasabiWindow,
+    midi::{MIDIFileBase, MIDIFileMetadata},
+    state::WasabiState,
+};
+
+fn format_time(secs: f64) -> String {
+    format!(
+        "{:02}:{:02}.{}",
+        secs as u64 / 60,
+        secs as u64 % 60,
+        (secs * 10.0) as u64 % 10
+    )
+}
+
+fn format_size(bytes: u64) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1024.0 * 1024.0 {
+        format!("{:.2} MB", bytes / 1024.0 / 1024.0)
+    } else {
+        format!("{:.2} KB", bytes / 1024.0)
+    }
+}
+
+pub fn draw_midi_info(win: &mut GuiWasabiWindow, state: &mut WasabiState, ctx: &Context) {
+    let metadata = win.midi_file.as_ref().map(|midi_file| midi_file.metadata());
+    let mut export_clicked = false;
+    let mut validate_clicked = false;
+    let mut tempo_override = win.tempo_override;
+
+    egui::Window::new("MIDI Info")
+        .resizable(true)
+        .collapsible(false)
+        .title_bar(true)
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .scroll2([false, true])
+        .open(&mut state.midi_info_visible)
+        .show(ctx, |ui| match metadata {
+            Some(metadata) => {
+                draw_metadata(ui, &metadata);
+                if metadata.scanned {
+                    ui.separator();
+                    draw_tempo_override(ui, &metadata, &mut tempo_override);
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Export MusicXML...").clicked() {
+                        export_clicked = true;
+                    }
+                    let validating = win.pending_sop.is_some();
+                    let label = if validating {
+                        "Validating..."
+                    } else {
+                        "Validate SOP"
+                    };
+                    if ui
+                        .add_enabled(!validating, egui::Button::new(label))
+                        .on_hover_text(
+                            "Checks the file for events that break the usual rules of MIDI files",
+                        )
+                        .clicked()
+                    {
+                        validate_clicked = true;
+                    }
+                });
+            }
+            None => {
+                ui.label("No MIDI loaded");
+            }
+        });
+
+    if export_clicked {
+        win.open_musicxml_dialog();
+    }
+    if tempo_override != win.tempo_override {
+        win.set_tempo_override(tempo_override);
+    }
+    if validate_clicked {
+        win.validate_sop();
+    }
+}
+
+/// Plays the MIDI at another tempo, like the detected one for files that have the wrong
+/// tempo or none at all
+fn draw_tempo_override(
+    ui: &mut egui::Ui,
+    metadata: &MIDIFileMetadata,
+    tempo_override: &mut Option<f64>,
+) {
+    let encoded = metadata.bpm_at(0.0);
+    ui.horizontal(|ui| {
+        let mut enabled = tempo_override.is_some();
+        if ui.checkbox(&mut enabled, "Override Tempo").changed() {
+            *tempo_override = enabled.then(|| metadata.detected_bpm.unwrap_or(encoded));
+        }
+
+        if let Some(bpm) = tempo_override {
+            ui.add(
+                egui::DragValue::new(bpm)
+                    .speed(0.1)
+                    .clamp_range(RangeInclusive::new(10.0, 1000.0))
+                    .suffix(" BPM"),
+            );
+            if let Some(detected) = metadata.detected_bpm {
+                if ui.button("Use Detected").clicked() {
+                    *bpm = detected;
+                }
+            }
+            ui.label(format!("{:.2}x speed", *bpm / encoded));
+        }
+    });
+}
+
+fn draw_metadata(ui: &mut egui::Ui, metadata: &MIDIFileMetadata) {
+    egui::Grid::new("midi_info_grid")
+        .num_columns(2)
+        .spacing([40.0, 4.0])
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("File Name: ");
+            ui.label(&metadata.file_name);
+            ui.end_row();
+
+            ui.label("File Size: ");
+            ui.label(format_size(metadata.file_size));
+            ui.end_row();
+
+            ui.label("Format: ");
+            ui.label(format!("SMF{}", metadata.format));
+            ui.end_row();
+
+            ui.label("Tick Resolution: ");
+            ui.label(format!("{} PPQ", metadata.ppq));
+            ui.end_row();
+
+            ui.label("Tracks: ");
+            ui.label(metadata.track_count.to_string());
+            ui.end_row();
+
+            if metadata.scanned {
+                ui.label("Notes: ");
+                ui.label(metadata.note_count.to_string());
+                ui.end_row();
+
+                ui.label("Duration: ");
+                ui.label(format_time(metadata.length));
+                ui.end_row();
+
+                // Files without tempo events play at 120 BPM
+                ui.label("Tempo: ");
+                ui.label(match metadata.tempo_changes.first() {
+                    Some(tempo) => format!("{:.2} BPM", tempo.bpm),
+                    None => "120.00 BPM (no tempo events)".to_owned(),
+                });
+                ui.end_row();
+
+                ui.label("Detected Tempo: ");
+                ui.label(match metadata.detected_bpm {
+                    Some(bpm) => format!("~{:.1} BPM", bpm),
+                    None => "Not enough notes".to_owned(),
+                });
+                ui.end_row();
+            }
+
+            if let Some(repairs) = metadata.repairs {
+                ui.label("Duplicate Notes Removed: ");
+                ui.label(repairs.duplicate_notes.to_string());
+                ui.end_row();
+
+                ui.label("Hanging Notes Ended: ");
+                ui.label(repairs.hanging_notes.to_string());
+                ui.end_row();
+            }
+        });
+
+    if !metadata.scanned {
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label("Reading events...");
+        });
+        return;
+    }
+
+    ui.collapsing(
+        format!("Tempo Changes ({})", metadata.tempo_changes.len()),
+        |ui| {
+            for tempo in metadata.tempo_changes.iter() {
+                ui.label(format!("{}  {:.2} BPM", format_time(tempo.time), tempo.bpm));
+            }
+        },
+    );
+
+    ui.collapsing(
+        format!("Time Signatures ({})", metadata.time_signatures.len()),
+        |ui| {
+            for signature in metadata.time_signatures.iter() {
+                ui.label(format!(
+                    "{}  {}/{}",
+                    format_time(signature.time),
+                    signature.numerator,
+                    signature.denominator
+                ));
+            }
+        },
+    );
+
+    ui.collapsing(
+        format!("Text Events ({})", metadata.text_events.len()),
+        |ui| {
+            for text in metadata.text_events.iter() {
+                ui.label(format!("{}: {}", text.kind, text.text));
+            }
+        },
+    );
+}
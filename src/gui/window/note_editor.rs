@@ -0,0 +1,205 @@
+use std::path::Path;
+
+use egui::{Align2, Color32, Pos2, Rect, Sense, Stroke, Ui};
+
+use crate::{
+    gui::window::keyboard_layout::KeyboardView,
+    midi::{EditableNote, InRamMIDIFile, MIDIFileBase, MIDISidecar, NoteEdit},
+    settings::WasabiSettings,
+};
+
+/// How close to a note's end the cursor has to be to drag it, in pixels
+const HANDLE_SIZE: f32 = 6.0;
+
+/// The shortest length a note can be trimmed to, in seconds
+const MIN_NOTE_LENGTH: f64 = 0.001;
+
+#[derive(Clone, Copy, PartialEq)]
+enum NoteEdge {
+    Start,
+    End,
+}
+
+struct NoteDrag {
+    note: EditableNote,
+    edge: NoteEdge,
+}
+
+/// Lets the user select notes in the piano roll, trim them by
+/// dragging their ends and delete them
+pub struct NoteEditor {
+    selected: Option<EditableNote>,
+    drag: Option<NoteDrag>,
+    pending_delete: Option<EditableNote>,
+}
+
+impl NoteEditor {
+    pub fn new() -> Self {
+        NoteEditor {
+            selected: None,
+            drag: None,
+            pending_delete: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.selected = None;
+        self.drag = None;
+        self.pending_delete = None;
+    }
+
+    pub fn update(
+        &mut self,
+        ui: &mut Ui,
+        rect: Rect,
+        key_view: &KeyboardView,
+        midi_file: &mut InRamMIDIFile,
+        settings: &WasabiSettings,
+        midi_path: Option<&Path>,
+    ) {
+        let now = midi_file.timer().get_time().as_secs_f64();
+        let range = settings.midi.note_speed;
+        let transpose = settings.midi.transpose_semitones as i32;
+
+        let y_for_time = |time: f64| rect.bottom() - ((time - now) / range) as f32 * rect.height();
+        let time_for_y = |y: f32| now + ((rect.bottom() - y) / rect.height()) as f64 * range;
+
+        // Black notes are drawn over white ones, so they are checked first
+        let key_at = |pos: Pos2| {
+            let x = (pos.x - rect.left()) / rect.width();
+            let (black, white): (Vec<_>, Vec<_>) = key_view
+                .iter_visible_notes()
+                .partition(|(_, key)| key.black);
+            let key = black
+                .into_iter()
+                .chain(white)
+                .find(|(_, key)| key.left <= x && x < key.right)?
+                .0 as i32
+                - transpose;
+            u8::try_from(key).ok()
+        };
+
+        let response = ui.interact(rect, ui.id().with("note_editor"), Sense::click_and_drag());
+        let pointer = response.interact_pointer_pos();
+
+        if response.drag_started() {
+            if let Some(pos) = pointer {
+                let note =
+                    key_at(pos).and_then(|key| midi_file.find_note_at(key, time_for_y(pos.y)));
+                self.selected = note;
+                self.drag = note.and_then(|note| {
+                    if (pos.y - y_for_time(note.end)).abs() <= HANDLE_SIZE {
+                        Some(NoteDrag {
+                            note,
+                            edge: NoteEdge::End,
+                        })
+                    } else if (pos.y - y_for_time(note.start)).abs() <= HANDLE_SIZE {
+                        Some(NoteDrag {
+                            note,
+                            edge: NoteEdge::Start,
+                        })
+                    } else {
+                        None
+                    }
+                });
+            }
+        } else if response.clicked() || response.secondary_clicked() {
+            if let Some(pos) = pointer {
+                self.selected =
+                    key_at(pos).and_then(|key| midi_file.find_note_at(key, time_for_y(pos.y)));
+            }
+            if response.secondary_clicked() {
+                self.pending_delete = self.selected;
+            }
+        }
+
+        // Trims can only shorten a note, never extend it past its original length
+        if let (Some(drag), Some(pos)) = (self.drag.as_mut(), pointer) {
+            let time = time_for_y(pos.y);
+            let note = &mut drag.note;
+            match drag.edge {
+                NoteEdge::Start => {
+                    note.start = time
+                        .min(note.end - MIN_NOTE_LENGTH)
+                        .max(note.original_start)
+                }
+                NoteEdge::End => {
+                    note.end = time
+                        .max(note.start + MIN_NOTE_LENGTH)
+                        .min(note.original_end)
+                }
+            }
+            self.selected = Some(*note);
+        }
+
+        if response.drag_released() {
+            if let Some(drag) = self.drag.take() {
+                let note = drag.note;
+                midi_file.push_note_edit(NoteEdit::Trim {
+                    note: note.id,
+                    start: note.start,
+                    end: note.end,
+                });
+                save_edits(midi_file, midi_path);
+            }
+        }
+
+        ui.input(|input| {
+            if input.key_pressed(egui::Key::Delete) {
+                self.pending_delete = self.selected;
+            }
+            if input.key_pressed(egui::Key::Escape) {
+                self.selected = None;
+            }
+        });
+
+        if let Some(note) = self.selected {
+            let key = note.id.key as i32 + transpose;
+            if (0..=255).contains(&key) {
+                let position = key_view.note(key as usize);
+                let note_rect = Rect::from_x_y_ranges(
+                    (rect.left() + position.left * rect.width())
+                        ..=(rect.left() + position.right * rect.width()),
+                    y_for_time(note.end)..=y_for_time(note.start),
+                )
+                .intersect(rect);
+                ui.painter()
+                    .rect_stroke(note_rect, 0.0, Stroke::new(2.0, Color32::WHITE));
+            }
+        }
+
+        if let Some(note) = self.pending_delete {
+            let mut close = false;
+            egui::Window::new("Delete note")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ui.ctx(), |ui| {
+                    ui.label("Delete the selected note?");
+                    ui.horizontal(|ui| {
+                        if ui.button("Delete").clicked() {
+                            midi_file.push_note_edit(NoteEdit::Delete { note: note.id });
+                            save_edits(midi_file, midi_path);
+                            self.selected = None;
+                            close = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            close = true;
+                        }
+                    });
+                });
+            if close {
+                self.pending_delete = None;
+            }
+        }
+    }
+}
+
+/// Writes the edits of a MIDI file into its sidecar file
+pub fn save_edits(midi_file: &InRamMIDIFile, midi_path: Option<&Path>) {
+    if let Some(midi_path) = midi_path {
+        let mut sidecar = MIDISidecar::load(midi_path);
+        sidecar.note_edits = midi_file.note_edits().to_vec();
+        sidecar.save(midi_path);
+    }
+}
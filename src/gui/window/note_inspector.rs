@@ -0,0 +1,130 @@
+use egui::{Color32, Context, Frame, Order, Pos2};
+
+use crate::gui::window::scene::PickedNote;
+
+pub const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// The name of a MIDI key with its octave, where key 60 is C4
+pub fn pitch_name(key: i32) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[key.rem_euclid(12) as usize],
+        key.div_euclid(12) - 1
+    )
+}
+
+struct InspectedNote {
+    note: PickedNote,
+    /// The MIDI key of the note before it was transposed
+    midi_key: i32,
+    /// The start of the note in seconds from the start of the MIDI
+    start: f64,
+    pos: Pos2,
+}
+
+/// A popup with the properties of the note that was right clicked in the notes panel
+pub struct NoteInspector {
+    inspected: Option<InspectedNote>,
+}
+
+impl NoteInspector {
+    pub fn new() -> Self {
+        Self { inspected: None }
+    }
+
+    pub fn inspect(&mut self, note: PickedNote, transpose: i8, now: f64, pos: Pos2) {
+        self.inspected = Some(InspectedNote {
+            note,
+            midi_key: note.key as i32 - transpose as i32,
+            start: now + note.start as f64,
+            pos,
+        });
+    }
+
+    pub fn close(&mut self) {
+        self.inspected = None;
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let Some(inspected) = &self.inspected else {
+            return;
+        };
+        let note = &inspected.note;
+
+        let response = egui::Area::new("note_inspector")
+            .order(Order::Foreground)
+            .fixed_pos(inspected.pos)
+            .show(ctx, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    egui::Grid::new("note_inspector_grid")
+                        .num_columns(2)
+                        .spacing([20.0, 2.0])
+                        .show(ui, |ui| {
+                            ui.label("Pitch:");
+                            ui.label(pitch_name(inspected.midi_key));
+                            ui.end_row();
+
+                            ui.label("Note Number:");
+                            ui.label(inspected.midi_key.to_string());
+                            ui.end_row();
+
+                            ui.label("Channel:");
+                            ui.label((note.channel + 1).to_string());
+                            ui.end_row();
+
+                            ui.label("Track:");
+                            ui.label(note.track.to_string());
+                            ui.end_row();
+
+                            ui.label("Start:");
+                            ui.label(format!("{:.3} s", inspected.start));
+                            ui.end_row();
+
+                            ui.label("Duration:");
+                            ui.label(format!("{:.3} s", note.len));
+                            ui.end_row();
+
+                            ui.label("Velocity:");
+                            ui.label(note.vel.to_string());
+                            ui.end_row();
+
+                            ui.label("Color:");
+                            let color = Color32::from_rgb(
+                                note.color.red(),
+                                note.color.green(),
+                                note.color.blue(),
+                            );
+                            ui.horizontal(|ui| {
+                                let (rect, _) = ui.allocate_exact_size(
+                                    egui::vec2(12.0, 12.0),
+                                    egui::Sense::hover(),
+                                );
+                                ui.painter().rect_filled(rect, 2.0, color);
+                                ui.label(format!(
+                                    "#{:02X}{:02X}{:02X}",
+                                    color.r(),
+                                    color.g(),
+                                    color.b()
+                                ));
+                            });
+                            ui.end_row();
+                        });
+                });
+            })
+            .response;
+
+        // Clicking anywhere else or pressing escape closes it, like a context menu
+        let close = ctx.input(|i| {
+            i.key_pressed(egui::Key::Escape)
+                || (i.pointer.any_pressed()
+                    && i.pointer
+                        .interact_pos()
+                        .map_or(true, |pos| !response.rect.contains(pos)))
+        });
+        if close {
+            self.inspected = None;
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::Serialize;
+
+use crate::notifications;
+
+/// A command sent over the HTTP API, which the GUI picks up on the next frame
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum WasabiRemoteCommand {
+    #[default]
+    Idle,
+    Play,
+    Pause,
+    /// Seek to a time in seconds
+    Seek(f64),
+    Load(PathBuf),
+}
+
+/// What `/status` answers with, kept up to date by the GUI
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RemoteStatus {
+    pub midi_file: Option<PathBuf>,
+    pub playing: bool,
+    /// The time in seconds
+    pub time: f64,
+    /// The length in seconds, if the loading method knows it
+    pub length: Option<f64>,
+}
+
+/// Answers HTTP requests on a separate thread. Only one command waits at a time, so
+/// requests that come in before the GUI took the last one are turned away. The server
+/// stops once this is dropped.
+#[cfg_attr(not(feature = "remote-control"), allow(dead_code))]
+pub struct RemoteControl {
+    command: Arc<Mutex<WasabiRemoteCommand>>,
+    status: Arc<Mutex<RemoteStatus>>,
+    /// The token that the requests have to send
+    token: Arc<Mutex<String>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl RemoteControl {
+    #[cfg(feature = "remote-control")]
+    pub fn start(port: u16) -> Option<Self> {
+        let command = Arc::new(Mutex::new(WasabiRemoteCommand::Idle));
+        let status = Arc::new(Mutex::new(RemoteStatus::default()));
+        let token = Arc::new(Mutex::new(String::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // Binding happens here, so a port that is taken is reported right away
+        let listener = match std::net::TcpListener::bind(("127.0.0.1", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                notifications::error(format!(
+                    "Failed to start the remote control on port {}: {}",
+                    port, e
+                ));
+                return None;
+            }
+        };
+
+        let command_clone = command.clone();
+        let status_clone = status.clone();
+        let token_clone = token.clone();
+        let stop_clone = stop.clone();
+        std::thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    notifications::error(format!("Failed to start the remote control: {}", e));
+                    return;
+                }
+            };
+            runtime.block_on(server::serve(
+                listener,
+                command_clone,
+                status_clone,
+                token_clone,
+                stop_clone,
+            ));
+        });
+
+        Some(Self {
+            command,
+            status,
+            token,
+            stop,
+        })
+    }
+
+    #[cfg(not(feature = "remote-control"))]
+    pub fn start(_port: u16) -> Option<Self> {
+        notifications::error(
+            "The remote control needs Wasabi to be built with the `remote-control` feature",
+        );
+        None
+    }
+
+    /// Takes the command that is waiting, if any
+    pub fn take_command(&self) -> WasabiRemoteCommand {
+        std::mem::take(&mut *self.command.lock().unwrap())
+    }
+
+    pub fn set_status(&self, status: RemoteStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    pub fn set_token(&self, token: &str) {
+        let mut current = self.token.lock().unwrap();
+        if *current != token {
+            *current = token.to_owned();
+        }
+    }
+}
+
+impl Drop for RemoteControl {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The command for the path and query of a request, or the reason it isn't one
+#[cfg_attr(not(feature = "remote-control"), allow(dead_code))]
+fn parse_command(target: &str) -> Result<WasabiRemoteCommand, String> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| percent_decode(value))
+            .ok_or_else(|| format!("Missing the `{}` parameter", name))
+    };
+
+    match path {
+        "/play" => Ok(WasabiRemoteCommand::Play),
+        "/pause" => Ok(WasabiRemoteCommand::Pause),
+        "/seek" => {
+            let time = param("t")?;
+            match time.parse::<f64>() {
+                Ok(time) if time.is_finite() && time >= 0.0 => Ok(WasabiRemoteCommand::Seek(time)),
+                _ => Err(format!("{} is not a time in seconds", time)),
+            }
+        }
+        "/load" => Ok(WasabiRemoteCommand::Load(PathBuf::from(param("path")?))),
+        _ => Err(format!("{} is not a command", path)),
+    }
+}
+
+/// Whether a request may be answered, from its headers. Web pages can send requests to
+/// localhost too, so requests with an `Origin` or with a `Host` that isn't localhost,
+/// which a page gets through DNS rebinding, are turned away before the token is checked.
+#[cfg_attr(not(feature = "remote-control"), allow(dead_code))]
+fn check_headers(head: &str, token: &str) -> Result<(), (u16, &'static str)> {
+    let header = |name: &str| {
+        head.lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    };
+
+    if header("Origin").is_some() {
+        return Err((403, "Requests from web pages aren't allowed"));
+    }
+
+    let host = header("Host").unwrap_or_default();
+    let hostname = match host.rsplit_once(':') {
+        Some((hostname, port)) if port.bytes().all(|b| b.is_ascii_digit()) => hostname,
+        _ => host,
+    };
+    if !matches!(hostname, "localhost" | "127.0.0.1" | "[::1]") {
+        return Err((403, "Only requests to localhost are allowed"));
+    }
+
+    let sent = header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compared in full every time, so the time doesn't tell how much of it matched
+    let matches = sent.len() == token.len()
+        && sent
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if token.is_empty() || !matches {
+        return Err((401, "Missing or wrong token"));
+    }
+
+    Ok(())
+}
+
+/// Decodes the `%XX` escapes and the `+` for spaces of a query value
+#[cfg_attr(not(feature = "remote-control"), allow(dead_code))]
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let decoded = std::str::from_utf8(rest.get(..2).unwrap_or_default())
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match decoded {
+                    Some(decoded) => {
+                        bytes.push(decoded);
+                        rest = &rest[2..];
+                    }
+                    None => bytes.push(b'%'),
+                }
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(feature = "remote-control")]
+mod server {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::notifications;
+
+    use super::{check_headers, parse_command, RemoteStatus, WasabiRemoteCommand};
+
+    /// How often the stop flag is checked while no requests arrive
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Requests with a bigger head than this are turned away
+    const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+    /// How long a client gets to send its request
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub async fn serve(
+        listener: std::net::TcpListener,
+        command: Arc<Mutex<WasabiRemoteCommand>>,
+        status: Arc<Mutex<RemoteStatus>>,
+        token: Arc<Mutex<String>>,
+        stop: Arc<AtomicBool>,
+    ) {
+        let listener = match TcpListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(e) => {
+                notifications::error(format!("Failed to start the remote control: {}", e));
+                return;
+            }
+        };
+
+        while !stop.load(Ordering::Relaxed) {
+            let stream = match tokio::time::timeout(POLL_INTERVAL, listener.accept()).await {
+                Ok(Ok((stream, _))) => stream,
+                Ok(Err(e)) => {
+                    notifications::error(format!(
+                        "Failed to accept a remote control connection: {}",
+                        e
+                    ));
+                    continue;
+                }
+                Err(_) => continue,
+            };
+
+            let command = command.clone();
+            let status = status.clone();
+            let token = token.lock().unwrap().clone();
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &command, &status, &token).await {
+                    notifications::error(format!(
+                        "Failed to answer a remote control request: {}",
+                        e
+                    ));
+                }
+            });
+        }
+    }
+
+    async fn respond(
+        mut stream: TcpStream,
+        command: &Mutex<WasabiRemoteCommand>,
+        status: &Mutex<RemoteStatus>,
+        token: &str,
+    ) -> Result<(), String> {
+        let head = tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream))
+            .await
+            .map_err(|_| "The request took too long".to_owned())??;
+
+        // POST /seek?t=12.5 HTTP/1.1
+        let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+        let method = request_line.next().unwrap_or_default();
+        let target = request_line.next().unwrap_or_default();
+        let is_status = target.split('?').next() == Some("/status");
+
+        let (code, body) = if let Err((code, reason)) = check_headers(&head, token) {
+            (code, reason.to_owned())
+        } else if is_status && method != "GET" {
+            (405, "The status is read with GET".to_owned())
+        } else if is_status {
+            let status = status.lock().unwrap().clone();
+            (200, serde_json::to_string(&status).unwrap_or_default())
+        } else if method != "POST" {
+            // Commands change the playback, so they can't be sent by following a link
+            (405, "Commands are sent with POST".to_owned())
+        } else {
+            match parse_command(target) {
+                Ok(new_command) => {
+                    let mut command = command.lock().unwrap();
+                    if *command == WasabiRemoteCommand::Idle {
+                        *command = new_command;
+                        (200, "OK".to_owned())
+                    } else {
+                        (503, "The last command wasn't handled yet".to_owned())
+                    }
+                }
+                Err(e) => (400, e),
+            }
+        };
+
+        let reason = match code {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            405 => "Method Not Allowed",
+            _ => "Service Unavailable",
+        };
+        let content_type = if code == 200 && body.starts_with('{') {
+            "application/json"
+        } else {
+            "text/plain; charset=utf-8"
+        };
+        let response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            code,
+            reason,
+            content_type,
+            body.len(),
+            body
+        );
+
+        stream
+            .write_all(response.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        stream.shutdown().await.ok();
+        Ok(())
+    }
+
+    /// Reads until the empty line after the headers, the body isn't used
+    async fn read_head(stream: &mut TcpStream) -> Result<String, String> {
+        let mut head = Vec::new();
+        let mut buffer = [0; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            if head.len() > MAX_REQUEST_SIZE {
+                return Err("The request is too big".to_owned());
+            }
+            let read = stream.read(&mut buffer).await.map_err(|e| e.to_string())?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&buffer[..read]);
+        }
+        Ok(String::from_utf8_lossy(&head).into_owned())
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use egui::Context;
+
+pub enum ResumeAction {
+    None,
+    Resume,
+    StartOver,
+}
+
+/// Asks whether to continue a MIDI from where it was when Wasabi was last closed
+pub fn show_resume_prompt(ctx: &Context, position: Duration) -> ResumeAction {
+    let mut action = ResumeAction::None;
+
+    let secs = position.as_secs();
+    let time = if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    };
+
+    egui::Window::new("Resume Playback")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label(format!("This MIDI was last stopped at {}.", time));
+            ui.horizontal(|ui| {
+                if ui.button("Resume").clicked() {
+                    action = ResumeAction::Resume;
+                }
+                if ui.button("Start Over").clicked() {
+                    action = ResumeAction::StartOver;
+                }
+            });
+        });
+
+    action
+}
@@ -1,14 +1,27 @@
+mod background;
+mod bloom;
 mod cake_system;
 mod note_list_system;
+mod upscale;
 
-use egui::Ui;
+use std::{sync::Arc, time::Instant};
+
+use egui::{Pos2, Rect, Ui};
+use vulkano::image::{ImageAccess, ImageViewAbstract};
 
 use crate::{
     midi::{MIDIColor, MIDIFileUnion},
     scenes::SceneSwapchain,
+    settings::{BackgroundMode, WasabiSettings},
 };
 
-use self::{cake_system::CakeRenderer, note_list_system::NoteRenderer};
+use self::{
+    background::BackgroundPass,
+    bloom::BloomPass,
+    cake_system::CakeRenderer,
+    note_list_system::{NoteRenderer, NOTE_BUFFER_SIZE},
+    upscale::UpscalePass,
+};
 
 use super::{keyboard_layout::KeyboardView, GuiRenderer, GuiState};
 
@@ -19,11 +32,29 @@ enum CurrentRenderer {
 }
 
 impl CurrentRenderer {
-    fn get_note_renderer(&mut self, renderer: &GuiRenderer) -> &mut NoteRenderer {
+    fn get_note_renderer(
+        &mut self,
+        renderer: &GuiRenderer,
+        msaa_samples: u32,
+        memory_block_size_mb: u64,
+        note_buffer_size: u64,
+    ) -> &mut NoteRenderer {
         match self {
-            CurrentRenderer::Note(renderer) => renderer,
+            // The render passes are made for a sample count and an allocator, so they are
+            // recreated when either changes
+            CurrentRenderer::Note(renderer)
+                if renderer.msaa_samples() == msaa_samples
+                    && renderer.memory_block_size_mb() == memory_block_size_mb =>
+            {
+                renderer
+            }
             _ => {
-                let renderer = NoteRenderer::new(renderer);
+                let renderer = NoteRenderer::new(
+                    renderer,
+                    msaa_samples,
+                    memory_block_size_mb,
+                    note_buffer_size,
+                );
                 *self = CurrentRenderer::Note(renderer);
                 match self {
                     CurrentRenderer::Note(renderer) => renderer,
@@ -51,18 +82,168 @@ impl CurrentRenderer {
 pub struct GuiRenderScene {
     swap_chain: SceneSwapchain,
     draw_system: CurrentRenderer,
+    bloom: Option<BloomPass>,
+    background: Option<BackgroundPass>,
+    upscale: Option<UpscalePass>,
+    /// Colors that replace the colors of the channels, indexed like the track colors.
+    /// Only the note list renderer uses them, since the cake renderer colors on the GPU.
+    pub slot_colors: Vec<Option<MIDIColor>>,
+    /// How long the notes fade in, in seconds, which is the attack that was written into
+    /// the SFZ of the synth. Only the note list renderer fades them in.
+    pub note_fadein: f32,
+    /// The notes that are drawn in one batch, which sets the size of the
+    /// vertex and culling buffers of the note list renderer
+    note_buffer_size: u64,
 }
 
 pub struct RenderResultData {
     pub notes_rendered: u64,
     pub key_colors: Vec<Option<MIDIColor>>,
+    pub most_active_key: Option<u8>,
+    /// The wall-clock time from submitting the frame until the GPU finished it, including
+    /// the bloom. This is measured on the host so it also counts the submit overhead.
+    pub render_time_ms: f32,
+    /// How long it took to write the notes into the vertex buffers
+    pub cpu_fill_time_ms: f32,
+    /// How many of the visible notes have a velocity in each range of 8, the cake
+    /// renderer doesn't read the notes on the CPU so it leaves this empty
+    pub velocity_histogram: [u64; 16],
+    /// The note found for the pick that was requested before drawing, if any
+    pub picked_note: Option<PickedNote>,
+    /// The color of the drum playing for each percussion note, when the percussion
+    /// channel is shown as drum pads. The cake renderer leaves this empty.
+    pub drum_colors: Vec<Option<MIDIColor>>,
+    /// A bitmask of the channels that have notes on screen, the cake renderer leaves
+    /// this empty
+    pub visible_channels: u16,
+    /// A bitmask of the channels playing on each key, the cake renderer leaves this
+    /// empty
+    pub key_channels: Vec<u16>,
+    /// The notes on screen without the echo, when they were asked for before drawing.
+    /// Only the note list renderer can keep them.
+    pub captured_notes: Option<Vec<CapturedNote>>,
+}
+
+/// A request to find the note drawn at a key and a time
+#[derive(Debug, Clone, Copy)]
+pub struct NotePick {
+    pub key: u8,
+    /// The time from the start of the notes on screen, in seconds
+    pub time: f32,
+    /// How far away in seconds a note can be and still get picked
+    pub tolerance: f32,
+}
+
+/// A note that was picked while its column was written, with its displayed key and
+/// its start relative to the start of the notes on screen
+#[derive(Debug, Clone, Copy)]
+pub struct PickedNote {
+    pub key: u8,
+    pub start: f32,
+    pub len: f32,
+    pub vel: u8,
+    pub track: u32,
+    pub channel: u8,
+    pub color: MIDIColor,
+}
+
+/// A note on screen that was kept for an export, with its displayed key and its start
+/// relative to the playhead
+#[derive(Debug, Clone, Copy)]
+pub struct CapturedNote {
+    pub key: u8,
+    pub start: f32,
+    pub len: f32,
+    pub color: MIDIColor,
+}
+
+/// Shifts a key by the transpose amount, returning `None` if a
+/// transposed key falls outside of the MIDI key range.
+pub fn transpose_key(key: usize, semitones: i8) -> Option<usize> {
+    if semitones == 0 {
+        return Some(key);
+    }
+
+    let key = key as i32 + semitones as i32;
+    if (0..=127).contains(&key) {
+        Some(key as usize)
+    } else {
+        None
+    }
 }
 
 impl GuiRenderScene {
     pub fn new(renderer: &GuiRenderer) -> Self {
+        Self::with_note_buffer_size(renderer, NOTE_BUFFER_SIZE)
+    }
+
+    /// A scene that draws the notes in smaller batches, for views that don't need
+    /// the memory of the main one
+    pub fn with_note_buffer_size(renderer: &GuiRenderer, note_buffer_size: u64) -> Self {
         Self {
             swap_chain: SceneSwapchain::new(renderer.device.clone()),
             draw_system: CurrentRenderer::None,
+            bloom: None,
+            background: None,
+            upscale: None,
+            slot_colors: Vec::new(),
+            note_fadein: 0.0,
+            note_buffer_size,
+        }
+    }
+
+    /// Finds the note under a position in the notes panel during the next draw. Only
+    /// the note list renderer can pick notes, since the cake renderer works on the GPU.
+    pub fn pick_note_at(
+        &mut self,
+        pos: Pos2,
+        rect: Rect,
+        key_view: &KeyboardView,
+        note_speed: f64,
+        waterfall: bool,
+    ) {
+        const TOLERANCE_PX: f32 = 4.0;
+
+        // The notes move from the top to the bottom, or from the right to the left in
+        // waterfall mode, where the lowest keys are at the bottom
+        let (key_pos, time_pos, time_length) = if waterfall {
+            (
+                (rect.bottom() - pos.y) / rect.height(),
+                (pos.x - rect.left()) / rect.width(),
+                rect.width(),
+            )
+        } else {
+            (
+                (pos.x - rect.left()) / rect.width(),
+                (rect.bottom() - pos.y) / rect.height(),
+                rect.height(),
+            )
+        };
+
+        // Black keys are drawn over white ones, so they are checked first
+        let (black, white): (Vec<_>, Vec<_>) = key_view
+            .iter_visible_notes()
+            .partition(|(_, key)| key.black);
+        let key = black
+            .into_iter()
+            .chain(white)
+            .find(|(_, key)| key.left <= key_pos && key_pos < key.right)
+            .map(|(key, _)| key);
+
+        if let (Some(key), CurrentRenderer::Note(renderer)) = (key, &mut self.draw_system) {
+            renderer.pick = Some(NotePick {
+                key: key as u8,
+                time: time_pos * note_speed as f32,
+                tolerance: TOLERANCE_PX / time_length * note_speed as f32,
+            });
+        }
+    }
+
+    /// Keeps the notes on screen during the next draw, so they can be exported. Only the
+    /// note list renderer can keep them, since the cake renderer works on the GPU.
+    pub fn capture_notes(&mut self) {
+        if let CurrentRenderer::Note(renderer) = &mut self.draw_system {
+            renderer.capture = true;
         }
     }
 
@@ -72,32 +253,142 @@ impl GuiRenderScene {
         ui: &mut Ui,
         key_view: &KeyboardView,
         midi_file: &mut MIDIFileUnion,
-        view_range: f64,
+        settings: &WasabiSettings,
+        brightness: f32,
+        track_order: &[usize],
     ) -> RenderResultData {
         let size = ui.available_size();
         let size = [size.x as u32, size.y as u32];
 
         let scene_image = self.swap_chain.get_next_image(state, size);
         let frame = scene_image.image.clone();
+        let image_id = scene_image.id;
+
+        let result = self.render(
+            state.renderer,
+            frame,
+            key_view,
+            midi_file,
+            settings,
+            brightness,
+            track_order,
+        );
+
+        ui.image(image_id, [size[0] as f32, size[1] as f32]);
+
+        result
+    }
+
+    /// Draws the notes into an image, which doesn't have to be one of the images
+    /// shown in the window
+    pub fn render(
+        &mut self,
+        renderer: &GuiRenderer,
+        frame: Arc<dyn ImageViewAbstract>,
+        key_view: &KeyboardView,
+        midi_file: &mut MIDIFileUnion,
+        settings: &WasabiSettings,
+        brightness: f32,
+        track_order: &[usize],
+    ) -> RenderResultData {
+        // The attachments of a render pass have to be the same size, so with a smaller
+        // depth buffer the notes are drawn into an image of its size and stretched onto
+        // the frame. The renderers size their depth buffers to the image they draw into.
+        let half_depth = settings.visual.depth_buffer_scale < 1.0;
+        let notes_frame: Arc<dyn ImageViewAbstract> = if half_depth {
+            let [width, height] = frame.image().dimensions().width_height();
+            self.upscale
+                .get_or_insert_with(|| UpscalePass::new(renderer))
+                .target([(width + 1) / 2, (height + 1) / 2])
+        } else {
+            frame.clone()
+        };
 
-        let result = match midi_file {
+        let msaa_samples = settings.visual.msaa_samples;
+        let memory_block_size_mb = settings.rendering.memory_block_size_mb;
+        let mut result = match midi_file {
             MIDIFileUnion::InRam(file) => self
                 .draw_system
-                .get_note_renderer(state.renderer)
-                .draw(key_view, frame, file, view_range),
+                .get_note_renderer(
+                    renderer,
+                    msaa_samples,
+                    memory_block_size_mb,
+                    self.note_buffer_size,
+                )
+                .with_slot_colors(&self.slot_colors)
+                .with_note_fadein(self.note_fadein)
+                .draw(
+                    key_view,
+                    notes_frame.clone(),
+                    file,
+                    settings,
+                    brightness,
+                    track_order,
+                ),
 
             MIDIFileUnion::Live(file) => self
                 .draw_system
-                .get_note_renderer(state.renderer)
-                .draw(key_view, frame, file, view_range),
+                .get_note_renderer(
+                    renderer,
+                    msaa_samples,
+                    memory_block_size_mb,
+                    self.note_buffer_size,
+                )
+                .with_slot_colors(&self.slot_colors)
+                .with_note_fadein(self.note_fadein)
+                .draw(
+                    key_view,
+                    notes_frame.clone(),
+                    file,
+                    settings,
+                    brightness,
+                    track_order,
+                ),
 
-            MIDIFileUnion::Cake(file) => self
-                .draw_system
-                .get_cake_renderer(state.renderer)
-                .draw(key_view, frame, file, view_range),
+            MIDIFileUnion::Cake(file) => self.draw_system.get_cake_renderer(renderer).draw(
+                key_view,
+                notes_frame.clone(),
+                file,
+                settings,
+                brightness,
+            ),
         };
 
-        ui.image(scene_image.id, [size[0] as f32, size[1] as f32]);
+        if half_depth {
+            if let Some(upscale) = &self.upscale {
+                let upscale_start = Instant::now();
+                upscale.draw(frame.clone());
+                result.gpu_time_ms += upscale_start.elapsed().as_secs_f32() * 1000.0;
+            }
+        }
+
+        // The pass is kept once created, so it can drop the old background when it is turned off
+        if self.background.is_some() || settings.visual.background != BackgroundMode::SolidColor {
+            let background_start = Instant::now();
+            self.background
+                .get_or_insert_with(|| BackgroundPass::new(renderer))
+                .draw(
+                    frame.clone(),
+                    &settings.visual.background,
+                    settings.visual.background_opacity,
+                );
+            result.render_time_ms += background_start.elapsed().as_secs_f32() * 1000.0;
+        }
+
+        if settings.visual.bloom_enabled {
+            // The note renderer writes a separate brightness image based on velocity,
+            // other renderers just bloom the whole frame
+            let source: Arc<dyn ImageViewAbstract> = match &self.draw_system {
+                CurrentRenderer::Note(renderer) => renderer.bloom_source(),
+                _ => frame.clone(),
+            };
+
+            let bloom_start = Instant::now();
+            self.bloom
+                .get_or_insert_with(|| BloomPass::new(renderer))
+                .draw(source, frame, settings.visual.bloom_radius);
+            result.render_time_ms += bloom_start.elapsed().as_secs_f32() * 1000.0;
+        }
 
         result
     }
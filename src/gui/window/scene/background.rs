@@ -0,0 +1,447 @@
+mod video;
+
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        CommandBufferExecFuture, CommandBufferUsage, CopyBufferToImageInfo,
+        PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Queue,
+    format::Format,
+    image::{
+        view::ImageView, ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage,
+        ImageViewAbstract, StorageImage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, BlendFactor, BlendOp, ColorBlendState},
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::{
+        self,
+        future::{FenceSignalFuture, NowFuture},
+        GpuFuture,
+    },
+    DeviceSize,
+};
+
+use crate::{gui::GuiRenderer, notifications, settings::BackgroundMode};
+
+use self::video::{VideoDecoder, VideoFrame};
+
+/// How many video frames can be on their way to the GPU at once, so a new frame
+/// doesn't have to wait until the copy of the previous one finished
+const UPLOAD_SLOTS: usize = 3;
+
+/// A staging buffer and the texture it is copied into, reused for every frame of a video
+struct UploadSlot {
+    buffer: Subbuffer<[u8]>,
+    texture: Arc<ImageView<StorageImage>>,
+    /// Set while the copy is still running on the GPU
+    fence: Option<FenceSignalFuture<CommandBufferExecFuture<NowFuture>>>,
+    frame: u64,
+}
+
+/// Draws an image or a video behind the notes that were already rendered into the frame
+pub struct BackgroundPass {
+    gfx_queue: Arc<Queue>,
+    pipeline: Arc<GraphicsPipeline>,
+    render_pass: Arc<RenderPass>,
+    sampler: Arc<Sampler>,
+    loaded: BackgroundMode,
+    texture: Option<Arc<ImageView<StorageImage>>>,
+    video: Option<VideoDecoder>,
+    video_slots: Vec<UploadSlot>,
+    /// The slot with the newest video frame that finished uploading
+    shown_slot: Option<usize>,
+    uploaded_frames: u64,
+    allocator: StandardMemoryAllocator,
+    cb_allocator: StandardCommandBufferAllocator,
+    sd_allocator: StandardDescriptorSetAllocator,
+}
+
+impl BackgroundPass {
+    pub fn new(renderer: &GuiRenderer) -> BackgroundPass {
+        let allocator = StandardMemoryAllocator::new_default(renderer.device.clone());
+
+        let gfx_queue = renderer.queue.clone();
+
+        let render_pass = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                final_color: {
+                    load: Load,
+                    store: Store,
+                    format: renderer.format,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [final_color],
+                    depth_stencil: {},
+                    input: []
+                }
+            ]
+        )
+        .unwrap();
+
+        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+
+        // The notes are already in the frame, so the background only
+        // shows through where they left the frame transparent
+        let behind = AttachmentBlend {
+            color_op: BlendOp::Add,
+            color_source: BlendFactor::OneMinusDstAlpha,
+            color_destination: BlendFactor::One,
+            alpha_op: BlendOp::Add,
+            alpha_source: BlendFactor::OneMinusDstAlpha,
+            alpha_destination: BlendFactor::One,
+        };
+
+        let pipeline = GraphicsPipeline::start()
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .color_blend_state(ColorBlendState::new(1).blend(behind))
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build_with_cache(renderer.pipeline_cache.clone())
+            .build(gfx_queue.device().clone())
+            .unwrap();
+
+        let sampler = Sampler::new(
+            gfx_queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        BackgroundPass {
+            gfx_queue,
+            pipeline,
+            render_pass,
+            sampler,
+            loaded: BackgroundMode::SolidColor,
+            texture: None,
+            video: None,
+            video_slots: Vec::new(),
+            shown_slot: None,
+            uploaded_frames: 0,
+            allocator,
+            cb_allocator: StandardCommandBufferAllocator::new(
+                renderer.device.clone(),
+                Default::default(),
+            ),
+            sd_allocator: StandardDescriptorSetAllocator::new(renderer.device.clone()),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        background: &BackgroundMode,
+        opacity: f32,
+    ) {
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            &self.cb_allocator,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        if &self.loaded != background {
+            self.load(&mut command_buffer_builder, background);
+        }
+
+        self.poll_video_uploads();
+        if let Some(frame) = self.video.as_ref().and_then(|video| video.next_frame()) {
+            self.upload_video_frame(frame);
+        }
+
+        let texture = match self.shown_slot {
+            Some(index) => Some(self.video_slots[index].texture.clone()),
+            None => self.texture.clone(),
+        };
+        let Some(texture) = texture else {
+            return;
+        };
+
+        let img_dims = final_image.image().dimensions().width_height();
+        let tex_dims = texture.image().dimensions().width_height();
+
+        // Crop the texture so it fills the frame without being stretched
+        let frame_aspect = img_dims[0] as f32 / img_dims[1].max(1) as f32;
+        let texture_aspect = tex_dims[0] as f32 / tex_dims[1].max(1) as f32;
+        let uv_scale = if frame_aspect > texture_aspect {
+            [1.0, texture_aspect / frame_aspect]
+        } else {
+            [frame_aspect / texture_aspect, 1.0]
+        };
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let pipeline_layout = self.pipeline.layout();
+        let set = PersistentDescriptorSet::new(
+            &self.sd_allocator,
+            pipeline_layout.set_layouts().get(0).unwrap().clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                texture,
+                self.sampler.clone(),
+            )],
+        )
+        .unwrap();
+
+        let push_constants = fs::PushConstants {
+            uv_scale,
+            opacity: opacity.clamp(0.0, 1.0),
+        };
+
+        command_buffer_builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassContents::Inline,
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [img_dims[0] as f32, img_dims[1] as f32],
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .push_constants(pipeline_layout.clone(), 0, push_constants)
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline_layout.clone(), 0, set)
+            .draw(3, 1, 0, 0)
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        sync::now(self.gfx_queue.device().clone())
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+
+    /// Switches to a different background, files that don't exist are skipped
+    /// so that a path can be typed into the settings without errors for each key
+    fn load(
+        &mut self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        background: &BackgroundMode,
+    ) {
+        self.loaded = background.clone();
+        self.texture = None;
+        self.video = None;
+        self.video_slots.clear();
+        self.shown_slot = None;
+
+        match background {
+            BackgroundMode::SolidColor => {}
+            BackgroundMode::Image(path) if path.is_file() => match image::open(path) {
+                Ok(image) => {
+                    let image = image.to_rgba8();
+                    let dims = [image.width(), image.height()];
+                    self.upload(command_buffer_builder, image.into_raw(), dims);
+                }
+                Err(e) => notifications::error(format!("Failed to load background image: {}", e)),
+            },
+            BackgroundMode::Video(path) if path.is_file() => {
+                self.video = VideoDecoder::open(path);
+            }
+            _ => {}
+        }
+    }
+
+    fn new_texture(&self, dims: [u32; 2]) -> Arc<ImageView<StorageImage>> {
+        let image = StorageImage::with_usage(
+            &self.allocator,
+            ImageDimensions::Dim2d {
+                width: dims[0],
+                height: dims[1],
+                array_layers: 1,
+            },
+            Format::R8G8B8A8_UNORM,
+            ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+            ImageCreateFlags::empty(),
+            [self.gfx_queue.queue_family_index()],
+        )
+        .unwrap();
+        ImageView::new_default(image).unwrap()
+    }
+
+    /// Shows the newest video frame whose copy finished, without waiting for the others
+    fn poll_video_uploads(&mut self) {
+        for slot in self.video_slots.iter_mut() {
+            let done = slot
+                .fence
+                .as_ref()
+                .map_or(false, |fence| fence.is_signaled().unwrap_or(false));
+            if done {
+                slot.fence = None;
+            }
+        }
+
+        self.shown_slot = self
+            .video_slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.fence.is_none())
+            .max_by_key(|(_, slot)| slot.frame)
+            .map(|(index, _)| index);
+    }
+
+    /// Copies a video frame through a free slot of the ring. The frame is skipped if
+    /// the GPU is still busy with all of them, a newer one follows soon anyway.
+    fn upload_video_frame(&mut self, frame: VideoFrame) {
+        let free = (0..UPLOAD_SLOTS).find(|&index| {
+            Some(index) != self.shown_slot
+                && self
+                    .video_slots
+                    .get(index)
+                    .map_or(true, |slot| slot.fence.is_none())
+        });
+        let Some(index) = free else {
+            return;
+        };
+
+        // The buffer and the texture are only recreated when the size of the video changes
+        let reusable = self.video_slots.get(index).map_or(false, |slot| {
+            slot.texture.image().dimensions().width_height() == frame.dims
+        });
+        if !reusable {
+            let buffer = Buffer::new_slice::<u8>(
+                &self.allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Upload,
+                    ..Default::default()
+                },
+                frame.pixels.len() as DeviceSize,
+            )
+            .expect("failed to create buffer");
+            let slot = UploadSlot {
+                buffer,
+                texture: self.new_texture(frame.dims),
+                fence: None,
+                frame: 0,
+            };
+            if index < self.video_slots.len() {
+                self.video_slots[index] = slot;
+            } else {
+                self.video_slots.push(slot);
+            }
+        }
+
+        let slot = &mut self.video_slots[index];
+        match slot.buffer.write() {
+            Ok(mut buffer) => buffer.copy_from_slice(&frame.pixels),
+            Err(_) => return,
+        }
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            &self.cb_allocator,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        command_buffer_builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                slot.buffer.clone(),
+                slot.texture.image().clone(),
+            ))
+            .unwrap();
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        let fence = sync::now(self.gfx_queue.device().clone())
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+
+        self.uploaded_frames += 1;
+        slot.fence = Some(fence);
+        slot.frame = self.uploaded_frames;
+    }
+
+    /// Copies the RGBA pixels of an image into the texture
+    fn upload(
+        &mut self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pixels: Vec<u8>,
+        dims: [u32; 2],
+    ) {
+        let texture = self.new_texture(dims);
+        self.texture = Some(texture.clone());
+
+        let buffer = Buffer::from_iter(
+            &self.allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            pixels,
+        )
+        .expect("failed to create buffer");
+
+        command_buffer_builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                buffer,
+                texture.image().clone(),
+            ))
+            .unwrap();
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/bloom/fullscreen.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/background/background.frag"
+    }
+}
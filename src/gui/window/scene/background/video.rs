@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use crossbeam_channel::Receiver;
+
+use crate::notifications;
+
+/// A decoded frame of the background video in RGBA
+#[cfg_attr(not(feature = "video-background"), allow(dead_code))]
+pub struct VideoFrame {
+    pub pixels: Vec<u8>,
+    pub dims: [u32; 2],
+}
+
+/// Decodes a video in a loop on a separate thread, at the speed it is meant to play at.
+/// The thread stops once the decoder is dropped.
+#[cfg_attr(not(feature = "video-background"), allow(dead_code))]
+pub struct VideoDecoder {
+    receiver: Receiver<VideoFrame>,
+}
+
+impl VideoDecoder {
+    #[cfg(feature = "video-background")]
+    pub fn open(path: &Path) -> Option<Self> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+
+        let path = path.to_owned();
+        std::thread::spawn(move || loop {
+            match decoding::decode(&path, &sender) {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(e) => {
+                    notifications::error(format!("Failed to decode background video: {}", e));
+                    break;
+                }
+            }
+        });
+
+        Some(Self { receiver })
+    }
+
+    #[cfg(not(feature = "video-background"))]
+    pub fn open(_path: &Path) -> Option<Self> {
+        notifications::error(
+            "Video backgrounds need Wasabi to be built with the `video-background` feature",
+        );
+        None
+    }
+
+    /// The newest frame that is due, if there is one since the last call
+    pub fn next_frame(&self) -> Option<VideoFrame> {
+        self.receiver.try_iter().last()
+    }
+}
+
+#[cfg(feature = "video-background")]
+mod decoding {
+    use std::{
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    use crossbeam_channel::Sender;
+    use ffmpeg_next::{
+        codec, decoder,
+        format::{self, Pixel},
+        media::Type,
+        software::scaling::{self, Flags},
+        util::frame::Video,
+        Error,
+    };
+
+    use super::VideoFrame;
+
+    /// Plays the video through once, returns `false` if the decoder was dropped
+    pub fn decode(path: &Path, sender: &Sender<VideoFrame>) -> Result<bool, Error> {
+        ffmpeg_next::init()?;
+
+        let mut input = format::input(&path)?;
+        let stream = input
+            .streams()
+            .best(Type::Video)
+            .ok_or(Error::StreamNotFound)?;
+        let stream_index = stream.index();
+        let time_base = f64::from(stream.time_base());
+
+        let context = codec::context::Context::from_parameters(stream.parameters())?;
+        let mut decoder = context.decoder().video()?;
+        let mut scaler = scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::RGBA,
+            decoder.width(),
+            decoder.height(),
+            Flags::BILINEAR,
+        )?;
+
+        let start = Instant::now();
+
+        let mut receive_frames = |decoder: &mut decoder::Video| -> Result<bool, Error> {
+            let mut decoded = Video::empty();
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                let mut rgba = Video::empty();
+                scaler.run(&decoded, &mut rgba)?;
+
+                // Wait until the frame is due
+                let time = decoded.timestamp().unwrap_or(0).max(0) as f64 * time_base;
+                let due = start + Duration::from_secs_f64(time);
+                if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                    std::thread::sleep(wait);
+                }
+
+                // The rows of the frame can be padded, so they are copied one by one
+                let width = rgba.width() as usize * 4;
+                let stride = rgba.stride(0);
+                let pixels = rgba
+                    .data(0)
+                    .chunks(stride)
+                    .take(rgba.height() as usize)
+                    .flat_map(|row| row[..width].iter().copied())
+                    .collect();
+
+                let frame = VideoFrame {
+                    pixels,
+                    dims: [rgba.width(), rgba.height()],
+                };
+                if sender.send(frame).is_err() {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+
+        for (stream, packet) in input.packets() {
+            if stream.index() == stream_index {
+                decoder.send_packet(&packet)?;
+                if !receive_frames(&mut decoder)? {
+                    return Ok(false);
+                }
+            }
+        }
+
+        decoder.send_eof()?;
+        receive_frames(&mut decoder)
+    }
+}
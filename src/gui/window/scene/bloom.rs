@@ -0,0 +1,328 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Queue,
+    format::Format,
+    image::{view::ImageView, AttachmentImage, ImageAccess, ImageViewAbstract},
+    memory::allocator::StandardMemoryAllocator,
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::{self, GpuFuture},
+};
+
+use crate::gui::GuiRenderer;
+
+/// The format of the images that the bloom brightness is written to and blurred in
+pub const BLOOM_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Blurs a brightness image and adds it on top of the rendered frame
+pub struct BloomPass {
+    gfx_queue: Arc<Queue>,
+    blur_pipeline: Arc<GraphicsPipeline>,
+    composite_pipeline: Arc<GraphicsPipeline>,
+    blur_render_pass: Arc<RenderPass>,
+    composite_render_pass: Arc<RenderPass>,
+    sampler: Arc<Sampler>,
+    blur_images: [Arc<ImageView<AttachmentImage>>; 2],
+    allocator: StandardMemoryAllocator,
+    cb_allocator: StandardCommandBufferAllocator,
+    sd_allocator: StandardDescriptorSetAllocator,
+}
+
+fn new_blur_image(
+    allocator: &StandardMemoryAllocator,
+    dims: [u32; 2],
+) -> Arc<ImageView<AttachmentImage>> {
+    ImageView::new_default(AttachmentImage::sampled(allocator, dims, BLOOM_FORMAT).unwrap())
+        .unwrap()
+}
+
+impl BloomPass {
+    pub fn new(renderer: &GuiRenderer) -> BloomPass {
+        let allocator = StandardMemoryAllocator::new_default(renderer.device.clone());
+
+        let gfx_queue = renderer.queue.clone();
+
+        let blur_render_pass = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                blurred: {
+                    load: DontCare,
+                    store: Store,
+                    format: BLOOM_FORMAT,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [blurred],
+                    depth_stencil: {},
+                    input: []
+                }
+            ]
+        )
+        .unwrap();
+
+        let composite_render_pass = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                final_color: {
+                    load: Load,
+                    store: Store,
+                    format: renderer.format,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [final_color],
+                    depth_stencil: {},
+                    input: []
+                }
+            ]
+        )
+        .unwrap();
+
+        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let blur_fs =
+            blur_fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let composite_fs =
+            composite_fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+
+        let blur_pipeline = GraphicsPipeline::start()
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .fragment_shader(blur_fs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .render_pass(Subpass::from(blur_render_pass.clone(), 0).unwrap())
+            .build_with_cache(renderer.pipeline_cache.clone())
+            .build(gfx_queue.device().clone())
+            .unwrap();
+
+        let composite_pipeline = GraphicsPipeline::start()
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .fragment_shader(composite_fs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .color_blend_state(ColorBlendState::new(1).blend_additive())
+            .render_pass(Subpass::from(composite_render_pass.clone(), 0).unwrap())
+            .build_with_cache(renderer.pipeline_cache.clone())
+            .build(gfx_queue.device().clone())
+            .unwrap();
+
+        let sampler = Sampler::new(
+            gfx_queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let blur_images = [
+            new_blur_image(&allocator, [1, 1]),
+            new_blur_image(&allocator, [1, 1]),
+        ];
+
+        BloomPass {
+            gfx_queue,
+            blur_pipeline,
+            composite_pipeline,
+            blur_render_pass,
+            composite_render_pass,
+            sampler,
+            blur_images,
+            allocator,
+            cb_allocator: StandardCommandBufferAllocator::new(
+                renderer.device.clone(),
+                Default::default(),
+            ),
+            sd_allocator: StandardDescriptorSetAllocator::new(renderer.device.clone()),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        source: Arc<dyn ImageViewAbstract + 'static>,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        radius: f32,
+    ) {
+        let img_dims = final_image.image().dimensions().width_height();
+        if self.blur_images[0].image().dimensions().width_height() != img_dims {
+            self.blur_images = [
+                new_blur_image(&self.allocator, img_dims),
+                new_blur_image(&self.allocator, img_dims),
+            ];
+        }
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            &self.cb_allocator,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        // The source can be smaller than the frame when the notes were drawn at a lower
+        // resolution, so its taps are spread by frame pixels rather than by its own texels
+        let source_width = source.image().dimensions().width() as f32;
+        let horizontal_step = source_width / img_dims[0] as f32;
+
+        // Blur horizontally, then vertically, then add the result onto the frame
+        let [horizontal, vertical] = self.blur_images.clone();
+        self.blur_pass(
+            &mut command_buffer_builder,
+            source,
+            horizontal.clone(),
+            [horizontal_step, 0.0],
+            radius,
+        );
+        self.blur_pass(
+            &mut command_buffer_builder,
+            horizontal,
+            vertical.clone(),
+            [0.0, 1.0],
+            radius,
+        );
+
+        let framebuffer = Framebuffer::new(
+            self.composite_render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let pipeline_layout = self.composite_pipeline.layout();
+        let set = PersistentDescriptorSet::new(
+            &self.sd_allocator,
+            pipeline_layout.set_layouts().get(0).unwrap().clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                vertical,
+                self.sampler.clone(),
+            )],
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassContents::Inline,
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.composite_pipeline.clone())
+            .set_viewport(0, [viewport(img_dims)])
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline_layout.clone(), 0, set)
+            .draw(3, 1, 0, 0)
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        sync::now(self.gfx_queue.device().clone())
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+
+    fn blur_pass(
+        &self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        source: Arc<dyn ImageViewAbstract + 'static>,
+        target: Arc<ImageView<AttachmentImage>>,
+        direction: [f32; 2],
+        radius: f32,
+    ) {
+        let img_dims = target.image().dimensions().width_height();
+
+        let framebuffer = Framebuffer::new(
+            self.blur_render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![target],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let pipeline_layout = self.blur_pipeline.layout();
+        let set = PersistentDescriptorSet::new(
+            &self.sd_allocator,
+            pipeline_layout.set_layouts().get(0).unwrap().clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                source,
+                self.sampler.clone(),
+            )],
+        )
+        .unwrap();
+
+        let push_constants = blur_fs::PushConstants { direction, radius };
+
+        command_buffer_builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassContents::Inline,
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.blur_pipeline.clone())
+            .set_viewport(0, [viewport(img_dims)])
+            .push_constants(pipeline_layout.clone(), 0, push_constants)
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline_layout.clone(), 0, set)
+            .draw(3, 1, 0, 0)
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+    }
+}
+
+fn viewport(dims: [u32; 2]) -> Viewport {
+    Viewport {
+        origin: [0.0, 0.0],
+        dimensions: [dims[0] as f32, dims[1] as f32],
+        depth_range: 0.0..1.0,
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/bloom/fullscreen.vert"
+    }
+}
+
+mod blur_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/bloom/blur.frag"
+    }
+}
+
+mod composite_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/bloom/composite.frag"
+    }
+}
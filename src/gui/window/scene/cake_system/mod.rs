@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use bytemuck::{Pod, Zeroable};
 use vulkano::{
@@ -33,9 +33,10 @@ use crate::{
         GuiRenderer,
     },
     midi::{CakeBlock, CakeMIDIFile, CakeSignature, IntVector4},
+    settings::WasabiSettings,
 };
 
-use super::RenderResultData;
+use super::{transpose_key, RenderResultData};
 
 const BUFFER_ARRAY_LEN: u64 = 256;
 
@@ -169,6 +170,7 @@ impl CakeRenderer {
         let pipeline_clear = pipeline_base
             .clone()
             .render_pass(Subpass::from(render_pass_clear.clone(), 0).unwrap())
+            .build_with_cache(renderer.pipeline_cache.clone())
             .build(gfx_queue.device().clone())
             .unwrap();
 
@@ -208,8 +210,13 @@ impl CakeRenderer {
         key_view: &KeyboardView,
         final_image: Arc<dyn ImageViewAbstract + 'static>,
         midi_file: &mut CakeMIDIFile,
-        view_range: f64,
+        settings: &WasabiSettings,
+        brightness: f32,
     ) -> RenderResultData {
+        let fill_start = Instant::now();
+        let view_range = settings.midi.note_speed;
+        let transpose = settings.midi.transpose_semitones;
+
         let img_dims = final_image.image().dimensions().width_height();
         if self.depth_buffer.image().dimensions().width_height() != img_dims {
             self.depth_buffer = ImageView::new_default(
@@ -237,18 +244,31 @@ impl CakeRenderer {
         let screen_start = (midi_time * midi_file.ticks_per_second() as f64) as i32;
         let screen_end = ((midi_time + view_range) * midi_file.ticks_per_second() as f64) as i32;
 
+        // The notes are laid out sideways in waterfall mode, so the sizes are swapped
+        let waterfall = settings.visual.waterfall_mode;
+        let (screen_width, screen_height) = if waterfall {
+            (img_dims[1] as i32, img_dims[0] as i32)
+        } else {
+            (img_dims[0] as i32, img_dims[1] as i32)
+        };
+
         let push_constants = gs::PushConstants {
             start_time: screen_start,
             end_time: screen_end,
-            screen_width: img_dims[0] as i32,
-            screen_height: img_dims[1] as i32,
+            screen_width,
+            screen_height,
+            brightness,
+            waterfall: waterfall as i32,
         };
 
         let mut buffer_instances = self.buffers_init.write().unwrap();
         // Black keys first
         let mut written_instances = 0;
         for (i, buffer) in self.buffers.buffers.iter().enumerate() {
-            let key = key_view.note(i);
+            let key = match transpose_key(i, transpose) {
+                Some(key) => key_view.note(key),
+                None => continue,
+            };
             if key.black {
                 buffer_instances[written_instances] = CakeNoteColumn {
                     buffer_index: i as i32,
@@ -262,7 +282,10 @@ impl CakeRenderer {
         }
         // Then white keys
         for (i, buffer) in self.buffers.buffers.iter().enumerate() {
-            let key = key_view.note(i);
+            let key = match transpose_key(i, transpose) {
+                Some(key) => key_view.note(key),
+                None => continue,
+            };
             if !key.black {
                 buffer_instances[written_instances] = CakeNoteColumn {
                     buffer_index: i as i32,
@@ -346,20 +369,25 @@ impl CakeRenderer {
         command_buffer_builder.end_render_pass().unwrap();
         let command_buffer = command_buffer_builder.build().unwrap();
 
+        let fill_time = fill_start.elapsed();
+
+        let submit_start = Instant::now();
         let now = sync::now(self.gfx_queue.device().clone()).boxed();
         let render_future = now
             .then_execute(self.gfx_queue.clone(), command_buffer)
             .unwrap()
             .boxed();
+        let submit_time = submit_start.elapsed();
 
         // Calculate the metadata before awaiting the future
         // to keep this more efficient
-        let colors = midi_file
-            .key_blocks()
-            .iter()
-            .map(|block| block.get_note_at(screen_start as u32).map(|n| n.color))
-            .collect();
-        let rendered_notes = midi_file
+        let mut colors = vec![None; 256];
+        for (i, block) in midi_file.key_blocks().iter().enumerate() {
+            if let Some(key) = transpose_key(i, transpose) {
+                colors[key] = block.get_note_at(screen_start as u32).map(|n| n.color);
+            }
+        }
+        let notes_per_key: Vec<u64> = midi_file
             .key_blocks()
             .iter()
             .map(|block| {
@@ -372,17 +400,36 @@ impl CakeRenderer {
                     passed as u64
                 }
             })
-            .sum();
+            .collect();
+        let rendered_notes = notes_per_key.iter().sum();
+        let most_active_key = notes_per_key
+            .iter()
+            .enumerate()
+            .filter_map(|(i, notes)| Some((*notes, transpose_key(i, transpose)?)))
+            .filter(|(notes, _)| *notes > 0)
+            .max()
+            .map(|(_, key)| key as u8);
 
+        let wait_start = Instant::now();
         render_future
             .then_signal_fence_and_flush()
             .unwrap()
             .wait(None)
             .unwrap();
+        let render_time = submit_time + wait_start.elapsed();
 
         RenderResultData {
             notes_rendered: rendered_notes,
             key_colors: colors,
+            most_active_key,
+            render_time_ms: render_time.as_secs_f32() * 1000.0,
+            cpu_fill_time_ms: fill_time.as_secs_f32() * 1000.0,
+            velocity_histogram: [0; 16],
+            picked_note: None,
+            drum_colors: Vec::new(),
+            visible_channels: 0,
+            key_channels: Vec::new(),
+            captured_notes: None,
         }
     }
 }
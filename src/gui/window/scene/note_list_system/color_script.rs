@@ -0,0 +1,225 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use mlua::{Function, Lua};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    midi::{DisplacedMIDINote, MIDIColor},
+    notifications,
+};
+
+/// How many results each thread keeps before it forgets them all, since every
+/// combination of key, velocity, channel and track could get its own
+const MAX_CACHED_RESULTS: usize = 1 << 16;
+
+/// Counts the loaded scripts, so each thread knows when its own copy is outdated
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The notes are filled on the rayon threads, so every thread runs its own copy
+    /// of the script instead of waiting on the others for a single one
+    static THREAD_SCRIPT: RefCell<Option<ThreadScript>> = RefCell::new(None);
+}
+
+struct ThreadScript {
+    generation: u64,
+    lua: Lua,
+    /// Packed as RGB with the alpha in the highest byte
+    colors: FxHashMap<u64, u32>,
+    keys: FxHashMap<u64, u8>,
+}
+
+impl ThreadScript {
+    fn cache<V: Copy>(
+        cache: &mut FxHashMap<u64, V>,
+        cache_key: u64,
+        call: impl FnOnce() -> V,
+    ) -> V {
+        if let Some(&value) = cache.get(&cache_key) {
+            return value;
+        }
+        if cache.len() >= MAX_CACHED_RESULTS {
+            cache.clear();
+        }
+        let value = call();
+        cache.insert(cache_key, value);
+        value
+    }
+}
+
+/// A Lua script that picks the color and the key of the notes. It can define a global
+/// function `color(key, velocity, channel, track)` that returns `r, g, b, a` from 0 to
+/// 255, where the alpha is optional and an alpha of 0 hides the note, and a global
+/// function `layout(key, velocity, channel, track)` that returns the key the note is
+/// drawn on, or nothing to keep it on its own key.
+pub struct ColorScript {
+    code: String,
+    name: String,
+    generation: u64,
+    has_color: bool,
+    has_layout: bool,
+    failed: AtomicBool,
+}
+
+impl ColorScript {
+    pub fn load(path: &Path) -> Option<Self> {
+        let code = match fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(e) => {
+                notifications::error(format!("Failed to read the color script: {}", e));
+                return None;
+            }
+        };
+        let name = path.to_string_lossy().to_string();
+
+        // Loaded once here to report the errors, the threads load their own copies
+        let lua = match Self::new_lua(&code, &name) {
+            Ok(lua) => lua,
+            Err(e) => {
+                notifications::error(format!("Failed to load the color script: {}", e));
+                return None;
+            }
+        };
+        let has_function = |function| lua.globals().get::<_, Function>(function).is_ok();
+        let has_color = has_function("color");
+        let has_layout = has_function("layout");
+        if !has_color && !has_layout {
+            notifications::error("The color script defines neither a color nor a layout function");
+            return None;
+        }
+
+        Some(Self {
+            code,
+            name,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            has_color,
+            has_layout,
+            failed: AtomicBool::new(false),
+        })
+    }
+
+    fn new_lua(code: &str, name: &str) -> mlua::Result<Lua> {
+        let lua = Lua::new();
+        lua.load(code).set_name(name).exec()?;
+        Ok(lua)
+    }
+
+    /// Runs `f` with this thread's copy of the script, loading it first if needed
+    fn with_thread_script<T>(&self, fallback: T, f: impl FnOnce(&mut ThreadScript) -> T) -> T {
+        if self.failed.load(Ordering::Relaxed) {
+            return fallback;
+        }
+
+        THREAD_SCRIPT.with(|script| {
+            let mut script = script.borrow_mut();
+            if script.as_ref().map(|s| s.generation) != Some(self.generation) {
+                match Self::new_lua(&self.code, &self.name) {
+                    Ok(lua) => {
+                        *script = Some(ThreadScript {
+                            generation: self.generation,
+                            lua,
+                            colors: FxHashMap::default(),
+                            keys: FxHashMap::default(),
+                        })
+                    }
+                    Err(e) => {
+                        self.fail(e);
+                        return fallback;
+                    }
+                }
+            }
+            f(script.as_mut().unwrap())
+        })
+    }
+
+    fn fail(&self, e: mlua::Error) {
+        // Only reported once, since this would run for every note
+        if !self.failed.swap(true, Ordering::Relaxed) {
+            notifications::error(format!(
+                "The color script failed, using the default colors: {}",
+                e
+            ));
+        }
+    }
+
+    fn cache_key(note: &DisplacedMIDINote, key: u8) -> u64 {
+        (note.track as u64) << 24
+            | (note.channel as u64) << 16
+            | (key as u64) << 8
+            | note.vel as u64
+    }
+
+    /// The color from the script, or `None` if the note is hidden. The default color
+    /// is used if the script has no color function or fails, so a broken script
+    /// doesn't hide everything.
+    pub fn color(
+        &self,
+        note: &DisplacedMIDINote,
+        key: u8,
+        default: MIDIColor,
+    ) -> Option<MIDIColor> {
+        if !self.has_color {
+            return Some(default);
+        }
+
+        let fallback = default.as_u32() | 0xFF000000;
+        let rgba = self.with_thread_script(fallback, |script| {
+            let lua = &script.lua;
+            ThreadScript::cache(&mut script.colors, Self::cache_key(note, key), || {
+                let result = lua
+                    .globals()
+                    .get::<_, Function>("color")
+                    .and_then(|function| {
+                        function.call::<_, (u8, u8, u8, Option<u8>)>((
+                            key,
+                            note.vel,
+                            note.channel,
+                            note.track,
+                        ))
+                    });
+                match result {
+                    Ok((r, g, b, a)) => {
+                        MIDIColor::new(r, g, b).as_u32() | (a.unwrap_or(255) as u32) << 24
+                    }
+                    Err(e) => {
+                        self.fail(e);
+                        fallback
+                    }
+                }
+            })
+        });
+
+        (rgba >> 24 != 0).then(|| MIDIColor::from_u32(rgba & 0xFFFFFF))
+    }
+
+    /// The key the script draws the note on, its own key without a layout function
+    pub fn key(&self, note: &DisplacedMIDINote, key: u8) -> u8 {
+        if !self.has_layout {
+            return key;
+        }
+
+        self.with_thread_script(key, |script| {
+            let lua = &script.lua;
+            ThreadScript::cache(&mut script.keys, Self::cache_key(note, key), || {
+                let result = lua
+                    .globals()
+                    .get::<_, Function>("layout")
+                    .and_then(|function| {
+                        function.call::<_, Option<u8>>((key, note.vel, note.channel, note.track))
+                    });
+                match result {
+                    Ok(layout_key) => layout_key.unwrap_or(key),
+                    Err(e) => {
+                        self.fail(e);
+                        key
+                    }
+                }
+            })
+        })
+    }
+}
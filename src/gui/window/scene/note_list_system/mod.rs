@@ -1,22 +1,271 @@
+mod color_script;
 mod notes_render_pass;
+mod particles;
 
-use std::{cell::UnsafeCell, sync::Arc};
+pub use notes_render_pass::NOTE_BUFFER_SIZE;
 
-use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
-use vulkano::image::ImageViewAbstract;
+use std::{
+    cell::UnsafeCell,
+    fs,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+use vulkano::image::{view::ImageView, AttachmentImage, ImageAccess, ImageViewAbstract};
 
 use crate::{
     gui::{window::keyboard_layout::KeyboardView, GuiRenderer},
-    midi::{DisplacedMIDINote, MIDIColor, MIDIFile, MIDINoteColumnView, MIDINoteViews},
+    midi::{
+        DisplacedMIDINote, MIDIColor, MIDIFile, MIDIFileMetadata, MIDIFileUniqueSignature,
+        MIDINoteColumnView, MIDINoteViews, MIDIViewRange,
+    },
+    settings::{ColorSpace, NoteGap, NoteRenderOrder, PercussionDisplayMode, WasabiSettings},
 };
 
-use self::notes_render_pass::{NotePassStatus, NoteRenderPass, NoteVertex};
+use self::{
+    color_script::ColorScript,
+    notes_render_pass::{NotePassStatus, NoteRenderPass, NoteVertex},
+    particles::{ParticleRenderPass, ParticleSystem, MAX_PARTICLES},
+};
+
+use super::{transpose_key, CapturedNote, NotePick, PickedNote, RenderResultData};
+
+/// How long a channel stays active after its last note stopped playing
+const CHANNEL_ACTIVE_TIME: Duration = Duration::from_secs(2);
 
-use super::RenderResultData;
+/// The channel that General MIDI uses for percussion, channel 10 counted from 0
+const PERCUSSION_CHANNEL: u8 = 9;
+
+/// Notes don't spawn particles when the time jumped further than this, like after a seek
+const MAX_PARTICLE_STEP: f64 = 0.5;
+
+/// How often the color script file is checked for changes
+const COLOR_SCRIPT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The colors of the hands when the notes are split between them
+fn hand_color(right: bool) -> MIDIColor {
+    if right {
+        MIDIColor::new(90, 200, 70)
+    } else {
+        MIDIColor::new(60, 130, 230)
+    }
+}
 
 pub struct NoteRenderer {
     render_pass: NoteRenderPass,
+    /// The MSAA sample count from the settings that the render pass was made for
+    msaa_samples: u32,
+    /// The memory block size from the settings that the render pass allocates with
+    memory_block_size_mb: u64,
     thrad_pool: rayon::ThreadPool,
+    color_script: Option<ColorScript>,
+    /// When the color script file was last changed as of the last time it was loaded,
+    /// so a broken script isn't loaded again every frame
+    color_script_loaded: Option<Option<SystemTime>>,
+    /// When the color script file was last checked for changes
+    color_script_checked: Option<Instant>,
+    /// When each channel last had a note playing, for fading out the inactive ones
+    channels_last_played: [Option<Instant>; 16],
+    /// The vertices of the drawn notes of each column from the last draw, kept so
+    /// their memory is reused
+    column_vertices: Vec<Vec<NoteVertex>>,
+    particles: ParticleSystem,
+    particle_pass: ParticleRenderPass,
+    /// The time of the MIDI and of the frame during the last draw, for finding the
+    /// notes that reached the playhead since then and moving the particles
+    last_time: Option<f64>,
+    last_frame: Instant,
+    /// The note to look for while the notes are written in the next draw
+    pub pick: Option<NotePick>,
+    /// Whether the notes on screen are kept in the next draw, for an export
+    pub capture: bool,
+    /// Colors that replace the colors of the channels in the next draw
+    slot_colors: Vec<Option<MIDIColor>>,
+    /// How long the notes fade in, in seconds, in the next draw
+    note_fadein: f32,
+    chords: ChordGroups,
+}
+
+/// The notes of a column, which are collected and sorted when they
+/// are drawn in a different order than the file has them in
+enum ColumnNotes<Iter> {
+    File(Iter),
+    Sorted(std::vec::IntoIter<DisplacedMIDINote>),
+}
+
+impl<Iter: Iterator<Item = DisplacedMIDINote>> Iterator for ColumnNotes<Iter> {
+    type Item = DisplacedMIDINote;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ColumnNotes::File(iter) => iter.next(),
+            ColumnNotes::Sorted(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            ColumnNotes::File(iter) => iter.size_hint(),
+            ColumnNotes::Sorted(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<Iter: ExactSizeIterator<Item = DisplacedMIDINote>> ExactSizeIterator for ColumnNotes<Iter> {}
+
+/// Puts the notes of a column in the order they are drawn in. The sorts are stable,
+/// so notes that are equal in the order stay in the order of the file.
+fn order_notes<Iter: ExactSizeIterator<Item = DisplacedMIDINote>>(
+    iter: Iter,
+    order: NoteRenderOrder,
+) -> ColumnNotes<Iter> {
+    if order == NoteRenderOrder::FileOrder {
+        return ColumnNotes::File(iter);
+    }
+
+    let mut notes: Vec<_> = iter.collect();
+    match order {
+        NoteRenderOrder::FileOrder => {}
+        NoteRenderOrder::EarliestFirst => notes.sort_by(|a, b| a.start.total_cmp(&b.start)),
+        NoteRenderOrder::LatestFirst => notes.sort_by(|a, b| b.start.total_cmp(&a.start)),
+        NoteRenderOrder::ByTrackDescending => notes.sort_by(|a, b| b.track.cmp(&a.track)),
+    }
+    ColumnNotes::Sorted(notes.into_iter())
+}
+
+/// The starts of the notes are relative to the view in single precision, so the same
+/// note lands on a slightly different time in every draw
+const CHORD_EPSILON: f64 = 0.0001;
+
+/// Notes on the same channel that start together, which are drawn as one block
+struct Chord {
+    start: f64,
+    end: f64,
+    /// The keys of the notes in the chord, from low to high
+    keys: Vec<u8>,
+}
+
+impl Chord {
+    /// How many keys of the chord are below this one
+    fn rank(&self, key: u8) -> usize {
+        self.keys.partition_point(|k| *k < key)
+    }
+
+    /// Whether the note of a key gets a border on its left and on its right. The
+    /// border is left out where the note of another key of the chord touches it.
+    fn borders(&self, key: u8, key_view: &KeyboardView) -> (bool, bool) {
+        let note = key_view.note(key as usize);
+        let mut left = true;
+        let mut right = true;
+        for &other in self.keys.iter().filter(|other| **other != key) {
+            let other = key_view.note(other as usize);
+            if other.left < note.left && other.right >= note.left - f32::EPSILON {
+                left = false;
+            }
+            if other.right > note.right && other.left <= note.right + f32::EPSILON {
+                right = false;
+            }
+        }
+        (left, right)
+    }
+}
+
+/// What the chords were grouped for, which groups them again when it changes
+#[derive(PartialEq)]
+struct ChordSource {
+    signature: MIDIFileUniqueSignature,
+    reversed: bool,
+    transpose: i8,
+    tolerance: f64,
+}
+
+/// The chords of each channel, sorted by their start. They are kept between draws in
+/// the time of the MIDI, so only the notes that came into view since the last draw
+/// are grouped.
+#[derive(Default)]
+struct ChordGroups {
+    channels: Vec<Vec<Chord>>,
+    source: Option<ChordSource>,
+    view_start: f64,
+    /// The end of the view in the last draw, the notes after it aren't grouped yet
+    grouped_until: f64,
+    /// The starts of the notes that came into view, with their keys, for each channel
+    new_starts: Vec<Vec<(f64, u8)>>,
+}
+
+impl ChordGroups {
+    /// Starts over when the chords were grouped for something else or the view went
+    /// back, and otherwise forgets the chords that ended before the view
+    fn prepare(&mut self, source: ChordSource, range: &MIDIViewRange) {
+        if self.source.as_ref() != Some(&source) || range.start < self.view_start {
+            self.channels = (0..16).map(|_| Vec::new()).collect();
+            self.new_starts = (0..16).map(|_| Vec::new()).collect();
+            self.grouped_until = f64::NEG_INFINITY;
+            self.source = Some(source);
+        } else {
+            for chords in self.channels.iter_mut() {
+                let behind =
+                    chords.partition_point(|chord| chord.end + CHORD_EPSILON < range.start);
+                chords.drain(..behind);
+            }
+        }
+        self.view_start = range.start;
+    }
+
+    /// Whether the notes up to this time were grouped in an earlier draw
+    fn grouped(&self, until: f64) -> bool {
+        until <= self.grouped_until
+    }
+
+    /// Whether a note starting at this time wasn't grouped in an earlier draw
+    fn is_new(&self, start: f64) -> bool {
+        start > self.grouped_until - CHORD_EPSILON
+    }
+
+    /// Keeps the start of a note for grouping, unless it was grouped in an earlier draw
+    fn push(&mut self, channel: u8, start: f64, key: u8) {
+        if self.is_new(start) {
+            self.new_starts[(channel & 0xF) as usize].push((start, key));
+        }
+    }
+
+    /// Groups the kept starts into chords. A chord begins at its first note and takes
+    /// every note that starts within the tolerance of it. The kept notes start after
+    /// the ones grouped before, so they only add to the last chords.
+    fn group(&mut self, until: f64, tolerance: f64) {
+        for (chords, starts) in self.channels.iter_mut().zip(self.new_starts.iter_mut()) {
+            starts.sort_by(|a, b| a.0.total_cmp(&b.0));
+            for (start, key) in starts.drain(..) {
+                match chords.last_mut() {
+                    Some(chord) if start - chord.start <= tolerance => {
+                        chord.end = chord.end.max(start);
+                        // Notes repeated on the same key aren't a chord
+                        if let Err(index) = chord.keys.binary_search(&key) {
+                            chord.keys.insert(index, key);
+                        }
+                    }
+                    _ => chords.push(Chord {
+                        start,
+                        end: start,
+                        keys: vec![key],
+                    }),
+                }
+            }
+        }
+        self.grouped_until = self.grouped_until.max(until);
+    }
+
+    /// The chord that a note starting at this time is part of, if any
+    fn find(&self, channel: u8, start: f64) -> Option<&Chord> {
+        let chords = self.channels.get(channel as usize)?;
+        let index = chords.partition_point(|chord| chord.start <= start + CHORD_EPSILON);
+        chords[..index]
+            .last()
+            .filter(|chord| start <= chord.end + CHORD_EPSILON && chord.keys.len() > 1)
+    }
 }
 
 struct UnsafeSyncCell<T>(UnsafeCell<T>);
@@ -35,159 +284,728 @@ impl<T> UnsafeSyncCell<T> {
 unsafe impl<T> Sync for UnsafeSyncCell<T> {}
 unsafe impl<T> Send for UnsafeSyncCell<T> {}
 
+/// A gap in ticks, which is turned into seconds with the tempo where each note ends
+struct TickGap {
+    /// The gap in quarter notes
+    beats: f64,
+    metadata: Arc<MIDIFileMetadata>,
+    time: f64,
+    /// The tempo map runs the other way when the MIDI is played backwards
+    reversed_length: Option<f64>,
+}
+
+impl TickGap {
+    /// The gap of a note that ends this many seconds from the current time
+    fn seconds_at(&self, end: f32) -> f32 {
+        let end = self.time + end as f64;
+        let end = match self.reversed_length {
+            Some(length) => length - end,
+            None => end,
+        };
+        (self.beats * 60.0 / self.metadata.bpm_at(end)) as f32
+    }
+}
+
 impl NoteRenderer {
-    pub fn new(renderer: &GuiRenderer) -> NoteRenderer {
+    pub fn new(
+        renderer: &GuiRenderer,
+        msaa_samples: u32,
+        memory_block_size_mb: u64,
+        note_buffer_size: u64,
+    ) -> NoteRenderer {
         NoteRenderer {
-            render_pass: NoteRenderPass::new(renderer),
+            render_pass: NoteRenderPass::new(
+                renderer,
+                msaa_samples,
+                memory_block_size_mb,
+                note_buffer_size,
+            ),
+            msaa_samples,
+            memory_block_size_mb,
             thrad_pool: rayon::ThreadPoolBuilder::new().build().unwrap(),
+            color_script: None,
+            color_script_loaded: None,
+            color_script_checked: None,
+            channels_last_played: [None; 16],
+            column_vertices: Vec::new(),
+            particles: ParticleSystem::new(),
+            particle_pass: ParticleRenderPass::new(renderer),
+            last_time: None,
+            last_frame: Instant::now(),
+            pick: None,
+            capture: false,
+            slot_colors: Vec::new(),
+            note_fadein: 0.0,
+            chords: ChordGroups::default(),
         }
     }
 
+    /// Replaces the colors of the channels, indexed like the track colors, in the next draw
+    pub fn with_slot_colors(&mut self, slot_colors: &[Option<MIDIColor>]) -> &mut Self {
+        self.slot_colors.clear();
+        self.slot_colors.extend_from_slice(slot_colors);
+        self
+    }
+
+    /// Fades the notes in over the attack of the voices in the next draw
+    pub fn with_note_fadein(&mut self, note_fadein: f32) -> &mut Self {
+        self.note_fadein = note_fadein;
+        self
+    }
+
+    /// A bitmask of the channels that haven't played a note recently. The notes that are
+    /// playing are only known after they are written, so this lags behind by a frame.
+    fn inactive_channels(&self) -> u16 {
+        let mut inactive = 0;
+        for (channel, last_played) in self.channels_last_played.iter().enumerate() {
+            let active = last_played.map_or(false, |time| time.elapsed() < CHANNEL_ACTIVE_TIME);
+            if !active {
+                inactive |= 1 << channel;
+            }
+        }
+        inactive
+    }
+
+    /// Loads the color script when it is enabled, and again when the file changed since
+    /// it was last checked
+    fn update_color_script(&mut self, settings: &WasabiSettings) {
+        if !settings.visual.use_color_script {
+            self.color_script = None;
+            self.color_script_loaded = None;
+            self.color_script_checked = None;
+            return;
+        }
+
+        if self.color_script_checked.map_or(false, |checked| {
+            checked.elapsed() < COLOR_SCRIPT_POLL_INTERVAL
+        }) {
+            return;
+        }
+        self.color_script_checked = Some(Instant::now());
+
+        let path = WasabiSettings::color_script_path();
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if self.color_script_loaded != Some(modified) {
+            self.color_script = ColorScript::load(&path);
+            self.color_script_loaded = Some(modified);
+        }
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    pub fn memory_block_size_mb(&self) -> u64 {
+        self.memory_block_size_mb
+    }
+
+    pub fn bloom_source(&self) -> Arc<ImageView<AttachmentImage>> {
+        self.render_pass.bloom_image()
+    }
+
     pub fn draw(
         &mut self,
         key_view: &KeyboardView,
         final_image: Arc<dyn ImageViewAbstract + 'static>,
         midi_file: &mut impl MIDIFile,
-        view_range: f64,
+        settings: &WasabiSettings,
+        brightness: f32,
+        track_order: &[usize],
     ) -> RenderResultData {
-        let note_views = midi_file.get_current_column_views(view_range);
+        self.update_color_script(settings);
+        let color_script = self.color_script.as_ref();
+        let pick = self.pick.take();
+        let capture = std::mem::take(&mut self.capture);
+        let slot_colors = &self.slot_colors;
+
+        // The echo shows the notes from a few beats ago, so the view starts that much earlier
+        let time = midi_file.timer().get_time().as_secs_f64();
+        let echo = settings
+            .visual
+            .echo_beats
+            .map(|beats| beats as f64 * 60.0 / midi_file.bpm_at(time));
+
+        let tick_gap = match settings.visual.note_gap {
+            NoteGap::Ticks { ticks } if ticks > 0 => Some(TickGap {
+                beats: ticks as f64 / midi_file.ppq().max(1) as f64,
+                metadata: midi_file.metadata(),
+                time,
+                reversed_length: midi_file
+                    .is_reversed()
+                    .then(|| midi_file.midi_length())
+                    .flatten(),
+            }),
+            _ => None,
+        };
+
+        // Notes that started since the last draw burst into particles
+        let particles_per_note = settings.visual.particles_per_note;
+        let played = self.last_time.map_or(0.0, |last| time - last);
+        let crossing_window = if (0.0..MAX_PARTICLE_STEP).contains(&played) {
+            played as f32
+        } else {
+            0.0
+        };
+        self.last_time = Some(time);
+
+        let chord_source = ChordSource {
+            signature: midi_file.signature().clone(),
+            reversed: midi_file.is_reversed(),
+            transpose: settings.midi.transpose_semitones,
+            tolerance: settings.visual.chord_tolerance_ms as f64 / 1000.0,
+        };
+
+        let note_views =
+            midi_file.get_current_column_views(settings.midi.note_speed, echo.unwrap_or(0.0));
+        let lookback = (note_views.range().length() - settings.midi.note_speed) as f32;
+        let echo_offset = echo.unwrap_or(0.0) as f32;
+        let transpose = settings.midi.transpose_semitones;
+        let render_order = settings.visual.note_render_order;
+        let percussion_length = settings
+            .visual
+            .normalize_percussion_length
+            .then_some(settings.visual.normalized_length_ms / 1000.0);
+        let drum_pads = settings.visual.percussion_display_mode == PercussionDisplayMode::DrumPad;
+        let hand_split = settings.visual.hand_split_mode;
+
+        // Tracks earlier in the order get a lower depth, so they are drawn on top.
+        // White keys get the back half of the depth range so black keys stay in front.
+        let mut track_depths = vec![0.0; track_order.len()];
+        for (rank, track) in track_order.iter().enumerate() {
+            if let Some(depth) = track_depths.get_mut(*track) {
+                *depth = rank as f32 / track_order.len() as f32 * 0.5;
+            }
+        }
 
         struct ColumnViewInfo<Iter: ExactSizeIterator<Item = DisplacedMIDINote> + Send> {
             offset: usize,
             iter: Iter,
             key: u8,
-            remaining: usize,
+            /// The note number before transposing, which picks the drum on the
+            /// percussion channel
+            source_key: u8,
+            base_depth: f32,
+            /// The vertices of the notes that are drawn, in the order they are drawn
+            vertices: Vec<NoteVertex>,
             color: Option<MIDIColor>,
+            velocities: [u64; 16],
+            playing_channels: u16,
+            /// A bitmask of the channels of the notes that weren't culled
+            visible_channels: u16,
+            /// The closest note to the pick in this column and how far it is from it
+            picked: Option<(f32, PickedNote)>,
+            /// Whether the column is drawn again as the faded echo of the notes
+            echo: bool,
+            /// The colors of the notes that reached the playhead since the last draw
+            crossed: Vec<MIDIColor>,
+            /// The color of the percussion note playing in this column, when the
+            /// percussion channel is shown as drum pads instead of notes
+            drum_color: Option<MIDIColor>,
         }
 
-        let mut total_notes = 0;
+        // The vertex lists of the last draw are reused, so they don't grow again every frame
+        let mut spare_vertices =
+            std::mem::take(&mut self.column_vertices)
+                .into_iter()
+                .map(|mut vertices| {
+                    vertices.clear();
+                    vertices
+                });
 
         let columns: Vec<_> = (0..256).map(|i| note_views.get_column(i)).collect();
 
+        // Chords span several columns, so they are found before the columns are filled
+        let chord_grouping = settings.visual.chord_grouping;
+        let arpeggio_offset = settings
+            .visual
+            .arpeggio_view
+            .then_some(settings.visual.arpeggio_offset_ms / 1000.0);
+        let view_range = note_views.range();
+        let view_time = view_range.start + lookback as f64;
+        let chords = if chord_grouping || arpeggio_offset.is_some() {
+            let chords = &mut self.chords;
+            let tolerance = chord_source.tolerance;
+            chords.prepare(chord_source, &view_range);
+
+            // Only the notes after the end of the view in the last draw are new. The
+            // columns go from the latest note back, so each one stops at the first note
+            // that was grouped before
+            if !chords.grouped(view_range.end) {
+                for (i, column) in columns.iter().enumerate() {
+                    let Some(key) = transpose_key(i, transpose) else {
+                        continue;
+                    };
+                    for note in column.iterate_displaced_notes() {
+                        let start = view_range.start + note.start as f64;
+                        if !chords.is_new(start) {
+                            break;
+                        }
+                        if note.len >= 0.0 {
+                            chords.push(note.channel, start, key as u8);
+                        }
+                    }
+                }
+                chords.group(view_range.end, tolerance);
+            }
+            Some(&self.chords)
+        } else {
+            None
+        };
+
         let mut columns_view_info = Vec::new();
 
         // Add black keys first
         for (i, column) in columns.iter().enumerate() {
-            if key_view.key(i).black {
-                let iter = column.iterate_displaced_notes();
-                let length = iter.len();
+            let key = match transpose_key(i, transpose) {
+                Some(key) => key,
+                None => continue,
+            };
+            if key_view.key(key).black {
+                let iter = order_notes(column.iterate_displaced_notes(), render_order);
                 columns_view_info.push(ColumnViewInfo {
-                    offset: total_notes,
                     iter,
-                    key: i as u8,
-                    remaining: length,
+                    key: key as u8,
+                    source_key: i as u8,
+                    base_depth: 0.0,
+                    vertices: spare_vertices.next().unwrap_or_default(),
                     color: None,
+                    velocities: [0; 16],
+                    playing_channels: 0,
+                    visible_channels: 0,
+                    picked: None,
+                    echo: false,
+                    crossed: Vec::new(),
+                    drum_color: None,
                 });
-                total_notes += length;
             }
         }
 
         // Then white keys after
         for (i, column) in columns.iter().enumerate() {
-            if !key_view.key(i).black {
-                let iter = column.iterate_displaced_notes();
-                let length = iter.len();
+            let key = match transpose_key(i, transpose) {
+                Some(key) => key,
+                None => continue,
+            };
+            if !key_view.key(key).black {
+                let iter = order_notes(column.iterate_displaced_notes(), render_order);
                 columns_view_info.push(ColumnViewInfo {
-                    offset: total_notes,
                     iter,
-                    key: i as u8,
-                    remaining: length,
+                    key: key as u8,
+                    source_key: i as u8,
+                    base_depth: 0.5,
+                    vertices: spare_vertices.next().unwrap_or_default(),
                     color: None,
+                    velocities: [0; 16],
+                    playing_channels: 0,
+                    visible_channels: 0,
+                    picked: None,
+                    echo: false,
+                    crossed: Vec::new(),
+                    drum_color: None,
                 });
-                total_notes += length;
             }
         }
 
-        let mut notes_pushed = 0;
+        // The echo copies of all the columns, which are drawn behind the notes
+        if echo.is_some() {
+            for (i, column) in columns.iter().enumerate() {
+                let key = match transpose_key(i, transpose) {
+                    Some(key) => key,
+                    None => continue,
+                };
+                let iter = order_notes(column.iterate_displaced_notes(), render_order);
+                columns_view_info.push(ColumnViewInfo {
+                    iter,
+                    key: key as u8,
+                    source_key: i as u8,
+                    base_depth: if key_view.key(key).black { 0.0 } else { 0.5 },
+                    vertices: spare_vertices.next().unwrap_or_default(),
+                    color: None,
+                    velocities: [0; 16],
+                    playing_channels: 0,
+                    visible_channels: 0,
+                    picked: None,
+                    echo: true,
+                    crossed: Vec::new(),
+                    drum_color: None,
+                });
+            }
+        }
 
-        let mut cycle = 0;
+        let view_range = note_views.range().length() as f32 - lookback;
 
-        let view_range = note_views.range().length() as f32;
+        let inactive_channels = self.inactive_channels() | settings.midi.muted_channels;
 
-        self.render_pass
-            .draw(final_image, key_view, view_range, |buffer| {
-                let buffer_length = buffer.len() as usize;
+        let img_dims = final_image.image().dimensions().width_height();
 
-                let buffer_writer = UnsafeSyncCell::new(buffer.write().unwrap());
+        // The GPU skips the notes of the keys outside of the view when it can cull them
+        let gpu_culling = settings.rendering.gpu_culling && self.render_pass.supports_gpu_culling();
 
-                // A system to write multiple note columns into 1 large allocated array in parallel
-                let written_notes = self.thrad_pool.install(|| {
-                    // For each note column, write it into the buffer
-                    let written_notes_per_key = columns_view_info.par_iter_mut().map(|column| {
-                        if column.remaining == 0 {
-                            return 0;
+        let fill_start = Instant::now();
+
+        // Each column collects the vertices of the notes it draws first, so the notes
+        // that aren't drawn don't take any space in the buffer
+        self.thrad_pool.install(|| {
+            columns_view_info.par_iter_mut().for_each(|column| {
+                for mut note in column.iter.by_ref() {
+                    // The notes are relative to the start of the view, which is
+                    // earlier than the current time when the echo is shown
+                    note.start -= lookback;
+                    if column.echo {
+                        note.start += echo_offset;
+                    }
+
+                    let chord = chords.as_ref().filter(|_| !column.echo).and_then(|chords| {
+                        chords.find(note.channel, view_time + note.start as f64)
+                    });
+
+                    // The arpeggio view only moves the drawn notes, from the
+                    // lowest key of the chord up
+                    if let (Some(chord), Some(offset)) = (chord, arpeggio_offset) {
+                        note.start += chord.rank(column.key) as f32 * offset;
+                    }
+
+                    // Percussion notes are drawn with the same length, but
+                    // hidden notes keep their negative length
+                    if let Some(length) = percussion_length {
+                        if note.channel == PERCUSSION_CHANNEL && note.len >= 0.0 {
+                            note.len = length;
                         }
+                    }
 
-                        let offset = (column.offset as i64 - notes_pushed as i64).max(0) as usize;
+                    // Every note ends a little early, which only shows where a note
+                    // ends at the start of the next one on its key
+                    if let Some(gap) = &tick_gap {
+                        if note.len >= 0.0 {
+                            let gap = gap.seconds_at(note.start + note.len);
+                            note.len -= gap.min(note.len / 2.0);
+                        }
+                    }
 
-                        if offset >= buffer_length {
-                            return 0;
+                    // The drum pads are drawn by the GUI, so the percussion notes
+                    // only light up their pad instead of being drawn
+                    if drum_pads && note.channel == PERCUSSION_CHANNEL {
+                        if !column.echo
+                            && note.len >= 0.0
+                            && note.start <= 0.0
+                            && note.start + note.len > 0.0
+                            && column.drum_color.is_none()
+                        {
+                            column.drum_color = Some(note.color);
                         }
+                        continue;
+                    }
 
-                        let remaining_buffer_space = buffer_length - offset;
-                        let iter_length = column.remaining;
+                    // Hidden notes with a negative length aren't drawn, so they don't
+                    // count for anything
+                    if note.len < 0.0 {
+                        continue;
+                    }
 
-                        let allowed_to_write = if iter_length > remaining_buffer_space {
-                            remaining_buffer_space
-                        } else {
-                            iter_length
-                        };
+                    // Notes entirely outside of the view don't count either. With GPU
+                    // culling they are still pushed and the compute shader skips them,
+                    // otherwise they are skipped here
+                    let in_view = note.start + note.len >= 0.0 && note.start <= view_range;
+                    if !in_view && !gpu_culling {
+                        continue;
+                    }
 
-                        unsafe {
-                            let buffer = buffer_writer.get_mut();
-
-                            for i in 0..allowed_to_write {
-                                let next_note = column.iter.next();
-                                if let Some(note) = next_note {
-                                    buffer[i + offset] = NoteVertex::new(
-                                        note.start,
-                                        note.len,
-                                        column.key,
-                                        note.color.as_u32(),
-                                    );
-
-                                    if note.start <= 0.0
-                                        && column.color.is_none()
-                                        && note.start + note.len > 0.0
-                                    {
-                                        column.color = Some(note.color);
-                                    }
-                                } else {
-                                    panic!("Invalid iterator length");
+                    // The hands are split on the keys of the file, before transposing
+                    let hand = hand_split.is_right_hand(note.channel, column.source_key);
+                    let default_color = || {
+                        slot_colors
+                            .get(note.track as usize * 16 + note.channel as usize)
+                            .copied()
+                            .flatten()
+                            .unwrap_or(note.color)
+                    };
+                    let color = match (hand, color_script) {
+                        (Some(right), _) => hand_color(right),
+                        (None, Some(script)) => {
+                            match script.color(&note, column.key, default_color()) {
+                                Some(color) => color,
+                                None => {
+                                    continue;
                                 }
                             }
                         }
+                        (None, None) => default_color(),
+                    };
+                    if in_view && !column.echo {
+                        // Broken files can have velocities past 127, which go in the top bucket
+                        column.velocities[(note.vel.min(127) >> 3) as usize] += 1;
+                        column.visible_channels |= 1 << (note.channel & 0xF);
+                    }
 
-                        column.remaining -= allowed_to_write;
+                    let pick =
+                        pick.filter(|pick| in_view && pick.key == column.key && !column.echo);
+                    if let Some(pick) = pick {
+                        let end = note.start + note.len;
+                        let distance = if pick.time < note.start {
+                            note.start - pick.time
+                        } else if pick.time > end {
+                            pick.time - end
+                        } else {
+                            0.0
+                        };
+                        let closer = column.picked.map_or(true, |(picked, _)| distance < picked);
+                        if distance <= pick.tolerance && closer {
+                            column.picked = Some((
+                                distance,
+                                PickedNote {
+                                    key: column.key,
+                                    start: note.start,
+                                    len: note.len,
+                                    vel: note.vel,
+                                    track: note.track,
+                                    channel: note.channel,
+                                    color,
+                                },
+                            ));
+                        }
+                    }
 
-                        allowed_to_write
-                    });
+                    // The echo gets the back half of the depth range, behind the notes
+                    let depth = (column.base_depth
+                        + track_depths
+                            .get(note.track as usize)
+                            .copied()
+                            .unwrap_or(0.0))
+                        * 0.5;
+                    let mut vertex = NoteVertex::new(
+                        note.start,
+                        note.len,
+                        color_script.map_or(column.key, |script| script.key(&note, column.key)),
+                        color.as_u32(),
+                        note.vel,
+                        note.channel,
+                        column.echo,
+                        if column.echo { depth + 0.5 } else { depth },
+                    );
+                    if let Some(chord) = chord.filter(|_| chord_grouping) {
+                        let (left, right) = chord.borders(column.key, key_view);
+                        vertex = vertex.in_chord(left, right);
+                    }
+                    column.vertices.push(vertex);
 
-                    written_notes_per_key.sum::<usize>()
-                });
+                    if !column.echo && note.start <= 0.0 && note.start + note.len > 0.0 {
+                        column.playing_channels |= 1 << (note.channel & 0xF);
+                        if column.color.is_none() {
+                            column.color = Some(color);
+                        }
+                    }
 
-                notes_pushed += written_notes;
+                    if particles_per_note > 0
+                        && !column.echo
+                        && note.start <= 0.0
+                        && note.start > -crossing_window
+                        && column.crossed.len() * (particles_per_note as usize) < MAX_PARTICLES
+                    {
+                        column.crossed.push(color);
+                    }
+                }
+            });
+        });
+
+        // The notes are kept the way they are drawn, so the export matches the screen
+        let captured_notes = capture.then(|| {
+            columns_view_info
+                .iter()
+                .filter(|column| !column.echo)
+                .flat_map(|column| {
+                    // The GPU culls the notes outside of the view, which are still here
+                    column
+                        .vertices
+                        .iter()
+                        .filter(|vertex| {
+                            let [start, len] = vertex.start_length;
+                            start + len >= 0.0 && start <= view_range
+                        })
+                        .map(|vertex| CapturedNote {
+                            key: column.key,
+                            start: vertex.start_length[0],
+                            len: vertex.start_length[1],
+                            color: MIDIColor::from_u32(vertex.key_color >> 8),
+                        })
+                })
+                .collect()
+        });
+
+        // Where the notes of each column start among the notes of all the columns
+        let mut column_offsets = Vec::with_capacity(columns_view_info.len());
+        let mut total_notes = 0;
+        for column in columns_view_info.iter() {
+            column_offsets.push(total_notes);
+            total_notes += column.vertices.len();
+        }
+
+        let mut fill_time = fill_start.elapsed();
+
+        let mut notes_pushed = 0;
+
+        let (render_time, notes_drawn) = self.render_pass.draw(
+            final_image.clone(),
+            key_view,
+            view_range,
+            brightness,
+            &settings.visual,
+            inactive_channels,
+            self.note_fadein,
+            gpu_culling,
+            |buffer| {
+                let fill_start = Instant::now();
+                let batch_start = notes_pushed;
+                let batch_end = (batch_start + buffer.len() as usize).min(total_notes);
+
+                let buffer_writer = UnsafeSyncCell::new(buffer.write().unwrap());
+
+                // Copies the part of each column that is in this batch, in parallel
+                self.thrad_pool.install(|| {
+                    columns_view_info
+                        .par_iter_mut()
+                        .zip(column_offsets.par_iter())
+                        .for_each(|(column, offset)| {
+                            let start = (*offset).max(batch_start);
+                            let end = (offset + column.vertices.len()).min(batch_end);
+                            if start >= end {
+                                return;
+                            }
+
+                            unsafe {
+                                let buffer = buffer_writer.get_mut();
+                                buffer[start - batch_start..end - batch_start].copy_from_slice(
+                                    &column.vertices[start - offset..end - offset],
+                                );
+                            }
+                        });
+                });
 
-                cycle += 1;
+                notes_pushed = batch_end;
+                fill_time += fill_start.elapsed();
 
                 if notes_pushed >= total_notes {
                     NotePassStatus::Finished {
-                        remaining: written_notes as u32,
+                        remaining: (batch_end - batch_start) as u32,
                     }
                 } else {
                     NotePassStatus::HasMoreNotes
                 }
-            });
+            },
+        );
+
+        let delta = self.last_frame.elapsed().as_secs_f32();
+        self.last_frame = Instant::now();
+        self.particles.update(delta);
+
+        // The particles are laid out like the notes, sideways in waterfall mode
+        let (width, height) = if settings.visual.waterfall_mode {
+            (img_dims[1] as f32, img_dims[0] as f32)
+        } else {
+            (img_dims[0] as f32, img_dims[1] as f32)
+        };
+        let lifetime = settings.visual.particle_lifetime_ms / 1000.0;
+
+        // Particles past the most that are alive at once would only replace the ones
+        // spawned in the same frame, so the spawning stops there
+        let mut spawn_budget = MAX_PARTICLES as u32;
+        'spawn: for column in columns_view_info.iter() {
+            let key = key_view.key(column.key as usize);
+            let position = [(key.left + key.right) / 2.0 * width, height];
+            for color in column.crossed.iter() {
+                if spawn_budget == 0 {
+                    break 'spawn;
+                }
+                let count = particles_per_note.min(spawn_budget);
+                self.particles.spawn(position, *color, count, lifetime);
+                spawn_budget -= count;
+            }
+        }
 
-        // Sort for output metrics
-        columns_view_info.sort_unstable_by_key(|k| k.key);
+        if !self.particles.is_empty() {
+            self.particle_pass.draw(
+                final_image,
+                &self.particles,
+                settings.visual.waterfall_mode,
+                settings.visual.color_space == ColorSpace::LinearHdr,
+            );
+        }
+
+        let most_active_key = columns_view_info
+            .iter()
+            .filter(|column| !column.echo)
+            .map(|column| (column.vertices.len(), column.key))
+            .filter(|(notes, _)| *notes > 0)
+            .max()
+            .map(|(_, key)| key);
+
+        let picked_note = columns_view_info
+            .iter()
+            .filter_map(|column| column.picked)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, note)| note);
+
+        // The echo is a copy of notes that are already counted
+        let notes_rendered: usize = columns_view_info
+            .iter()
+            .filter(|column| !column.echo)
+            .map(|column| column.vertices.len())
+            .sum();
+
+        let mut velocity_histogram = [0; 16];
+        for column in columns_view_info.iter() {
+            for (total, count) in velocity_histogram.iter_mut().zip(column.velocities) {
+                *total += count;
+            }
+        }
+
+        let visible_channels = columns_view_info
+            .iter()
+            .fold(0, |channels, column| channels | column.visible_channels);
+
+        let now = Instant::now();
+        for column in columns_view_info.iter() {
+            for (channel, last_played) in self.channels_last_played.iter_mut().enumerate() {
+                if column.playing_channels & (1 << channel) != 0 {
+                    *last_played = Some(now);
+                }
+            }
+        }
+
+        // Columns transposed out of range are skipped, so index colors by key
+        let mut key_colors = vec![None; 256];
+        let mut key_channels = vec![0; 256];
+        for column in columns_view_info.iter() {
+            key_colors[column.key as usize] = column.color;
+            key_channels[column.key as usize] |= column.playing_channels;
+        }
+
+        // Indexed by the drum, so transposing doesn't change which pad lights up
+        let mut drum_colors = vec![None; 256];
+        for column in columns_view_info.iter() {
+            if column.drum_color.is_some() {
+                drum_colors[column.source_key as usize] = column.drum_color;
+            }
+        }
+
+        self.column_vertices = columns_view_info
+            .iter_mut()
+            .map(|column| std::mem::take(&mut column.vertices))
+            .collect();
 
         RenderResultData {
-            notes_rendered: notes_pushed as u64,
-            key_colors: columns_view_info
-                .iter()
-                .map(|column| column.color)
-                .collect(),
+            notes_rendered: notes_drawn.map_or(notes_rendered as u64, u64::from),
+            key_colors,
+            most_active_key,
+            render_time_ms: render_time.as_secs_f32() * 1000.0,
+            cpu_fill_time_ms: fill_time.as_secs_f32() * 1000.0,
+            velocity_histogram,
+            picked_note,
+            drum_colors,
+            visible_channels,
+            key_channels,
+            captured_notes,
         }
     }
 }
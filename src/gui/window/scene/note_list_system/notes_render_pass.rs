@@ -1,35 +1,66 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bytemuck::{Pod, Zeroable};
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        RenderPassBeginInfo, SubpassContents,
+        DrawIndexedIndirectCommand, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
     },
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
     },
     device::{Device, Queue},
     format::Format,
-    image::{view::ImageView, AttachmentImage, ImageAccess, ImageViewAbstract},
-    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    image::{
+        view::ImageView, AttachmentImage, ImageAccess, ImageUsage, ImageViewAbstract, SampleCount,
+        SampleCounts,
+    },
+    memory::allocator::{
+        AllocationCreateInfo, GenericMemoryAllocatorCreateInfo, MemoryUsage,
+        StandardMemoryAllocator,
+    },
     pipeline::{
         graphics::{
+            color_blend::{
+                AttachmentBlend, BlendFactor, BlendOp, ColorBlendState, ColorComponents,
+            },
             depth_stencil::DepthStencilState,
             input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
             vertex_input::Vertex,
             viewport::{Viewport, ViewportState},
         },
-        GraphicsPipeline, Pipeline, PipelineBindPoint,
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     sync::{self, future::FenceSignalFuture, GpuFuture},
 };
 
-use crate::gui::{window::keyboard_layout::KeyboardView, GuiRenderer};
+use crate::{
+    gui::{window::keyboard_layout::KeyboardView, GuiRenderer},
+    notifications,
+    settings::{ColorSpace, NoteGap, VisualSettings},
+};
+
+use super::super::bloom::BLOOM_FORMAT;
+
+/// The notes that fit in one batch of the main view
+pub const NOTE_BUFFER_SIZE: u64 = 25000000;
+
+/// The invocations in a group of the culling shader, which has to match its `GROUP_SIZE`
+const CULL_GROUP_SIZE: u32 = 256;
 
-const NOTE_BUFFER_SIZE: u64 = 25000000;
+/// The most groups that every device can dispatch along one dimension
+const MAX_GROUPS_X: u32 = 65535;
+
+/// The words the culling shader reads a note as, which has to match its `NOTE_WORDS`
+const NOTE_WORDS: usize = 4;
+
+const _: () = assert!(NOTE_WORDS * 4 == std::mem::size_of::<NoteVertex>());
 
 #[repr(C)]
 #[derive(Default, Debug, Copy, Clone, Zeroable, Pod, Vertex)]
@@ -38,15 +69,42 @@ pub struct NoteVertex {
     pub start_length: [f32; 2],
     #[format(R32_UINT)]
     pub key_color: u32,
+    /// The velocity in the lowest 8 bits, then the channel, whether the note is part of
+    /// the echo, the chord bits, and the depth as a 16 bit fraction in the highest bits
+    #[format(R32_UINT)]
+    pub flags: u32,
 }
 
 impl NoteVertex {
-    pub fn new(start: f32, len: f32, key: u8, color: u32) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        start: f32,
+        len: f32,
+        key: u8,
+        color: u32,
+        velocity: u8,
+        channel: u8,
+        echo: bool,
+        depth: f32,
+    ) -> Self {
+        // The depth buffer only has 16 bits, so the depth doesn't lose anything more
+        let depth = (depth.clamp(0.0, 1.0) * u16::MAX as f32).round() as u32;
         Self {
             start_length: [start, len],
             key_color: key as u32 | (color << 8),
+            flags: velocity as u32
+                | ((channel as u32 & 0xF) << 8)
+                | ((echo as u32) << 12)
+                | (depth << 16),
         }
     }
+
+    /// Marks the note as part of a chord, which only gets a border on the sides where
+    /// no other note of the chord touches it, and reaches into the notes on the others
+    pub fn in_chord(mut self, left_border: bool, right_border: bool) -> Self {
+        self.flags |= (1 << 13) | ((left_border as u32) << 14) | ((right_border as u32) << 15);
+        self
+    }
 }
 
 struct BufferSet {
@@ -54,28 +112,29 @@ struct BufferSet {
     index: usize,
 }
 
-fn get_buffer(device: &Arc<Device>) -> (Subbuffer<[NoteVertex]>, Subbuffer<[NoteVertex]>) {
-    let allocator = StandardMemoryAllocator::new_default(device.clone());
-
+fn get_buffer(
+    allocator: &StandardMemoryAllocator,
+    note_buffer_size: u64,
+) -> (Subbuffer<[NoteVertex]>, Subbuffer<[NoteVertex]>) {
     Buffer::new_slice(
-        &allocator,
+        allocator,
         BufferCreateInfo {
-            usage: BufferUsage::VERTEX_BUFFER,
+            usage: BufferUsage::VERTEX_BUFFER | BufferUsage::STORAGE_BUFFER,
             ..Default::default()
         },
         AllocationCreateInfo {
             usage: MemoryUsage::Upload,
             ..Default::default()
         },
-        NOTE_BUFFER_SIZE * 2,
+        note_buffer_size * 2,
     )
     .expect("failed to create buffer")
-    .split_at(NOTE_BUFFER_SIZE)
+    .split_at(note_buffer_size)
 }
 
 impl BufferSet {
-    fn new(device: &Arc<Device>) -> Self {
-        let buffer = get_buffer(device);
+    fn new(allocator: &StandardMemoryAllocator, note_buffer_size: u64) -> Self {
+        let buffer = get_buffer(allocator, note_buffer_size);
         Self {
             vertex_buffers: [buffer.0, buffer.1],
             index: 0,
@@ -88,6 +147,106 @@ impl BufferSet {
     }
 }
 
+/// The buffers that the culling shader writes the indices of the visible notes of a
+/// batch to, which are only allocated once GPU culling is turned on
+#[derive(Clone)]
+struct CullBuffers {
+    indices: Subbuffer<[u32]>,
+    /// The visible notes of each group, and then the offsets of the groups
+    group_counts: Subbuffer<[u32]>,
+    draw_args: Subbuffer<[DrawIndexedIndirectCommand]>,
+    /// The notes drawn by all the batches of a frame, without the echo
+    drawn: Subbuffer<u32>,
+}
+
+impl CullBuffers {
+    fn new(allocator: &StandardMemoryAllocator, note_buffer_size: u64) -> Self {
+        let device_only = || AllocationCreateInfo {
+            usage: MemoryUsage::DeviceOnly,
+            ..Default::default()
+        };
+        let buffer_info = |usage| BufferCreateInfo {
+            usage,
+            ..Default::default()
+        };
+
+        let group_count = (note_buffer_size as u32).div_ceil(CULL_GROUP_SIZE) as u64;
+        Self {
+            indices: Buffer::new_slice(
+                allocator,
+                buffer_info(BufferUsage::INDEX_BUFFER | BufferUsage::STORAGE_BUFFER),
+                device_only(),
+                note_buffer_size,
+            )
+            .expect("failed to create buffer"),
+            group_counts: Buffer::new_slice(
+                allocator,
+                buffer_info(BufferUsage::STORAGE_BUFFER),
+                device_only(),
+                group_count,
+            )
+            .expect("failed to create buffer"),
+            draw_args: Buffer::new_slice(
+                allocator,
+                buffer_info(BufferUsage::INDIRECT_BUFFER | BufferUsage::STORAGE_BUFFER),
+                device_only(),
+                1,
+            )
+            .expect("failed to create buffer"),
+            drawn: Buffer::new_sized(
+                allocator,
+                buffer_info(BufferUsage::STORAGE_BUFFER),
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Download,
+                    ..Default::default()
+                },
+            )
+            .expect("failed to create buffer"),
+        }
+    }
+}
+
+/// Records the culling of a batch, which leaves the indices of the visible notes in
+/// order in the index buffer of `buffers` and their count in its draw arguments. The
+/// stage of `constants` is set for each dispatch.
+fn record_culling(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    sd_allocator: &StandardDescriptorSetAllocator,
+    pipeline: &Arc<ComputePipeline>,
+    buffers: &CullBuffers,
+    notes: &Subbuffer<[NoteVertex]>,
+    constants: cs::PushConstants,
+) {
+    let layout = pipeline.layout();
+    let set = PersistentDescriptorSet::new(
+        sd_allocator,
+        layout.set_layouts().get(0).unwrap().clone(),
+        [
+            WriteDescriptorSet::buffer(0, notes.clone()),
+            WriteDescriptorSet::buffer(1, buffers.indices.clone()),
+            WriteDescriptorSet::buffer(2, buffers.group_counts.clone()),
+            WriteDescriptorSet::buffer(3, buffers.draw_args.clone()),
+            WriteDescriptorSet::buffer(4, buffers.drawn.clone()),
+        ],
+    )
+    .unwrap();
+
+    // Big batches are spread over a second dimension, as the first one is limited
+    let groups = constants.note_count.div_ceil(CULL_GROUP_SIZE).max(1);
+    let groups_x = groups.min(MAX_GROUPS_X);
+    let note_groups = [groups_x, groups.div_ceil(groups_x), 1];
+
+    builder
+        .bind_pipeline_compute(pipeline.clone())
+        .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set);
+    for (stage, group_counts) in [(0, note_groups), (1, [1, 1, 1]), (2, note_groups)] {
+        builder
+            .push_constants(layout.clone(), 0, cs::PushConstants { stage, ..constants })
+            .dispatch(group_counts)
+            .unwrap();
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NotePassStatus {
     Finished { remaining: u32 },
@@ -102,129 +261,129 @@ pub struct KeyPosition {
     _padding: [u8; 8],
 }
 
+/// The pipeline that draws the first batch over the cleared attachments, and the one
+/// that draws the other batches over it
+struct NotePipelines {
+    clear: Arc<GraphicsPipeline>,
+    draw_over: Arc<GraphicsPipeline>,
+}
+
 pub struct NoteRenderPass {
     gfx_queue: Arc<Queue>,
     buffer_set: BufferSet,
-    pipeline_clear: Arc<GraphicsPipeline>,
-    pipeline_draw_over: Arc<GraphicsPipeline>,
+    pipelines: NotePipelines,
+    /// The same pipelines without the bloom image, which isn't written when bloom is off
+    pipelines_no_bloom: NotePipelines,
     render_pass_clear: Arc<RenderPass>,
     render_pass_draw_over: Arc<RenderPass>,
-    key_locations: Subbuffer<[[KeyPosition; 256]]>,
-    depth_buffer: Arc<ImageView<AttachmentImage>>,
+    /// The positions of the visible keys only, allocated on the first draw and again
+    /// whenever the number of visible keys changes
+    key_locations: Option<Subbuffer<[KeyPosition]>>,
+    /// The culling shader, when the device can bind a whole note buffer for it
+    cull_pipeline: Option<Arc<ComputePipeline>>,
+    cull_buffers: Option<CullBuffers>,
+    images: NoteImages,
+    format: Format,
+    samples: SampleCount,
     allocator: StandardMemoryAllocator,
     cb_allocator: StandardCommandBufferAllocator,
     sd_allocator: StandardDescriptorSetAllocator,
 }
 
 impl NoteRenderPass {
-    pub fn new(renderer: &GuiRenderer) -> NoteRenderPass {
-        let allocator = StandardMemoryAllocator::new_default(renderer.device.clone());
+    pub fn new(
+        renderer: &GuiRenderer,
+        msaa_samples: u32,
+        memory_block_size_mb: u64,
+        note_buffer_size: u64,
+    ) -> NoteRenderPass {
+        let allocator = create_allocator(&renderer.device, memory_block_size_mb);
 
         let gfx_queue = renderer.queue.clone();
 
-        let render_pass_clear = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
-            attachments: {
-                final_color: {
-                    load: Clear,
-                    store: Store,
-                    format: renderer.format,
-                    samples: 1,
-                },
-                depth: {
-                    load: Clear,
-                    store: Store,
-                    format: Format::D16_UNORM,
-                    samples: 1,
-                }
-            },
-            passes: [
-                {
-                    color: [final_color],
-                    depth_stencil: {depth},
-                    input: []
-                }
-            ]
-        )
-        .unwrap();
+        let samples = supported_sample_count(&renderer.device, msaa_samples);
+        let (render_pass_clear, render_pass_draw_over) =
+            create_render_passes(gfx_queue.device(), renderer.format, samples);
 
-        let render_pass_draw_over = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
-            attachments: {
-                final_color: {
-                    load: DontCare,
-                    store: Store,
-                    format: renderer.format,
-                    samples: 1,
-                },
-                depth: {
-                    load: DontCare,
-                    store: Store,
-                    format: Format::D16_UNORM,
-                    samples: 1,
-                }
-            },
-            passes: [
-                {
-                    color: [final_color],
-                    depth_stencil: {depth},
-                    input: []
-                }
-            ]
-        )
-        .unwrap();
-
-        let depth_buffer = ImageView::new_default(
-            AttachmentImage::transient_input_attachment(&allocator, [1, 1], Format::D16_UNORM)
-                .unwrap(),
-        )
-        .unwrap();
-
-        let key_locations = Buffer::from_iter(
-            &allocator,
-            BufferCreateInfo {
-                usage: BufferUsage::UNIFORM_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                usage: MemoryUsage::Upload,
-                ..Default::default()
-            },
-            [[Default::default(); 256]].into_iter(),
-        )
-        .unwrap();
+        let images = NoteImages::new(&allocator, renderer.format, samples, [1, 1]);
 
         let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
         let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
         let gs = gs::load(gfx_queue.device().clone()).expect("failed to create shader module");
 
-        let pipeline_base = GraphicsPipeline::start()
-            .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::PointList))
-            .vertex_input_state(NoteVertex::per_vertex())
-            .vertex_shader(vs.entry_point("main").unwrap(), ())
-            .geometry_shader(gs.entry_point("main").unwrap(), ())
-            .fragment_shader(fs.entry_point("main").unwrap(), ())
-            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-            .depth_stencil_state(DepthStencilState::simple_depth_test());
-
-        let pipeline_clear = pipeline_base
-            .clone()
-            .render_pass(Subpass::from(render_pass_clear.clone(), 0).unwrap())
-            .build(gfx_queue.device().clone())
-            .unwrap();
-
-        let pipeline_draw_over = pipeline_base
-            .render_pass(Subpass::from(render_pass_draw_over.clone(), 0).unwrap())
-            .build(gfx_queue.device().clone())
-            .unwrap();
+        // The notes write premultiplied colors, so the faded ones show what is behind them
+        let premultiplied = AttachmentBlend {
+            color_op: BlendOp::Add,
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::OneMinusSrcAlpha,
+            alpha_op: BlendOp::Add,
+            alpha_source: BlendFactor::One,
+            alpha_destination: BlendFactor::OneMinusSrcAlpha,
+        };
+
+        let pipeline_base = |bloom: bool| {
+            let mut color_blend = ColorBlendState::new(2);
+            color_blend.attachments[0].blend = Some(premultiplied);
+            if !bloom {
+                color_blend.attachments[1].color_write_mask = ColorComponents::empty();
+            }
+            GraphicsPipeline::start()
+                .input_assembly_state(
+                    InputAssemblyState::new().topology(PrimitiveTopology::PointList),
+                )
+                .vertex_input_state(NoteVertex::per_vertex())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .geometry_shader(gs.entry_point("main").unwrap(), ())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .color_blend_state(color_blend)
+                .depth_stencil_state(DepthStencilState::simple_depth_test())
+                .multisample_state(MultisampleState {
+                    rasterization_samples: samples,
+                    ..Default::default()
+                })
+        };
+
+        let pipelines = |bloom: bool| NotePipelines {
+            clear: pipeline_base(bloom)
+                .render_pass(Subpass::from(render_pass_clear.clone(), 0).unwrap())
+                .build_with_cache(renderer.pipeline_cache.clone())
+                .build(gfx_queue.device().clone())
+                .unwrap(),
+            draw_over: pipeline_base(bloom)
+                .render_pass(Subpass::from(render_pass_draw_over.clone(), 0).unwrap())
+                .build_with_cache(renderer.pipeline_cache.clone())
+                .build(gfx_queue.device().clone())
+                .unwrap(),
+        };
+        let pipelines_no_bloom = pipelines(false);
+        let pipelines = pipelines(true);
+
+        let cull_pipeline = supports_culling(&renderer.device, note_buffer_size).then(|| {
+            let cs = cs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+            ComputePipeline::new(
+                gfx_queue.device().clone(),
+                cs.entry_point("main").unwrap(),
+                &(),
+                Some(renderer.pipeline_cache.clone()),
+                |_| {},
+            )
+            .unwrap()
+        });
 
         NoteRenderPass {
             gfx_queue,
-            buffer_set: BufferSet::new(&renderer.device),
-            pipeline_clear,
-            pipeline_draw_over,
+            buffer_set: BufferSet::new(&allocator, note_buffer_size),
+            pipelines,
+            pipelines_no_bloom,
             render_pass_clear,
             render_pass_draw_over,
-            depth_buffer,
-            key_locations,
+            images,
+            format: renderer.format,
+            samples,
+            key_locations: None,
+            cull_pipeline,
+            cull_buffers: None,
             allocator,
             cb_allocator: StandardCommandBufferAllocator::new(
                 renderer.device.clone(),
@@ -234,29 +393,68 @@ impl NoteRenderPass {
         }
     }
 
+    /// Whether the notes outside of the view can be culled by the GPU
+    pub fn supports_gpu_culling(&self) -> bool {
+        self.cull_pipeline.is_some()
+    }
+
+    /// The brightness of the notes drawn in the last pass, used as the bloom source
+    pub fn bloom_image(&self) -> Arc<ImageView<AttachmentImage>> {
+        self.images.bloom.clone()
+    }
+
+    /// Draws the notes in batches filled by `fill_buffer`, and returns the time spent on the
+    /// GPU. With `gpu_culling` each batch is culled by a compute shader first, which skips
+    /// the notes of the keys outside of the view, and the count of drawn notes without the
+    /// echo is returned.
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &mut self,
         final_image: Arc<dyn ImageViewAbstract + 'static>,
         key_view: &KeyboardView,
         view_range: f32,
+        brightness: f32,
+        visual: &VisualSettings,
+        inactive_channels: u16,
+        note_fadein: f32,
+        gpu_culling: bool,
         mut fill_buffer: impl FnMut(&Subbuffer<[NoteVertex]>) -> NotePassStatus,
-    ) {
+    ) -> (Duration, Option<u32>) {
         let img_dims = final_image.image().dimensions().width_height();
-        if self.depth_buffer.image().dimensions().width_height() != img_dims {
-            self.depth_buffer = ImageView::new_default(
-                AttachmentImage::transient_input_attachment(
+        if self.images.depth.image().dimensions().width_height() != img_dims {
+            self.images = NoteImages::new(&self.allocator, self.format, self.samples, img_dims);
+        }
+
+        // Notes only store their key in 8 bits, so keys past 255 are never drawn. The
+        // buffer always holds at least one key, as empty buffers can't be created.
+        let key_offset = key_view.visible_range.start.min(255);
+        let key_count = key_view.visible_range.end.clamp(key_offset + 1, 256) - key_offset;
+
+        let key_locations = match &self.key_locations {
+            Some(buffer) if buffer.len() == key_count as u64 => buffer.clone(),
+            _ => {
+                let buffer = Buffer::from_iter(
                     &self.allocator,
-                    img_dims,
-                    Format::D16_UNORM,
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        usage: MemoryUsage::Upload,
+                        ..Default::default()
+                    },
+                    (0..key_count).map(|_| KeyPosition::default()),
                 )
-                .unwrap(),
-            )
-            .unwrap();
-        }
+                .unwrap();
+                self.key_locations = Some(buffer.clone());
+                buffer
+            }
+        };
 
         {
-            let mut keys = self.key_locations.write().unwrap();
-            for (write, key) in keys[0].iter_mut().zip(key_view.iter_all_notes()) {
+            let mut keys = key_locations.write().unwrap();
+            for (i, write) in keys.iter_mut().enumerate() {
+                let key = key_view.note(key_offset + i);
                 *write = KeyPosition {
                     left: key.left,
                     right: key.right,
@@ -265,12 +463,29 @@ impl NoteRenderPass {
             }
         }
 
+        let culling = match &self.cull_pipeline {
+            Some(pipeline) if gpu_culling => {
+                let allocator = &self.allocator;
+                let note_buffer_size = self.buffer_set.vertex_buffers[0].len();
+                let buffers = self
+                    .cull_buffers
+                    .get_or_insert_with(|| CullBuffers::new(allocator, note_buffer_size));
+                // The last frame is done on the GPU, so the count can start over
+                *buffers.drawn.write().unwrap() = 0;
+                Some((pipeline.clone(), buffers.clone()))
+            }
+            _ => None,
+        };
+
         let mut prev_future: Option<FenceSignalFuture<Box<dyn GpuFuture>>> = None;
 
         let mut status = NotePassStatus::HasMoreNotes;
 
         let mut first_pass = true;
 
+        // The time spent submitting the command buffers and waiting for them to finish
+        let mut render_time = Duration::ZERO;
+
         while status == NotePassStatus::HasMoreNotes {
             let buffer = self.buffer_set.next();
 
@@ -291,17 +506,57 @@ impl NoteRenderPass {
             )
             .unwrap();
 
+            // The batches run one after another on the GPU, so they share the culled buffers
+            if let Some((pipeline, buffers)) = &culling {
+                record_culling(
+                    &mut command_buffer_builder,
+                    &self.sd_allocator,
+                    pipeline,
+                    buffers,
+                    buffer,
+                    cs::PushConstants {
+                        note_count: items_to_render,
+                        height_time: view_range,
+                        key_offset: key_offset as u32,
+                        key_count: key_count as u32,
+                        stage: 0,
+                    },
+                );
+            }
+
+            let mut attachments: Vec<Arc<dyn ImageViewAbstract>> =
+                vec![final_image.clone(), self.images.bloom.clone()];
+            let mut clears = vec![
+                Some([0.0, 0.0, 0.0, 0.0].into()),
+                Some([0.0, 0.0, 0.0, 0.0].into()),
+            ];
+
+            // With MSAA the frame and the bloom image are only written by the resolve
+            if let Some(msaa) = &self.images.msaa {
+                attachments.push(msaa.color.clone());
+                attachments.push(msaa.bloom.clone());
+                clears = vec![
+                    None,
+                    None,
+                    Some([0.0, 0.0, 0.0, 0.0].into()),
+                    Some([0.0, 0.0, 0.0, 0.0].into()),
+                ];
+            }
+            attachments.push(self.images.depth.clone());
+            clears.push(Some(1.0f32.into()));
+
+            let pipelines = if visual.bloom_enabled {
+                &self.pipelines
+            } else {
+                &self.pipelines_no_bloom
+            };
             let (clears, pipeline, render_pass) = if first_pass {
                 first_pass = false;
-                (
-                    vec![Some([0.0, 0.0, 0.0, 0.0].into()), Some(1.0f32.into())],
-                    &self.pipeline_clear,
-                    &self.render_pass_clear,
-                )
+                (clears, &pipelines.clear, &self.render_pass_clear)
             } else {
                 (
-                    vec![None, None],
-                    &self.pipeline_draw_over,
+                    vec![None; clears.len()],
+                    &pipelines.draw_over,
                     &self.render_pass_draw_over,
                 )
             };
@@ -309,7 +564,7 @@ impl NoteRenderPass {
             let framebuffer = Framebuffer::new(
                 render_pass.clone(),
                 FramebufferCreateInfo {
-                    attachments: vec![final_image.clone(), self.depth_buffer.clone()],
+                    attachments,
                     ..Default::default()
                 },
             )
@@ -321,7 +576,7 @@ impl NoteRenderPass {
             let set = PersistentDescriptorSet::new(
                 &self.sd_allocator,
                 desc_layout.clone(),
-                [WriteDescriptorSet::buffer(0, self.key_locations.clone())],
+                [WriteDescriptorSet::buffer(0, key_locations.clone())],
             )
             .unwrap();
 
@@ -335,10 +590,36 @@ impl NoteRenderPass {
                 )
                 .unwrap();
 
+            // The notes are laid out sideways in waterfall mode, so the sizes are swapped
+            let (win_width, win_height) = if visual.waterfall_mode {
+                (img_dims[1] as f32, img_dims[0] as f32)
+            } else {
+                (img_dims[0] as f32, img_dims[1] as f32)
+            };
+
             let push_constants = gs::PushConstants {
                 height_time: view_range,
-                win_width: img_dims[0] as f32,
-                win_height: img_dims[1] as f32,
+                win_width,
+                win_height,
+                brightness,
+                waterfall: visual.waterfall_mode as i32,
+                inner_glow_color: visual.inner_glow_color,
+                inner_glow_intensity: visual.inner_glow_intensity,
+                note_shape: visual.note_shape as i32,
+                tone_map: (visual.color_space == ColorSpace::LinearHdr) as i32,
+                note_gap: match visual.note_gap {
+                    NoteGap::Pixels { pixels } => pixels,
+                    NoteGap::Ticks { .. } => 0.0,
+                },
+                inactive_channels: inactive_channels as u32,
+                inactive_alpha: visual.inactive_channel_alpha,
+                min_note_height: visual.min_note_height_px,
+                spotlight: visual.spotlight_mode as i32,
+                key_offset: key_offset as u32,
+                length_opacity: visual.length_opacity_mode as i32,
+                length_opacity_min: visual.length_opacity_min_ms / 1000.0,
+                length_opacity_max: visual.length_opacity_max_ms / 1000.0,
+                note_fadein,
             };
 
             command_buffer_builder
@@ -357,14 +638,23 @@ impl NoteRenderPass {
                     pipeline_layout.clone(),
                     0,
                     set.clone(),
-                )
-                .bind_vertex_buffers(0, buffer.clone())
-                .draw(items_to_render, 1, 0, 0)
-                .unwrap();
+                );
+            match &culling {
+                Some((_, buffers)) => command_buffer_builder
+                    .bind_vertex_buffers(0, buffer.clone())
+                    .bind_index_buffer(buffers.indices.clone())
+                    .draw_indexed_indirect(buffers.draw_args.clone()),
+                None => command_buffer_builder
+                    .bind_vertex_buffers(0, buffer.clone())
+                    .draw(items_to_render, 1, 0, 0),
+            }
+            .unwrap();
 
             command_buffer_builder.end_render_pass().unwrap();
             let command_buffer = command_buffer_builder.build().unwrap();
 
+            let render_start = Instant::now();
+
             if let Some(prev_future) = prev_future.take() {
                 match prev_future.wait(None) {
                     Ok(x) => x,
@@ -383,17 +673,299 @@ impl NoteRenderPass {
                 .expect("Failed to signal fence and flush");
 
             prev_future = Some(future);
+            render_time += render_start.elapsed();
         }
 
+        let render_start = Instant::now();
         if let Some(prev_future) = prev_future {
             match prev_future.wait(None) {
                 Ok(x) => x,
                 Err(err) => println!("err: {err:?}"),
             }
         }
+        let render_time = render_time + render_start.elapsed();
+
+        let drawn = culling.map(|(_, buffers)| *buffers.drawn.read().unwrap());
+        (render_time, drawn)
     }
 }
 
+/// The images the notes are drawn into besides the frame, which match its size
+struct NoteImages {
+    depth: Arc<ImageView<AttachmentImage>>,
+    /// The brightness of the notes that the bloom is made from
+    bloom: Arc<ImageView<AttachmentImage>>,
+    /// The multisampled images that are resolved into the frame and the bloom image
+    msaa: Option<MsaaImages>,
+}
+
+struct MsaaImages {
+    color: Arc<ImageView<AttachmentImage>>,
+    bloom: Arc<ImageView<AttachmentImage>>,
+}
+
+impl NoteImages {
+    fn new(
+        allocator: &StandardMemoryAllocator,
+        format: Format,
+        samples: SampleCount,
+        dims: [u32; 2],
+    ) -> Self {
+        let bloom = ImageView::new_default(
+            AttachmentImage::sampled(allocator, dims, BLOOM_FORMAT).unwrap(),
+        )
+        .unwrap();
+
+        if samples == SampleCount::Sample1 {
+            let depth = ImageView::new_default(
+                AttachmentImage::transient_input_attachment(allocator, dims, Format::D16_UNORM)
+                    .unwrap(),
+            )
+            .unwrap();
+            return Self {
+                depth,
+                bloom,
+                msaa: None,
+            };
+        }
+
+        let multisampled = |format: Format, usage: ImageUsage| {
+            ImageView::new_default(
+                AttachmentImage::multisampled_with_usage(allocator, dims, samples, format, usage)
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+
+        Self {
+            depth: multisampled(Format::D16_UNORM, ImageUsage::DEPTH_STENCIL_ATTACHMENT),
+            bloom,
+            msaa: Some(MsaaImages {
+                color: multisampled(format, ImageUsage::COLOR_ATTACHMENT),
+                bloom: multisampled(BLOOM_FORMAT, ImageUsage::COLOR_ATTACHMENT),
+            }),
+        }
+    }
+}
+
+/// An allocator that takes memory from the device in blocks of the given size, or in
+/// the blocks vulkano picks for the device when it is 0. Bigger blocks mean fewer
+/// allocations from the driver for MIDIs with many notes.
+fn create_allocator(device: &Arc<Device>, block_size_mb: u64) -> StandardMemoryAllocator {
+    if block_size_mb == 0 {
+        return StandardMemoryAllocator::new_default(device.clone());
+    }
+
+    let block_sizes = [(0, block_size_mb * 1024 * 1024)];
+    let create_info = GenericMemoryAllocatorCreateInfo {
+        block_sizes: &block_sizes,
+        ..Default::default()
+    };
+    StandardMemoryAllocator::new(device.clone(), create_info).unwrap_or_else(|e| {
+        notifications::error(format!(
+            "Failed to create an allocator with {} MB blocks, using the default: {}",
+            block_size_mb, e
+        ));
+        StandardMemoryAllocator::new_default(device.clone())
+    })
+}
+
+/// The culling shader reads a whole note buffer, which has to fit in a storage buffer
+fn supports_culling(device: &Arc<Device>, note_buffer_size: u64) -> bool {
+    let max_range = device
+        .physical_device()
+        .properties()
+        .max_storage_buffer_range as u64;
+    note_buffer_size * std::mem::size_of::<NoteVertex>() as u64 <= max_range
+}
+
+/// The highest sample count up to the requested one that the device supports
+/// for both the color and the depth attachments
+fn supported_sample_count(device: &Arc<Device>, requested: u32) -> SampleCount {
+    let properties = device.physical_device().properties();
+    let supported =
+        properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts;
+
+    [
+        (SampleCount::Sample8, SampleCounts::SAMPLE_8),
+        (SampleCount::Sample4, SampleCounts::SAMPLE_4),
+        (SampleCount::Sample2, SampleCounts::SAMPLE_2),
+    ]
+    .into_iter()
+    .find(|(count, flag)| *count as u32 <= requested && supported.intersects(*flag))
+    .map_or(SampleCount::Sample1, |(count, _)| count)
+}
+
+/// Creates the render pass that clears the attachments, and the one that draws more
+/// notes over them. With MSAA the notes are drawn into the multisampled images, which
+/// are resolved into the frame and the bloom image at the end of each pass. The depth
+/// isn't read after the pass, so it stays multisampled.
+fn create_render_passes(
+    device: &Arc<Device>,
+    format: Format,
+    samples: SampleCount,
+) -> (Arc<RenderPass>, Arc<RenderPass>) {
+    if samples == SampleCount::Sample1 {
+        let clear = vulkano::ordered_passes_renderpass!(device.clone(),
+            attachments: {
+                final_color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                },
+                bloom: {
+                    load: Clear,
+                    store: Store,
+                    format: BLOOM_FORMAT,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: Store,
+                    format: Format::D16_UNORM,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [final_color, bloom],
+                    depth_stencil: {depth},
+                    input: []
+                }
+            ]
+        )
+        .unwrap();
+
+        // The later batches draw over the notes and depth of the earlier ones, so
+        // everything is loaded
+        let draw_over = vulkano::ordered_passes_renderpass!(device.clone(),
+            attachments: {
+                final_color: {
+                    load: Load,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                },
+                bloom: {
+                    load: Load,
+                    store: Store,
+                    format: BLOOM_FORMAT,
+                    samples: 1,
+                },
+                depth: {
+                    load: Load,
+                    store: Store,
+                    format: Format::D16_UNORM,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [final_color, bloom],
+                    depth_stencil: {depth},
+                    input: []
+                }
+            ]
+        )
+        .unwrap();
+
+        return (clear, draw_over);
+    }
+
+    let samples = samples as u32;
+
+    let clear = vulkano::ordered_passes_renderpass!(device.clone(),
+        attachments: {
+            final_color: {
+                load: DontCare,
+                store: Store,
+                format: format,
+                samples: 1,
+            },
+            bloom: {
+                load: DontCare,
+                store: Store,
+                format: BLOOM_FORMAT,
+                samples: 1,
+            },
+            msaa_color: {
+                load: Clear,
+                store: Store,
+                format: format,
+                samples: samples,
+            },
+            msaa_bloom: {
+                load: Clear,
+                store: Store,
+                format: BLOOM_FORMAT,
+                samples: samples,
+            },
+            depth: {
+                load: Clear,
+                store: Store,
+                format: Format::D16_UNORM,
+                samples: samples,
+            }
+        },
+        passes: [
+            {
+                color: [msaa_color, msaa_bloom],
+                depth_stencil: {depth},
+                input: [],
+                resolve: [final_color, bloom],
+            }
+        ]
+    )
+    .unwrap();
+
+    let draw_over = vulkano::ordered_passes_renderpass!(device.clone(),
+        attachments: {
+            final_color: {
+                load: DontCare,
+                store: Store,
+                format: format,
+                samples: 1,
+            },
+            bloom: {
+                load: DontCare,
+                store: Store,
+                format: BLOOM_FORMAT,
+                samples: 1,
+            },
+            msaa_color: {
+                load: Load,
+                store: Store,
+                format: format,
+                samples: samples,
+            },
+            msaa_bloom: {
+                load: Load,
+                store: Store,
+                format: BLOOM_FORMAT,
+                samples: samples,
+            },
+            depth: {
+                load: Load,
+                store: Store,
+                format: Format::D16_UNORM,
+                samples: samples,
+            }
+        },
+        passes: [
+            {
+                color: [msaa_color, msaa_bloom],
+                depth_stencil: {depth},
+                input: [],
+                resolve: [final_color, bloom],
+            }
+        ]
+    )
+    .unwrap();
+
+    (clear, draw_over)
+}
+
 mod gs {
     vulkano_shaders::shader! {
         ty: "geometry",
@@ -408,13 +980,16 @@ mod vs {
 #version 450
 layout(location = 0) in vec2 start_length;
 layout(location = 1) in uint key_color;
+layout(location = 2) in uint flags;
 
 layout(location = 0) out vec2 v_start_length;
 layout(location = 1) out uint v_key_color;
+layout(location = 2) out uint v_flags;
 
 void main() {
     v_start_length = start_length;
     v_key_color = key_color;
+    v_flags = flags;
 }"
     }
 }
@@ -425,3 +1000,10 @@ mod fs {
         path: "shaders/notes/notes.frag"
     }
 }
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/notes/cull.comp",
+    }
+}
@@ -0,0 +1,344 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        RenderPassBeginInfo, SubpassContents,
+    },
+    device::Queue,
+    image::{ImageAccess, ImageViewAbstract},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, BlendFactor, BlendOp, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            vertex_input::Vertex,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sync::{self, GpuFuture},
+};
+
+use crate::{gui::GuiRenderer, midi::MIDIColor};
+
+/// The most particles alive at once, the oldest ones are replaced after that
+pub const MAX_PARTICLES: usize = 32768;
+
+/// The particles are pulled back towards the playhead, in pixels per second squared
+const GRAVITY: f32 = 900.0;
+
+/// How much of their speed the particles keep after a second
+const DRAG: f32 = 0.1;
+
+/// The size of a particle when it spawns, in pixels
+const PARTICLE_SIZE: f32 = 4.0;
+
+#[derive(Clone, Copy)]
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    color: u32,
+    age: f32,
+    lifetime: f32,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod, Vertex)]
+pub struct ParticleVertex {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+    #[format(R32_UINT)]
+    pub color: u32,
+    #[format(R32_SFLOAT)]
+    pub alpha: f32,
+}
+
+/// The particles that burst out of the notes as they reach the playhead. The positions
+/// are in pixels with the keys along the width and the playhead at the bottom, which
+/// the shader turns sideways in waterfall mode the same way as the notes.
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    /// Where the next particle goes once the buffer is full
+    next: usize,
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        Self {
+            particles: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn spawn(&mut self, position: [f32; 2], color: MIDIColor, count: u32, lifetime: f32) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..count {
+            // Mostly away from the playhead, spread out to the sides
+            let angle = -PI / 2.0 + rng.gen_range(-1.0..1.0);
+            let speed = rng.gen_range(150.0..450.0);
+            let particle = Particle {
+                position,
+                velocity: [angle.cos() * speed, angle.sin() * speed],
+                color: color.as_u32(),
+                age: 0.0,
+                lifetime: lifetime * rng.gen_range(0.75..1.0),
+            };
+
+            if self.particles.len() < MAX_PARTICLES {
+                self.particles.push(particle);
+            } else {
+                self.particles[self.next] = particle;
+                self.next = (self.next + 1) % MAX_PARTICLES;
+            }
+        }
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        let drag = DRAG.powf(delta);
+        for particle in self.particles.iter_mut() {
+            particle.age += delta;
+            particle.velocity[0] *= drag;
+            particle.velocity[1] = particle.velocity[1] * drag + GRAVITY * delta;
+            particle.position[0] += particle.velocity[0] * delta;
+            particle.position[1] += particle.velocity[1] * delta;
+        }
+
+        // The buffer only grows while there are particles, so it starts over once they are gone
+        if self.particles.iter().all(|p| p.age >= p.lifetime) {
+            self.particles.clear();
+            self.next = 0;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    fn vertices(&self) -> impl Iterator<Item = ParticleVertex> + '_ {
+        self.particles
+            .iter()
+            .filter(|p| p.age < p.lifetime)
+            .map(|p| ParticleVertex {
+                position: p.position,
+                color: p.color,
+                alpha: 1.0 - p.age / p.lifetime,
+            })
+    }
+}
+
+/// Draws the particles over the notes that were already rendered into the frame
+pub struct ParticleRenderPass {
+    gfx_queue: Arc<Queue>,
+    pipeline: Arc<GraphicsPipeline>,
+    render_pass: Arc<RenderPass>,
+    vertex_buffer: Subbuffer<[ParticleVertex]>,
+    cb_allocator: StandardCommandBufferAllocator,
+}
+
+impl ParticleRenderPass {
+    pub fn new(renderer: &GuiRenderer) -> ParticleRenderPass {
+        let allocator = StandardMemoryAllocator::new_default(renderer.device.clone());
+
+        let gfx_queue = renderer.queue.clone();
+
+        let render_pass = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                final_color: {
+                    load: Load,
+                    store: Store,
+                    format: renderer.format,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [final_color],
+                    depth_stencil: {},
+                    input: []
+                }
+            ]
+        )
+        .unwrap();
+
+        let vertex_buffer = Buffer::new_slice(
+            &allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            MAX_PARTICLES as u64,
+        )
+        .expect("failed to create buffer");
+
+        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let gs = gs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+
+        // The frame has premultiplied colors, so the particles are blended the same way
+        let premultiplied = AttachmentBlend {
+            color_op: BlendOp::Add,
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::OneMinusSrcAlpha,
+            alpha_op: BlendOp::Add,
+            alpha_source: BlendFactor::One,
+            alpha_destination: BlendFactor::OneMinusSrcAlpha,
+        };
+
+        let pipeline = GraphicsPipeline::start()
+            .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::PointList))
+            .vertex_input_state(ParticleVertex::per_vertex())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .geometry_shader(gs.entry_point("main").unwrap(), ())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .color_blend_state(ColorBlendState::new(1).blend(premultiplied))
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build_with_cache(renderer.pipeline_cache.clone())
+            .build(gfx_queue.device().clone())
+            .unwrap();
+
+        ParticleRenderPass {
+            gfx_queue,
+            pipeline,
+            render_pass,
+            vertex_buffer,
+            cb_allocator: StandardCommandBufferAllocator::new(
+                renderer.device.clone(),
+                Default::default(),
+            ),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        particles: &ParticleSystem,
+        waterfall: bool,
+        tone_map: bool,
+    ) {
+        let count = {
+            let mut buffer = self.vertex_buffer.write().unwrap();
+            let mut count = 0;
+            for (write, vertex) in buffer.iter_mut().zip(particles.vertices()) {
+                *write = vertex;
+                count += 1;
+            }
+            count
+        };
+        if count == 0 {
+            return;
+        }
+
+        let img_dims = final_image.image().dimensions().width_height();
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // The particles are laid out sideways in waterfall mode, so the sizes are swapped
+        let (win_width, win_height) = if waterfall {
+            (img_dims[1] as f32, img_dims[0] as f32)
+        } else {
+            (img_dims[0] as f32, img_dims[1] as f32)
+        };
+
+        let push_constants = gs::PushConstants {
+            win_width,
+            win_height,
+            size: PARTICLE_SIZE,
+            waterfall: waterfall as i32,
+            tone_map: tone_map as i32,
+        };
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            &self.cb_allocator,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassContents::Inline,
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [img_dims[0] as f32, img_dims[1] as f32],
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .bind_vertex_buffers(0, self.vertex_buffer.clone())
+            .draw(count, 1, 0, 0)
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        sync::now(self.gfx_queue.device().clone())
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+}
+
+mod gs {
+    vulkano_shaders::shader! {
+        ty: "geometry",
+        path: "shaders/particles/particles.geom",
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec2 position;
+layout(location = 1) in uint color;
+layout(location = 2) in float alpha;
+
+layout(location = 0) out vec2 v_position;
+layout(location = 1) out uint v_color;
+layout(location = 2) out float v_alpha;
+
+void main() {
+    v_position = position;
+    v_color = color;
+    v_alpha = alpha;
+}"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/particles/particles.frag"
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        RenderPassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Queue,
+    format::Format,
+    image::{view::ImageView, AttachmentImage, ImageAccess, ImageViewAbstract},
+    memory::allocator::StandardMemoryAllocator,
+    pipeline::{
+        graphics::viewport::{Viewport, ViewportState},
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::{self, GpuFuture},
+};
+
+use crate::gui::GuiRenderer;
+
+/// Holds an image smaller than the frame that the notes are drawn into, so their
+/// depth buffer can be smaller too, and stretches it onto the frame afterwards
+pub struct UpscalePass {
+    gfx_queue: Arc<Queue>,
+    pipeline: Arc<GraphicsPipeline>,
+    render_pass: Arc<RenderPass>,
+    sampler: Arc<Sampler>,
+    format: Format,
+    image: Arc<ImageView<AttachmentImage>>,
+    allocator: StandardMemoryAllocator,
+    cb_allocator: StandardCommandBufferAllocator,
+    sd_allocator: StandardDescriptorSetAllocator,
+}
+
+fn new_image(
+    allocator: &StandardMemoryAllocator,
+    format: Format,
+    dims: [u32; 2],
+) -> Arc<ImageView<AttachmentImage>> {
+    ImageView::new_default(AttachmentImage::sampled(allocator, dims, format).unwrap()).unwrap()
+}
+
+impl UpscalePass {
+    pub fn new(renderer: &GuiRenderer) -> UpscalePass {
+        let allocator = StandardMemoryAllocator::new_default(renderer.device.clone());
+
+        let gfx_queue = renderer.queue.clone();
+
+        // Every pixel of the frame is overwritten, so it isn't loaded
+        let render_pass = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                final_color: {
+                    load: DontCare,
+                    store: Store,
+                    format: renderer.format,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [final_color],
+                    depth_stencil: {},
+                    input: []
+                }
+            ]
+        )
+        .unwrap();
+
+        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+
+        let pipeline = GraphicsPipeline::start()
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build_with_cache(renderer.pipeline_cache.clone())
+            .build(gfx_queue.device().clone())
+            .unwrap();
+
+        let sampler = Sampler::new(
+            gfx_queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let image = new_image(&allocator, renderer.format, [1, 1]);
+
+        UpscalePass {
+            gfx_queue,
+            pipeline,
+            render_pass,
+            sampler,
+            format: renderer.format,
+            image,
+            allocator,
+            cb_allocator: StandardCommandBufferAllocator::new(
+                renderer.device.clone(),
+                Default::default(),
+            ),
+            sd_allocator: StandardDescriptorSetAllocator::new(renderer.device.clone()),
+        }
+    }
+
+    /// The image to draw the notes into, recreated when the size changes
+    pub fn target(&mut self, dims: [u32; 2]) -> Arc<ImageView<AttachmentImage>> {
+        if self.image.image().dimensions().width_height() != dims {
+            self.image = new_image(&self.allocator, self.format, dims);
+        }
+        self.image.clone()
+    }
+
+    /// Stretches the image the notes were drawn into over the whole frame
+    pub fn draw(&self, final_image: Arc<dyn ImageViewAbstract + 'static>) {
+        let img_dims = final_image.image().dimensions().width_height();
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            &self.cb_allocator,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let pipeline_layout = self.pipeline.layout();
+        let set = PersistentDescriptorSet::new(
+            &self.sd_allocator,
+            pipeline_layout.set_layouts().get(0).unwrap().clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                self.image.clone(),
+                self.sampler.clone(),
+            )],
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassContents::Inline,
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [img_dims[0] as f32, img_dims[1] as f32],
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline_layout.clone(), 0, set)
+            .draw(3, 1, 0, 0)
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        sync::now(self.gfx_queue.device().clone())
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/bloom/fullscreen.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/upscale/upscale.frag"
+    }
+}
@@ -1,14 +1,16 @@
-use egui::Context;
+use egui::{Color32, Context};
 
-use std::ops::RangeInclusive;
+use std::{ops::RangeInclusive, path::PathBuf};
 
 use crate::{
-    audio_playback::{
-        xsynth::{convert_to_channel_init, convert_to_sf_init},
-        AudioPlayerType,
+    gui::window::{cc_lanes::controller_label, GuiWasabiWindow},
+    midi::{MIDIClockSync, MUSICXML_GRIDS},
+    settings::{
+        BackgroundMode, ColorSpace, EventFilterFlags, HandSplitMode, KeyBinding, KeyLabelMode,
+        LyricsPosition, MidiLoading, MidiRepairMode, MidiSettings, NoteGap, NoteRenderOrder,
+        NoteShape, PercussionDisplayMode, SpectrogramColorMap, Synth, TimeRulerMode,
+        VisualSettings, WasabiAction, WasabiSettings, WatermarkConfig, WatermarkCorner,
     },
-    gui::window::GuiWasabiWindow,
-    settings::{MidiLoading, Synth, WasabiSettings},
     state::WasabiState,
 };
 
@@ -44,37 +46,113 @@ pub fn draw_settings(
                         .show_ui(ui, |ui| {
                             ui.selectable_value(&mut settings.synth.synth, Synth::XSynth, "XSynth");
                             ui.selectable_value(&mut settings.synth.synth, Synth::Kdmapi, "KDMAPI");
+                            if cfg!(feature = "jack") {
+                                ui.selectable_value(&mut settings.synth.synth, Synth::Jack, "JACK");
+                            }
                         });
                     if settings.synth.synth != synth_prev {
-                        match settings.synth.synth {
-                            Synth::Kdmapi => {
-                                win.synth
-                                    .write()
-                                    .unwrap()
-                                    .switch_player(AudioPlayerType::Kdmapi);
-                            }
-                            Synth::XSynth => {
-                                win.synth
-                                    .write()
-                                    .unwrap()
-                                    .switch_player(AudioPlayerType::XSynth {
-                                        buffer: settings.synth.buffer_ms,
-                                        ignore_range: settings.synth.vel_ignore.clone(),
-                                        options: convert_to_channel_init(settings),
-                                    });
-                                win.synth.write().unwrap().set_soundfont(
-                                    &settings.synth.sfz_path,
-                                    convert_to_sf_init(settings),
-                                );
-                                win.synth.write().unwrap().set_layer_count(
-                                    match settings.synth.layer_count {
-                                        0 => None,
-                                        _ => Some(settings.synth.layer_count),
-                                    },
-                                );
-                            }
+                        win.synth.write().unwrap().start_synth(settings);
+                    }
+                    ui.end_row();
+
+                    if settings.synth.synth == Synth::Jack {
+                        ui.label("JACK Client Name*: ");
+                        ui.text_edit_singleline(&mut settings.synth.jack_client_name);
+                        ui.end_row();
+
+                        let ports = win.synth.read().unwrap().get_jack_ports();
+                        let left_prev = settings.synth.jack_port_left.clone();
+                        let right_prev = settings.synth.jack_port_right.clone();
+
+                        for (label, port) in [
+                            ("JACK Left Output: ", &mut settings.synth.jack_port_left),
+                            ("JACK Right Output: ", &mut settings.synth.jack_port_right),
+                        ] {
+                            ui.label(label);
+                            egui::ComboBox::from_id_source(label)
+                                .selected_text(if port.is_empty() {
+                                    "Default"
+                                } else {
+                                    port.as_str()
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(port, String::new(), "Default");
+                                    for name in ports.iter() {
+                                        ui.selectable_value(port, name.clone(), name);
+                                    }
+                                });
+                            ui.end_row();
+                        }
+
+                        if settings.synth.jack_port_left != left_prev
+                            || settings.synth.jack_port_right != right_prev
+                        {
+                            win.synth.write().unwrap().connect_jack_ports(
+                                &settings.synth.jack_port_left,
+                                &settings.synth.jack_port_right,
+                            );
                         }
                     }
+
+                    ui.label("MIDI Clock Sync: ");
+                    ui.checkbox(&mut settings.synth.midi_clock_sync, "");
+                    ui.end_row();
+
+                    if settings.synth.midi_clock_sync {
+                        ui.label("MIDI Clock Input: ");
+                        let port = &mut settings.synth.midi_clock_port;
+                        egui::ComboBox::from_id_source("midi_clock_port")
+                            .selected_text(if port.is_empty() {
+                                "Default"
+                            } else {
+                                port.as_str()
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(port, String::new(), "Default");
+                                for name in MIDIClockSync::available_ports() {
+                                    ui.selectable_value(port, name.clone(), name);
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("MIDI Clock Tempo: ");
+                        match win.midi_clock.as_ref().and_then(|clock| clock.bpm()) {
+                            Some(bpm) => ui.label(format!("{:.1} BPM", bpm)),
+                            None => ui.label("No clock"),
+                        };
+                        ui.end_row();
+                    }
+
+                    ui.label("Loop Record Input: ");
+                    let port = &mut settings.synth.loop_input_port;
+                    egui::ComboBox::from_id_source("loop_input_port")
+                        .selected_text(if port.is_empty() {
+                            "Default"
+                        } else {
+                            port.as_str()
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(port, String::new(), "Default");
+                            for name in MIDIClockSync::available_ports() {
+                                ui.selectable_value(port, name.clone(), name);
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Loop Measures: ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.synth.loop_measures)
+                            .speed(0.1)
+                            .clamp_range(1..=64),
+                    );
+                    ui.end_row();
+
+                    ui.label("Loop BPM: ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.synth.loop_bpm)
+                            .speed(0.5)
+                            .clamp_range(20.0..=999.0),
+                    );
                     ui.end_row();
 
                     ui.label("Configure:");
@@ -102,10 +180,69 @@ pub fn draw_settings(
                     ));
                     ui.end_row();
 
+                    ui.label("Auto Note Speed: ");
+                    ui.checkbox(&mut settings.midi.auto_note_speed, "");
+                    ui.end_row();
+
+                    ui.label("Target Notes On Screen: ");
+                    ui.add_enabled(
+                        settings.midi.auto_note_speed,
+                        egui::DragValue::new(&mut settings.midi.auto_note_speed_target)
+                            .speed(10)
+                            .clamp_range(RangeInclusive::new(1, 1000000)),
+                    );
+                    ui.end_row();
+
+                    ui.label("Auto Note Speed Smoothing: ");
+                    ui.add_enabled(
+                        settings.midi.auto_note_speed,
+                        egui::Slider::new(
+                            &mut settings.midi.auto_note_speed_smoothing,
+                            0.001..=1.0,
+                        ),
+                    );
+                    ui.end_row();
+
                     ui.label("Random Track Colors*: ");
                     ui.checkbox(&mut settings.midi.random_colors, "");
                     ui.end_row();
 
+                    ui.label("Program Colors*: ")
+                        .on_hover_text("Not supported with the cake MIDI loading mode");
+                    ui.checkbox(&mut settings.midi.program_colors, "");
+                    ui.end_row();
+
+                    ui.label("Smart Colors*: ").on_hover_text(
+                        "Colors the programs by how bright the SFZ sounds in the register \
+                        their General MIDI family usually plays in, warm for bright ones and \
+                        cool for dull ones, instead of with the program color map. The same \
+                        SFZ plays every program, so the programs of a family share a hue. \
+                        Not supported with the cake MIDI loading mode",
+                    );
+                    ui.checkbox(&mut settings.midi.smart_colors, "");
+                    ui.end_row();
+
+                    ui.label("Program Color Map: ");
+                    ui.vertical(|ui| {
+                        ui.collapsing("Colors", |ui| {
+                            for (row, colors) in
+                                settings.midi.program_color_map.chunks_mut(8).enumerate()
+                            {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{:>3}:", row * 8));
+                                    for color in colors.iter_mut() {
+                                        ui.color_edit_button_srgba(color);
+                                    }
+                                });
+                            }
+                        });
+                        if ui.button("Reset").clicked() {
+                            settings.midi.program_color_map =
+                                MidiSettings::default_program_colors();
+                        }
+                    });
+                    ui.end_row();
+
                     ui.label("Keyboard Range: ");
                     let mut firstkey = *settings.midi.key_range.start();
                     let mut lastkey = *settings.midi.key_range.end();
@@ -148,6 +285,135 @@ pub fn draw_settings(
                                 "Cake",
                             );
                         });
+                    ui.end_row();
+
+                    if settings.midi.midi_loading == MidiLoading::Ram {
+                        ui.label("Repair MIDI*: ");
+                        egui::ComboBox::from_id_source("repair_select")
+                            .selected_text(settings.midi.repair_mode.as_str())
+                            .show_ui(ui, |ui| {
+                                for mode in [
+                                    MidiRepairMode::Off,
+                                    MidiRepairMode::Duplicates,
+                                    MidiRepairMode::Full,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut settings.midi.repair_mode,
+                                        mode,
+                                        mode.as_str(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
+
+                    if settings.midi.midi_loading == MidiLoading::Live {
+                        ui.label("Memory Map MIDI*: ").on_hover_text(
+                            "Maps the MIDI into memory and parses it in place instead of \
+                            reading it in buffers, which helps with big files on fast drives. \
+                            The file must not be changed or shortened while it plays",
+                        );
+                        ui.checkbox(&mut settings.midi.use_mmap, "");
+                        ui.end_row();
+                    }
+
+                    ui.label("Events Sent to Synth: ");
+                    ui.horizontal_wrapped(|ui| {
+                        for (flag, label) in [
+                            (EventFilterFlags::NOTE_ON, "Note On"),
+                            (EventFilterFlags::NOTE_OFF, "Note Off"),
+                            (EventFilterFlags::PITCH_BEND, "Pitch Bend"),
+                            (EventFilterFlags::CONTROL_CHANGE, "CC"),
+                            (EventFilterFlags::PROGRAM_CHANGE, "Program Change"),
+                        ] {
+                            let mut enabled = settings.midi.event_filter.contains(flag);
+                            if ui.checkbox(&mut enabled, label).changed() {
+                                settings.midi.event_filter.set(flag, enabled);
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Muted Channels: ").on_hover_text(
+                        "The notes of the muted channels aren't played, and are drawn with the \
+                        inactive channel opacity",
+                    );
+                    ui.horizontal_wrapped(|ui| {
+                        for channel in 0..16 {
+                            let mut muted = settings.midi.muted_channels & (1 << channel) != 0;
+                            if ui.checkbox(&mut muted, (channel + 1).to_string()).changed() {
+                                settings.midi.muted_channels ^= 1 << channel;
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Reverse Playback (In RAM only)*: ");
+                    ui.checkbox(&mut settings.midi.reverse_playback, "");
+                    ui.end_row();
+
+                    ui.label("Transpose: ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.midi.transpose_semitones)
+                            .speed(0.1)
+                            .clamp_range(RangeInclusive::new(-24, 24)),
+                    );
+                    ui.end_row();
+
+                    ui.label("Follow Mode: ");
+                    ui.checkbox(&mut settings.midi.follow_mode, "");
+                    ui.end_row();
+
+                    ui.label("Clickable Keyboard: ")
+                        .on_hover_text("Plays the keys of the keyboard while they are clicked");
+                    ui.checkbox(&mut settings.midi.keyboard_clickable, "");
+                    ui.end_row();
+
+                    ui.label("Pause On Focus Loss: ");
+                    ui.checkbox(&mut settings.midi.pause_on_focus_loss, "");
+                    ui.end_row();
+
+                    ui.label("Seek Step: ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.midi.seek_step_secs)
+                            .speed(0.01)
+                            .suffix(" s")
+                            .clamp_range(RangeInclusive::new(0.01, 60.0)),
+                    );
+                    ui.end_row();
+
+                    ui.label("Fast Seek (Shift): ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.midi.seek_fast_multiplier)
+                            .speed(0.1)
+                            .prefix("x")
+                            .clamp_range(RangeInclusive::new(1.0, 100.0)),
+                    );
+                    ui.end_row();
+
+                    ui.label("Fine Seek (Ctrl): ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.midi.seek_fine_multiplier)
+                            .speed(0.01)
+                            .prefix("x")
+                            .clamp_range(RangeInclusive::new(0.01, 1.0)),
+                    );
+                    ui.end_row();
+
+                    ui.label("MusicXML Grid: ")
+                        .on_hover_text("The shortest note value of exported sheet music");
+                    egui::ComboBox::from_id_source("musicxml_grid_select")
+                        .selected_text(format!("1/{}", settings.midi.musicxml_grid))
+                        .show_ui(ui, |ui| {
+                            for grid in MUSICXML_GRIDS {
+                                ui.selectable_value(
+                                    &mut settings.midi.musicxml_grid,
+                                    grid,
+                                    format!("1/{}", grid),
+                                );
+                            }
+                        });
+                    ui.end_row();
                 });
 
             // Visual settings section
@@ -169,16 +435,948 @@ pub fn draw_settings(
                     ui.color_edit_button_srgba(&mut settings.visual.bg_color);
                     ui.end_row();
 
+                    ui.label("Background: ");
+                    let background_path = settings
+                        .visual
+                        .background
+                        .path()
+                        .map(|path| path.to_owned())
+                        .unwrap_or_default();
+                    egui::ComboBox::from_id_source("background_select")
+                        .selected_text(settings.visual.background.as_str())
+                        .show_ui(ui, |ui| {
+                            for background in [
+                                BackgroundMode::SolidColor,
+                                BackgroundMode::Image(background_path.clone()),
+                                BackgroundMode::Video(background_path.clone()),
+                            ] {
+                                let text = background.as_str();
+                                ui.selectable_value(
+                                    &mut settings.visual.background,
+                                    background,
+                                    text,
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    if let BackgroundMode::Image(path) | BackgroundMode::Video(path) =
+                        &mut settings.visual.background
+                    {
+                        ui.label("Background Path: ");
+                        let mut background_path = path.to_string_lossy().into_owned();
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut background_path))
+                            .changed()
+                        {
+                            *path = PathBuf::from(background_path);
+                        }
+                        ui.end_row();
+
+                        ui.label("Background Opacity: ");
+                        ui.add(egui::Slider::new(
+                            &mut settings.visual.background_opacity,
+                            0.0..=1.0,
+                        ));
+                        ui.end_row();
+                    }
+
                     ui.label("Bar Color: ");
                     ui.color_edit_button_srgba(&mut settings.visual.bar_color);
                     ui.end_row();
+
+                    ui.label("White Key Color: ");
+                    ui.color_edit_button_srgba(&mut settings.visual.keyboard_white_key_color);
+                    ui.end_row();
+
+                    ui.label("Black Key Color: ");
+                    ui.color_edit_button_srgba(&mut settings.visual.keyboard_black_key_color);
+                    ui.end_row();
+
+                    ui.label("Pressed White Key Tint: ");
+                    ui.color_edit_button_srgba(
+                        &mut settings.visual.keyboard_white_key_pressed_color,
+                    );
+                    ui.end_row();
+
+                    ui.label("Pressed Black Key Tint: ");
+                    ui.color_edit_button_srgba(
+                        &mut settings.visual.keyboard_black_key_pressed_color,
+                    );
+                    ui.end_row();
+
+                    ui.label("Key Labels: ");
+                    egui::ComboBox::from_id_source("keylabels_select")
+                        .selected_text(settings.visual.keyboard_key_labels.as_str())
+                        .show_ui(ui, |ui| {
+                            for mode in [
+                                KeyLabelMode::None,
+                                KeyLabelMode::All,
+                                KeyLabelMode::OctaveC,
+                                KeyLabelMode::ChromaticSharps,
+                            ] {
+                                ui.selectable_value(
+                                    &mut settings.visual.keyboard_key_labels,
+                                    mode,
+                                    mode.as_str(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Black Key Width: ")
+                        .on_hover_text("The width of the black keys relative to the white keys");
+                    ui.add(egui::Slider::new(
+                        &mut settings.visual.black_key_width_ratio,
+                        0.1..=1.0,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Black Key Height: ")
+                        .on_hover_text("How far down the keyboard the black keys reach");
+                    ui.add(egui::Slider::new(
+                        &mut settings.visual.black_key_height_ratio,
+                        0.1..=1.0,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Keyboard Heatmap: ");
+                    ui.checkbox(&mut settings.visual.keyboard_heatmap, "");
+                    ui.end_row();
+
+                    if settings.visual.keyboard_heatmap {
+                        ui.label("Heatmap Gradient: ");
+                        ui.horizontal(|ui| {
+                            let gradient = &mut settings.visual.heatmap_gradient;
+                            for color in gradient.iter_mut() {
+                                ui.color_edit_button_srgba(color);
+                            }
+                            if ui.button("+").clicked() {
+                                let last = gradient.last().copied().unwrap_or(Color32::WHITE);
+                                gradient.push(last);
+                            }
+                            if gradient.len() > 2 && ui.button("-").clicked() {
+                                gradient.pop();
+                            }
+                            if ui.button("Reset").clicked() {
+                                *gradient = VisualSettings::default_heatmap_gradient();
+                            }
+                        });
+                        ui.end_row();
+                    }
+
+                    ui.label("UI Font Path (restart required): ");
+                    let mut font_path = settings
+                        .visual
+                        .ui_font_path
+                        .as_ref()
+                        .map(|path| path.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    if ui.add(egui::TextEdit::singleline(&mut font_path)).changed() {
+                        settings.visual.ui_font_path = if font_path.is_empty() {
+                            None
+                        } else {
+                            Some(PathBuf::from(font_path))
+                        };
+                    }
+                    ui.end_row();
+
+                    ui.label("UI Font Size: ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.visual.ui_font_size)
+                            .speed(0.1)
+                            .clamp_range(RangeInclusive::new(6.0, 32.0)),
+                    );
+                    ui.end_row();
+
+                    ui.label("Safe Zone Margin: ")
+                        .on_hover_text("An empty border around everything, for streaming overlays");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.visual.safe_zone_margin_px)
+                            .speed(1)
+                            .clamp_range(RangeInclusive::new(0, 500))
+                            .suffix(" px"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Bloom: ");
+                    ui.checkbox(&mut settings.visual.bloom_enabled, "");
+                    ui.end_row();
+
+                    ui.label("Bloom Radius: ");
+                    ui.add_enabled(
+                        settings.visual.bloom_enabled,
+                        egui::DragValue::new(&mut settings.visual.bloom_radius)
+                            .speed(0.1)
+                            .clamp_range(RangeInclusive::new(1.0, 64.0)),
+                    );
+                    ui.end_row();
+
+                    ui.label("Inner Glow: ");
+                    ui.add(egui::Slider::new(
+                        &mut settings.visual.inner_glow_intensity,
+                        0.0..=1.0,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Inner Glow Color: ");
+                    ui.color_edit_button_rgba_unmultiplied(&mut settings.visual.inner_glow_color);
+                    ui.end_row();
+
+                    ui.label("Mini Keyboard: ");
+                    ui.checkbox(&mut settings.visual.show_mini_keyboard, "");
+                    ui.end_row();
+
+                    ui.label("Waterfall Mode: ");
+                    ui.checkbox(&mut settings.visual.waterfall_mode, "");
+                    ui.end_row();
+
+                    ui.label("Invert Pitch Axis: ").on_hover_text(
+                        "Flips the keyboard and the notes, so the bass notes are on the right \
+                        or at the top in waterfall mode",
+                    );
+                    ui.checkbox(&mut settings.visual.invert_pitch_axis, "");
+                    ui.end_row();
+
+                    ui.label("Note Shape: ");
+                    egui::ComboBox::from_id_source("noteshape_select")
+                        .selected_text(settings.visual.note_shape.as_str())
+                        .show_ui(ui, |ui| {
+                            for shape in
+                                [NoteShape::Rectangle, NoteShape::Circle, NoteShape::Diamond]
+                            {
+                                ui.selectable_value(
+                                    &mut settings.visual.note_shape,
+                                    shape,
+                                    shape.as_str(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Note Render Order: ");
+                    egui::ComboBox::from_id_source("noterenderorder_select")
+                        .selected_text(settings.visual.note_render_order.as_str())
+                        .show_ui(ui, |ui| {
+                            for order in [
+                                NoteRenderOrder::FileOrder,
+                                NoteRenderOrder::EarliestFirst,
+                                NoteRenderOrder::LatestFirst,
+                                NoteRenderOrder::ByTrackDescending,
+                            ] {
+                                ui.selectable_value(
+                                    &mut settings.visual.note_render_order,
+                                    order,
+                                    order.as_str(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Percussion Display: ");
+                    egui::ComboBox::from_id_source("percussiondisplay_select")
+                        .selected_text(settings.visual.percussion_display_mode.as_str())
+                        .show_ui(ui, |ui| {
+                            for mode in
+                                [PercussionDisplayMode::Piano, PercussionDisplayMode::DrumPad]
+                            {
+                                ui.selectable_value(
+                                    &mut settings.visual.percussion_display_mode,
+                                    mode,
+                                    mode.as_str(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Hand Split: ")
+                        .on_hover_text("Not supported with the cake MIDI loading mode");
+                    ui.horizontal(|ui| {
+                        let hand_split = &mut settings.visual.hand_split_mode;
+                        egui::ComboBox::from_id_source("handsplit_select")
+                            .selected_text(hand_split.as_str())
+                            .show_ui(ui, |ui| {
+                                for mode in [
+                                    HandSplitMode::None,
+                                    HandSplitMode::ByChannel { right: 0, left: 1 },
+                                    HandSplitMode::ByPitch { split_key: 60 },
+                                ] {
+                                    let selected = hand_split.as_str() == mode.as_str();
+                                    if ui.selectable_label(selected, mode.as_str()).clicked()
+                                        && !selected
+                                    {
+                                        *hand_split = mode;
+                                    }
+                                }
+                            });
+
+                        // The channels are shown counted from 1, like in the rest of the UI
+                        match hand_split {
+                            HandSplitMode::None => {}
+                            HandSplitMode::ByChannel { right, left } => {
+                                for (name, channel) in [("Right", right), ("Left", left)] {
+                                    let mut shown = *channel + 1;
+                                    ui.label(name);
+                                    ui.add(egui::DragValue::new(&mut shown).clamp_range(1..=16));
+                                    *channel = shown - 1;
+                                }
+                            }
+                            HandSplitMode::ByPitch { split_key } => {
+                                ui.label("Split Key");
+                                ui.add(egui::DragValue::new(split_key).clamp_range(0..=127));
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Note Gap: ").on_hover_text(
+                        "Shortens every note, so notes that follow each other on a key don't \
+                        merge. Ticks are counted with the tempo where each note ends. \
+                        Not supported with the cake MIDI loading mode",
+                    );
+                    ui.horizontal(|ui| {
+                        let note_gap = &mut settings.visual.note_gap;
+                        egui::ComboBox::from_id_source("note_gap_select")
+                            .selected_text(note_gap.as_str())
+                            .show_ui(ui, |ui| {
+                                for gap in
+                                    [NoteGap::Pixels { pixels: 0.0 }, NoteGap::Ticks { ticks: 0 }]
+                                {
+                                    let selected = note_gap.as_str() == gap.as_str();
+                                    if ui.selectable_label(selected, gap.as_str()).clicked()
+                                        && !selected
+                                    {
+                                        *note_gap = gap;
+                                    }
+                                }
+                            });
+
+                        match note_gap {
+                            NoteGap::Pixels { pixels } => {
+                                ui.add(egui::Slider::new(pixels, 0.0..=5.0).suffix(" px"));
+                            }
+                            NoteGap::Ticks { ticks } => {
+                                ui.add(
+                                    egui::DragValue::new(ticks)
+                                        .clamp_range(0..=960)
+                                        .suffix(" ticks"),
+                                );
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Minimum Note Height: ");
+                    ui.add(
+                        egui::Slider::new(&mut settings.visual.min_note_height_px, 0.0..=10.0)
+                            .suffix(" px"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Spotlight Playing Notes: ")
+                        .on_hover_text("Not supported with the cake MIDI loading mode");
+                    ui.checkbox(&mut settings.visual.spotlight_mode, "");
+                    ui.end_row();
+
+                    ui.label("Fade Short Notes: ").on_hover_text(
+                        "Notes fade from transparent at the first length to opaque at the \
+                        second one. Not supported with the cake MIDI loading mode",
+                    );
+                    ui.horizontal(|ui| {
+                        let visual = &mut settings.visual;
+                        ui.checkbox(&mut visual.length_opacity_mode, "");
+                        ui.add_enabled_ui(visual.length_opacity_mode, |ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut visual.length_opacity_min_ms)
+                                    .speed(1.0)
+                                    .clamp_range(RangeInclusive::new(0.0, 10000.0))
+                                    .suffix(" ms"),
+                            );
+                            ui.label("to");
+                            ui.add(
+                                egui::DragValue::new(&mut visual.length_opacity_max_ms)
+                                    .speed(1.0)
+                                    .clamp_range(RangeInclusive::new(0.0, 10000.0))
+                                    .suffix(" ms"),
+                            );
+                        });
+                    });
+                    ui.end_row();
+
+                    ui.label("Anti-Aliasing (MSAA): ");
+                    let msaa_text = |samples: u32| match samples {
+                        1 => "Off".to_owned(),
+                        samples => format!("{}x", samples),
+                    };
+                    egui::ComboBox::from_id_source("msaa_select")
+                        .selected_text(msaa_text(settings.visual.msaa_samples))
+                        .show_ui(ui, |ui| {
+                            for samples in [1, 2, 4, 8] {
+                                ui.selectable_value(
+                                    &mut settings.visual.msaa_samples,
+                                    samples,
+                                    msaa_text(samples),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Depth Buffer Scale: ").on_hover_text(
+                        "Draws the notes with a depth buffer of half the frame size, which \
+                        saves memory on big frames. The notes are drawn into an image of that \
+                        size and stretched onto the frame, so they get softer edges",
+                    );
+                    egui::ComboBox::from_id_source("depth_scale_select")
+                        .selected_text(if settings.visual.depth_buffer_scale < 1.0 {
+                            "Half"
+                        } else {
+                            "Full"
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut settings.visual.depth_buffer_scale,
+                                1.0,
+                                "Full",
+                            );
+                            ui.selectable_value(
+                                &mut settings.visual.depth_buffer_scale,
+                                0.5,
+                                "Half",
+                            );
+                        });
+                    ui.end_row();
+
+                    ui.label("Note Echo: ");
+                    let mut echo = settings.visual.echo_beats.is_some();
+                    if ui.checkbox(&mut echo, "").changed() {
+                        settings.visual.echo_beats = echo.then_some(1.0);
+                    }
+                    ui.end_row();
+
+                    if let Some(beats) = settings.visual.echo_beats.as_mut() {
+                        ui.label("Echo Beats: ");
+                        ui.add(
+                            egui::DragValue::new(beats)
+                                .speed(0.05)
+                                .clamp_range(0.25..=16.0),
+                        );
+                        ui.end_row();
+                    }
+
+                    ui.label("Particles per Note: ");
+                    ui.add(egui::Slider::new(
+                        &mut settings.visual.particles_per_note,
+                        0..=64,
+                    ));
+                    ui.end_row();
+
+                    if settings.visual.particles_per_note > 0 {
+                        ui.label("Particle Lifetime: ");
+                        ui.add(
+                            egui::Slider::new(
+                                &mut settings.visual.particle_lifetime_ms,
+                                50.0..=5000.0,
+                            )
+                            .logarithmic(true)
+                            .suffix(" ms"),
+                        );
+                        ui.end_row();
+                    }
+
+                    ui.label("CC Lanes: ");
+                    let lanes = &mut settings.visual.cc_lanes;
+                    let selected_text = if lanes.is_empty() {
+                        "None".to_owned()
+                    } else {
+                        lanes
+                            .iter()
+                            .map(|cc| cc.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    };
+                    egui::ComboBox::from_id_source("cc_lanes_select")
+                        .selected_text(selected_text)
+                        .show_ui(ui, |ui| {
+                            for cc in 0..128 {
+                                let mut shown = lanes.contains(&cc);
+                                if ui.checkbox(&mut shown, controller_label(cc)).changed() {
+                                    if shown {
+                                        lanes.push(cc);
+                                        lanes.sort_unstable();
+                                    } else {
+                                        lanes.retain(|lane| *lane != cc);
+                                    }
+                                }
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("CC Lane Height: ");
+                    ui.add_enabled(
+                        !settings.visual.cc_lanes.is_empty(),
+                        egui::Slider::new(&mut settings.visual.cc_lane_height, 16.0..=128.0)
+                            .suffix(" px"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Instrument Labels: ");
+                    ui.checkbox(&mut settings.visual.show_instrument_labels, "");
+                    ui.end_row();
+
+                    ui.label("Channel Activity: ").on_hover_text(
+                        "Shows which channels have notes on screen in a bar above the keyboard. \
+                        Not supported with the cake MIDI loading mode",
+                    );
+                    ui.checkbox(&mut settings.visual.show_channel_activity, "");
+                    ui.end_row();
+
+                    ui.label("Lyrics: ")
+                        .on_hover_text("Shows the lyrics of karaoke files while they are sung");
+                    ui.checkbox(&mut settings.visual.show_lyrics, "");
+                    ui.end_row();
+
+                    if settings.visual.show_lyrics {
+                        ui.label("Lyrics Font Size: ");
+                        ui.add(egui::Slider::new(
+                            &mut settings.visual.lyrics_font_size,
+                            8.0..=96.0,
+                        ));
+                        ui.end_row();
+
+                        ui.label("Lyrics Color: ");
+                        ui.color_edit_button_srgba(&mut settings.visual.lyrics_color);
+                        ui.end_row();
+
+                        ui.label("Lyrics Highlight Color: ");
+                        ui.color_edit_button_srgba(&mut settings.visual.lyrics_highlight_color);
+                        ui.end_row();
+
+                        ui.label("Lyrics Position: ");
+                        egui::ComboBox::from_id_source("lyrics_position_select")
+                            .selected_text(settings.visual.lyrics_position.as_str())
+                            .show_ui(ui, |ui| {
+                                for position in [
+                                    LyricsPosition::Top,
+                                    LyricsPosition::Center,
+                                    LyricsPosition::Bottom,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut settings.visual.lyrics_position,
+                                        position,
+                                        position.as_str(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
+
+                    ui.label("Time Ruler: ");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut settings.visual.show_time_ruler, "");
+                        ui.add_enabled_ui(settings.visual.show_time_ruler, |ui| {
+                            egui::ComboBox::from_id_source("time_ruler_mode_select")
+                                .selected_text(settings.visual.time_ruler_mode.as_str())
+                                .show_ui(ui, |ui| {
+                                    for mode in [TimeRulerMode::Seconds, TimeRulerMode::Musical] {
+                                        ui.selectable_value(
+                                            &mut settings.visual.time_ruler_mode,
+                                            mode,
+                                            mode.as_str(),
+                                        );
+                                    }
+                                });
+                        });
+                    });
+                    ui.end_row();
+
+                    if settings.visual.show_time_ruler {
+                        ui.label("Time Ruler Spacing: ")
+                            .on_hover_text("The least space between two labels of the ruler");
+                        ui.add(
+                            egui::Slider::new(
+                                &mut settings.visual.time_ruler_spacing_px,
+                                20.0..=400.0,
+                            )
+                            .suffix(" px"),
+                        );
+                        ui.end_row();
+                    }
+
+                    ui.label("Chord Names: ").on_hover_text(
+                        "Shows the name of the chord being played above the keyboard",
+                    );
+                    ui.checkbox(&mut settings.visual.show_chord_names, "");
+                    ui.end_row();
+
+                    ui.label("Inactive Channel Opacity: ");
+                    ui.add(egui::Slider::new(
+                        &mut settings.visual.inactive_channel_alpha,
+                        0.0..=1.0,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Normalize Percussion Length: ");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut settings.visual.normalize_percussion_length, "");
+                        ui.add_enabled(
+                            settings.visual.normalize_percussion_length,
+                            egui::DragValue::new(&mut settings.visual.normalized_length_ms)
+                                .speed(1.0)
+                                .clamp_range(RangeInclusive::new(1.0, 2000.0))
+                                .suffix(" ms"),
+                        );
+                    });
+                    ui.end_row();
+
+                    ui.label("Group Chords: ")
+                        .on_hover_text("Not supported with the cake MIDI loading mode");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut settings.visual.chord_grouping, "");
+                        ui.add_enabled(
+                            settings.visual.chord_grouping,
+                            egui::DragValue::new(&mut settings.visual.chord_tolerance_ms)
+                                .speed(0.5)
+                                .clamp_range(RangeInclusive::new(0.0, 200.0))
+                                .suffix(" ms"),
+                        );
+                    });
+                    ui.end_row();
+
+                    ui.label("Arpeggio View: ").on_hover_text(
+                        "Draws the notes of a chord one after the other from the lowest key up. \
+                        Not supported with the cake MIDI loading mode",
+                    );
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut settings.visual.arpeggio_view, "");
+                        ui.add_enabled(
+                            settings.visual.arpeggio_view,
+                            egui::DragValue::new(&mut settings.visual.arpeggio_offset_ms)
+                                .speed(0.5)
+                                .clamp_range(RangeInclusive::new(0.0, 200.0))
+                                .suffix(" ms"),
+                        );
+                    });
+                    ui.end_row();
+
+                    ui.label("Color Space (restart required): ");
+                    egui::ComboBox::from_id_source("colorspace_select")
+                        .selected_text(settings.visual.color_space.as_str())
+                        .show_ui(ui, |ui| {
+                            for color_space in [ColorSpace::Srgb, ColorSpace::LinearHdr] {
+                                ui.selectable_value(
+                                    &mut settings.visual.color_space,
+                                    color_space,
+                                    color_space.as_str(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("GPU (restart required): ");
+                    let gpu_name = |index: usize| match index {
+                        0 => "Automatic".to_owned(),
+                        _ => win
+                            .gpu_devices
+                            .get(index - 1)
+                            .cloned()
+                            .unwrap_or_else(|| format!("GPU {}", index)),
+                    };
+                    egui::ComboBox::from_id_source("gpu_select")
+                        .selected_text(gpu_name(settings.rendering.gpu_device_index))
+                        .show_ui(ui, |ui| {
+                            for index in 0..=win.gpu_devices.len() {
+                                ui.selectable_value(
+                                    &mut settings.rendering.gpu_device_index,
+                                    index,
+                                    gpu_name(index),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Memory Block Size: ").on_hover_text(
+                        "The size of the GPU memory blocks that the notes are stored in. \
+                        Bigger blocks help with MIDIs that have many notes. \
+                        Not supported with the cake MIDI loading mode",
+                    );
+                    let block_size_text = |size: u64| match size {
+                        0 => "Automatic".to_owned(),
+                        size => format!("{} MB", size),
+                    };
+                    egui::ComboBox::from_id_source("memory_block_size_select")
+                        .selected_text(block_size_text(settings.rendering.memory_block_size_mb))
+                        .show_ui(ui, |ui| {
+                            for size in [0, 64, 128, 256, 512, 1024] {
+                                ui.selectable_value(
+                                    &mut settings.rendering.memory_block_size_mb,
+                                    size,
+                                    block_size_text(size),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("GPU Culling: ").on_hover_text(
+                        "Skips the notes outside of the view, by key and by time, on the GPU, and \
+                        counts the drawn notes there. Not supported with the cake MIDI loading mode",
+                    );
+                    ui.checkbox(&mut settings.rendering.gpu_culling, "");
+                    ui.end_row();
+
+                    ui.label("Color Script: ").on_hover_text(format!(
+                        "Colors and places the notes with the color(key, velocity, channel, \
+                        track) and layout(key, velocity, channel, track) functions in {}",
+                        WasabiSettings::color_script_path().display()
+                    ));
+                    ui.checkbox(&mut settings.visual.use_color_script, "");
+                    ui.end_row();
+
+                    ui.label("Twitch Chat Colors: ").on_hover_text(
+                        "Viewers pick the colors of the channels with !color <hex> in the chat, \
+                        the first viewer colors the first channel and so on",
+                    );
+                    let mut chat = settings.visual.twitch_chat_channel.is_some();
+                    if ui.checkbox(&mut chat, "").changed() {
+                        settings.visual.twitch_chat_channel = chat.then(String::new);
+                    }
+                    ui.end_row();
+
+                    if let Some(channel) = settings.visual.twitch_chat_channel.as_mut() {
+                        ui.label("Twitch Channel: ");
+                        ui.text_edit_singleline(channel);
+                        ui.end_row();
+                    }
+
+                    // Only the JACK synth renders its audio inside of Wasabi, the
+                    // others send it straight to the audio device
+                    if settings.synth.synth == Synth::Jack {
+                        ui.label("Show Spectrogram: ");
+                        ui.checkbox(&mut settings.visual.show_spectrogram, "");
+                        ui.end_row();
+
+                        if settings.visual.show_spectrogram {
+                            ui.label("Spectrogram FFT Size: ");
+                            egui::ComboBox::from_id_source("spectrogram_fft_select")
+                                .selected_text(settings.visual.spectrogram_fft_size.to_string())
+                                .show_ui(ui, |ui| {
+                                    for size in [512, 1024, 2048, 4096, 8192, 16384] {
+                                        ui.selectable_value(
+                                            &mut settings.visual.spectrogram_fft_size,
+                                            size,
+                                            size.to_string(),
+                                        );
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("Spectrogram Colors: ");
+                            egui::ComboBox::from_id_source("spectrogram_colors_select")
+                                .selected_text(settings.visual.spectrogram_color_map.as_str())
+                                .show_ui(ui, |ui| {
+                                    for color_map in [
+                                        SpectrogramColorMap::Magma,
+                                        SpectrogramColorMap::Rainbow,
+                                        SpectrogramColorMap::Grayscale,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut settings.visual.spectrogram_color_map,
+                                            color_map,
+                                            color_map.as_str(),
+                                        );
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("Spectrogram Height: ");
+                            ui.add(
+                                egui::Slider::new(
+                                    &mut settings.visual.spectrogram_height,
+                                    40.0..=400.0,
+                                )
+                                .suffix(" px"),
+                            );
+                            ui.end_row();
+                        }
+                    }
+
+                    ui.label("Dim When Idle: ");
+                    let mut dim = settings.visual.dim_after_secs.is_some();
+                    if ui.checkbox(&mut dim, "").changed() {
+                        settings.visual.dim_after_secs = dim.then_some(600);
+                    }
+                    ui.end_row();
+
+                    if let Some(secs) = settings.visual.dim_after_secs.as_mut() {
+                        ui.label("Dim After (seconds): ");
+                        ui.add(egui::DragValue::new(secs).speed(1.0).clamp_range(1..=86400));
+                        ui.end_row();
+                    }
+
+                    ui.label("Watermark: ");
+                    let mut watermark = settings.visual.watermark.is_some();
+                    if ui.checkbox(&mut watermark, "").changed() {
+                        settings.visual.watermark = watermark.then(WatermarkConfig::default);
+                    }
+                    ui.end_row();
+
+                    if let Some(watermark) = settings.visual.watermark.as_mut() {
+                        ui.label("Watermark Text: ");
+                        ui.text_edit_singleline(&mut watermark.text);
+                        ui.end_row();
+
+                        ui.label("Watermark Size: ");
+                        ui.add(
+                            egui::DragValue::new(&mut watermark.font_size)
+                                .speed(0.5)
+                                .clamp_range(6.0..=120.0),
+                        );
+                        ui.end_row();
+
+                        ui.label("Watermark Opacity: ");
+                        ui.add(egui::Slider::new(&mut watermark.opacity, 0.0..=1.0));
+                        ui.end_row();
+
+                        ui.label("Watermark Corner: ");
+                        egui::ComboBox::from_id_source("watermark_corner_select")
+                            .selected_text(watermark.corner.as_str())
+                            .show_ui(ui, |ui| {
+                                for corner in [
+                                    WatermarkCorner::TopLeft,
+                                    WatermarkCorner::TopRight,
+                                    WatermarkCorner::BottomLeft,
+                                    WatermarkCorner::BottomRight,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut watermark.corner,
+                                        corner,
+                                        corner.as_str(),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Animate Watermark: ");
+                        ui.checkbox(&mut watermark.animated, "");
+                        ui.end_row();
+                    }
+                });
+
+            // Input settings section
+            ui.add_space(6.0);
+            ui.heading("Input");
+            ui.separator();
+
+            egui::Grid::new("input_settings_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .min_col_width(col_width)
+                .show(ui, |ui| {
+                    ui.label("Touch Gestures: ");
+                    ui.checkbox(&mut settings.input.touch_enabled, "");
+                    ui.end_row();
+
+                    ui.label("Pinch Sensitivity: ");
+                    ui.add(egui::Slider::new(
+                        &mut settings.input.touch_zoom_sensitivity,
+                        0.1..=5.0,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Swipe Sensitivity: ");
+                    ui.add(egui::Slider::new(
+                        &mut settings.input.touch_swipe_sensitivity,
+                        0.1..=5.0,
+                    ));
+                    ui.end_row();
+                });
+
+            ui.add_space(6.0);
+            ui.label("Key Bindings:");
+            egui::Grid::new("key_bindings_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .min_col_width(col_width)
+                .show(ui, |ui| {
+                    for action in WasabiAction::ALL {
+                        ui.label(format!("{}: ", action.as_str()));
+                        ui.horizontal(|ui| {
+                            if state.recording_binding == Some(action) {
+                                ui.label("Press a key...");
+                                if ui.button("Cancel").clicked() {
+                                    state.recording_binding = None;
+                                }
+                            } else {
+                                ui.label(settings.input.key_bindings.get(action).to_string());
+                                if ui.button("Record").clicked() {
+                                    state.recording_binding = Some(action);
+                                }
+                            }
+                        });
+                        ui.end_row();
+                    }
+
+                    ui.label("");
+                    if ui.button("Reset Key Bindings").clicked() {
+                        settings.input.key_bindings.reset();
+                        state.recording_binding = None;
+                    }
+                    ui.end_row();
+                });
+
+            // The key is taken out of the input, so it doesn't also trigger its old action
+            if let Some(action) = state.recording_binding {
+                let pressed = ctx.input_mut(|i| {
+                    let index = i.events.iter().position(|event| {
+                        matches!(event, egui::Event::Key { pressed: true, .. })
+                    })?;
+                    match i.events.remove(index) {
+                        egui::Event::Key { key, modifiers, .. } => Some((key, modifiers)),
+                        _ => None,
+                    }
+                });
+                if let Some((key, modifiers)) = pressed {
+                    if key != egui::Key::Escape {
+                        settings
+                            .input
+                            .key_bindings
+                            .set(action, KeyBinding::new(key, modifiers));
+                    }
+                    state.recording_binding = None;
+                }
+            }
+
+            // Remote control settings section
+            ui.add_space(6.0);
+            ui.heading("Remote Control");
+            ui.separator();
+
+            egui::Grid::new("remote_settings_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .min_col_width(col_width)
+                .show(ui, |ui| {
+                    ui.label("HTTP API: ")
+                        .on_hover_text("Lets other programs control the playback over HTTP");
+                    ui.checkbox(&mut settings.remote.enabled, "");
+                    ui.end_row();
+
+                    ui.label("Port: ");
+                    ui.add(egui::DragValue::new(&mut settings.remote.port).clamp_range(1..=65535));
+                    ui.end_row();
+
+                    ui.label("Token: ").on_hover_text(
+                        "Every request has to send this in an `Authorization: Bearer` header. \
+                        A random one is made up when the API starts without one",
+                    );
+                    ui.add(egui::TextEdit::singleline(&mut settings.remote.token).password(true));
+                    ui.end_row();
                 });
 
             ui.separator();
             ui.vertical_centered(|ui| {
                 ui.label("Options marked with (*) will apply when a new MIDI is loaded.");
                 if ui.button("Save").clicked() {
-                    settings.save_to_file();
+                    settings.save_without_override(win.settings_override.as_ref());
                 }
             });
         });
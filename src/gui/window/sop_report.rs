@@ -0,0 +1,69 @@
+use egui::Context;
+
+use crate::midi::{SopViolation, MAX_SOP_VIOLATIONS};
+
+/// The result of checking the open MIDI for events that break the usual rules
+pub struct SopReport {
+    file_name: String,
+    violations: Vec<SopViolation>,
+}
+
+impl SopReport {
+    pub fn new(file_name: String, violations: Vec<SopViolation>) -> Self {
+        Self {
+            file_name,
+            violations,
+        }
+    }
+
+    /// Shows the violations in a window, which returns false once it was closed
+    pub fn show(&self, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut close_clicked = false;
+
+        egui::Window::new("SOP Validation")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(520.0)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let summary = match self.violations.len() {
+                    0 => format!("{} follows all of the checked rules.", self.file_name),
+                    MAX_SOP_VIOLATIONS.. => format!(
+                        "{} has at least {} violations, only the first ones are shown.",
+                        self.file_name, MAX_SOP_VIOLATIONS
+                    ),
+                    count => format!("{} has {} violations.", self.file_name, count),
+                };
+                ui.label(summary);
+
+                if !self.violations.is_empty() {
+                    ui.separator();
+                    egui::ScrollArea::vertical().max_height(300.0).show_rows(
+                        ui,
+                        ui.text_style_height(&egui::TextStyle::Body),
+                        self.violations.len(),
+                        |ui, rows| {
+                            for violation in &self.violations[rows] {
+                                ui.horizontal(|ui| {
+                                    ui.strong(violation.kind.as_str());
+                                    ui.label(format!(
+                                        "Track {}, tick {}: {}",
+                                        violation.track, violation.tick, violation.message
+                                    ));
+                                });
+                            }
+                        },
+                    );
+                }
+
+                ui.separator();
+                if ui.button("Close").clicked() {
+                    close_clicked = true;
+                }
+            });
+
+        open && !close_clicked
+    }
+}
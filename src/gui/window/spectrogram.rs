@@ -0,0 +1,195 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use egui::{Color32, ColorImage, Rect, Sense, TextureHandle, TextureOptions, Ui};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::{
+    audio_playback::pcm::PcmRingBuffer,
+    settings::{SpectrogramColorMap, VisualSettings},
+};
+
+/// How many columns of history the texture holds
+const COLUMNS: usize = 512;
+/// How many frequency rows the texture holds
+const ROWS: usize = 256;
+
+/// The lowest frequency shown, the rows are spaced logarithmically up to the nyquist frequency
+const MIN_FREQ: f32 = 20.0;
+/// Anything quieter than this is drawn with the lowest color
+const MIN_DB: f32 = -90.0;
+
+const MAGMA: [(u8, u8, u8); 6] = [
+    (0, 0, 4),
+    (59, 15, 112),
+    (140, 41, 129),
+    (222, 73, 104),
+    (254, 159, 109),
+    (252, 253, 191),
+];
+
+/// A scrolling heatmap of the frequencies in the rendered audio over time
+pub struct SpectrogramPanel {
+    fft: Option<Arc<dyn Fft<f32>>>,
+    window: Vec<f32>,
+    pending: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    /// The loudness of each row from 0 to 1, newest column last
+    columns: VecDeque<[f32; ROWS]>,
+    color_map: SpectrogramColorMap,
+    texture: Option<TextureHandle>,
+    dirty: bool,
+}
+
+impl SpectrogramPanel {
+    pub fn new() -> Self {
+        Self {
+            fft: None,
+            window: Vec::new(),
+            pending: Vec::new(),
+            buffer: Vec::new(),
+            columns: VecDeque::with_capacity(COLUMNS),
+            color_map: SpectrogramColorMap::Magma,
+            texture: None,
+            dirty: true,
+        }
+    }
+
+    pub fn draw(&mut self, ui: &mut Ui, pcm: &PcmRingBuffer, settings: &VisualSettings) {
+        if self.color_map != settings.spectrogram_color_map {
+            self.color_map = settings.spectrogram_color_map;
+            self.dirty = true;
+        }
+
+        self.analyze(pcm, settings.spectrogram_fft_size);
+
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+
+        if self.dirty {
+            let image = self.build_image();
+            match &mut self.texture {
+                Some(texture) => texture.set(image, TextureOptions::LINEAR),
+                None => {
+                    self.texture = Some(ui.ctx().load_texture(
+                        "spectrogram",
+                        image,
+                        TextureOptions::LINEAR,
+                    ))
+                }
+            }
+            self.dirty = false;
+        }
+
+        if let Some(texture) = &self.texture {
+            ui.painter().image(
+                texture.id(),
+                rect,
+                Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                Color32::WHITE,
+            );
+        }
+    }
+
+    /// Turns every full window of new samples into a column, windows overlap by half
+    fn analyze(&mut self, pcm: &PcmRingBuffer, fft_size: usize) {
+        let fft_size = fft_size.clamp(256, 16384).next_power_of_two();
+        if self.window.len() != fft_size {
+            self.fft = Some(FftPlanner::new().plan_fft_forward(fft_size));
+            self.window = (0..fft_size)
+                .map(|i| {
+                    let phase = i as f32 / fft_size as f32 * std::f32::consts::TAU;
+                    0.5 - 0.5 * phase.cos()
+                })
+                .collect();
+            self.pending.clear();
+        }
+
+        pcm.take_into(&mut self.pending);
+
+        // Only the most recent columns fit, so older samples aren't worth analyzing
+        let hop = fft_size / 2;
+        let max_pending = fft_size + hop * COLUMNS;
+        if self.pending.len() > max_pending {
+            self.pending.drain(..self.pending.len() - max_pending);
+        }
+
+        let Some(fft) = self.fft.clone() else {
+            return;
+        };
+        let nyquist = pcm.sample_rate() as f32 / 2.0;
+
+        while self.pending.len() >= fft_size {
+            self.buffer.clear();
+            self.buffer.extend(
+                self.pending[..fft_size]
+                    .iter()
+                    .zip(self.window.iter())
+                    .map(|(sample, window)| Complex::new(sample * window, 0.0)),
+            );
+            fft.process(&mut self.buffer);
+
+            let column = self.column_from_bins(nyquist);
+            if self.columns.len() == COLUMNS {
+                self.columns.pop_front();
+            }
+            self.columns.push_back(column);
+            self.dirty = true;
+
+            self.pending.drain(..hop);
+        }
+    }
+
+    /// Takes the loudest bin in the frequency range of every row
+    fn column_from_bins(&self, nyquist: f32) -> [f32; ROWS] {
+        let bins = self.buffer.len() / 2;
+        // A Hann window halves the amplitude of a sine wave
+        let scale = 4.0 / self.buffer.len() as f32;
+        let bin_at = |row: f32| {
+            let freq = MIN_FREQ * (nyquist / MIN_FREQ).powf(row / ROWS as f32);
+            ((freq / nyquist * bins as f32) as usize).min(bins - 1)
+        };
+
+        let mut column = [0.0; ROWS];
+        for (row, level) in column.iter_mut().enumerate() {
+            let start = bin_at(row as f32);
+            let end = bin_at(row as f32 + 1.0).max(start + 1);
+            let magnitude = self.buffer[start..end.min(bins)]
+                .iter()
+                .map(|bin| bin.norm() * scale)
+                .fold(0.0, f32::max);
+
+            let db = 20.0 * magnitude.max(1e-9).log10();
+            *level = ((db - MIN_DB) / -MIN_DB).clamp(0.0, 1.0);
+        }
+        column
+    }
+
+    /// The newest column is drawn on the right and high frequencies on the top
+    fn build_image(&self) -> ColorImage {
+        let mut image = ColorImage::new([COLUMNS, ROWS], self.color(0.0));
+        let offset = COLUMNS - self.columns.len();
+        for (x, column) in self.columns.iter().enumerate() {
+            for (row, level) in column.iter().enumerate() {
+                image.pixels[(ROWS - 1 - row) * COLUMNS + offset + x] = self.color(*level);
+            }
+        }
+        image
+    }
+
+    fn color(&self, level: f32) -> Color32 {
+        match self.color_map {
+            SpectrogramColorMap::Magma => {
+                let position = level * (MAGMA.len() - 1) as f32;
+                let index = (position as usize).min(MAGMA.len() - 2);
+                let t = position - index as f32;
+                let (r0, g0, b0) = MAGMA[index];
+                let (r1, g1, b1) = MAGMA[index + 1];
+                let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+                Color32::from_rgb(lerp(r0, r1), lerp(g0, g1), lerp(b0, b1))
+            }
+            SpectrogramColorMap::Rainbow => {
+                egui::ecolor::Hsva::new((1.0 - level) * 0.7, 1.0, level, 1.0).into()
+            }
+            SpectrogramColorMap::Grayscale => Color32::from_gray((level * 255.0) as u8),
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use egui::{Context, Frame, Pos2};
 
-use crate::{gui::window::GuiWasabiWindow, midi::MIDIFileBase};
+use crate::{
+    gui::window::{velocity_histogram::VelocityHistogram, GuiWasabiWindow},
+    midi::MIDIFileBase,
+};
 
 pub struct GuiMidiStats {
     time_passed: f64,
@@ -8,6 +11,9 @@ pub struct GuiMidiStats {
     notes_total: u64,
     notes_on_screen: u64,
     voice_count: u64,
+    render_time_ms: f32,
+    cpu_fill_time_ms: f32,
+    velocity_histogram: [u64; 16],
 }
 
 impl GuiMidiStats {
@@ -18,6 +24,9 @@ impl GuiMidiStats {
             notes_total: 0,
             notes_on_screen: 0,
             voice_count: 0,
+            render_time_ms: 0.0,
+            cpu_fill_time_ms: 0.0,
+            velocity_histogram: [0; 16],
         }
     }
 
@@ -28,6 +37,17 @@ impl GuiMidiStats {
     pub fn set_rendered_note_count(&mut self, notes: u64) {
         self.notes_on_screen = notes;
     }
+
+    /// The wall-clock time from submitting the frame until the GPU finished it, and the time
+    /// spent filling the note buffers on the CPU
+    pub fn set_frame_times(&mut self, render_time_ms: f32, cpu_fill_time_ms: f32) {
+        self.render_time_ms = render_time_ms;
+        self.cpu_fill_time_ms = cpu_fill_time_ms;
+    }
+
+    pub fn set_velocity_histogram(&mut self, buckets: [u64; 16]) {
+        self.velocity_histogram = buckets;
+    }
 }
 
 pub fn draw_stats(win: &mut GuiWasabiWindow, ctx: &Context, pos: Pos2, mut stats: GuiMidiStats) {
@@ -50,7 +70,7 @@ pub fn draw_stats(win: &mut GuiWasabiWindow, ctx: &Context, pos: Pos2, mut stats
         .enabled(true)
         .frame(stats_frame)
         .fixed_pos(pos)
-        .fixed_size(egui::Vec2::new(200.0, 128.0))
+        .fixed_size(egui::Vec2::new(200.0, 200.0))
         .show(ctx, |ui| {
             let mut time_millis: u64 = 0;
             let mut time_sec: u64 = 0;
@@ -67,7 +87,11 @@ pub fn draw_stats(win: &mut GuiWasabiWindow, ctx: &Context, pos: Pos2, mut stats
                 } else {
                     0.0
                 };
-                let time = midi_file.timer().get_time().as_secs_f64();
+                let mut time = midi_file.timer().get_time().as_secs_f64();
+                if midi_file.is_reversed() {
+                    // Count down from the end when playing backwards
+                    time = (stats.time_total - time).max(0.0);
+                }
 
                 length_millis = (stats.time_total * 10.0) as u64 % 10;
                 length_sec = stats.time_total as u64 % 60;
@@ -116,6 +140,37 @@ pub fn draw_stats(win: &mut GuiWasabiWindow, ctx: &Context, pos: Pos2, mut stats
                 });
             });
 
+            if let Some(summary) = win.fps.frame_time_summary() {
+                ui.horizontal(|ui| {
+                    ui.monospace("Frame Time:");
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.monospace(format!(
+                            "{:.1}/{:.1}/{:.1} ms",
+                            summary.min, summary.avg, summary.max
+                        ));
+                    });
+                })
+                .response
+                .on_hover_text("The minimum, average and maximum of the last 100 frames");
+                win.fps.draw_history(ui, 24.0);
+            }
+
+            ui.horizontal(|ui| {
+                ui.monospace("Render Time:");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.monospace(format!("{:.2} ms", stats.render_time_ms));
+                });
+            })
+            .response
+            .on_hover_text("From submitting the frame until the GPU finished it, timed on the CPU");
+
+            ui.horizontal(|ui| {
+                ui.monospace("Fill Time:");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.monospace(format!("{:.2} ms", stats.cpu_fill_time_ms));
+                });
+            });
+
             ui.horizontal(|ui| {
                 ui.monospace("Voice Count:");
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -146,5 +201,8 @@ pub fn draw_stats(win: &mut GuiWasabiWindow, ctx: &Context, pos: Pos2, mut stats
                 }
                 _ => {}
             }
+
+            ui.monospace("Velocities:");
+            VelocityHistogram::new(stats.velocity_histogram).draw(ui, 30.0);
         });
 }
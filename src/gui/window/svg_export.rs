@@ -0,0 +1,173 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use egui::{Color32, Vec2};
+use svg::node::element::{Line, Rectangle};
+
+use crate::{
+    midi::{MIDIFileBase, MIDIFileUnion},
+    settings::WasabiSettings,
+};
+
+use super::{
+    keyboard_layout::{KeyPosition, KeyboardView},
+    scene::CapturedNote,
+};
+
+/// The color of the lines between the keys and of the beats
+const GRID_COLOR: &str = "#404040";
+
+/// Where the notes go in the image, taken from the window in the frame the notes were
+/// captured, so the image can be written on another thread
+pub struct SvgLayout {
+    size: Vec2,
+    /// The time shown in the image in seconds
+    range: f32,
+    bg_color: Color32,
+    /// The position of each visible note, indexed by key
+    notes: Vec<Option<KeyPosition>>,
+    /// The lines between the white keys, from 0 to 1
+    key_lines: Vec<f32>,
+    /// The beats from the playhead in seconds
+    beats: Vec<f32>,
+}
+
+impl SvgLayout {
+    /// The layout of an image the size of the notes panel, with the playhead at the
+    /// bottom like outside of waterfall mode
+    pub fn new(
+        midi_file: &MIDIFileUnion,
+        key_view: &KeyboardView,
+        settings: &WasabiSettings,
+        size: Vec2,
+    ) -> Self {
+        let range = settings.midi.note_speed as f32;
+        let time = midi_file.timer().get_time().as_secs_f64();
+        let beat_length = 60.0 / midi_file.bpm_at(time);
+
+        // The beats at the tempo of the current time, starting from the next whole beat
+        let mut beats = Vec::new();
+        let mut beat = (time / beat_length).ceil() * beat_length - time;
+        while beat < range as f64 {
+            beats.push(beat as f32);
+            beat += beat_length;
+        }
+
+        let notes = (0..256)
+            .map(|key| {
+                key_view
+                    .visible_range
+                    .contains(&key)
+                    .then(|| key_view.note(key))
+            })
+            .collect();
+
+        let key_lines = key_view
+            .iter_visible_keys()
+            .filter(|(_, key)| !key.black)
+            .map(|(_, key)| key.left)
+            .collect();
+
+        Self {
+            size,
+            range,
+            bg_color: settings.visual.bg_color,
+            notes,
+            key_lines,
+            beats,
+        }
+    }
+}
+
+fn hex_color(r: u8, g: u8, b: u8) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Writes the captured notes to an SVG file. The elements are written one by one, so
+/// the whole document is never held in memory for files with many notes on screen.
+pub fn write_svg(path: &Path, notes: &[CapturedNote], layout: &SvgLayout) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("Failed to write SVG: {}", e);
+    let mut out = BufWriter::new(File::create(path).map_err(error)?);
+    write_document(&mut out, notes, layout).map_err(error)?;
+    out.flush().map_err(error)
+}
+
+fn write_document(
+    out: &mut impl Write,
+    notes: &[CapturedNote],
+    layout: &SvgLayout,
+) -> std::io::Result<()> {
+    let (width, height) = (layout.size.x, layout.size.y);
+    let range = layout.range;
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width} {height}" width="{width}" height="{height}">"#
+    )?;
+
+    let bg = layout.bg_color;
+    writeln!(
+        out,
+        "{}",
+        Rectangle::new()
+            .set("width", width)
+            .set("height", height)
+            .set("fill", hex_color(bg.r(), bg.g(), bg.b()))
+    )?;
+
+    for x in layout.key_lines.iter() {
+        let x = x * width;
+        let line = Line::new()
+            .set("x1", x)
+            .set("y1", 0.0)
+            .set("x2", x)
+            .set("y2", height)
+            .set("stroke", GRID_COLOR)
+            .set("stroke-width", 1.0);
+        writeln!(out, "{}", line)?;
+    }
+
+    for beat in layout.beats.iter() {
+        let y = height - beat / range * height;
+        let line = Line::new()
+            .set("x1", 0.0)
+            .set("y1", y)
+            .set("x2", width)
+            .set("y2", y)
+            .set("stroke", GRID_COLOR)
+            .set("stroke-width", 1.0);
+        writeln!(out, "{}", line)?;
+    }
+
+    // Black keys are written last, so they are drawn over the white ones like on screen
+    for black in [false, true] {
+        for note in notes.iter() {
+            let Some(position) = layout.notes[note.key as usize] else {
+                continue;
+            };
+            if position.black != black {
+                continue;
+            }
+
+            let start = note.start.max(0.0);
+            let end = (note.start + note.len).min(range);
+            if end <= start {
+                continue;
+            }
+
+            let color = note.color;
+            let rect = Rectangle::new()
+                .set("x", position.left * width)
+                .set("y", height - end / range * height)
+                .set("width", (position.right - position.left) * width)
+                .set("height", (end - start) / range * height)
+                .set("fill", hex_color(color.red(), color.green(), color.blue()));
+            writeln!(out, "{}", rect)?;
+        }
+    }
+
+    writeln!(out, "</svg>")
+}
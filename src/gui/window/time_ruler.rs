@@ -0,0 +1,195 @@
+use egui::{Align2, Color32, FontId, Rect, Stroke, Ui};
+
+use crate::{
+    midi::{MIDIFileBase, MIDIFileUnion},
+    settings::TimeRulerMode,
+};
+
+/// How wide the ruler is in pixels
+const RULER_SIZE: f32 = 40.0;
+
+/// The steps in seconds that the labels of the absolute time can be apart
+const SECOND_STEPS: [f64; 16] = [
+    0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+/// A tick of the ruler at a time of the playback in seconds
+struct Tick {
+    time: f64,
+    label: String,
+    /// Bars get longer ticks than beats
+    major: bool,
+}
+
+/// Draws a ruler along the edge of the notes where the time runs, on the left or on the
+/// top in waterfall mode. The labels are spread at least `min_spacing` pixels apart by
+/// picking a bigger step when the notes are zoomed out.
+pub fn draw_time_ruler(
+    ui: &Ui,
+    rect: Rect,
+    midi_file: &MIDIFileUnion,
+    range: f64,
+    mode: TimeRulerMode,
+    min_spacing: f32,
+    waterfall: bool,
+) {
+    let time = midi_file.timer().get_time().as_secs_f64();
+    let depth_length = if waterfall {
+        rect.width()
+    } else {
+        rect.height()
+    };
+    let pixels_per_second = depth_length as f64 / range;
+
+    let ticks = match mode {
+        TimeRulerMode::Seconds => second_ticks(time, range, pixels_per_second, min_spacing),
+        TimeRulerMode::Musical => {
+            musical_ticks(midi_file, time, range, pixels_per_second, min_spacing)
+        }
+    };
+
+    let ruler = if waterfall {
+        Rect::from_min_max(rect.min, egui::pos2(rect.right(), rect.top() + RULER_SIZE))
+    } else {
+        Rect::from_min_max(
+            rect.min,
+            egui::pos2(rect.left() + RULER_SIZE, rect.bottom()),
+        )
+    };
+    let painter = ui.painter().with_clip_rect(ruler);
+    painter.rect_filled(ruler, 0.0, Color32::from_black_alpha(140));
+
+    let font = FontId::proportional(10.0);
+    for tick in ticks {
+        let offset = ((tick.time - time) / range) as f32;
+        let length = if tick.major { 10.0 } else { 5.0 };
+        let stroke = Stroke::new(1.0, Color32::from_gray(if tick.major { 220 } else { 140 }));
+
+        if waterfall {
+            let x = rect.left() + offset * rect.width();
+            painter.line_segment(
+                [
+                    egui::pos2(x, ruler.bottom() - length),
+                    egui::pos2(x, ruler.bottom()),
+                ],
+                stroke,
+            );
+            painter.text(
+                egui::pos2(x + 2.0, ruler.top() + 2.0),
+                Align2::LEFT_TOP,
+                tick.label,
+                font.clone(),
+                Color32::WHITE,
+            );
+        } else {
+            let y = rect.bottom() - offset * rect.height();
+            painter.line_segment(
+                [
+                    egui::pos2(ruler.right() - length, y),
+                    egui::pos2(ruler.right(), y),
+                ],
+                stroke,
+            );
+            painter.text(
+                egui::pos2(ruler.left() + 2.0, y - 1.0),
+                Align2::LEFT_BOTTOM,
+                tick.label,
+                font.clone(),
+                Color32::WHITE,
+            );
+        }
+    }
+}
+
+fn second_ticks(time: f64, range: f64, pixels_per_second: f64, min_spacing: f32) -> Vec<Tick> {
+    let step = SECOND_STEPS
+        .into_iter()
+        .find(|step| step * pixels_per_second >= min_spacing as f64)
+        .unwrap_or(SECOND_STEPS[SECOND_STEPS.len() - 1]);
+
+    let first = (time / step).ceil() as i64;
+    let last = ((time + range) / step).floor() as i64;
+    (first..=last)
+        .map(|i| {
+            let tick_time = i as f64 * step;
+            let label = if step >= 1.0 {
+                let seconds = tick_time.round() as i64;
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            } else if step >= 0.1 {
+                format!("{:.1}", tick_time)
+            } else {
+                format!("{:.2}", tick_time)
+            };
+            Tick {
+                time: tick_time,
+                label,
+                major: true,
+            }
+        })
+        .collect()
+}
+
+fn musical_ticks(
+    midi_file: &MIDIFileUnion,
+    time: f64,
+    range: f64,
+    pixels_per_second: f64,
+    min_spacing: f32,
+) -> Vec<Tick> {
+    // The beats are counted in the time of the file, which runs the other way when reversed
+    let reversed_length = midi_file
+        .is_reversed()
+        .then(|| midi_file.midi_length())
+        .flatten();
+    let beats = match reversed_length {
+        Some(length) => midi_file.beats_between(length - time - range, length - time),
+        None => midi_file.beats_between(time, time + range),
+    };
+
+    let spacing_of = |seconds: f64| (seconds * pixels_per_second) as f32;
+    let beat_spacing = match beats.as_slice() {
+        [first, second, ..] => spacing_of((second.time - first.time).abs()),
+        _ => f32::INFINITY,
+    };
+
+    // Zoomed out, only every few bars is labeled
+    let bar_step = if beat_spacing >= min_spacing {
+        None
+    } else {
+        let bar_length = beats
+            .iter()
+            .filter(|beat| beat.beat == 1)
+            .map(|beat| beat.time)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .next()
+            .map_or(beat_spacing * 4.0, |bars| {
+                spacing_of((bars[1] - bars[0]).abs())
+            });
+        let mut step = 1;
+        while bar_length * (step as f32) < min_spacing && step < 1 << 16 {
+            step *= 2;
+        }
+        Some(step)
+    };
+
+    beats
+        .into_iter()
+        .filter(|beat| match bar_step {
+            None => true,
+            Some(step) => beat.beat == 1 && (beat.bar - 1) % step == 0,
+        })
+        .map(|beat| Tick {
+            time: match reversed_length {
+                Some(length) => length - beat.time,
+                None => beat.time,
+            },
+            label: if beat.beat == 1 {
+                beat.bar.to_string()
+            } else {
+                format!("{}.{}", beat.bar, beat.beat)
+            },
+            major: beat.beat == 1,
+        })
+        .collect()
+}
@@ -0,0 +1,57 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use egui::{Color32, Context, Frame};
+
+use crate::notifications::{self, Notification, NotificationLevel};
+
+/// How long a notification stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+/// How many notifications are on screen at once, the oldest ones go first
+const MAX_TOASTS: usize = 5;
+
+/// Shows the notifications in the bottom right corner for a few seconds each
+#[derive(Default)]
+pub struct Toasts {
+    shown: VecDeque<(Notification, Instant)>,
+}
+
+impl Toasts {
+    pub fn show(&mut self, ctx: &Context) {
+        let now = Instant::now();
+        for notification in notifications::take_pending() {
+            if self.shown.len() == MAX_TOASTS {
+                self.shown.pop_front();
+            }
+            self.shown.push_back((notification, now));
+        }
+        self.shown
+            .retain(|(_, shown_at)| now.duration_since(*shown_at) < TOAST_DURATION);
+
+        if self.shown.is_empty() {
+            return;
+        }
+
+        egui::Area::new("notifications")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                for (notification, _) in self.shown.iter() {
+                    Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(400.0);
+                        let color = match notification.level {
+                            NotificationLevel::Info => ui.visuals().text_color(),
+                            NotificationLevel::Error => Color32::from_rgb(255, 110, 100),
+                        };
+                        ui.colored_label(color, &notification.message);
+                    });
+                }
+            });
+
+        // Keeps drawing so the notifications go away on time even without input
+        ctx.request_repaint_after(Duration::from_millis(250));
+    }
+}
@@ -1,11 +1,47 @@
-use egui::{Context, Frame};
+use egui::{ColorImage, Context, Frame, TextureHandle, TextureOptions};
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use crate::{
-    gui::window::GuiWasabiWindow, midi::MIDIFileBase, settings::WasabiSettings, state::WasabiState,
+    gui::window::{note_editor, GuiWasabiWindow},
+    midi::{MIDIFileBase, MIDIFileUnion, MAX_LOOP_LAYERS},
+    settings::WasabiSettings,
+    state::WasabiState,
 };
 
+/// The note speed is how many seconds the notes panel shows, so showing
+/// the whole MIDI means showing its length from the start
+fn fit_to_window(win: &mut GuiWasabiWindow, settings: &mut WasabiSettings, length: f64) {
+    settings.midi.note_speed = length.max(0.001);
+    // The automatic note speed would change it back straight away
+    settings.midi.auto_note_speed = false;
+
+    if let Some(midi_file) = win.midi_file.as_mut() {
+        if midi_file.allows_seeking_backward() {
+            midi_file.timer_mut().pause();
+            midi_file.timer_mut().seek(Duration::ZERO);
+        }
+    }
+}
+
+/// The texture of the album art of the MIDI, which is uploaded the first time it is shown
+fn album_art_texture(win: &mut GuiWasabiWindow, ctx: &Context) -> Option<TextureHandle> {
+    let art = win.midi_file.as_ref()?.metadata().album_art.clone()?;
+    if let Some((loaded, texture)) = &win.album_art {
+        if Arc::ptr_eq(loaded, &art) {
+            return Some(texture.clone());
+        }
+    }
+
+    let image = ColorImage::from_rgba_unmultiplied(
+        [art.width() as usize, art.height() as usize],
+        art.as_raw(),
+    );
+    let texture = ctx.load_texture("album_art", image, TextureOptions::LINEAR);
+    win.album_art = Some((art, texture.clone()));
+    Some(texture)
+}
+
 pub fn draw_panel(
     win: &mut GuiWasabiWindow,
     settings: &mut WasabiSettings,
@@ -21,15 +57,53 @@ pub fn draw_panel(
         .show_separator_line(false)
         .show(ctx, |ui| {
             ui.horizontal(|ui| {
+                if let Some(texture) = album_art_texture(win, ctx) {
+                    let size = texture.size_vec2();
+                    let height = ui.spacing().interact_size.y;
+                    ui.image(texture.id(), size * (height / size.y))
+                        .on_hover_ui(|ui| {
+                            ui.image(texture.id(), size);
+                        });
+                }
+
                 if ui.button("Open").clicked() {
                     win.open_midi_dialog(state);
                 }
 
+                if win.pending_extract.is_some() {
+                    ui.spinner();
+                    ui.label("Decompressing...");
+                }
+
+                if win.is_loading() && ui.button("Cancel").clicked() {
+                    win.cancel_loading();
+                }
+
                 if let Some(midi_file) = win.midi_file.as_mut() {
                     if ui.button("Unload").clicked() {
                         midi_file.timer_mut().pause();
                         win.synth.write().unwrap().reset();
                         win.midi_file = None;
+                        win.extracted_midi = None;
+                        if let Some(cancel) = win.metadata_scan.take() {
+                            cancel.trigger();
+                        }
+                        win.note_editor.clear();
+                        win.restore_settings(settings);
+                        state.view_key_offset = 0;
+                    }
+                }
+
+                if let Some(MIDIFileUnion::InRam(midi_file)) = win.midi_file.as_mut() {
+                    ui.add_space(10.0);
+                    ui.toggle_value(&mut state.note_editor, "Edit Notes");
+                    if state.note_editor
+                        && !midi_file.note_edits().is_empty()
+                        && ui.button("Revert Edits").clicked()
+                    {
+                        midi_file.set_note_edits(Vec::new());
+                        note_editor::save_edits(midi_file, state.last_midi_file.as_deref());
+                        win.note_editor.clear();
                     }
                 }
 
@@ -42,6 +116,79 @@ pub fn draw_panel(
                     }
                 }
 
+                if ui
+                    .button("Batch Export")
+                    .on_hover_text("Render several MIDI files to videos one after the other")
+                    .clicked()
+                {
+                    state.batch_export_visible = !state.batch_export_visible;
+                }
+
+                if ui.button("Tracks").clicked() {
+                    state.tracks_visible = !state.tracks_visible;
+                }
+
+                if ui.button("MIDI Info").clicked() {
+                    state.midi_info_visible = !state.midi_info_visible;
+                }
+
+                if win.midi_file.is_some() && ui.button("Export SVG").clicked() {
+                    win.open_svg_dialog();
+                }
+
+                match win.comparison.as_mut() {
+                    Some(comparison) => {
+                        if comparison.is_loading() {
+                            ui.spinner();
+                        }
+                        ui.add(
+                            egui::Slider::new(&mut comparison.split, 0.0..=1.0).show_value(false),
+                        )
+                        .on_hover_text("Where the notes are split between the two MIDIs");
+                        if ui.button("Close Comparison").clicked() {
+                            win.comparison = None;
+                        }
+                    }
+                    None => {
+                        let compare = ui
+                            .add_enabled(win.midi_file.is_some(), egui::Button::new("Compare"))
+                            .on_hover_text("Show a second MIDI next to this one");
+                        if compare.clicked() {
+                            win.open_comparison_dialog(state);
+                        }
+                    }
+                }
+
+                let loop_status = win.loop_station.as_ref().map(|station| {
+                    if station.is_recording() {
+                        format!(
+                            "Recording layer {}/{} (measure {}/{})",
+                            station.layer_count() + 1,
+                            MAX_LOOP_LAYERS,
+                            station.measure(),
+                            station.measures()
+                        )
+                    } else {
+                        format!("{} layers", station.layer_count())
+                    }
+                });
+                match loop_status {
+                    Some(status) => {
+                        ui.label(status);
+                        if ui.button("Stop Loop").clicked() {
+                            win.stop_loop_station();
+                        }
+                    }
+                    None => {
+                        let record = ui
+                            .button("Loop Record")
+                            .on_hover_text("Record layers of MIDI input that play back in a loop");
+                        if record.clicked() {
+                            win.start_loop_station(settings, state);
+                        }
+                    }
+                }
+
                 ui.add_space(10.0);
 
                 if ui.button("Play").clicked() {
@@ -61,9 +208,39 @@ pub fn draw_panel(
                     ui.label("Note speed: ");
                     ui.add(
                         egui::Slider::new(&mut settings.midi.note_speed, 2.0..=0.001)
-                            .show_value(false),
+                            .show_value(false)
+                            .clamp_to_range(false),
                     );
-                })
+
+                    let length = win
+                        .midi_file
+                        .as_ref()
+                        .and_then(|midi_file| midi_file.midi_length());
+                    let fit = ui
+                        .add_enabled(length.is_some(), egui::Button::new("Fit to window"))
+                        .on_hover_text("Show the whole MIDI at once");
+                    if let (true, Some(length)) = (fit.clicked(), length) {
+                        fit_to_window(win, settings, length);
+                    }
+                });
+
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("Transpose: ");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.midi.transpose_semitones)
+                            .speed(0.1)
+                            .clamp_range(-24..=24),
+                    );
+                });
+
+                ui.add_space(10.0);
+
+                let peaks = win.synth.read().unwrap().take_channel_peaks();
+                win.vu_meter.update(peaks);
+                let height = ui.spacing().interact_size.y;
+                win.vu_meter.draw(ui, height);
             });
 
             ui.spacing_mut().slider_width = ctx.available_rect().width() - 20.0;
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use egui::{Context, Sense};
+
+use crate::{
+    gui::window::GuiWasabiWindow,
+    midi::{MIDIFileBase, MIDIFileUnion, MIDISidecar},
+    state::WasabiState,
+};
+
+pub fn draw_tracks(win: &mut GuiWasabiWindow, state: &mut WasabiState, ctx: &Context) {
+    egui::Window::new("Tracks")
+        .resizable(true)
+        .collapsible(true)
+        .title_bar(true)
+        .scroll2([false, true])
+        .enabled(true)
+        .open(&mut state.tracks_visible)
+        .show(ctx, |ui| {
+            let midi_file = match win.midi_file.as_ref() {
+                Some(midi_file) => midi_file,
+                None => {
+                    ui.label("No MIDI loaded");
+                    return;
+                }
+            };
+
+            let cake = matches!(midi_file, MIDIFileUnion::Cake(_));
+            if cake {
+                ui.label("The Cake loading method loads the MIDI again to change the order");
+                ui.separator();
+            }
+
+            ui.label("Drag the tracks to change which ones are drawn on top");
+            ui.separator();
+
+            // An empty order means the tracks are in their original order
+            let track_count = midi_file.track_count();
+            let mut order = if state.track_order.len() == track_count {
+                state.track_order.clone()
+            } else {
+                (0..track_count).collect()
+            };
+
+            for i in 0..order.len() {
+                let response =
+                    ui.add(egui::Label::new(format!("☰ Track {}", order[i])).sense(Sense::drag()));
+
+                if response.drag_started() {
+                    win.dragged_track = Some(i);
+                }
+
+                if let Some(from) = win.dragged_track {
+                    if from != i && ui.rect_contains_pointer(response.rect) {
+                        let track = order.remove(from);
+                        order.insert(i, track);
+                        win.dragged_track = Some(i);
+                        state.track_order = order.clone();
+                    }
+                }
+            }
+
+            let released = ui.input(|i| i.pointer.any_released());
+            if released && win.dragged_track.take().is_some() {
+                save_track_order(state.last_midi_file.as_deref(), &state.track_order);
+                win.track_order_changed = cake;
+            }
+
+            ui.separator();
+            if ui.button("Reset Order").clicked() {
+                state.track_order.clear();
+                save_track_order(state.last_midi_file.as_deref(), &state.track_order);
+                win.track_order_changed = cake;
+            }
+        });
+}
+
+/// Writes the track order into the sidecar file of the MIDI
+fn save_track_order(midi_path: Option<&Path>, track_order: &[usize]) {
+    if let Some(midi_path) = midi_path {
+        let mut sidecar = MIDISidecar::load(midi_path);
+        sidecar.track_order = track_order.to_vec();
+        sidecar.save(midi_path);
+    }
+}
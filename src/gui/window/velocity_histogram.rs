@@ -0,0 +1,38 @@
+use egui::{Color32, Rect, Sense, Ui, Vec2};
+
+const BAR_GAP: f32 = 1.0;
+
+/// A bar chart of how many of the visible notes fall into each of 16 velocity
+/// buckets, 0 to 7 on the left up to 120 to 127 on the right
+pub struct VelocityHistogram {
+    buckets: [u64; 16],
+}
+
+impl VelocityHistogram {
+    pub fn new(buckets: [u64; 16]) -> Self {
+        Self { buckets }
+    }
+
+    /// The bars are scaled to the fullest bucket
+    pub fn draw(&self, ui: &mut Ui, height: f32) {
+        let size = Vec2::new(ui.available_width(), height);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter();
+
+        let max = self.buckets.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let bar_width = (rect.width() + BAR_GAP) / 16.0 - BAR_GAP;
+
+        for (i, count) in self.buckets.iter().enumerate() {
+            let left = rect.left() + i as f32 * (bar_width + BAR_GAP);
+            let top = rect.bottom() - rect.height() * (*count as f32 / max);
+            let bar = Rect::from_min_max(
+                egui::pos2(left, top),
+                egui::pos2(left + bar_width, rect.bottom()),
+            );
+
+            // Louder buckets are drawn brighter
+            let brightness = 100 + (i as u8) * 10;
+            painter.rect_filled(bar, 0.0, Color32::from_gray(brightness));
+        }
+    }
+}
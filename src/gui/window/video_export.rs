@@ -0,0 +1,787 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, RwLock},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use egui::{Color32, Context};
+use tempfile::TempPath;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        CommandBufferExecFuture, CommandBufferUsage, CopyImageToBufferInfo,
+    },
+    format::Format,
+    image::{view::ImageView, AttachmentImage, ImageUsage},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    sync::{
+        self,
+        future::{FenceSignalFuture, NowFuture},
+        GpuFuture,
+    },
+    DeviceSize,
+};
+
+use crate::{
+    audio_playback::{
+        export::{export_audio, TAIL_SECONDS},
+        timbre::TimbreColors,
+        AudioPlayerType, SimpleTemporaryPlayer,
+    },
+    gui::GuiRenderer,
+    midi::{
+        decompress_gzip_midi, is_gzip_midi, InRamMIDIFile, MIDIColor, MIDIFileBase, MIDIFileUnion,
+    },
+    settings::WasabiSettings,
+};
+
+use super::{keyboard_layout::KeyboardView, scene::GuiRenderScene};
+
+/// How many frames can be copied back from the GPU while the next ones are drawn
+const READBACK_SLOTS: usize = 3;
+
+/// How long the export can draw frames in each frame of the window. The renderers are
+/// created from the window's renderer, so the frames are drawn between the window's
+/// own frames instead of on a thread of their own, and the window stays responsive.
+const FRAME_BUDGET: Duration = Duration::from_millis(30);
+
+/// The output settings of a video
+#[derive(Clone, Copy)]
+pub struct VideoOptions {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Mux the audio rendered with XSynth into the video
+    pub audio: bool,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            fps: 60,
+            audio: true,
+        }
+    }
+}
+
+impl VideoOptions {
+    /// H.264 in 4:2:0 needs an even width and height
+    fn frame_size(&self) -> [u32; 2] {
+        [self.width.max(16) & !1, self.height.max(16) & !1]
+    }
+}
+
+enum ExportStatus {
+    Queued,
+    Loading,
+    Rendering { progress: f32 },
+    Done,
+    Failed(String),
+}
+
+struct QueueEntry {
+    midi_path: PathBuf,
+    /// The settings of this file, or `None` to use the ones of the whole queue
+    options: Option<VideoOptions>,
+    status: ExportStatus,
+    /// Where the video was written, picked when the export of the file starts
+    output_path: Option<PathBuf>,
+}
+
+/// A MIDI file that was loaded for an export, with its audio if it was rendered
+struct PreparedExport {
+    midi_file: InRamMIDIFile,
+    audio: Option<TempPath>,
+}
+
+enum ExportStage {
+    Loading(Receiver<Result<PreparedExport, String>>),
+    Rendering(Box<VideoRender>),
+}
+
+struct ActiveExport {
+    index: usize,
+    /// Taken when the file was started, so changes while it loads don't mix two settings
+    options: VideoOptions,
+    stage: ExportStage,
+}
+
+/// A list of MIDI files that are rendered to videos one after the other. The frames
+/// are drawn offscreen in the window's own frames and piped into FFmpeg, which has
+/// to be installed and in the PATH.
+#[derive(Default)]
+pub struct VideoExportQueue {
+    entries: Vec<QueueEntry>,
+    options: VideoOptions,
+    running: bool,
+    active: Option<ActiveExport>,
+}
+
+impl VideoExportQueue {
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn add_file(&mut self, midi_path: PathBuf) {
+        self.entries.push(QueueEntry {
+            midi_path,
+            options: None,
+            status: ExportStatus::Queued,
+            output_path: None,
+        });
+    }
+
+    /// Advances the export that is running, and starts the next file in the queue
+    /// once it is done
+    pub fn update(
+        &mut self,
+        renderer: &GuiRenderer,
+        key_view: &KeyboardView,
+        settings: &WasabiSettings,
+    ) {
+        if !self.running {
+            return;
+        }
+
+        if self.active.is_none() {
+            let next = self
+                .entries
+                .iter()
+                .position(|entry| matches!(entry.status, ExportStatus::Queued));
+            let Some(index) = next else {
+                self.running = false;
+                return;
+            };
+            self.start(index, settings);
+        }
+
+        let Some(active) = self.active.as_mut() else {
+            return;
+        };
+        let entry = &mut self.entries[active.index];
+
+        let result = match &mut active.stage {
+            ExportStage::Loading(receiver) => match receiver.try_recv() {
+                Ok(Ok(prepared)) => {
+                    let output_path = free_output_path(&entry.midi_path);
+                    match VideoRender::new(
+                        renderer,
+                        prepared,
+                        active.options,
+                        &output_path,
+                        settings.visual.bg_color,
+                    ) {
+                        Ok(render) => {
+                            entry.status = ExportStatus::Rendering { progress: 0.0 };
+                            entry.output_path = Some(output_path);
+                            active.stage = ExportStage::Rendering(Box::new(render));
+                            None
+                        }
+                        Err(e) => Some(Err(e)),
+                    }
+                }
+                Ok(Err(e)) => Some(Err(e)),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    Some(Err("The MIDI file failed to load".to_string()))
+                }
+            },
+            ExportStage::Rendering(render) => {
+                let result = render.advance(renderer, key_view, settings);
+                entry.status = ExportStatus::Rendering {
+                    progress: render.progress(),
+                };
+                result
+            }
+        };
+
+        // A failed file is marked and the queue moves on to the next one
+        match result {
+            Some(Ok(())) => entry.status = ExportStatus::Done,
+            Some(Err(e)) => entry.status = ExportStatus::Failed(e),
+            None => return,
+        }
+        self.active = None;
+    }
+
+    fn start(&mut self, index: usize, settings: &WasabiSettings) {
+        let entry = &mut self.entries[index];
+        entry.status = ExportStatus::Loading;
+
+        let options = entry.options.unwrap_or(self.options);
+        let midi_path = entry.midi_path.clone();
+        let settings = settings.clone();
+
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        thread::spawn(move || {
+            sender
+                .send(prepare_export(&midi_path, &settings, options.audio))
+                .ok();
+        });
+
+        self.active = Some(ActiveExport {
+            index,
+            options,
+            stage: ExportStage::Loading(receiver),
+        });
+    }
+
+    pub fn show(&mut self, ctx: &Context, open: &mut bool) {
+        let active_index = self.active.as_ref().map(|active| active.index);
+        let mut remove = None;
+
+        egui::Window::new("Batch Export")
+            .resizable(true)
+            .collapsible(false)
+            .title_bar(true)
+            .scroll2([false, true])
+            .open(open)
+            .show(ctx, |ui| {
+                ui.label("Drop MIDI files onto the window to add them to the queue.");
+                ui.add_space(4.0);
+
+                egui::Grid::new("batch_export_options")
+                    .num_columns(2)
+                    .spacing([40.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        draw_options(ui, &mut self.options);
+                    });
+
+                ui.separator();
+
+                for (index, entry) in self.entries.iter_mut().enumerate() {
+                    let active = Some(index) == active_index;
+                    ui.horizontal(|ui| {
+                        let name = entry
+                            .midi_path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        ui.label(name)
+                            .on_hover_text(entry.midi_path.display().to_string());
+
+                        match &entry.status {
+                            ExportStatus::Queued => {
+                                ui.label("Queued");
+                            }
+                            ExportStatus::Loading => {
+                                ui.spinner();
+                                ui.label("Loading...");
+                            }
+                            ExportStatus::Rendering { progress } => {
+                                ui.add(
+                                    egui::ProgressBar::new(*progress)
+                                        .desired_width(150.0)
+                                        .show_percentage(),
+                                );
+                            }
+                            ExportStatus::Done => {
+                                let label = ui.label("Done");
+                                if let Some(path) = &entry.output_path {
+                                    label.on_hover_text(path.display().to_string());
+                                }
+                            }
+                            ExportStatus::Failed(_) => {
+                                ui.colored_label(Color32::LIGHT_RED, "Failed");
+                            }
+                        }
+
+                        let mut own_options = entry.options.is_some();
+                        let changed = ui
+                            .add_enabled(
+                                !active,
+                                egui::Checkbox::new(&mut own_options, "Own settings"),
+                            )
+                            .changed();
+                        if changed {
+                            entry.options = own_options.then_some(self.options);
+                        }
+
+                        if ui
+                            .add_enabled(!active, egui::Button::new("Remove"))
+                            .clicked()
+                        {
+                            remove = Some(index);
+                        }
+                    });
+
+                    if let ExportStatus::Failed(e) = &entry.status {
+                        ui.colored_label(Color32::LIGHT_RED, e);
+                    }
+
+                    if let Some(options) = entry.options.as_mut() {
+                        ui.add_enabled_ui(!active, |ui| {
+                            egui::Grid::new(("batch_export_entry", index))
+                                .num_columns(2)
+                                .spacing([40.0, 4.0])
+                                .show(ui, |ui| draw_options(ui, options));
+                        });
+                    }
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    let pending = self
+                        .entries
+                        .iter()
+                        .any(|entry| !matches!(entry.status, ExportStatus::Done));
+                    if ui
+                        .add_enabled(!self.running && pending, egui::Button::new("Export all"))
+                        .on_hover_text(
+                            "Saves an MP4 next to each file, under a new name if one \
+                            already exists, and retries the failed ones",
+                        )
+                        .clicked()
+                    {
+                        for entry in self.entries.iter_mut() {
+                            if let ExportStatus::Failed(_) = entry.status {
+                                entry.status = ExportStatus::Queued;
+                            }
+                        }
+                        self.running = true;
+                    }
+
+                    if ui
+                        .add_enabled(!self.running, egui::Button::new("Clear finished"))
+                        .clicked()
+                    {
+                        self.entries
+                            .retain(|entry| !matches!(entry.status, ExportStatus::Done));
+                    }
+                });
+            });
+
+        if let Some(index) = remove {
+            self.entries.remove(index);
+            if let Some(active) = self.active.as_mut() {
+                if active.index > index {
+                    active.index -= 1;
+                }
+            }
+        }
+    }
+}
+
+fn draw_options(ui: &mut egui::Ui, options: &mut VideoOptions) {
+    ui.label("Resolution:");
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut options.width).clamp_range(16..=7680));
+        ui.label("x");
+        ui.add(egui::DragValue::new(&mut options.height).clamp_range(16..=4320));
+    });
+    ui.end_row();
+
+    ui.label("Frame Rate:");
+    ui.add(
+        egui::DragValue::new(&mut options.fps)
+            .clamp_range(1..=240)
+            .suffix(" FPS"),
+    );
+    ui.end_row();
+
+    ui.label("Audio:").on_hover_text(
+        "Renders the audio with XSynth and the current synth settings, and adds it to the video",
+    );
+    ui.checkbox(&mut options.audio, "");
+    ui.end_row();
+}
+
+/// Loads the MIDI file for an export and renders its audio into a temporary file
+fn prepare_export(
+    midi_path: &Path,
+    settings: &WasabiSettings,
+    audio: bool,
+) -> Result<PreparedExport, String> {
+    // Nothing cancels the load, the file can't be removed while it is exported
+    let (_cancel, listener) = triggered::trigger();
+
+    // The decompressed copy is deleted when this is dropped, once the file was read
+    let decompressed = if is_gzip_midi(midi_path) {
+        Some(
+            decompress_gzip_midi(midi_path.to_path_buf(), listener.clone())
+                .recv()
+                .ok()
+                .flatten()
+                .ok_or_else(|| format!("Failed to decompress {}", midi_path.display()))?,
+        )
+    } else {
+        None
+    };
+
+    let program_colors = settings.midi.program_colors.then(|| {
+        settings
+            .midi
+            .program_color_map
+            .iter()
+            .map(|c| MIDIColor::new(c.r(), c.g(), c.b()))
+            .collect::<Vec<_>>()
+    });
+    let program_colors = TimbreColors::from_settings(settings)
+        .and_then(|timbre| timbre.program_colors())
+        .or(program_colors);
+
+    // The notes are only drawn, so they are played into a player without any output
+    let midi_file = InRamMIDIFile::load_from_file(
+        decompressed.as_deref().unwrap_or(midi_path),
+        Arc::new(RwLock::new(SimpleTemporaryPlayer::new(
+            AudioPlayerType::Silent,
+        ))),
+        settings.midi.random_colors,
+        program_colors,
+        false,
+        settings.midi.repair_mode,
+        &listener,
+    )
+    .map_err(|e| format!("Failed to load MIDI: {}", e))?;
+
+    let audio = if audio {
+        let path = tempfile::Builder::new()
+            .suffix(".wav")
+            .tempfile()
+            .map_err(|e| format!("Failed to create the audio file: {}", e))?
+            .into_temp_path();
+        export_audio(settings, midi_path, &path)?;
+        Some(path)
+    } else {
+        None
+    };
+
+    Ok(PreparedExport { midi_file, audio })
+}
+
+/// The MP4 next to the MIDI file, numbered when a file of that name already exists so
+/// an earlier video is never overwritten
+fn free_output_path(midi_path: &Path) -> PathBuf {
+    let stem = midi_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    (1..)
+        .map(|n| match n {
+            1 => midi_path.with_file_name(format!("{stem}.mp4")),
+            n => midi_path.with_file_name(format!("{stem} ({n}).mp4")),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// An image the frames are drawn into and the buffer it is copied back into
+struct ReadbackSlot {
+    image: Arc<ImageView<AttachmentImage>>,
+    buffer: Subbuffer<[u8]>,
+    /// Set while the copy of a frame is still running on the GPU
+    fence: Option<FenceSignalFuture<CommandBufferExecFuture<NowFuture>>>,
+}
+
+impl ReadbackSlot {
+    fn new(
+        allocator: &StandardMemoryAllocator,
+        size: [u32; 2],
+        format: Format,
+    ) -> Result<Self, String> {
+        let image = AttachmentImage::with_usage(
+            allocator,
+            size,
+            format,
+            ImageUsage::SAMPLED | ImageUsage::INPUT_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+        )
+        .map_err(|e| format!("Failed to create the frame image: {}", e))?;
+        let image = ImageView::new_default(image)
+            .map_err(|e| format!("Failed to create the frame image: {}", e))?;
+
+        let buffer = Buffer::new_slice::<u8>(
+            allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            size[0] as DeviceSize * size[1] as DeviceSize * 4,
+        )
+        .map_err(|e| format!("Failed to create the readback buffer: {}", e))?;
+
+        Ok(Self {
+            image,
+            buffer,
+            fence: None,
+        })
+    }
+}
+
+/// Draws the frames of a MIDI file and sends them to the FFmpeg writer thread. Each
+/// frame is drawn into its own slot of a ring, so a frame is only waited on once the
+/// slot is needed again and the others were drawn in the meantime.
+struct VideoRender {
+    midi_file: MIDIFileUnion,
+    scene: GuiRenderScene,
+    slots: Vec<ReadbackSlot>,
+    cb_allocator: StandardCommandBufferAllocator,
+    /// Dropped once every frame was sent, which lets FFmpeg finish the file
+    frames: Option<Sender<Vec<u8>>>,
+    /// The frames the writer is done with, so their memory is reused
+    recycled: Receiver<Vec<u8>>,
+    writer: Option<JoinHandle<Result<(), String>>>,
+    /// Deleted once FFmpeg read it
+    _audio: Option<TempPath>,
+    fps: u32,
+    frame: u64,
+    frame_count: u64,
+}
+
+impl VideoRender {
+    fn new(
+        renderer: &GuiRenderer,
+        prepared: PreparedExport,
+        options: VideoOptions,
+        output: &Path,
+        bg_color: Color32,
+    ) -> Result<Self, String> {
+        let size = options.frame_size();
+
+        // The pixels are sent as they are in the image, so FFmpeg is told their order
+        let (pixel_format, bg) = match renderer.format {
+            Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => {
+                ("bgra", [bg_color.b(), bg_color.g(), bg_color.r()])
+            }
+            Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => {
+                ("rgba", [bg_color.r(), bg_color.g(), bg_color.b()])
+            }
+            format => return Err(format!("Can't export frames in the {:?} format", format)),
+        };
+
+        // The images are allocated before FFmpeg creates the file, so a file that
+        // can't be exported doesn't leave an empty video behind
+        let allocator = StandardMemoryAllocator::new_default(renderer.device.clone());
+        let slots = (0..READBACK_SLOTS)
+            .map(|_| ReadbackSlot::new(&allocator, size, renderer.format))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The output is picked to be free, `-n` only keeps FFmpeg from asking if a
+        // file of that name showed up in the meantime
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-n", "-loglevel", "error", "-f", "rawvideo"])
+            .args(["-pix_fmt", pixel_format])
+            .args(["-s", &format!("{}x{}", size[0], size[1])])
+            .args(["-r", &options.fps.to_string()])
+            .args(["-i", "-"]);
+        if let Some(audio) = &prepared.audio {
+            command.arg("-i").arg(audio);
+        }
+        command.args(["-c:v", "libx264", "-pix_fmt", "yuv420p"]);
+        if prepared.audio.is_some() {
+            command.args(["-c:a", "aac", "-shortest"]);
+        }
+        let child = command
+            .arg(output)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+        let (frames, frame_receiver) = crossbeam_channel::bounded(READBACK_SLOTS);
+        let (recycle, recycled) = crossbeam_channel::unbounded();
+        let writer = thread::spawn(move || write_frames(child, frame_receiver, recycle, bg));
+
+        let length = prepared.midi_file.midi_length().unwrap_or(0.0) + TAIL_SECONDS;
+
+        Ok(Self {
+            midi_file: MIDIFileUnion::InRam(prepared.midi_file),
+            scene: GuiRenderScene::new(renderer),
+            slots,
+            cb_allocator: StandardCommandBufferAllocator::new(
+                renderer.device.clone(),
+                Default::default(),
+            ),
+            frames: Some(frames),
+            recycled,
+            writer: Some(writer),
+            _audio: prepared.audio,
+            fps: options.fps,
+            frame: 0,
+            frame_count: (length * options.fps as f64).ceil() as u64,
+        })
+    }
+
+    fn progress(&self) -> f32 {
+        self.frame as f32 / (self.frame_count + READBACK_SLOTS as u64).max(1) as f32
+    }
+
+    /// Draws frames until the budget of this window frame is used up, returns the
+    /// result once FFmpeg finished the file
+    fn advance(
+        &mut self,
+        renderer: &GuiRenderer,
+        key_view: &KeyboardView,
+        settings: &WasabiSettings,
+    ) -> Option<Result<(), String>> {
+        let start = Instant::now();
+
+        // The slots of the last frames are emptied too, after all frames were drawn
+        let end = self.frame_count + READBACK_SLOTS as u64;
+        while self.frame < end && start.elapsed() < FRAME_BUDGET {
+            let Some(frames) = &self.frames else {
+                break;
+            };
+            // FFmpeg is behind, the window keeps running until it caught up
+            if frames.is_full() {
+                break;
+            }
+
+            let slot = (self.frame % READBACK_SLOTS as u64) as usize;
+            if let Err(e) = self.send_slot(slot) {
+                return Some(Err(e));
+            }
+            if self.frame < self.frame_count {
+                if let Err(e) = self.draw_frame(slot, renderer, key_view, settings) {
+                    return Some(Err(e));
+                }
+            }
+            self.frame += 1;
+        }
+
+        if self.frame >= end {
+            self.frames = None;
+        }
+
+        // The writer also stops early when FFmpeg failed, which makes the sends fail
+        if self.frames.is_none() && self.writer.as_ref().map_or(false, |w| w.is_finished()) {
+            let writer = self.writer.take().unwrap();
+            return Some(
+                writer
+                    .join()
+                    .unwrap_or_else(|_| Err("The FFmpeg writer crashed".to_string())),
+            );
+        }
+        None
+    }
+
+    /// Waits for the frame that was copied into a slot and sends it to the writer
+    fn send_slot(&mut self, slot: usize) -> Result<(), String> {
+        let slot = &mut self.slots[slot];
+        let Some(fence) = slot.fence.take() else {
+            return Ok(());
+        };
+        fence
+            .wait(None)
+            .map_err(|e| format!("Failed to read a frame back: {}", e))?;
+
+        let mut pixels = self.recycled.try_recv().unwrap_or_default();
+        pixels.clear();
+        pixels.extend_from_slice(
+            &slot
+                .buffer
+                .read()
+                .map_err(|e| format!("Failed to read a frame back: {}", e))?,
+        );
+
+        if let Some(frames) = &self.frames {
+            if frames.send(pixels).is_err() {
+                // The writer stopped, its result has the error
+                self.frames = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_frame(
+        &mut self,
+        slot: usize,
+        renderer: &GuiRenderer,
+        key_view: &KeyboardView,
+        settings: &WasabiSettings,
+    ) -> Result<(), String> {
+        let time = self.frame as f64 / self.fps as f64;
+        let timer = self.midi_file.timer_mut();
+        timer.seek(Duration::from_secs_f64(time));
+        timer.pause();
+
+        let slot = &mut self.slots[slot];
+        self.scene.render(
+            renderer,
+            slot.image.clone(),
+            key_view,
+            &mut self.midi_file,
+            settings,
+            1.0,
+            &[],
+        );
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            &self.cb_allocator,
+            renderer.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        command_buffer_builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                slot.image.image().clone(),
+                slot.buffer.clone(),
+            ))
+            .unwrap();
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        let fence = sync::now(renderer.device.clone())
+            .then_execute(renderer.queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .map_err(|e| format!("Failed to copy a frame back: {}", e))?;
+        slot.fence = Some(fence);
+        Ok(())
+    }
+}
+
+/// Puts the frames over the background color and writes them into FFmpeg. The notes
+/// leave the frame transparent where there are none, so the background is added there.
+fn write_frames(
+    mut child: Child,
+    frames: Receiver<Vec<u8>>,
+    recycle: Sender<Vec<u8>>,
+    bg: [u8; 3],
+) -> Result<(), String> {
+    let mut stdin = child.stdin.take().unwrap();
+
+    let mut write_error = None;
+    for mut frame in frames.iter() {
+        for pixel in frame.chunks_exact_mut(4) {
+            let transparency = 255 - pixel[3] as u32;
+            for (channel, bg) in pixel.iter_mut().zip(bg) {
+                *channel = (*channel as u32 + bg as u32 * transparency / 255).min(255) as u8;
+            }
+        }
+
+        if let Err(e) = stdin.write_all(&frame) {
+            write_error = Some(e);
+            break;
+        }
+        recycle.send(frame).ok();
+    }
+
+    // Closing the input lets FFmpeg finish the file
+    drop(stdin);
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for FFmpeg: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("FFmpeg failed: {}", stderr.trim()));
+    }
+    match write_error {
+        Some(e) => Err(format!("Failed to write to FFmpeg: {}", e)),
+        None => Ok(()),
+    }
+}
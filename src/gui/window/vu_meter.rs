@@ -0,0 +1,66 @@
+use std::time::Instant;
+
+use egui::{Color32, Rect, Sense, Ui, Vec2};
+
+use crate::midi::MIDIColor;
+
+/// How much of the full bar height is lost per second
+const DECAY_PER_SECOND: f32 = 1.5;
+
+const BAR_WIDTH: f32 = 4.0;
+const BAR_GAP: f32 = 2.0;
+
+/// A row of 16 bars showing how loud each MIDI channel recently played
+pub struct VuMeter {
+    levels: [f32; 16],
+    colors: Vec<MIDIColor>,
+    last_update: Instant,
+}
+
+impl VuMeter {
+    pub fn new() -> Self {
+        Self {
+            levels: [0.0; 16],
+            // The colors that the channels of the first track get
+            colors: MIDIColor::new_vec_for_tracks(1),
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Jumps up to new peaks straight away and slowly falls back down otherwise
+    pub fn update(&mut self, peaks: [u8; 16]) {
+        let delta = self.last_update.elapsed().as_secs_f32();
+        self.last_update = Instant::now();
+
+        for (level, peak) in self.levels.iter_mut().zip(peaks) {
+            *level = (*level - delta * DECAY_PER_SECOND)
+                .max(peak as f32 / 127.0)
+                .max(0.0);
+        }
+    }
+
+    pub fn draw(&self, ui: &mut Ui, height: f32) {
+        let size = Vec2::new(16.0 * (BAR_WIDTH + BAR_GAP) - BAR_GAP, height);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter();
+
+        for (i, (level, color)) in self.levels.iter().zip(self.colors.iter()).enumerate() {
+            let left = rect.left() + i as f32 * (BAR_WIDTH + BAR_GAP);
+            let bar = Rect::from_min_max(
+                egui::pos2(left, rect.top()),
+                egui::pos2(left + BAR_WIDTH, rect.bottom()),
+            );
+            painter.rect_filled(bar, 0.0, Color32::from_gray(20));
+
+            let filled = Rect::from_min_max(
+                egui::pos2(bar.left(), bar.bottom() - bar.height() * level),
+                bar.right_bottom(),
+            );
+            painter.rect_filled(
+                filled,
+                0.0,
+                Color32::from_rgb(color.red(), color.green(), color.blue()),
+            );
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use egui::{Align2, Color32, FontId, Rect, Ui};
+
+use crate::settings::{WatermarkConfig, WatermarkCorner};
+
+/// How far the text is from the edges of the notes
+const MARGIN: f32 = 12.0;
+
+/// How long one pulse of an animated watermark takes, in seconds
+const PULSE_PERIOD: f64 = 4.0;
+
+/// Draws the watermark text in its corner of `rect`, with a shadow so it
+/// stays readable over bright notes
+pub fn draw_watermark(ui: &Ui, rect: Rect, config: &WatermarkConfig) {
+    if config.text.is_empty() {
+        return;
+    }
+
+    let mut opacity = config.opacity.clamp(0.0, 1.0);
+    if config.animated {
+        let phase = ui.input(|i| i.time) / PULSE_PERIOD * std::f64::consts::TAU;
+        opacity *= 0.65 + 0.35 * phase.sin() as f32;
+    }
+
+    let (pos, align) = match config.corner {
+        WatermarkCorner::TopLeft => (
+            rect.left_top() + egui::vec2(MARGIN, MARGIN),
+            Align2::LEFT_TOP,
+        ),
+        WatermarkCorner::TopRight => (
+            rect.right_top() + egui::vec2(-MARGIN, MARGIN),
+            Align2::RIGHT_TOP,
+        ),
+        WatermarkCorner::BottomLeft => (
+            rect.left_bottom() + egui::vec2(MARGIN, -MARGIN),
+            Align2::LEFT_BOTTOM,
+        ),
+        WatermarkCorner::BottomRight => (
+            rect.right_bottom() + egui::vec2(-MARGIN, -MARGIN),
+            Align2::RIGHT_BOTTOM,
+        ),
+    };
+
+    let painter = ui.painter().with_clip_rect(rect);
+    let font = FontId::proportional(config.font_size);
+    let alpha = (opacity * 255.0) as u8;
+    painter.text(
+        pos + egui::vec2(1.0, 1.0),
+        align,
+        &config.text,
+        font.clone(),
+        Color32::from_black_alpha(alpha),
+    );
+    painter.text(
+        pos,
+        align,
+        &config.text,
+        font,
+        Color32::from_white_alpha(alpha),
+    );
+}
@@ -1,12 +1,9 @@
-use egui::Context;
+use egui::{Context, Ui};
 
 use std::ops::RangeInclusive;
 
 use crate::{
-    audio_playback::{
-        xsynth::{convert_to_channel_init, convert_to_sf_init},
-        AudioPlayerType,
-    },
+    audio_playback::xsynth::{convert_to_sf_init, soundfont_path},
     gui::window::GuiWasabiWindow,
     settings::WasabiSettings,
     state::WasabiState,
@@ -46,16 +43,41 @@ pub fn draw_xsynth_settings(
                     );
                     ui.end_row();
 
+                    ui.label("Latency Compensation (ms): ").on_hover_text(
+                        "Plays the audio ahead of the notes, so they hit the keys when they \
+                        are heard. About the size of the render buffer makes up for it, \
+                        so it follows the buffer until it is changed",
+                    );
+                    ui.horizontal(|ui| {
+                        let mut latency = settings.synth.latency_compensation_ms();
+                        let changed = ui
+                            .add(
+                                egui::DragValue::new(&mut latency)
+                                    .speed(0.1)
+                                    .clamp_range(RangeInclusive::new(0.0, 1000.0)),
+                            )
+                            .changed();
+                        if changed {
+                            settings.synth.audio_latency_compensation_ms = Some(latency);
+                        }
+
+                        let matched = settings.synth.audio_latency_compensation_ms.is_none();
+                        if ui
+                            .add_enabled(!matched, egui::Button::new("Match Buffer"))
+                            .on_hover_text("Follows the render buffer again")
+                            .clicked()
+                        {
+                            settings.synth.audio_latency_compensation_ms = None;
+                        }
+                    });
+                    ui.end_row();
+
                     ui.label("SFZ Path: ");
                     ui.horizontal(|ui| {
                         ui.add(egui::TextEdit::singleline(&mut settings.synth.sfz_path));
 
                         fn filter(path: &std::path::Path) -> bool {
-                            if let Some(path) = path.to_str() {
-                                path.ends_with(".sfz")
-                            } else {
-                                false
-                            }
+                            path.extension().is_some_and(|ext| ext == "sfz")
                         }
 
                         if ui.button("Browse...").clicked() {
@@ -73,18 +95,45 @@ pub fn draw_xsynth_settings(
                             if dialog.show(ctx).selected() {
                                 if let Some(sfz_path) = dialog.path() {
                                     state.last_sfz_file = Some(sfz_path.clone());
-                                    settings.synth.sfz_path =
-                                        sfz_path.to_str().unwrap_or("").to_owned();
+                                    // The settings are saved as TOML, which only holds UTF-8
+                                    match sfz_path.to_str() {
+                                        Some(path) => settings.synth.sfz_path = path.to_owned(),
+                                        None => println!(
+                                            "The SFZ path {} isn't valid UTF-8, so it can't be saved \
+                                            in the settings",
+                                            sfz_path.display()
+                                        ),
+                                    }
                                 }
                             }
                         }
 
+                        // The regions of another SFZ are cleared first, so its copies are
+                        // written without them
                         if ui.button("Load").clicked() {
+                            update_sfz_regions(win, settings);
                             win.synth.write().unwrap().set_soundfont(
-                                &settings.synth.sfz_path,
+                                &soundfont_path(settings),
                                 convert_to_sf_init(settings),
                             );
                         }
+
+                        let loading = win.synth.read().unwrap().is_loading_soundfont();
+                        let reload = ui
+                            .add_enabled(!loading, egui::Button::new("Reload SoundFont"))
+                            .on_hover_text(
+                                "Loads the SFZ in the background, the old one keeps playing until it is ready",
+                            );
+                        if reload.clicked() {
+                            update_sfz_regions(win, settings);
+                            win.synth.write().unwrap().reload_soundfont(
+                                &soundfont_path(settings),
+                                convert_to_sf_init(settings),
+                            );
+                        }
+                        if loading {
+                            ui.spinner();
+                        }
                     });
                     ui.end_row();
 
@@ -159,33 +208,181 @@ pub fn draw_xsynth_settings(
                     ui.label("Use Effects*: ");
                     ui.checkbox(&mut settings.synth.use_effects, "");
                     ui.end_row();
+
+                    ui.label("Release Time Multiplier*: ");
+                    ui.add(
+                        egui::Slider::new(&mut settings.synth.release_multiplier, 0.1..=4.0)
+                            .logarithmic(true),
+                    );
+                    ui.end_row();
+
+                    ui.label("Cap Release Time*: ");
+                    let mut cutoff = settings.synth.release_cutoff_ms.is_some();
+                    if ui.checkbox(&mut cutoff, "").changed() {
+                        settings.synth.release_cutoff_ms = cutoff.then_some(500.0);
+                    }
+                    ui.end_row();
+
+                    if let Some(cutoff) = settings.synth.release_cutoff_ms.as_mut() {
+                        ui.label("Release Cutoff*: ");
+                        ui.add(
+                            egui::Slider::new(cutoff, 1.0..=10000.0)
+                                .logarithmic(true)
+                                .suffix(" ms"),
+                        );
+                        ui.end_row();
+                    }
+
+                    ui.label("Note Fade In*: ").on_hover_text(
+                        "The shortest attack of the voices, which is written into the SFZ \
+                        when it is loaded. The notes on screen fade in over the same time \
+                        once it is, so not with KDMAPI or an SFZ that can't be rewritten",
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut settings.synth.note_fadein_ms, 0.0..=1000.0)
+                            .suffix(" ms"),
+                    );
+                    ui.end_row();
                 });
 
+            ui.add_space(6.0);
+            ui.heading("Channel Tuning");
+            ui.separator();
+
+            egui::Grid::new("tuning_settings_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .min_col_width(col_width)
+                .show(ui, |ui| {
+                    let tuning_prev = settings.synth.channel_tuning;
+                    for (channel, cents) in settings.synth.channel_tuning.iter_mut().enumerate() {
+                        ui.label(format!("Channel {}: ", channel + 1));
+                        ui.add(egui::Slider::new(cents, -100.0..=100.0).suffix(" cents"));
+                        ui.end_row();
+                    }
+                    if settings.synth.channel_tuning != tuning_prev {
+                        win.synth
+                            .write()
+                            .unwrap()
+                            .set_channel_tuning(settings.synth.channel_tuning);
+                    }
+
+                    ui.label("Reset: ");
+                    if ui.button("Reset Tuning").clicked() {
+                        settings.synth.channel_tuning = [0.0; 16];
+                        win.synth
+                            .write()
+                            .unwrap()
+                            .set_channel_tuning(settings.synth.channel_tuning);
+                    }
+                    ui.end_row();
+                });
+
+            ui.add_space(6.0);
+            ui.heading("Regions");
+            ui.separator();
+
+            draw_region_settings(win, settings, ui);
+
             ui.separator();
             ui.vertical_centered(|ui| {
                 ui.label("Options marked with (*) will apply when the synth is reloaded.");
                 if ui.button("Reload XSynth").clicked() {
-                    win.synth
-                        .write()
-                        .unwrap()
-                        .switch_player(AudioPlayerType::XSynth {
-                            buffer: settings.synth.buffer_ms,
-                            ignore_range: settings.synth.vel_ignore.clone(),
-                            options: convert_to_channel_init(settings),
-                        });
-                    win.synth
-                        .write()
-                        .unwrap()
-                        .set_soundfont(&settings.synth.sfz_path, convert_to_sf_init(settings));
-                    win.synth
-                        .write()
-                        .unwrap()
-                        .set_layer_count(if settings.synth.limit_layers {
-                            Some(settings.synth.layer_count)
-                        } else {
-                            None
-                        });
+                    win.synth.write().unwrap().start_synth(settings);
                 }
             });
         });
 }
+
+/// Lists the groups and regions of the loaded SFZ, so they can be turned off per channel
+fn draw_region_settings(win: &mut GuiWasabiWindow, settings: &mut WasabiSettings, ui: &mut Ui) {
+    let Some(sfz) = win.sfz_instrument.as_ref() else {
+        ui.label("No SFZ loaded");
+        return;
+    };
+
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Channel: ");
+        egui::ComboBox::from_id_source("region_channel_select")
+            .selected_text(format!("Channel {}", win.region_channel + 1))
+            .show_ui(ui, |ui| {
+                for channel in 0..16 {
+                    ui.selectable_value(
+                        &mut win.region_channel,
+                        channel,
+                        format!("Channel {}", channel + 1),
+                    );
+                }
+            });
+
+        if ui.button("Enable All").clicked() {
+            changed |= settings
+                .synth
+                .disabled_regions
+                .remove(&(win.region_channel + 1).to_string())
+                .is_some();
+        }
+    });
+
+    let channel = (win.region_channel + 1).to_string();
+    let disabled = settings
+        .synth
+        .disabled_regions
+        .entry(channel.clone())
+        .or_default();
+
+    let mut index = 0;
+    for (i, group) in sfz.groups.iter().enumerate() {
+        egui::CollapsingHeader::new(format!("{} ({} regions)", group.name, group.regions.len()))
+            .id_source(("sfz_group", i))
+            .show(ui, |ui| {
+                for (j, region) in group.regions.iter().enumerate() {
+                    let mut enabled = !disabled.contains(&(index + j));
+                    let label = format!(
+                        "{} (keys {}-{}, velocities {}-{})",
+                        region.name,
+                        region.keys.start(),
+                        region.keys.end(),
+                        region.velocities.start(),
+                        region.velocities.end()
+                    );
+                    if ui.checkbox(&mut enabled, label).changed() {
+                        if enabled {
+                            disabled.remove(&(index + j));
+                        } else {
+                            disabled.insert(index + j);
+                        }
+                        changed = true;
+                    }
+                }
+            });
+        index += group.regions.len();
+    }
+
+    if disabled.is_empty() {
+        settings.synth.disabled_regions.remove(&channel);
+    }
+
+    if changed {
+        win.update_region_filter(settings);
+        // The disabled regions that share notes with enabled ones need a copy of the SFZ
+        win.synth
+            .write()
+            .unwrap()
+            .set_channel_soundfonts(&soundfont_path(settings), convert_to_sf_init(settings));
+    }
+}
+
+/// The regions are stored by index, so they only fit the same SFZ
+fn update_sfz_regions(win: &mut GuiWasabiWindow, settings: &mut WasabiSettings) {
+    let same_sfz = win
+        .sfz_instrument
+        .as_ref()
+        .is_some_and(|sfz| sfz.path == settings.synth.sfz_path);
+    if !same_sfz {
+        settings.synth.disabled_regions.clear();
+    }
+    win.update_region_filter(settings);
+}
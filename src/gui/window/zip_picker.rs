@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use egui::Context;
+
+pub enum ZipPickerAction {
+    None,
+    Selected(String),
+    Cancelled,
+}
+
+/// Lets the user pick which of the MIDIs inside a ZIP archive to open
+pub struct ZipPicker {
+    pub path: PathBuf,
+    entries: Vec<String>,
+}
+
+impl ZipPicker {
+    pub fn new(path: PathBuf, entries: Vec<String>) -> Self {
+        Self { path, entries }
+    }
+
+    pub fn show(&self, ctx: &Context) -> ZipPickerAction {
+        let mut action = ZipPickerAction::None;
+
+        let title = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "ZIP archive".to_owned());
+
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(true)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("Select a MIDI to open:");
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for entry in self.entries.iter() {
+                            if ui.selectable_label(false, entry).clicked() {
+                                action = ZipPickerAction::Selected(entry.clone());
+                            }
+                        }
+                    });
+                ui.separator();
+                if ui.button("Cancel").clicked() {
+                    action = ZipPickerAction::Cancelled;
+                }
+            });
+
+        action
+    }
+}
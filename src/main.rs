@@ -5,13 +5,18 @@
 mod audio_playback;
 mod gui;
 mod midi;
+mod notifications;
 mod renderer;
 mod scenes;
 mod settings;
 mod state;
 
+use std::path::{Path, PathBuf};
+
+use audio_playback::export::export_audio;
 use egui_winit_vulkano::{Gui, GuiConfig};
 use gui::{window::GuiWasabiWindow, GuiRenderer, GuiState};
+use midi::{analyze_midi, export_musicxml};
 use renderer::Renderer;
 use vulkano::swapchain::PresentMode;
 
@@ -32,6 +37,45 @@ pub const PRESENT_MODE: PresentMode = PresentMode::Immediate;
 pub const WAYLAND_PRESENT_MODE: PresentMode = PresentMode::Mailbox;
 
 pub fn main() {
+    // Load the settings values
+    let mut settings = WasabiSettings::new_or_load();
+    let mut wasabi_state = WasabiState::default();
+
+    // Exporting audio doesn't need a window, so it happens before one is created
+    if let Some(output) = settings.export_audio.clone() {
+        let Some(midi_file) = settings.load_midi_file.clone() else {
+            notifications::error("A MIDI file is required to export audio");
+            std::process::exit(1);
+        };
+        if let Err(e) = export_audio(&settings, Path::new(&midi_file), &output) {
+            notifications::error(e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(output) = settings.export_musicxml.clone() {
+        let Some(midi_file) = settings.load_midi_file.clone() else {
+            println!("A MIDI file is required to export MusicXML");
+            std::process::exit(1);
+        };
+        if let Err(e) = export_musicxml(Path::new(&midi_file), &output, settings.midi.musicxml_grid)
+        {
+            notifications::error(e);
+            std::process::exit(1);
+        }
+        println!("Wrote the sheet music to {}", output.display());
+        return;
+    }
+
+    if let (Some(midi_path), Some(output)) = (&settings.analyze_midi, &settings.analyze_output) {
+        if let Err(e) = analyze_midi(midi_path, output) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Winit event loop
     let event_loop = EventLoop::new();
     let monitor = event_loop
@@ -41,16 +85,14 @@ pub fn main() {
 
     let mode = monitor.video_modes().next().expect("no mode found");
 
-    // Load the settings values
-    let mut settings = WasabiSettings::new_or_load();
-    let mut wasabi_state = WasabiState::default();
-
     // Create renderer for our scene & ui
     let mut renderer = Renderer::new(
         &event_loop,
         "Wasabi",
         settings.visual.fullscreen,
         mode.clone(),
+        settings.visual.color_space,
+        settings.rendering.gpu_device_index,
     );
 
     // Vulkano & Winit & egui integration
@@ -70,14 +112,22 @@ pub fn main() {
         device: renderer.device(),
         queue: renderer.queue(),
         format: renderer.format(),
+        pipeline_cache: renderer.pipeline_cache(),
     };
 
     let mut gui_state = GuiWasabiWindow::new(&mut gui_render_data, &mut settings);
 
+    // Load the MIDI file passed on the command line, if any
+    if let Some(midi_file) = settings.load_midi_file.clone() {
+        let midi_path = PathBuf::from(midi_file);
+        gui_state.load_midi(&mut settings, &mut wasabi_state, midi_path);
+    }
+
     event_loop.run(move |event, _, control_flow| {
         let device = renderer.device();
         let queue = renderer.queue();
         let format = renderer.format();
+        let pipeline_cache = renderer.pipeline_cache();
 
         // Update Egui integration so the UI works!
         match event {
@@ -93,8 +143,11 @@ pub fn main() {
                     WindowEvent::CloseRequested => {
                         *control_flow = ControlFlow::Exit;
                     }
+                    WindowEvent::Focused(focused) => {
+                        gui_state.focus_changed(&settings, focused);
+                    }
                     WindowEvent::DroppedFile(path) => {
-                        gui_state.load_midi(&mut settings, path);
+                        gui_state.load_midi(&mut settings, &mut wasabi_state, path);
                     }
                     _ => (),
                 }
@@ -108,6 +161,7 @@ pub fn main() {
                             device,
                             queue,
                             format,
+                            pipeline_cache,
                         };
 
                         let mut state = GuiState {
@@ -124,6 +178,12 @@ pub fn main() {
             Event::MainEventsCleared => {
                 renderer.window().request_redraw();
             }
+            Event::LoopDestroyed => {
+                renderer.save_pipeline_cache();
+                if let Some(session) = gui_state.session(&wasabi_state) {
+                    WasabiSettings::save_session(session);
+                }
+            }
             _ => (),
         }
 
@@ -0,0 +1,162 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use image::RgbaImage;
+
+/// Blobs bigger than this are skipped instead of being read into memory
+const MAX_BLOB_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The art is put near the start of a track, so only the first events are looked at
+const MAX_EVENTS_PER_TRACK: usize = 64;
+
+/// The art is only shown as a thumbnail, so it is scaled down to fit in this size
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// The start of every JPEG and PNG file
+const IMAGE_SIGNATURES: [&[u8]; 2] = [&[0xFF, 0xD8, 0xFF], b"\x89PNG\r\n\x1a\n"];
+
+/// Looks for album art in a MIDI file. There is no standard for it, so this is a best
+/// guess: the first JPEG or PNG in an ID3 tag in front of the header, in a chunk that
+/// isn't a track, or in a meta or SysEx event near the start of a track is used.
+pub fn find_album_art(path: &Path) -> Option<RgbaImage> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+
+    // Reading stops at the end of the file, or at anything that doesn't look like a chunk
+    loop {
+        let mut id = [0; 4];
+        reader.read_exact(&mut id).ok()?;
+
+        // An ID3v2 tag has a 10 byte header, which ends with the size of the tag
+        // stored in 7 bits per byte
+        if &id[..3] == b"ID3" {
+            let mut header = [0; 6];
+            reader.read_exact(&mut header).ok()?;
+            let size = header[2..]
+                .iter()
+                .fold(0u64, |size, byte| (size << 7) | (byte & 0x7F) as u64);
+            if let Some(art) = decode_art(&read_blob(&mut reader, size).ok()?) {
+                return Some(art);
+            }
+            continue;
+        }
+
+        let mut length = [0; 4];
+        reader.read_exact(&mut length).ok()?;
+        let length = u32::from_be_bytes(length) as u64;
+
+        let art = match &id {
+            b"MThd" => {
+                reader.seek(SeekFrom::Current(length as i64)).ok()?;
+                None
+            }
+            b"MTrk" => {
+                let start = reader.stream_position().ok()?;
+                let art = scan_track(&mut (&mut reader).take(length));
+                reader.seek(SeekFrom::Start(start + length)).ok()?;
+                art
+            }
+            _ => decode_art(&read_blob(&mut reader, length).ok()?),
+        };
+
+        if art.is_some() {
+            return art;
+        }
+    }
+}
+
+/// Looks at the meta and SysEx events at the start of a track
+fn scan_track(track: &mut impl Read) -> Option<RgbaImage> {
+    let mut running_status = 0;
+
+    for _ in 0..MAX_EVENTS_PER_TRACK {
+        read_var_len(track).ok()?;
+        let status = read_u8(track).ok()?;
+
+        let blob = match status {
+            0xFF => {
+                read_u8(track).ok()?;
+                let length = read_var_len(track).ok()?;
+                read_blob(track, length).ok()?
+            }
+            0xF0 | 0xF7 => {
+                let length = read_var_len(track).ok()?;
+                read_blob(track, length).ok()?
+            }
+            _ => {
+                // Without a status byte the first data byte was already read
+                let (status, read) = if status < 0x80 {
+                    (running_status, 1)
+                } else {
+                    (status, 0)
+                };
+                let length = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                skip(track, length - read).ok()?;
+                running_status = status;
+                continue;
+            }
+        };
+
+        if let Some(art) = decode_art(&blob) {
+            return Some(art);
+        }
+    }
+
+    None
+}
+
+/// Decodes the first image found in the bytes, as a thumbnail
+fn decode_art(bytes: &[u8]) -> Option<RgbaImage> {
+    (0..bytes.len())
+        .filter(|&i| {
+            IMAGE_SIGNATURES
+                .iter()
+                .any(|signature| bytes[i..].starts_with(signature))
+        })
+        .find_map(|i| image::load_from_memory(&bytes[i..]).ok())
+        .map(|image| image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgba8())
+}
+
+/// Reads the bytes of a blob, ones that are too big are skipped and read as empty
+fn read_blob(reader: &mut impl Read, length: u64) -> io::Result<Vec<u8>> {
+    if length > MAX_BLOB_SIZE {
+        skip(reader, length)?;
+        return Ok(Vec::new());
+    }
+
+    let mut blob = vec![0; length as usize];
+    reader.read_exact(&mut blob)?;
+    Ok(blob)
+}
+
+fn skip(reader: &mut impl Read, length: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(length), &mut io::sink())?;
+    if skipped < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+/// Reads a number in the variable length format of MIDI files
+fn read_var_len(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0;
+    for _ in 0..4 {
+        let byte = read_u8(reader)?;
+        value = (value << 7) | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(value)
+}
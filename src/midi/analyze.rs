@@ -0,0 +1,184 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use midi_toolkit::{
+    events::{Event, MIDIEventEnum},
+    io::MIDIFile as TKMIDIFile,
+    pipe,
+    sequence::{
+        event::{cancel_tempo_events, scale_event_time},
+        unwrap_items, TimeCaster,
+    },
+};
+use rustc_hash::FxHashMap;
+
+use super::{decompress_gzip_midi, is_gzip_midi};
+
+/// The statistics of the notes on one channel of one track
+struct ChannelStats {
+    notes: u64,
+    min_velocity: u8,
+    max_velocity: u8,
+    total_velocity: u64,
+    min_duration: f64,
+    max_duration: f64,
+    total_duration: f64,
+    polyphony: u32,
+    peak_polyphony: u32,
+    /// The start times of the held notes by key, the first note on is ended by the first note off
+    held: FxHashMap<u8, VecDeque<f64>>,
+}
+
+impl ChannelStats {
+    fn new() -> Self {
+        Self {
+            notes: 0,
+            min_velocity: u8::MAX,
+            max_velocity: 0,
+            total_velocity: 0,
+            min_duration: f64::MAX,
+            max_duration: 0.0,
+            total_duration: 0.0,
+            polyphony: 0,
+            peak_polyphony: 0,
+            held: FxHashMap::default(),
+        }
+    }
+
+    fn note_on(&mut self, key: u8, velocity: u8, time: f64) {
+        self.notes += 1;
+        self.min_velocity = self.min_velocity.min(velocity);
+        self.max_velocity = self.max_velocity.max(velocity);
+        self.total_velocity += velocity as u64;
+
+        self.held.entry(key).or_default().push_back(time);
+        self.polyphony += 1;
+        self.peak_polyphony = self.peak_polyphony.max(self.polyphony);
+    }
+
+    fn note_off(&mut self, key: u8, time: f64) {
+        let Some(start) = self.held.get_mut(&key).and_then(|held| held.pop_front()) else {
+            return;
+        };
+        self.polyphony -= 1;
+
+        let duration = time - start;
+        self.min_duration = self.min_duration.min(duration);
+        self.max_duration = self.max_duration.max(duration);
+        self.total_duration += duration;
+    }
+
+    /// Notes that never get a note off last until the end of the MIDI
+    fn end_all(&mut self, time: f64) {
+        let held: Vec<_> = self
+            .held
+            .iter()
+            .flat_map(|(key, starts)| starts.iter().map(|_| *key))
+            .collect();
+        for key in held {
+            self.note_off(key, time);
+        }
+    }
+
+    fn write_row(&self, out: &mut impl Write, track: u32, channel: u8) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "{},{},{},{},{},{:.2},{:.6},{:.6},{:.6},{}",
+            track,
+            channel,
+            self.notes,
+            self.min_velocity,
+            self.max_velocity,
+            self.total_velocity as f64 / self.notes as f64,
+            self.min_duration,
+            self.max_duration,
+            self.total_duration / self.notes as f64,
+            self.peak_polyphony,
+        )
+    }
+}
+
+/// Reads every event of a MIDI file and writes the note statistics of each track and
+/// channel to a CSV file, without opening a window or playing any audio.
+/// The durations are in seconds, and the channels are numbered from 0.
+pub fn analyze_midi(midi_path: &Path, output: &Path) -> Result<(), String> {
+    // Nothing cancels the export, it runs until it is done
+    let (_cancel, listener) = triggered::trigger();
+    // The decompressed copy is deleted when this is dropped, once the file was read
+    let decompressed = if is_gzip_midi(midi_path) {
+        Some(
+            decompress_gzip_midi(midi_path.to_path_buf(), listener)
+                .recv()
+                .ok()
+                .flatten()
+                .ok_or_else(|| format!("Failed to decompress {}", midi_path.display()))?,
+        )
+    } else {
+        None
+    };
+    let midi_path = decompressed.as_deref().unwrap_or(midi_path);
+
+    let file = File::open(midi_path).map_err(|e| format!("Failed to open MIDI: {}", e))?;
+    let midi = TKMIDIFile::open_from_stream(file, None)
+        .map_err(|e| format!("Failed to parse MIDI: {:?}", e))?;
+
+    let ppq = midi.ppq();
+    let merged = pipe!(
+        midi.iter_all_track_events_merged_batches()
+        |>TimeCaster::<f64>::cast_event_delta()
+        |>cancel_tempo_events(250000)
+        |>scale_event_time(1.0 / ppq as f64)
+        |>unwrap_items()
+    );
+
+    // Sorted so the rows come out in order of the track and channel
+    let mut stats: BTreeMap<(u32, u8), ChannelStats> = BTreeMap::new();
+    let mut time = 0.0;
+
+    for batch in merged {
+        time += batch.delta;
+        for event in batch.iter_events() {
+            match event.as_event() {
+                Event::NoteOn(e) => stats
+                    .entry((event.track, e.channel))
+                    .or_insert_with(ChannelStats::new)
+                    .note_on(e.key, e.velocity, time),
+                Event::NoteOff(e) => {
+                    if let Some(stats) = stats.get_mut(&(event.track, e.channel)) {
+                        stats.note_off(e.key, time);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let file = File::create(output).map_err(|e| format!("Failed to create CSV: {}", e))?;
+    let mut out = BufWriter::new(file);
+    let write_error = |e: std::io::Error| format!("Failed to write CSV: {}", e);
+
+    writeln!(
+        out,
+        "track,channel,notes,min_velocity,max_velocity,avg_velocity,\
+        min_duration,max_duration,avg_duration,peak_polyphony"
+    )
+    .map_err(write_error)?;
+    for ((track, channel), stats) in stats.iter_mut() {
+        stats.end_all(time);
+        stats
+            .write_row(&mut out, *track, *channel)
+            .map_err(write_error)?;
+    }
+    out.flush().map_err(write_error)?;
+
+    println!(
+        "Wrote the statistics of {} track channels to {}",
+        stats.len(),
+        output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use crossbeam_channel::{bounded, Receiver};
+use tempfile::TempPath;
+use triggered::Listener;
+use zip::ZipArchive;
+
+use crate::notifications;
+
+use super::gzip::copy_until_cancelled;
+
+/// Checks if a path points to a ZIP archive, which can contain MIDIs
+pub fn is_zip_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("zip"))
+        .unwrap_or(false)
+}
+
+/// MIDIs inside of an archive that unpack to more than this aren't extracted, so a
+/// broken or malicious archive can't fill up the disk
+const MAX_EXTRACTED_SIZE: u64 = 4 << 30;
+
+/// The names of the MIDIs inside a ZIP archive, in the order they are stored
+pub fn list_zip_midis(path: &Path) -> io::Result<Vec<String>> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let mut names = Vec::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        let name = entry.name().to_ascii_lowercase();
+        if entry.is_file() && (name.ends_with(".mid") || name.ends_with(".midi")) {
+            names.push(entry.name().to_owned());
+        }
+    }
+    Ok(names)
+}
+
+/// Extracts a MIDI from a ZIP archive into a temporary file on a separate thread.
+/// The receiver gets the path of the extracted file, or `None` if it failed. The file
+/// is deleted once the path is dropped.
+pub fn extract_zip_midi(
+    path: PathBuf,
+    name: String,
+    cancel: Listener,
+) -> Receiver<Option<TempPath>> {
+    let (sender, receiver) = bounded(1);
+
+    thread::spawn(move || {
+        let result = extract_to_temp(&path, &name, &cancel);
+        // A cancelled copy stops with an error, which isn't worth reporting
+        match &result {
+            Err(_) if cancel.is_triggered() => {}
+            Err(e) => notifications::error(format!(
+                "Failed to extract {} from {}: {}",
+                name,
+                path.display(),
+                e
+            )),
+            Ok(_) => {}
+        }
+        sender.send(result.ok()).ok();
+    });
+
+    receiver
+}
+
+fn extract_to_temp(path: &Path, name: &str, cancel: &Listener) -> io::Result<TempPath> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let entry = archive.by_name(name)?;
+    if entry.size() > MAX_EXTRACTED_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the MIDI is too big to extract",
+        ));
+    }
+
+    // Every file gets its own name, so MIDIs with the same name in different archives
+    // don't overwrite each other, and the folders in the archive can't point outside
+    // of the temp dir
+    let temp_file = tempfile::Builder::new()
+        .prefix("wasabi-")
+        .suffix(".mid")
+        .tempfile()?;
+    let (file, temp_path) = temp_file.into_parts();
+    let mut output = BufWriter::new(file);
+
+    // The stored size can be wrong, so the copy stops at the limit either way
+    let copied =
+        copy_until_cancelled(&mut entry.take(MAX_EXTRACTED_SIZE + 1), &mut output, cancel)?;
+    if copied > MAX_EXTRACTED_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the MIDI is too big to extract",
+        ));
+    }
+    output.flush()?;
+
+    Ok(temp_path)
+}
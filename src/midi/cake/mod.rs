@@ -1,4 +1,5 @@
 use std::{
+    path::Path,
     sync::{Arc, RwLock},
     thread,
     time::Duration,
@@ -13,6 +14,7 @@ use midi_toolkit::{
         unwrap_items, TimeCaster,
     },
 };
+use triggered::Listener;
 
 use crate::{
     audio_playback::SimpleTemporaryPlayer,
@@ -21,12 +23,16 @@ use crate::{
         cake::tree_threader::{NoteEvent, ThreadedTreeSerializers},
         open_file_and_signature,
         shared::{audio::CompressedAudio, timer::TimeKeeper},
+        MIDIOpenError,
     },
 };
 
 use self::blocks::CakeBlock;
 
-use super::{MIDIFileBase, MIDIFileStats, MIDIFileUniqueSignature};
+use super::{
+    Beat, ControlChanges, LyricLine, MIDIFileBase, MIDIFileMetadata, MIDIFileStats,
+    MIDIFileUniqueSignature, ProgramChange, SharedMetadata,
+};
 
 pub mod blocks;
 pub mod intvec4;
@@ -41,20 +47,34 @@ pub struct CakeMIDIFile {
     note_count: u64,
     ticks_per_second: u32,
     signature: MIDIFileUniqueSignature,
+    track_count: usize,
+    metadata: SharedMetadata,
 }
 
 impl CakeMIDIFile {
     pub fn load_from_file(
-        path: &str,
+        path: &Path,
         player: Arc<RwLock<SimpleTemporaryPlayer>>,
         _random_colors: bool,
-    ) -> Self {
+        track_order: &[usize],
+        cancel: &Listener,
+    ) -> Result<Self, MIDIOpenError> {
         let ticks_per_second = 1000;
 
-        let (file, signature) = open_file_and_signature(path);
-        let midi = TKMIDIFile::open_from_stream(file, None).unwrap();
+        let (file, signature) = open_file_and_signature(path)?;
+        let midi = TKMIDIFile::open_from_stream(file, None).map_err(MIDIOpenError::Parse)?;
 
         let ppq = midi.ppq();
+        let track_count = midi.track_count();
+
+        // The trees only keep the note on top, so the tracks earlier in the order get a
+        // higher priority. Without an order the latest note is on top.
+        let mut priorities = vec![0; track_count];
+        for (rank, track) in track_order.iter().enumerate() {
+            if let Some(priority) = priorities.get_mut(*track) {
+                *priority = (track_order.len() - rank) as u32;
+            }
+        }
         let merged = pipe!(
             midi.iter_all_track_events_merged_batches()
             |>TimeCaster::<f64>::cast_event_delta()
@@ -92,6 +112,7 @@ impl CakeMIDIFile {
                                 NoteEvent::On {
                                     time: int_time,
                                     channel_track: channel_track(e.channel, track),
+                                    priority: priorities.get(track as usize).copied().unwrap_or(0),
                                 },
                             );
                             note_count += 1;
@@ -133,6 +154,9 @@ impl CakeMIDIFile {
 
         // Write events to the threads
         for batch in merged {
+            if cancel.is_triggered() {
+                return Err(MIDIOpenError::Cancelled);
+            }
             length += batch.delta;
             let batch = Arc::new(batch);
             key_snd.send(batch.clone()).unwrap();
@@ -149,14 +173,16 @@ impl CakeMIDIFile {
 
         InRamAudioPlayer::new(audio, timer.get_listener(), player).spawn_playback();
 
-        CakeMIDIFile {
+        Ok(CakeMIDIFile {
             blocks: keys,
             timer,
             length,
             note_count,
             ticks_per_second,
             signature,
-        }
+            track_count,
+            metadata: MIDIFileMetadata::load(path, cancel),
+        })
     }
 
     pub fn key_blocks(&self) -> &[CakeBlock] {
@@ -217,4 +243,42 @@ impl MIDIFileBase for CakeMIDIFile {
     fn signature(&self) -> &MIDIFileUniqueSignature {
         &self.signature
     }
+
+    fn is_reversed(&self) -> bool {
+        false
+    }
+
+    fn track_count(&self) -> usize {
+        self.track_count
+    }
+
+    fn metadata(&self) -> Arc<MIDIFileMetadata> {
+        self.metadata.read().unwrap().clone()
+    }
+
+    fn control_changes(&self) -> Arc<ControlChanges> {
+        self.metadata.read().unwrap().control_changes.clone()
+    }
+
+    fn program_changes(&self) -> Arc<Vec<ProgramChange>> {
+        self.metadata.read().unwrap().program_changes.clone()
+    }
+
+    fn lyrics(&self) -> Arc<Vec<LyricLine>> {
+        self.metadata.read().unwrap().lyrics.clone()
+    }
+
+    fn bpm_at(&self, time: f64) -> f64 {
+        self.metadata.read().unwrap().bpm_at(time)
+    }
+
+    fn beats_between(&self, start: f64, end: f64) -> Vec<Beat> {
+        // The beats are found after the lock is let go of, the scan may be waiting on it
+        let metadata = self.metadata.read().unwrap().clone();
+        metadata.beats_between(start, end)
+    }
+
+    fn ppq(&self) -> u16 {
+        self.metadata.read().unwrap().ppq
+    }
 }
@@ -112,8 +112,9 @@ impl TreeSerializer {
     }
 
     /// Processes a note start. If the time is greater than the last tree time, the tree is
-    /// updated to the new time. Then, the note is pushed to the note stack.
-    pub fn start_note(&mut self, time: i32, track_channel: i32) {
+    /// updated to the new time. Then, the note is pushed to the note stack, where it is
+    /// shown over the notes with a lower priority.
+    pub fn start_note(&mut self, time: i32, track_channel: i32, priority: u32) {
         if time > self.last_tree_time {
             self.process_change(time);
         }
@@ -122,6 +123,7 @@ impl TreeSerializer {
 
         self.note_stack.push_note(
             track_channel,
+            priority,
             NoteMarker {
                 start: time,
                 track_channel,
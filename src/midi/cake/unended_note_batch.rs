@@ -5,10 +5,12 @@ pub struct RemovedValue<T> {
     pub is_last: bool,
 }
 
+/// The notes that haven't ended yet. The top note is the one with the highest priority,
+/// and the latest one of those when several have the same priority.
 pub struct UnendedNotes<K: Ord, T> {
     id_counter: u32,
-    notes: BTreeMap<u32, T>,
-    ids: BTreeMap<K, VecDeque<u32>>,
+    notes: BTreeMap<(u32, u32), T>,
+    ids: BTreeMap<K, VecDeque<(u32, u32)>>,
 }
 
 impl<K: Ord, T> UnendedNotes<K, T> {
@@ -41,14 +43,14 @@ impl<K: Ord, T> UnendedNotes<K, T> {
         })
     }
 
-    pub fn push_note(&mut self, key: K, note: T) -> u32 {
+    pub fn push_note(&mut self, key: K, priority: u32, note: T) -> u32 {
         let id = self.id_counter;
         self.id_counter += 1;
 
         let ids = self.ids.entry(key).or_insert_with(VecDeque::new);
-        ids.push_back(id);
+        ids.push_back((priority, id));
 
-        self.notes.insert(id, note);
+        self.notes.insert((priority, id), note);
 
         id
     }
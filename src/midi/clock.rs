@@ -0,0 +1,113 @@
+use std::{
+    collections::VecDeque,
+    sync::{atomic::Ordering, Arc},
+};
+
+use atomic_float::AtomicF64;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+
+use crate::notifications;
+
+/// The amount of timing clock messages that are sent per quarter note
+const CLOCKS_PER_QUARTER: f64 = 24.0;
+
+/// The amount of clock intervals that are averaged to smooth out jitter
+const CLOCK_WINDOW: usize = 24;
+
+/// Reads MIDI timing clock messages from an input port and
+/// derives the tempo of the external sequencer from them
+pub struct MIDIClockSync {
+    bpm: Arc<AtomicF64>,
+    _connection: MidiInputConnection<()>,
+}
+
+impl MIDIClockSync {
+    /// Connects to the input port with the given name, an empty
+    /// name picks the first available port
+    pub fn new(port_name: &str) -> Option<Self> {
+        let mut input = match MidiInput::new("wasabi") {
+            Ok(input) => input,
+            Err(e) => {
+                notifications::error(format!("Failed to open MIDI input: {}", e));
+                return None;
+            }
+        };
+        input.ignore(Ignore::Sysex);
+
+        let port = input.ports().into_iter().find(|port| {
+            port_name.is_empty() || input.port_name(port).ok().as_deref() == Some(port_name)
+        })?;
+
+        let bpm = Arc::new(AtomicF64::new(0.0));
+        let bpm_clone = bpm.clone();
+
+        let mut last_stamp: Option<u64> = None;
+        let mut intervals = VecDeque::with_capacity(CLOCK_WINDOW);
+
+        let connection = input.connect(
+            &port,
+            "wasabi-clock",
+            move |stamp, message, _| match message.first() {
+                // Timing clock
+                Some(0xF8) => {
+                    if let Some(last) = last_stamp {
+                        if intervals.len() == CLOCK_WINDOW {
+                            intervals.pop_front();
+                        }
+                        intervals.push_back(stamp.saturating_sub(last));
+
+                        let average = intervals.iter().sum::<u64>() as f64 / intervals.len() as f64;
+                        if average > 0.0 {
+                            bpm_clone.store(
+                                60_000_000.0 / (average * CLOCKS_PER_QUARTER),
+                                Ordering::Relaxed,
+                            );
+                        }
+                    }
+                    last_stamp = Some(stamp);
+                }
+                // Start, continue and stop restart the measurement
+                Some(0xFA) | Some(0xFB) | Some(0xFC) => {
+                    last_stamp = None;
+                    intervals.clear();
+                }
+                _ => {}
+            },
+            (),
+        );
+
+        match connection {
+            Ok(connection) => Some(MIDIClockSync {
+                bpm,
+                _connection: connection,
+            }),
+            Err(e) => {
+                notifications::error(format!("Failed to connect to MIDI input: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Lists the names of the MIDI input ports that a clock can be read from
+    pub fn available_ports() -> Vec<String> {
+        if let Ok(input) = MidiInput::new("wasabi") {
+            input
+                .ports()
+                .iter()
+                .filter_map(|port| input.port_name(port).ok())
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// The tempo of the received clock, if enough clock messages have arrived
+    pub fn bpm(&self) -> Option<f64> {
+        let bpm = self.bpm.load(Ordering::Relaxed);
+        if bpm > 0.0 {
+            Some(bpm)
+        } else {
+            None
+        }
+    }
+}
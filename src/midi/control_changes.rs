@@ -0,0 +1,46 @@
+use rustc_hash::FxHashMap;
+
+/// Changes closer together than this are merged into the last one, since the lanes
+/// can't show them apart. Some MIDIs send a controller on every tick.
+const MERGE_WINDOW: f64 = 1.0 / 240.0;
+
+/// The most changes kept per channel and controller, the later ones are dropped
+const MAX_EVENTS: usize = 200000;
+
+/// The control change events of a MIDI, by channel and controller number
+#[derive(Debug, Default)]
+pub struct ControlChanges {
+    /// The time in seconds and the value of every change, in order of time
+    events: FxHashMap<(u8, u8), Vec<(f64, u8)>>,
+}
+
+impl ControlChanges {
+    pub fn push(&mut self, channel: u8, controller: u8, time: f64, value: u8) {
+        let events = self.events.entry((channel, controller)).or_default();
+        match events.last_mut() {
+            Some((_, last)) if *last == value => {}
+            Some((last_time, last)) if time - *last_time < MERGE_WINDOW => *last = value,
+            _ if events.len() >= MAX_EVENTS => {}
+            _ => events.push((time, value)),
+        }
+    }
+
+    /// The channels that have any changes of a controller, in order
+    pub fn channels_with(&self, controller: u8) -> Vec<u8> {
+        let mut channels: Vec<_> = self
+            .events
+            .keys()
+            .filter(|(_, cc)| *cc == controller)
+            .map(|(channel, _)| *channel)
+            .collect();
+        channels.sort_unstable();
+        channels
+    }
+
+    /// The value of a controller at a time, or `None` before its first change
+    pub fn value_at(&self, channel: u8, controller: u8, time: f64) -> Option<u8> {
+        let events = self.events.get(&(channel, controller))?;
+        let index = events.partition_point(|(t, _)| *t <= time);
+        index.checked_sub(1).map(|index| events[index].1)
+    }
+}
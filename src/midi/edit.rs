@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use rustc_hash::FxHashMap;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::notifications;
+
+/// Identifies a single note by its key, the time of the note block
+/// it belongs to and its index inside of that block
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NoteId {
+    pub key: u8,
+    pub start: f64,
+    pub index: u32,
+}
+
+impl NoteId {
+    /// The key used to look the note up inside of its column
+    pub fn column_key(&self) -> (u64, u32) {
+        (self.start.to_bits(), self.index)
+    }
+}
+
+/// A change made to a note in the note editor. Edits are kept as an overlay
+/// on top of the loaded MIDI and applied when the note buffers are filled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NoteEdit {
+    Delete { note: NoteId },
+    Trim { note: NoteId, start: f64, end: f64 },
+}
+
+impl NoteEdit {
+    pub fn note(&self) -> NoteId {
+        match self {
+            NoteEdit::Delete { note } => *note,
+            NoteEdit::Trim { note, .. } => *note,
+        }
+    }
+}
+
+/// The edits of a single key column, later edits to a note replace earlier ones
+pub type ColumnEdits = FxHashMap<(u64, u32), NoteEdit>;
+
+/// Groups a list of edits by the key of the note they change
+pub fn edits_per_key(edits: &[NoteEdit]) -> Vec<ColumnEdits> {
+    let mut columns = vec![ColumnEdits::default(); 256];
+    for edit in edits {
+        let note = edit.note();
+        columns[note.key as usize].insert(note.column_key(), *edit);
+    }
+    columns
+}
+
+/// The data stored next to a MIDI file in its `.mid.wasabi.json` sidecar file
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MIDISidecar {
+    pub note_edits: Vec<NoteEdit>,
+    /// The order the tracks are drawn in, front-most first. Empty means the original order.
+    pub track_order: Vec<usize>,
+    /// A partial copy of the settings that overrides the global ones while the MIDI is loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<serde_json::Value>,
+}
+
+impl MIDISidecar {
+    /// `song.mid` has its sidecar at `song.mid.wasabi.json`
+    pub fn path_for(midi_path: &Path) -> PathBuf {
+        let mut path = midi_path.as_os_str().to_owned();
+        path.push(".wasabi.json");
+        PathBuf::from(path)
+    }
+
+    /// Older versions wrote the sidecar of `song.mid` to `song.wasabi.json`
+    fn legacy_path_for(midi_path: &Path) -> PathBuf {
+        midi_path.with_extension("wasabi.json")
+    }
+
+    /// Loads the sidecar of a MIDI file, or an empty one if it doesn't exist.
+    /// A sidecar with the old name is moved to the new one first.
+    pub fn load(midi_path: &Path) -> Self {
+        let mut path = Self::path_for(midi_path);
+        let legacy_path = Self::legacy_path_for(midi_path);
+        if !path.exists() && legacy_path.exists() {
+            if let Err(e) = std::fs::rename(&legacy_path, &path) {
+                notifications::error(format!("Failed to rename the old sidecar file: {}", e));
+                path = legacy_path;
+            }
+        }
+
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                notifications::error(format!("Failed to parse sidecar file: {}", e));
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, midi_path: &Path) {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        if let Err(e) = std::fs::write(Self::path_for(midi_path), json) {
+            notifications::error(format!("Failed to save sidecar file: {}", e));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use crossbeam_channel::{bounded, Receiver};
+use flate2::bufread::GzDecoder;
+use tempfile::TempPath;
+use triggered::Listener;
+
+use crate::notifications;
+
+/// Checks if a path points to a GZip-compressed MIDI, like `.mid.gz`
+pub fn is_gzip_midi(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("gz"))
+        .unwrap_or(false)
+}
+
+/// Decompresses a GZip-compressed MIDI into a temporary file on a separate thread.
+/// The receiver gets the path of the decompressed file, or `None` if it failed. The
+/// file is deleted once the path is dropped.
+pub fn decompress_gzip_midi(path: PathBuf, cancel: Listener) -> Receiver<Option<TempPath>> {
+    let (sender, receiver) = bounded(1);
+
+    thread::spawn(move || {
+        let result = decompress_to_temp(&path, &cancel);
+        // A cancelled copy stops with an error, which isn't worth reporting
+        match &result {
+            Err(_) if cancel.is_triggered() => {}
+            Err(e) => {
+                notifications::error(format!("Failed to decompress {}: {}", path.display(), e))
+            }
+            Ok(_) => {}
+        }
+        sender.send(result.ok()).ok();
+    });
+
+    receiver
+}
+
+fn decompress_to_temp(path: &Path, cancel: &Listener) -> io::Result<TempPath> {
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(path)?));
+
+    // Every file gets its own name, so MIDIs with the same name don't overwrite each other
+    let temp_file = tempfile::Builder::new()
+        .prefix("wasabi-")
+        .suffix(".mid")
+        .tempfile()?;
+    let (file, temp_path) = temp_file.into_parts();
+    let mut output = BufWriter::new(file);
+    copy_until_cancelled(&mut decoder, &mut output, cancel)?;
+    output.flush()?;
+
+    Ok(temp_path)
+}
+
+/// Copies like [`io::copy`], but in chunks, so it stops soon after it is cancelled
+pub(super) fn copy_until_cancelled(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    cancel: &Listener,
+) -> io::Result<u64> {
+    let mut buffer = vec![0; 1 << 16];
+    let mut copied = 0;
+    loop {
+        if cancel.is_triggered() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+        }
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+    }
+}
@@ -14,21 +14,26 @@ pub struct LiveNoteBlock {
 pub struct LiveMIDINote {
     pub len: f32,
     pub track_chan: TrackAndChannel,
+    pub vel: u8,
+    /// The last program change on the channel of the note, if there was one
+    pub program: Option<u8>,
 }
 
 impl LiveNoteBlock {
-    /// Creates a new block from an iterator of Track/Channel values.
+    /// Creates a new block from an iterator of Track/Channel, velocity and program values.
     /// This assumes that the lengths will be added in the future.
     pub fn new_from_trackchans(
         time: f64,
-        track_chans_iter: impl ExactSizeIterator<Item = TrackAndChannel>,
+        track_chans_iter: impl ExactSizeIterator<Item = (TrackAndChannel, u8, Option<u8>)>,
     ) -> Self {
         let mut notes: Vec<LiveMIDINote> = Vec::with_capacity(track_chans_iter.len());
 
-        for track_chan in track_chans_iter {
+        for (track_chan, vel, program) in track_chans_iter {
             notes.push(LiveMIDINote {
                 len: f32::INFINITY,
                 track_chan,
+                vel,
+                program,
             });
         }
 
@@ -63,7 +68,7 @@ unsafe impl Sync for LiveRefNoteBlock {}
 impl LiveRefNoteBlock {
     pub fn new_from_trackchans(
         time: f64,
-        track_chans_iter: impl ExactSizeIterator<Item = TrackAndChannel>,
+        track_chans_iter: impl ExactSizeIterator<Item = (TrackAndChannel, u8, Option<u8>)>,
     ) -> (
         Self,
         impl ExactSizeIterator<Item = LiveNoteEnderHandleWithTrackChan>,
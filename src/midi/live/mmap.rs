@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use midi_toolkit::{
+    events::Event,
+    io::{MIDILoadError, MIDIParseError, MIDIReader, TrackParser, TrackReader},
+    sequence::{
+        channels_into_threadpool,
+        event::{
+            convert_events_into_batches, into_track_events, merge_events_array, Delta, EventBatch,
+            Track,
+        },
+    },
+};
+
+/// Reads the MIDI straight out of a map of the file, the same way the toolkit's RAM
+/// reader reads out of a copy of it
+#[derive(Debug)]
+pub struct MmapReader {
+    map: Arc<Mmap>,
+}
+
+impl MmapReader {
+    pub fn new(map: Mmap) -> Self {
+        Self { map: Arc::new(map) }
+    }
+}
+
+impl MIDIReader for MmapReader {
+    type ByteReader = MmapTrackReader;
+
+    fn read_bytes_to(&self, pos: u64, mut bytes: Vec<u8>) -> Result<Vec<u8>, MIDILoadError> {
+        let start = pos as usize;
+        let source = self
+            .map
+            .get(start..start + bytes.len())
+            .ok_or(MIDILoadError::CorruptChunks)?;
+        bytes.copy_from_slice(source);
+        Ok(bytes)
+    }
+
+    fn len(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    fn open_reader(&self, track_number: Option<u32>, start: u64, len: u64) -> MmapTrackReader {
+        // A track that claims to run past the end of the file ends with it instead
+        let end = ((start + len) as usize).min(self.map.len());
+        MmapTrackReader {
+            track_number,
+            start: start as usize,
+            pos: start as usize,
+            end,
+            map: self.map.clone(),
+        }
+    }
+}
+
+pub struct MmapTrackReader {
+    /// The track number and start are only for the errors
+    track_number: Option<u32>,
+    start: usize,
+
+    map: Arc<Mmap>,
+    pos: usize,
+    end: usize,
+}
+
+impl TrackReader for MmapTrackReader {
+    #[inline(always)]
+    fn read(&mut self) -> Result<u8, MIDIParseError> {
+        if self.pos == self.end {
+            return Err(MIDIParseError::UnexpectedTrackEnd {
+                track_number: self.track_number,
+                track_start: self.start as u64,
+                expected_track_end: self.end as u64,
+                found_track_end: self.pos as u64,
+            });
+        }
+        let b = self.map[self.pos];
+        self.pos += 1;
+        Ok(b)
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> u64 {
+        self.pos as u64
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.pos == self.end
+    }
+
+    fn track_number(&self) -> Option<u32> {
+        self.track_number
+    }
+}
+
+/// A MIDI mapped into memory. The toolkit only opens its own readers, so the chunks
+/// are found here, and the tracks are parsed like the toolkit parses them.
+pub struct MappedMIDIFile {
+    reader: MmapReader,
+    /// Where the events of each track start, and how many bytes they take
+    tracks: Vec<(u64, u32)>,
+    ppq: u16,
+}
+
+impl MappedMIDIFile {
+    pub fn new(map: Mmap) -> Result<Self, MIDILoadError> {
+        let reader = MmapReader::new(map);
+
+        let read_header = |pos: u64, name: &[u8; 4]| -> Result<u32, MIDILoadError> {
+            let bytes = reader.read_bytes(pos, 8)?;
+            if &bytes[..4] != name {
+                return Err(MIDILoadError::CorruptChunks);
+            }
+            Ok(u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]))
+        };
+
+        if read_header(0, b"MThd")? != 6 {
+            return Err(MIDILoadError::CorruptChunks);
+        }
+        let header = reader.read_bytes(8, 6)?;
+        let ppq = u16::from_be_bytes([header[4], header[5]]);
+
+        let mut tracks = Vec::new();
+        let mut pos = 14;
+        while pos < reader.len() {
+            let len = read_header(pos, b"MTrk")?;
+            pos += 8;
+            tracks.push((pos, len));
+            pos += len as u64;
+        }
+
+        Ok(Self {
+            reader,
+            tracks,
+            ppq,
+        })
+    }
+
+    pub fn ppq(&self) -> u16 {
+        self.ppq
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn iter_track(&self, track: u32) -> TrackParser<MmapTrackReader> {
+        let (pos, len) = self.tracks[track as usize];
+        TrackParser::new(self.reader.open_reader(Some(track), pos, len as u64))
+    }
+
+    pub fn iter_all_tracks(&self) -> impl Iterator<Item = TrackParser<MmapTrackReader>> {
+        let tracks: Vec<_> = (0..self.track_count())
+            .map(|i| self.iter_track(i as u32))
+            .collect();
+        tracks.into_iter()
+    }
+
+    pub fn iter_all_track_events_merged_batches(
+        &self,
+    ) -> impl Iterator<Item = Result<Delta<u64, Track<EventBatch<Event>>>, MIDIParseError>> {
+        let batched_tracks = self
+            .iter_all_tracks()
+            .map(convert_events_into_batches)
+            .enumerate()
+            .map(|(i, track)| into_track_events(track, i as u32))
+            .collect();
+        let batched_tracks_threaded = channels_into_threadpool(batched_tracks, 10);
+        merge_events_array(batched_tracks_threaded)
+    }
+}
@@ -1,25 +1,36 @@
 use std::{
+    fs::File,
+    path::Path,
     sync::{atomic::Ordering, Arc, RwLock},
     thread,
 };
 
 use atomic_float::AtomicF64;
-use midi_toolkit::{io::MIDIFile as TKMIDIFile, sequence::event::get_channels_array_statistics};
+use memmap2::Mmap;
+use midi_toolkit::{
+    events::Event,
+    io::{DiskReader, MIDIFile as TKMIDIFile, MIDIParseError},
+    sequence::event::{get_channels_array_statistics, Delta},
+};
+use triggered::Listener;
 
-use crate::audio_playback::SimpleTemporaryPlayer;
+use crate::{audio_playback::SimpleTemporaryPlayer, notifications};
 
 use self::{
+    mmap::MappedMIDIFile,
     parse::LiveMidiParser,
     view::{LiveCurrentNoteViews, LiveNoteViewData},
 };
 
 use super::{
-    open_file_and_signature, shared::timer::TimeKeeper, MIDIFile, MIDIFileBase, MIDIFileStats,
-    MIDIFileUniqueSignature, MIDIViewRange,
+    open_file_and_signature, shared::timer::TimeKeeper, Beat, ControlChanges, LyricLine, MIDIColor,
+    MIDIFile, MIDIFileBase, MIDIFileMetadata, MIDIFileStats, MIDIFileUniqueSignature,
+    MIDIOpenError, MIDIViewRange, ProgramChange, SharedMetadata,
 };
 
 pub mod block;
 pub mod column;
+mod mmap;
 mod parse;
 pub mod view;
 
@@ -28,45 +39,104 @@ pub struct LiveLoadMIDIFile {
     timer: TimeKeeper,
     length: Arc<AtomicF64>,
     signature: MIDIFileUniqueSignature,
+    metadata: SharedMetadata,
 }
 
 impl LiveLoadMIDIFile {
     pub fn load_from_file(
-        path: &str,
+        path: &Path,
         player: Arc<RwLock<SimpleTemporaryPlayer>>,
         random_colors: bool,
-    ) -> Self {
-        let (file, signature) = open_file_and_signature(path);
-
-        let midi = TKMIDIFile::open_from_stream(file, None).unwrap();
-
-        let parse_length_outer = Arc::new(AtomicF64::new(f64::NAN));
-        let parse_length = parse_length_outer.clone();
-
-        let ppq = midi.ppq();
-        let tracks = midi.iter_all_tracks().collect();
-        thread::spawn(move || {
-            let stats = get_channels_array_statistics(tracks);
-            if let Ok(stats) = stats {
-                parse_length.store(
-                    stats.calculate_total_duration(ppq).as_secs_f64(),
-                    Ordering::Relaxed,
-                );
-            }
-        });
+        program_colors: Option<Vec<MIDIColor>>,
+        use_mmap: bool,
+        cancel: &Listener,
+    ) -> Result<Self, MIDIOpenError> {
+        let (file, signature) = open_file_and_signature(path)?;
+        if cancel.is_triggered() {
+            return Err(MIDIOpenError::Cancelled);
+        }
 
+        let length = Arc::new(AtomicF64::new(f64::NAN));
         let mut timer = TimeKeeper::new();
 
-        let parer = LiveMidiParser::init(&midi, player, &mut timer);
-        let file = LiveNoteViewData::new(parer, midi.track_count(), random_colors);
+        let (parser, track_count) = match open_midi(file, use_mmap)? {
+            OpenedMIDI::Mapped(midi) => {
+                spawn_length_scan(midi.iter_all_tracks().collect(), midi.ppq(), length.clone());
+                let events = midi.iter_all_track_events_merged_batches();
+                let parser = LiveMidiParser::init(midi.ppq(), events, player, &mut timer);
+                (parser, midi.track_count())
+            }
+            OpenedMIDI::Read(midi) => {
+                spawn_length_scan(midi.iter_all_tracks().collect(), midi.ppq(), length.clone());
+                let events = midi.iter_all_track_events_merged_batches();
+                let parser = LiveMidiParser::init(midi.ppq(), events, player, &mut timer);
+                (parser, midi.track_count())
+            }
+        };
+        let file = LiveNoteViewData::new(parser, track_count, random_colors, program_colors);
 
-        LiveLoadMIDIFile {
+        Ok(LiveLoadMIDIFile {
             view_data: file,
             timer,
-            length: parse_length_outer,
+            length,
             signature,
+            metadata: MIDIFileMetadata::load(path, cancel),
+        })
+    }
+}
+
+enum OpenedMIDI {
+    Read(TKMIDIFile<DiskReader>),
+    Mapped(MappedMIDIFile),
+}
+
+/// Opens the MIDI either with buffered reads of the file, or mapped into memory. The
+/// parser keeps reading from the file for as long as it plays. Buffered reads go
+/// through a reader thread that copies every buffer out of the file with a system
+/// call, while the parser reads the map in place, so the OS only has to fault in the
+/// pages as they are reached. That helps most with big files on fast drives. The map
+/// takes up address space for the whole file though, and pages that were never read
+/// still have to come from the disk, so slow or network drives stall the parser the
+/// same either way.
+fn open_midi(file: File, use_mmap: bool) -> Result<OpenedMIDI, MIDIOpenError> {
+    if use_mmap {
+        // SAFETY: the map is only valid while nothing else changes the file. Writing
+        // to it from another program changes the notes under the parser, and
+        // truncating it makes reading the pages past the new end raise SIGBUS, which
+        // kills the whole process. It is the same for any other program mapping it.
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => match MappedMIDIFile::new(map) {
+                Ok(midi) => return Ok(OpenedMIDI::Mapped(midi)),
+                Err(e) => notifications::error(format!(
+                    "Failed to read the mapped MIDI, reading it from the file instead: {:?}",
+                    e
+                )),
+            },
+            Err(e) => notifications::error(format!(
+                "Failed to map the MIDI into memory, reading it from the file instead: {}",
+                e
+            )),
         }
     }
+    let midi = TKMIDIFile::open_from_stream(file, None).map_err(MIDIOpenError::Parse)?;
+    Ok(OpenedMIDI::Read(midi))
+}
+
+/// Works out the length of the MIDI from its tracks in the background
+fn spawn_length_scan(
+    tracks: Vec<impl 'static + Iterator<Item = Result<Delta<u64, Event>, MIDIParseError>> + Send>,
+    ppq: u16,
+    length: Arc<AtomicF64>,
+) {
+    thread::spawn(move || {
+        let stats = get_channels_array_statistics(tracks);
+        if let Ok(stats) = stats {
+            length.store(
+                stats.calculate_total_duration(ppq).as_secs_f64(),
+                Ordering::Relaxed,
+            );
+        }
+    });
 }
 
 impl MIDIFileBase for LiveLoadMIDIFile {
@@ -102,14 +172,55 @@ impl MIDIFileBase for LiveLoadMIDIFile {
     fn signature(&self) -> &MIDIFileUniqueSignature {
         &self.signature
     }
+
+    fn is_reversed(&self) -> bool {
+        false
+    }
+
+    fn track_count(&self) -> usize {
+        self.view_data.track_count()
+    }
+
+    fn metadata(&self) -> Arc<MIDIFileMetadata> {
+        self.metadata.read().unwrap().clone()
+    }
+
+    fn control_changes(&self) -> Arc<ControlChanges> {
+        self.metadata.read().unwrap().control_changes.clone()
+    }
+
+    fn program_changes(&self) -> Arc<Vec<ProgramChange>> {
+        self.metadata.read().unwrap().program_changes.clone()
+    }
+
+    fn lyrics(&self) -> Arc<Vec<LyricLine>> {
+        self.metadata.read().unwrap().lyrics.clone()
+    }
+
+    fn bpm_at(&self, time: f64) -> f64 {
+        self.metadata.read().unwrap().bpm_at(time)
+    }
+
+    fn beats_between(&self, start: f64, end: f64) -> Vec<Beat> {
+        // The beats are found after the lock is let go of, the scan may be waiting on it
+        let metadata = self.metadata.read().unwrap().clone();
+        metadata.beats_between(start, end)
+    }
+
+    fn ppq(&self) -> u16 {
+        self.metadata.read().unwrap().ppq
+    }
 }
 
 impl MIDIFile for LiveLoadMIDIFile {
     type ColumnsViews<'a> = LiveCurrentNoteViews<'a> where Self: 'a;
 
-    fn get_current_column_views(&mut self, range: f64) -> Self::ColumnsViews<'_> {
+    fn get_current_column_views(&mut self, range: f64, lookback: f64) -> Self::ColumnsViews<'_> {
         let time = self.timer.get_time().as_secs_f64();
-        let new_range = MIDIViewRange::new(time, time + range);
+        // The notes that were already dropped can't be loaded again, so the
+        // view can't start earlier than it did before
+        let start = (time - lookback).max(self.view_data.view_range().start);
+        let new_range = MIDIViewRange::new(start, time + range);
         self.view_data.shift_view_range(new_range);
 
         LiveCurrentNoteViews::new(&self.view_data)
@@ -8,7 +8,7 @@ use atomic_float::AtomicF64;
 use crossbeam_channel::Receiver;
 use midi_toolkit::{
     events::Event,
-    io::{DiskReader, MIDIFile as TKMIDIFile},
+    io::MIDIParseError,
     pipe,
     sequence::{
         event::{cancel_tempo_events, scale_event_time, Delta, EventBatch, Track},
@@ -44,14 +44,17 @@ pub struct LiveMidiParser {
 }
 
 impl LiveMidiParser {
+    /// Parses the merged events of the tracks of a MIDI with this PPQ
     pub fn init(
-        midi: &TKMIDIFile<DiskReader>,
+        ppq: u16,
+        events: impl 'static
+            + Iterator<Item = Result<Delta<u64, Track<EventBatch<Event>>>, MIDIParseError>>
+            + Send,
         player: Arc<RwLock<SimpleTemporaryPlayer>>,
         timer: &mut TimeKeeper,
     ) -> Self {
-        let ppq = midi.ppq();
         let merged = pipe!(
-            midi.iter_all_track_events_merged_batches()
+            events
             |>TimeCaster::<f64>::cast_event_delta()
             |>cancel_tempo_events(250000)
             |>scale_event_time(1.0 / ppq as f64)
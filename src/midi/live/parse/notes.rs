@@ -88,7 +88,7 @@ impl UnendedNotesHandler {
 
 struct ParserState {
     unended_notes: UnendedNotesHandler,
-    keys: Box<[Vec<TrackAndChannel>]>,
+    keys: Box<[Vec<(TrackAndChannel, u8, Option<u8>)>]>,
     sender: Sender<LiveNoteBlockWithKey>,
 }
 
@@ -105,8 +105,8 @@ impl ParserState {
         }
     }
 
-    fn add_note(&mut self, key: u8, track_chan: TrackAndChannel) {
-        self.keys[key as usize].push(track_chan);
+    fn add_note(&mut self, key: u8, track_chan: TrackAndChannel, vel: u8, program: Option<u8>) {
+        self.keys[key as usize].push((track_chan, vel, program));
     }
 
     fn flush(&mut self, time: f64) -> Result<(), ()> {
@@ -154,6 +154,9 @@ pub fn init_note_manager(blocks: Receiver<Arc<TrackEventBatch>>) -> NoteParserRe
     let mut state = ParserState::new(sender);
     let join_handle = std::thread::spawn(move || {
         let mut time: f64 = 0.0;
+
+        // Program changes apply to a channel across all of the tracks
+        let mut programs = [None; 16];
         for block in blocks.into_iter() {
             if block.delta > 0.0 {
                 let result = state.flush(time);
@@ -166,11 +169,19 @@ pub fn init_note_manager(blocks: Receiver<Arc<TrackEventBatch>>) -> NoteParserRe
             for event in block.iter_events() {
                 match event.as_event() {
                     Event::NoteOn(e) => {
-                        state.add_note(e.key, TrackAndChannel::new(event.track, e.channel));
+                        state.add_note(
+                            e.key,
+                            TrackAndChannel::new(event.track, e.channel),
+                            e.velocity,
+                            programs[e.channel as usize],
+                        );
                     }
                     Event::NoteOff(e) => {
                         state.end_note(e.key, TrackAndChannel::new(event.track, e.channel), time);
                     }
+                    Event::ProgramChange(e) => {
+                        programs[e.channel as usize] = Some(e.program);
+                    }
                     _ => {}
                 }
             }
@@ -11,6 +11,7 @@ pub struct LiveNoteViewData {
     parser: LiveMidiParser,
     columns: Vec<LiveNoteColumn>,
     default_track_colors: Vec<MIDIColor>,
+    program_colors: Option<Vec<MIDIColor>>,
     view_range: MIDIViewRange,
 }
 
@@ -25,7 +26,12 @@ impl<'a> LiveCurrentNoteViews<'a> {
 }
 
 impl LiveNoteViewData {
-    pub fn new(parser: LiveMidiParser, track_count: usize, random_colors: bool) -> Self {
+    pub fn new(
+        parser: LiveMidiParser,
+        track_count: usize,
+        random_colors: bool,
+        program_colors: Option<Vec<MIDIColor>>,
+    ) -> Self {
         let mut columns = Vec::with_capacity(256);
         columns.resize_with(256, LiveNoteColumn::new);
         LiveNoteViewData {
@@ -40,6 +46,7 @@ impl LiveNoteViewData {
             } else {
                 MIDIColor::new_vec_for_tracks(track_count)
             },
+            program_colors,
         }
     }
 
@@ -90,9 +97,17 @@ impl LiveNoteViewData {
         });
     }
 
+    pub fn view_range(&self) -> MIDIViewRange {
+        self.view_range
+    }
+
     pub fn parse_time(&self) -> f64 {
         self.parser.parse_time()
     }
+
+    pub fn track_count(&self) -> usize {
+        self.default_track_colors.len() / 16
+    }
 }
 
 pub struct LiveNoteColumnView<'a> {
@@ -127,6 +142,7 @@ impl<'a> MIDINoteColumnView for LiveNoteColumnView<'a> {
 
     fn iterate_displaced_notes(&self) -> Self::Iter<'_> {
         let colors = &self.view.default_track_colors;
+        let program_colors = self.view.program_colors.as_deref();
 
         let iter = GenIter(move || {
             for block_index in (0..self.column.data.end_block).rev() {
@@ -137,7 +153,15 @@ impl<'a> MIDINoteColumnView for LiveNoteColumnView<'a> {
                     yield DisplacedMIDINote {
                         start,
                         len: note.len,
-                        color: colors[note.track_chan.as_usize()],
+                        color: MIDIColor::for_note(
+                            colors,
+                            program_colors,
+                            note.track_chan,
+                            note.program,
+                        ),
+                        vel: note.vel,
+                        track: note.track_chan.track(),
+                        channel: note.track_chan.channel(),
                     };
                 }
             }
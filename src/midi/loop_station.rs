@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use midir::{Ignore, MidiInput, MidiInputConnection};
+
+use crate::{audio_playback::SimpleTemporaryPlayer, notifications};
+
+/// The most layers that can be recorded on top of each other
+pub const MAX_LOOP_LAYERS: usize = 8;
+
+/// The loops are always recorded in 4/4
+const BEATS_PER_MEASURE: f64 = 4.0;
+
+/// The resolution of the MIDI the layers are written to
+const LOOP_PPQ: u16 = 480;
+
+/// The layer that is being recorded, with the events packed the same way
+/// as for the synth and their time in seconds from `start`
+struct LoopTake {
+    start: Instant,
+    events: Vec<(f64, u32)>,
+}
+
+/// Records live MIDI input in layers of a fixed amount of measures. Every finished
+/// layer is written to a MIDI together with the earlier ones, which is then played
+/// back in a loop while the next layer is recorded on top of it.
+pub struct LoopStation {
+    measures: u32,
+    bpm: f64,
+    layers: Vec<Vec<(f64, u32)>>,
+    take: Arc<Mutex<LoopTake>>,
+    started: Instant,
+    path: PathBuf,
+    _connection: MidiInputConnection<()>,
+}
+
+impl LoopStation {
+    /// Connects to the input port with the given name, an empty name picks the first
+    /// available port. The input is also sent to the synth, so it can be heard while playing.
+    pub fn new(
+        port_name: &str,
+        measures: u32,
+        bpm: f64,
+        player: Arc<RwLock<SimpleTemporaryPlayer>>,
+    ) -> Option<Self> {
+        let mut input = match MidiInput::new("wasabi") {
+            Ok(input) => input,
+            Err(e) => {
+                notifications::error(format!("Failed to open MIDI input: {}", e));
+                return None;
+            }
+        };
+        input.ignore(Ignore::All);
+
+        let port = input.ports().into_iter().find(|port| {
+            port_name.is_empty() || input.port_name(port).ok().as_deref() == Some(port_name)
+        })?;
+
+        let started = Instant::now();
+        let take = Arc::new(Mutex::new(LoopTake {
+            start: started,
+            events: Vec::new(),
+        }));
+        let take_clone = take.clone();
+
+        let connection = input.connect(
+            &port,
+            "wasabi-loop",
+            move |_, message, _| {
+                // Only channel messages are recorded
+                let Some(&status) = message.first() else {
+                    return;
+                };
+                if !(0x80..0xF0).contains(&status) {
+                    return;
+                }
+                let data = message
+                    .iter()
+                    .take(3)
+                    .enumerate()
+                    .fold(0u32, |data, (i, byte)| data | ((*byte as u32) << (i * 8)));
+
+                player.write().unwrap().push_event(data);
+
+                let mut take = take_clone.lock().unwrap();
+                let time = take.start.elapsed().as_secs_f64();
+                take.events.push((time, data));
+            },
+            (),
+        );
+
+        match connection {
+            Ok(connection) => Some(LoopStation {
+                measures: measures.max(1),
+                bpm: bpm.max(1.0),
+                layers: Vec::new(),
+                take,
+                started,
+                path: std::env::temp_dir().join("wasabi-loop.mid"),
+                _connection: connection,
+            }),
+            Err(e) => {
+                notifications::error(format!("Failed to connect to MIDI input: {}", e));
+                None
+            }
+        }
+    }
+
+    fn beat_length(&self) -> f64 {
+        60.0 / self.bpm
+    }
+
+    /// The length of one loop in seconds
+    pub fn loop_length(&self) -> f64 {
+        self.beat_length() * BEATS_PER_MEASURE * self.measures as f64
+    }
+
+    /// How far into the loop the playback should be right now
+    pub fn position(&self) -> f64 {
+        self.started.elapsed().as_secs_f64() % self.loop_length()
+    }
+
+    /// The measure of the loop that is playing, starting at 1
+    pub fn measure(&self) -> u32 {
+        (self.position() / (self.beat_length() * BEATS_PER_MEASURE)) as u32 + 1
+    }
+
+    pub fn measures(&self) -> u32 {
+        self.measures
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.layers.len() < MAX_LOOP_LAYERS
+    }
+
+    /// Finishes the layer that is being recorded once a whole loop has passed. Returns
+    /// the path of the MIDI with all the layers when a new one was added to it.
+    pub fn update(&mut self) -> Option<PathBuf> {
+        if !self.is_recording() {
+            return None;
+        }
+
+        let length = self.loop_length();
+        let mut take = self.take.lock().unwrap();
+        if take.start.elapsed().as_secs_f64() < length {
+            return None;
+        }
+
+        // The next layer starts where this one ended, so the layers stay in time
+        take.start += Duration::from_secs_f64(length);
+        let events = std::mem::take(&mut take.events);
+        let (layer, next): (Vec<_>, Vec<_>) =
+            events.into_iter().partition(|(time, _)| *time < length);
+        take.events = next
+            .into_iter()
+            .map(|(time, data)| (time - length, data))
+            .collect();
+        drop(take);
+
+        // A loop without anything played isn't kept, the next one is recorded instead
+        if layer.is_empty() {
+            return None;
+        }
+        self.layers.push(layer);
+
+        match self.write_midi() {
+            Ok(()) => Some(self.path.clone()),
+            Err(e) => {
+                notifications::error(format!("Failed to write the loop layers: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Writes every layer as a track of a MIDI file, after a track with the tempo
+    fn write_midi(&self) -> io::Result<()> {
+        let ticks_per_second = LOOP_PPQ as f64 / self.beat_length();
+        let loop_ticks = (self.loop_length() * ticks_per_second).round() as u32;
+
+        let mut tempo_track = Vec::new();
+        let tempo = (60_000_000.0 / self.bpm).round() as u32;
+        write_var_len(&mut tempo_track, 0);
+        tempo_track.extend_from_slice(&[0xFF, 0x51, 0x03]);
+        tempo_track.extend_from_slice(&tempo.to_be_bytes()[1..]);
+        write_var_len(&mut tempo_track, loop_ticks);
+        tempo_track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+        let mut tracks = vec![tempo_track];
+        for layer in &self.layers {
+            let mut track = Vec::new();
+            let mut last_tick = 0;
+            // The notes that are still held at the end of the loop are ended there
+            let mut held: HashMap<(u8, u8), u32> = HashMap::new();
+
+            for (time, data) in layer {
+                let tick = ((time * ticks_per_second).round() as u32).min(loop_ticks);
+                let bytes = data.to_le_bytes();
+                let (status, key) = (bytes[0], bytes[1]);
+                let len = match status & 0xF0 {
+                    0xC0 | 0xD0 => 2,
+                    _ => 3,
+                };
+
+                match status & 0xF0 {
+                    0x90 if bytes[2] > 0 => *held.entry((status & 0x0F, key)).or_default() += 1,
+                    0x80 | 0x90 => {
+                        if let Some(count) = held.get_mut(&(status & 0x0F, key)) {
+                            *count = count.saturating_sub(1);
+                        }
+                    }
+                    _ => {}
+                }
+
+                write_var_len(&mut track, tick - last_tick);
+                track.extend_from_slice(&bytes[..len]);
+                last_tick = tick;
+            }
+
+            for ((channel, key), count) in held {
+                for _ in 0..count {
+                    write_var_len(&mut track, loop_ticks - last_tick);
+                    track.extend_from_slice(&[0x80 | channel, key, 0]);
+                    last_tick = loop_ticks;
+                }
+            }
+
+            write_var_len(&mut track, loop_ticks - last_tick);
+            track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+            tracks.push(track);
+        }
+
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        file.write_all(b"MThd")?;
+        file.write_all(&6u32.to_be_bytes())?;
+        file.write_all(&1u16.to_be_bytes())?;
+        file.write_all(&(tracks.len() as u16).to_be_bytes())?;
+        file.write_all(&LOOP_PPQ.to_be_bytes())?;
+        for track in tracks {
+            file.write_all(b"MTrk")?;
+            file.write_all(&(track.len() as u32).to_be_bytes())?;
+            file.write_all(&track)?;
+        }
+        file.flush()
+    }
+}
+
+/// Writes a number in the variable length format of MIDI files
+fn write_var_len(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
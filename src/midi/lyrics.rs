@@ -0,0 +1,64 @@
+/// A syllable is highlighted until the next one starts, but never for longer than this
+const MAX_SYLLABLE_LENGTH: f64 = 2.0;
+
+/// A part of the lyrics with its own lyric event, which is sung from its start to its end
+#[derive(Debug, Clone)]
+pub struct LyricSyllable {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// The syllables that are shown together on one line
+#[derive(Debug, Clone)]
+pub struct LyricLine {
+    pub start: f64,
+    pub end: f64,
+    pub syllables: Vec<LyricSyllable>,
+}
+
+/// Splits the lyric events, with their times in seconds, into lines. Karaoke files start
+/// a syllable with `/` for a new line and `\` for a new paragraph, while lyric events
+/// end the line with a carriage return or a line feed.
+pub fn lyric_lines(events: &[(f64, String)]) -> Vec<LyricLine> {
+    fn end_line(syllables: &mut Vec<LyricSyllable>, lines: &mut Vec<LyricLine>) {
+        if let (Some(first), Some(last)) = (syllables.first(), syllables.last()) {
+            lines.push(LyricLine {
+                start: first.start,
+                end: last.end,
+                syllables: std::mem::take(syllables),
+            });
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut syllables = Vec::new();
+    for (i, (start, text)) in events.iter().enumerate() {
+        let next = events.get(i + 1).map_or(f64::INFINITY, |(next, _)| *next);
+        let end = next.min(start + MAX_SYLLABLE_LENGTH);
+
+        let (new_line, text) = match text.strip_prefix(['/', '\\']) {
+            Some(text) => (true, text),
+            None => (false, text.as_str()),
+        };
+        let line_ends = text.ends_with(['\r', '\n']);
+        let text = text.trim_end_matches(['\r', '\n']);
+
+        if new_line {
+            end_line(&mut syllables, &mut lines);
+        }
+        if !text.is_empty() {
+            syllables.push(LyricSyllable {
+                start: *start,
+                end,
+                text: text.to_owned(),
+            });
+        }
+        if line_ends {
+            end_line(&mut syllables, &mut lines);
+        }
+    }
+    end_line(&mut syllables, &mut lines);
+
+    lines
+}
@@ -0,0 +1,439 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::{Arc, RwLock},
+    thread,
+};
+
+use image::RgbaImage;
+use midi_toolkit::{
+    events::{Event, MIDIEventEnum, TextEventKind},
+    io::MIDIFile as TKMIDIFile,
+    pipe,
+    sequence::{unwrap_items, TimeCaster},
+};
+use triggered::Listener;
+
+use super::{
+    album_art::find_album_art,
+    lyrics::{lyric_lines, LyricLine},
+    shared::repair::MIDIRepairStats,
+    tempo_detect::{detect_bpm, CHORD_WINDOW},
+    ControlChanges,
+};
+
+/// The most text events that are kept, some MIDIs have a lot of lyrics or markers
+const MAX_TEXT_EVENTS: usize = 1000;
+
+/// The tempo is detected from the start of the file, which is plenty for a guess
+const MAX_TEMPO_ONSETS: usize = 20000;
+
+/// Some black MIDIs set absurd tempos, where the beats of a range are far closer
+/// together than the pixels, so only this many of them are returned
+const MAX_BEATS_IN_RANGE: usize = 100000;
+
+#[derive(Debug, Clone)]
+pub struct TempoChange {
+    pub time: f64,
+    pub bpm: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeSignature {
+    pub time: f64,
+    pub numerator: u8,
+    pub denominator: u32,
+}
+
+/// A beat of the musical time, counted from 1 like on sheet music
+#[derive(Debug, Clone, Copy)]
+pub struct Beat {
+    pub time: f64,
+    pub bar: u32,
+    pub beat: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProgramChange {
+    pub time: f64,
+    pub channel: u8,
+    pub program: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextMetaEvent {
+    pub kind: String,
+    pub text: String,
+}
+
+/// The metadata of a loaded MIDI. The scan replaces it as a whole, so reading
+/// it only clones the inner `Arc` and not the lists of events.
+pub type SharedMetadata = Arc<RwLock<Arc<MIDIFileMetadata>>>;
+
+/// Information about a MIDI file, shown in the MIDI info dialog
+#[derive(Debug, Clone, Default)]
+pub struct MIDIFileMetadata {
+    pub file_name: String,
+    pub file_size: u64,
+    pub format: u16,
+    pub ppq: u16,
+    pub track_count: usize,
+    /// An image that was embedded in the file, scaled down to a thumbnail
+    pub album_art: Option<Arc<RgbaImage>>,
+    /// The fields below are filled in once the events have been scanned in the background
+    pub scanned: bool,
+    pub note_count: u64,
+    /// How many notes each key has, indexed by key
+    pub key_counts: Vec<u64>,
+    pub length: f64,
+    pub tempo_changes: Vec<TempoChange>,
+    pub time_signatures: Vec<TimeSignature>,
+    pub text_events: Vec<TextMetaEvent>,
+    /// What the repair pass changed, if it was enabled while loading
+    pub repairs: Option<MIDIRepairStats>,
+    /// Shared, since the metadata is cloned whenever it is read
+    pub control_changes: Arc<ControlChanges>,
+    /// The program changes in order of time, shared like the control changes
+    pub program_changes: Arc<Vec<ProgramChange>>,
+    /// The tempo guessed from the timing of the notes, for files without tempo events
+    pub detected_bpm: Option<f64>,
+    /// The lines of the lyric events, or of the text events of karaoke files
+    pub lyrics: Arc<Vec<LyricLine>>,
+    /// The beats of the tempo changes and time signatures above
+    pub beat_grid: BeatGrid,
+}
+
+impl MIDIFileMetadata {
+    /// Reads the header of a MIDI file right away and scans its events for the
+    /// rest of the metadata on a separate thread, until `cancel` is triggered
+    pub fn load(path: &Path, cancel: &Listener) -> SharedMetadata {
+        let mut metadata = MIDIFileMetadata {
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        if let Ok(mut file) = File::open(path) {
+            metadata.file_size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+            // "MThd", the header length, then the format, track count and division
+            let mut header = [0; 14];
+            if file.read_exact(&mut header).is_ok() && &header[0..4] == b"MThd" {
+                metadata.format = u16::from_be_bytes([header[8], header[9]]);
+                metadata.track_count = u16::from_be_bytes([header[10], header[11]]) as usize;
+                metadata.ppq = u16::from_be_bytes([header[12], header[13]]);
+            }
+        }
+
+        let metadata = Arc::new(RwLock::new(Arc::new(metadata)));
+
+        let path = path.to_owned();
+        let metadata_clone = metadata.clone();
+        let cancel = cancel.clone();
+        thread::spawn(move || {
+            // The art is only at the start of the tracks, so it is found before the events
+            if let Some(art) = find_album_art(&path) {
+                Arc::make_mut(&mut metadata_clone.write().unwrap()).album_art = Some(Arc::new(art));
+            }
+
+            if let Some(events) = scan_events(&path, &cancel) {
+                let mut metadata = metadata_clone.write().unwrap();
+                let metadata = Arc::make_mut(&mut metadata);
+                metadata.note_count = events.note_count;
+                metadata.key_counts = events.key_counts.to_vec();
+                metadata.length = events.length;
+                metadata.beat_grid = BeatGrid::new(&events.tempo_changes, &events.time_signatures);
+                metadata.tempo_changes = events.tempo_changes;
+                metadata.time_signatures = events.time_signatures;
+                metadata.text_events = events.text_events;
+                metadata.control_changes = Arc::new(events.control_changes);
+                metadata.program_changes = Arc::new(events.program_changes);
+                metadata.detected_bpm = detect_bpm(&events.onsets);
+                metadata.lyrics = Arc::new(events.lyrics);
+                metadata.scanned = true;
+            }
+        });
+
+        metadata
+    }
+
+    /// The tempo at a time in seconds, 120 BPM before the first tempo change
+    pub fn bpm_at(&self, time: f64) -> f64 {
+        let index = self
+            .tempo_changes
+            .partition_point(|change| change.time <= time);
+        index
+            .checked_sub(1)
+            .map_or(120.0, |index| self.tempo_changes[index].bpm)
+    }
+
+    /// The beats between two times in seconds, following the tempo changes and the time
+    /// signatures, which are 4/4 before the first one. A new time signature starts a bar.
+    pub fn beats_between(&self, start: f64, end: f64) -> Vec<Beat> {
+        self.beat_grid.beats_between(start, end)
+    }
+}
+
+/// A stretch of the file with the same time signature, in quarter notes
+#[derive(Debug, Clone)]
+struct BeatSegment {
+    start: f64,
+    bar: u32,
+    numerator: u32,
+    /// The length of a beat in quarter notes
+    beat_length: f64,
+}
+
+/// Where the beats and bars of a file are, worked out once from its tempo map. The times
+/// are turned into quarter notes with the tempo changes, where the beats are evenly spaced
+/// between the time signatures, so finding the beats of a range is a binary search.
+#[derive(Debug, Clone)]
+pub struct BeatGrid {
+    /// The tempo changes, with the quarter notes before each of them
+    tempos: Vec<(TempoChange, f64)>,
+    segments: Vec<BeatSegment>,
+}
+
+impl Default for BeatGrid {
+    fn default() -> Self {
+        Self::new(&[], &[])
+    }
+}
+
+impl BeatGrid {
+    pub fn new(tempo_changes: &[TempoChange], time_signatures: &[TimeSignature]) -> Self {
+        let mut grid = BeatGrid {
+            tempos: Vec::with_capacity(tempo_changes.len()),
+            segments: vec![BeatSegment {
+                start: 0.0,
+                bar: 1,
+                numerator: 4,
+                beat_length: 1.0,
+            }],
+        };
+
+        // Tempo events of 0 would stop the time, so they are left out
+        for change in tempo_changes
+            .iter()
+            .filter(|c| c.bpm.is_finite() && c.bpm > 0.0)
+        {
+            let quarters = grid.time_to_quarters(change.time);
+            grid.tempos.push((change.clone(), quarters));
+        }
+
+        for signature in time_signatures {
+            let quarters = grid.time_to_quarters(signature.time);
+            let last = grid.segments.last().unwrap();
+
+            // Time signatures that fall between two beats take effect on the later one
+            let beat = ((quarters - last.start) / last.beat_length - 1e-6)
+                .ceil()
+                .max(0.0);
+            let bar = last.bar + (beat as u32).div_ceil(last.numerator);
+            let segment = BeatSegment {
+                start: last.start + beat * last.beat_length,
+                bar,
+                numerator: signature.numerator.max(1) as u32,
+                beat_length: 4.0 / signature.denominator.max(1) as f64,
+            };
+
+            if beat == 0.0 {
+                *grid.segments.last_mut().unwrap() = segment;
+            } else {
+                grid.segments.push(segment);
+            }
+        }
+
+        grid
+    }
+
+    /// The tempo change in effect at a point, found with the given key
+    fn tempo_before(&self, key: impl Fn(&(TempoChange, f64)) -> bool) -> (f64, f64, f64) {
+        let index = self.tempos.partition_point(key);
+        match index.checked_sub(1) {
+            Some(index) => {
+                let (change, quarters) = &self.tempos[index];
+                (change.time, *quarters, change.bpm)
+            }
+            None => (0.0, 0.0, 120.0),
+        }
+    }
+
+    fn time_to_quarters(&self, time: f64) -> f64 {
+        let (start, quarters, bpm) = self.tempo_before(|(change, _)| change.time <= time);
+        quarters + (time - start) * bpm / 60.0
+    }
+
+    fn quarters_to_time(&self, quarters: f64) -> f64 {
+        let (start, start_quarters, bpm) = self.tempo_before(|(_, q)| *q <= quarters);
+        start + (quarters - start_quarters) * 60.0 / bpm
+    }
+
+    /// The beats between two times in seconds
+    pub fn beats_between(&self, start: f64, end: f64) -> Vec<Beat> {
+        let start_quarters = self.time_to_quarters(start.max(0.0));
+        let end_quarters = self.time_to_quarters(end);
+
+        let first = self
+            .segments
+            .partition_point(|segment| segment.start <= start_quarters)
+            .saturating_sub(1);
+
+        let mut beats = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate().skip(first) {
+            if segment.start > end_quarters {
+                break;
+            }
+            let segment_end = self
+                .segments
+                .get(i + 1)
+                .map_or(f64::INFINITY, |next| next.start);
+
+            let mut beat = ((start_quarters - segment.start) / segment.beat_length - 1e-6)
+                .ceil()
+                .max(0.0) as u64;
+            loop {
+                let quarters = segment.start + beat as f64 * segment.beat_length;
+                if quarters > end_quarters || quarters >= segment_end - 1e-6 {
+                    break;
+                }
+                if beats.len() >= MAX_BEATS_IN_RANGE {
+                    return beats;
+                }
+
+                beats.push(Beat {
+                    time: self.quarters_to_time(quarters),
+                    bar: segment.bar + (beat / segment.numerator as u64) as u32,
+                    beat: (beat % segment.numerator as u64) as u32 + 1,
+                });
+                beat += 1;
+            }
+        }
+
+        beats
+    }
+}
+
+struct ScannedEvents {
+    note_count: u64,
+    key_counts: [u64; 128],
+    length: f64,
+    tempo_changes: Vec<TempoChange>,
+    time_signatures: Vec<TimeSignature>,
+    text_events: Vec<TextMetaEvent>,
+    control_changes: ControlChanges,
+    program_changes: Vec<ProgramChange>,
+    /// The times that notes start at, once per chord
+    onsets: Vec<f64>,
+    lyrics: Vec<LyricLine>,
+}
+
+fn scan_events(path: &Path, cancel: &Listener) -> Option<ScannedEvents> {
+    let file = File::open(path).ok()?;
+    let midi = TKMIDIFile::open_from_stream(file, None).ok()?;
+
+    let ppq = midi.ppq() as f64;
+    let merged = pipe!(
+        midi.iter_all_track_events_merged_batches()
+        |>TimeCaster::<f64>::cast_event_delta()
+        |>unwrap_items()
+    );
+
+    let mut events = ScannedEvents {
+        note_count: 0,
+        key_counts: [0; 128],
+        length: 0.0,
+        tempo_changes: Vec::new(),
+        time_signatures: Vec::new(),
+        text_events: Vec::new(),
+        control_changes: ControlChanges::default(),
+        program_changes: Vec::new(),
+        onsets: Vec::new(),
+        lyrics: Vec::new(),
+    };
+
+    // Karaoke files have their lyrics in text events, after a header of `@` tags
+    let mut lyric_events = Vec::new();
+    let mut karaoke_events = Vec::new();
+    let mut karaoke = false;
+
+    // The deltas are in ticks, so the time is kept in seconds with the current tempo
+    let mut tempo = 500000.0;
+    let mut time = 0.0;
+
+    for batch in merged {
+        if cancel.is_triggered() {
+            return None;
+        }
+        time += batch.delta / ppq * tempo / 1_000_000.0;
+
+        for event in batch.iter_events() {
+            match event.as_event() {
+                Event::NoteOn(e) if e.velocity > 0 => {
+                    events.note_count += 1;
+                    events.key_counts[e.key as usize & 0x7F] += 1;
+
+                    let new_onset = events
+                        .onsets
+                        .last()
+                        .map_or(true, |last| time - last > CHORD_WINDOW);
+                    if new_onset && events.onsets.len() < MAX_TEMPO_ONSETS {
+                        events.onsets.push(time);
+                    }
+                }
+                Event::Tempo(e) => {
+                    tempo = e.tempo as f64;
+                    events.tempo_changes.push(TempoChange {
+                        time,
+                        bpm: 60_000_000.0 / tempo,
+                    });
+                }
+                Event::TimeSignature(e) => events.time_signatures.push(TimeSignature {
+                    time,
+                    numerator: e.numerator,
+                    denominator: 1 << e.denominator,
+                }),
+                Event::ControlChange(e) => {
+                    events
+                        .control_changes
+                        .push(e.channel, e.controller, time, e.value)
+                }
+                Event::ProgramChange(e) => events.program_changes.push(ProgramChange {
+                    time,
+                    channel: e.channel,
+                    program: e.program,
+                }),
+                Event::Text(e) => {
+                    let text = String::from_utf8_lossy(&e.bytes).into_owned();
+                    match e.kind {
+                        TextEventKind::Lyric => lyric_events.push((time, text.clone())),
+                        TextEventKind::TextEvent if text.starts_with('@') => karaoke = true,
+                        TextEventKind::TextEvent => karaoke_events.push((time, text.clone())),
+                        _ => {}
+                    }
+
+                    if events.text_events.len() < MAX_TEXT_EVENTS {
+                        events.text_events.push(TextMetaEvent {
+                            kind: format!("{:?}", e.kind),
+                            text,
+                        })
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    events.length = time;
+    events.lyrics = if !lyric_events.is_empty() {
+        lyric_lines(&lyric_events)
+    } else if karaoke {
+        lyric_lines(&karaoke_events)
+    } else {
+        Vec::new()
+    };
+    Some(events)
+}
@@ -5,20 +5,53 @@ mod live;
 #[allow(dead_code)]
 mod ram;
 
+mod album_art;
+mod analyze;
+mod archive;
 mod audio;
+mod clock;
+mod control_changes;
+mod edit;
+mod gzip;
+mod loop_station;
+mod lyrics;
+mod metadata;
+mod musicxml;
 
 mod shared;
-use std::{fs::File, time::UNIX_EPOCH};
+mod sop;
+mod tempo_detect;
+use std::{
+    fmt,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
 
 use enum_dispatch::enum_dispatch;
+use midi_toolkit::io::MIDILoadError;
 use palette::{convert::FromColorUnclamped, Hsv, Srgb};
 use rand::Rng;
 
+pub use analyze::analyze_midi;
+pub use archive::{extract_zip_midi, is_zip_archive, list_zip_midis};
 pub use cake::{blocks::CakeBlock, intvec4::IntVector4, CakeMIDIFile, CakeSignature};
+pub use clock::MIDIClockSync;
+pub use control_changes::ControlChanges;
+pub use edit::{MIDISidecar, NoteEdit};
+pub use gzip::{decompress_gzip_midi, is_gzip_midi};
 pub use live::LiveLoadMIDIFile;
-pub use ram::{InRamMIDIFile, MIDIFileStats};
+pub use loop_station::{LoopStation, MAX_LOOP_LAYERS};
+pub use lyrics::LyricLine;
+pub use metadata::{Beat, MIDIFileMetadata, ProgramChange, SharedMetadata};
+pub use musicxml::{export_musicxml, MUSICXML_GRIDS};
+pub use ram::{view::EditableNote, InRamMIDIFile, MIDIFileStats};
+pub use shared::audio::CompressedAudio;
+pub use sop::{validate_sop, SopViolation, MAX_SOP_VIOLATIONS};
 
-use self::shared::timer::TimeKeeper;
+use self::shared::{timer::TimeKeeper, track_channel::TrackAndChannel};
 
 /// A struct that represents the view range of a midi screen render
 #[derive(Debug, Clone, Copy, Default)]
@@ -39,30 +72,48 @@ impl MIDIViewRange {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MIDIFileUniqueSignature {
-    pub filepath: String,
+    pub filepath: PathBuf,
     pub length_in_bytes: u64,
     pub last_modified: u128,
 }
 
-fn open_file_and_signature(path: &str) -> (File, MIDIFileUniqueSignature) {
-    let file = std::fs::File::open(path).unwrap();
-    let file_length = file.metadata().unwrap().len();
-    let file_last_modified = file
-        .metadata()
-        .unwrap()
+/// Why a MIDI couldn't be loaded
+#[derive(Debug)]
+pub enum MIDIOpenError {
+    /// The load was cancelled before it finished
+    Cancelled,
+    Open(io::Error),
+    Parse(MIDILoadError),
+}
+
+impl fmt::Display for MIDIOpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MIDIOpenError::Cancelled => write!(f, "The load was cancelled"),
+            MIDIOpenError::Open(e) => write!(f, "{}", e),
+            MIDIOpenError::Parse(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+
+fn open_file_and_signature(path: &Path) -> Result<(File, MIDIFileUniqueSignature), MIDIOpenError> {
+    let file = std::fs::File::open(path).map_err(MIDIOpenError::Open)?;
+    let metadata = file.metadata().map_err(MIDIOpenError::Open)?;
+    let file_length = metadata.len();
+    let file_last_modified = metadata
         .modified()
-        .unwrap()
+        .map_err(MIDIOpenError::Open)?
         .duration_since(UNIX_EPOCH)
-        .unwrap()
+        .unwrap_or_default()
         .as_micros();
 
     let signature = MIDIFileUniqueSignature {
-        filepath: path.to_string(),
+        filepath: path.to_owned(),
         length_in_bytes: file_length,
         last_modified: file_last_modified,
     };
 
-    (file, signature)
+    Ok((file, signature))
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -112,6 +163,37 @@ impl MIDIColor {
         vec
     }
 
+    /// The color of the program the note was played with if program colors are
+    /// enabled and the channel had a program change, otherwise the track color
+    pub fn for_note(
+        track_colors: &[MIDIColor],
+        program_colors: Option<&[MIDIColor]>,
+        track_chan: TrackAndChannel,
+        program: Option<u8>,
+    ) -> Self {
+        program_colors
+            .zip(program)
+            .and_then(|(program_colors, program)| program_colors.get(program as usize).copied())
+            .unwrap_or_else(|| track_colors[track_chan.as_usize()])
+    }
+
+    /// The same color with its hue turned around the color wheel by the given degrees
+    pub fn rotate_hue(&self, degrees: f64) -> Self {
+        let rgb: Srgb<f64> = Srgb::new(
+            self.red() as f64 / 255.0,
+            self.green() as f64 / 255.0,
+            self.blue() as f64 / 255.0,
+        );
+        let mut hsv: Hsv<Srgb, f64> = Hsv::from_color_unclamped(rgb);
+        hsv.hue += degrees;
+        let rgb = Srgb::from_color_unclamped(hsv);
+        Self::new(
+            (rgb.red * 255.0) as u8,
+            (rgb.green * 255.0) as u8,
+            (rgb.blue * 255.0) as u8,
+        )
+    }
+
     pub fn as_u32(&self) -> u32 {
         self.0
     }
@@ -148,6 +230,33 @@ pub trait MIDIFileBase {
     fn allows_seeking_backward(&self) -> bool;
 
     fn signature(&self) -> &MIDIFileUniqueSignature;
+
+    /// Whether the MIDI was loaded to be played backwards
+    fn is_reversed(&self) -> bool;
+
+    fn track_count(&self) -> usize;
+
+    /// The metadata as it is now, which is only partly filled in until the scan finished
+    fn metadata(&self) -> Arc<MIDIFileMetadata>;
+
+    /// The control changes found while scanning the metadata, which is empty until the scan finished
+    fn control_changes(&self) -> Arc<ControlChanges>;
+
+    /// The program changes found while scanning the metadata, which is empty until the scan finished
+    fn program_changes(&self) -> Arc<Vec<ProgramChange>>;
+
+    /// The lines of the lyrics found while scanning the metadata, which is empty until the scan finished
+    fn lyrics(&self) -> Arc<Vec<LyricLine>>;
+
+    /// The tempo at a time in seconds, which is 120 BPM until the metadata scan finished
+    fn bpm_at(&self, time: f64) -> f64;
+
+    /// The beats between two times in seconds, which are at 120 BPM in 4/4 until the
+    /// metadata scan finished
+    fn beats_between(&self, start: f64, end: f64) -> Vec<Beat>;
+
+    /// The ticks per quarter note from the header of the file
+    fn ppq(&self) -> u16;
 }
 
 /// This trait contains a function to retrieve the column view of the midi
@@ -156,7 +265,8 @@ pub trait MIDIFile: MIDIFileBase {
     where
         Self: 'a;
 
-    fn get_current_column_views(&mut self, range: f64) -> Self::ColumnsViews<'_>;
+    /// The notes from `lookback` seconds before the current time up to `range` seconds after it
+    fn get_current_column_views(&mut self, range: f64, lookback: f64) -> Self::ColumnsViews<'_>;
 }
 
 pub trait MIDINoteViews {
@@ -173,6 +283,8 @@ pub trait MIDINoteColumnView: Send {
     where
         Self: 'a;
 
+    /// The notes of the column in the view, from the latest start back to the earliest.
+    /// Trimmed notes keep the place of their start before the trim.
     fn iterate_displaced_notes(&self) -> Self::Iter<'_>;
 }
 
@@ -180,6 +292,9 @@ pub struct DisplacedMIDINote {
     pub start: f32,
     pub len: f32,
     pub color: MIDIColor,
+    pub vel: u8,
+    pub track: u32,
+    pub channel: u8,
 }
 
 #[enum_dispatch(MIDIFileBase)]
@@ -0,0 +1,508 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use midi_toolkit::{
+    events::{Event, MIDIEventEnum},
+    io::MIDIFile as TKMIDIFile,
+    pipe,
+    sequence::{unwrap_items, TimeCaster},
+};
+use rustc_hash::FxHashMap;
+
+use super::{decompress_gzip_midi, is_gzip_midi};
+
+/// The note values the grid can be set to, as the fraction of a whole note
+pub const MUSICXML_GRIDS: [u32; 5] = [4, 8, 16, 32, 64];
+
+/// The names of the note types in MusicXML, from a whole note down to a 64th
+const NOTE_TYPES: [&str; 7] = ["whole", "half", "quarter", "eighth", "16th", "32nd", "64th"];
+
+/// The step and alter of every key in an octave, spelled with sharps
+const STEPS: [(&str, i8); 12] = [
+    ("C", 0),
+    ("C", 1),
+    ("D", 0),
+    ("D", 1),
+    ("E", 0),
+    ("F", 0),
+    ("F", 1),
+    ("G", 0),
+    ("G", 1),
+    ("A", 0),
+    ("A", 1),
+    ("B", 0),
+];
+
+/// A note with its start and end in quarter notes, before it is snapped to the grid
+struct HeldNote {
+    start: f64,
+    end: f64,
+    key: u8,
+}
+
+/// A note with its start and end snapped to the grid, in divisions
+#[derive(Clone, Copy)]
+struct GridNote {
+    start: u64,
+    end: u64,
+    key: u8,
+}
+
+/// A chord or a rest, split so that it fits inside of one measure
+struct Element {
+    start: u64,
+    duration: u64,
+    /// Empty for a rest
+    keys: Vec<u8>,
+    tie_start: bool,
+    tie_stop: bool,
+}
+
+/// A piece of an element that can be written as a single note type
+struct Piece {
+    start: u64,
+    duration: u64,
+    /// The index into [`NOTE_TYPES`]
+    note_type: usize,
+    dotted: bool,
+}
+
+/// Reads every note of a MIDI file and writes it as MusicXML 4.0, with one part for
+/// every track that has notes. The notes are snapped to the grid, which is given as
+/// a fraction of a whole note, so `16` snaps them to 16th notes.
+///
+/// The conversion is kept simple: each part is a single voice where notes that start
+/// together become a chord, and a chord is cut off when the next one starts. The time
+/// signature and tempo are the first ones in the MIDI.
+pub fn export_musicxml(midi_path: &Path, output: &Path, grid: u32) -> Result<(), String> {
+    if !MUSICXML_GRIDS.contains(&grid) {
+        return Err(format!(
+            "{} is not a valid grid. Expected one of {:?}",
+            grid, MUSICXML_GRIDS
+        ));
+    }
+
+    // Nothing cancels the export, it runs until it is done
+    let (_cancel, listener) = triggered::trigger();
+    // The decompressed copy is deleted when this is dropped, once the file was read
+    let decompressed = if is_gzip_midi(midi_path) {
+        Some(
+            decompress_gzip_midi(midi_path.to_path_buf(), listener)
+                .recv()
+                .ok()
+                .flatten()
+                .ok_or_else(|| format!("Failed to decompress {}", midi_path.display()))?,
+        )
+    } else {
+        None
+    };
+    let midi_path = decompressed.as_deref().unwrap_or(midi_path);
+
+    let file = File::open(midi_path).map_err(|e| format!("Failed to open MIDI: {}", e))?;
+    let midi = TKMIDIFile::open_from_stream(file, None)
+        .map_err(|e| format!("Failed to parse MIDI: {:?}", e))?;
+
+    let ppq = midi.ppq() as f64;
+    let merged = pipe!(
+        midi.iter_all_track_events_merged_batches()
+        |>TimeCaster::<f64>::cast_event_delta()
+        |>unwrap_items()
+    );
+
+    // Sorted so the parts come out in the order of the tracks
+    let mut held_notes: BTreeMap<u32, Vec<HeldNote>> = BTreeMap::new();
+    // The start times of the held notes, the first note on is ended by the first note off
+    let mut held: FxHashMap<(u32, u8, u8), VecDeque<f64>> = FxHashMap::default();
+    let mut time_signature = None;
+    let mut tempo = None;
+    let mut time = 0.0;
+
+    for batch in merged {
+        time += batch.delta;
+        for event in batch.iter_events() {
+            let (channel, key) = match event.as_event() {
+                Event::NoteOn(e) if e.velocity > 0 => {
+                    held.entry((event.track, e.channel, e.key))
+                        .or_default()
+                        .push_back(time);
+                    continue;
+                }
+                Event::NoteOn(e) => (e.channel, e.key),
+                Event::NoteOff(e) => (e.channel, e.key),
+                Event::TimeSignature(e) => {
+                    time_signature
+                        .get_or_insert((e.numerator as u64, 1u64 << e.denominator.min(6)));
+                    continue;
+                }
+                Event::Tempo(e) => {
+                    tempo.get_or_insert(60_000_000.0 / e.tempo as f64);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let start = held
+                .get_mut(&(event.track, channel, key))
+                .and_then(|starts| starts.pop_front());
+            if let Some(start) = start {
+                held_notes.entry(event.track).or_default().push(HeldNote {
+                    start: start / ppq,
+                    end: time / ppq,
+                    key,
+                });
+            }
+        }
+    }
+
+    // Notes that never get a note off last until the end of the MIDI
+    for ((track, _, key), starts) in held {
+        for start in starts {
+            held_notes.entry(track).or_default().push(HeldNote {
+                start: start / ppq,
+                end: time / ppq,
+                key,
+            });
+        }
+    }
+
+    // MusicXML counts the divisions per quarter note. Both the grid and the beat type are
+    // powers of two, so counting in the shorter of them makes a grid step and a measure
+    // whole numbers of divisions, like 7/16 with a quarter note grid counting 16ths.
+    let (beats, beat_type) = time_signature.unwrap_or((4, 4));
+    let divisions = (grid as u64).max(beat_type) / 4;
+    let divisions = divisions.max(1);
+    let grid_step = divisions * 4 / grid as u64;
+    let snap = |quarters: f64| (quarters * grid as f64 / 4.0).round() as u64 * grid_step;
+
+    let mut tracks: BTreeMap<u32, Vec<GridNote>> = held_notes
+        .into_iter()
+        .map(|(track, notes)| {
+            let notes = notes
+                .into_iter()
+                .map(|note| GridNote {
+                    start: snap(note.start),
+                    end: snap(note.end),
+                    key: note.key,
+                })
+                .collect();
+            (track, notes)
+        })
+        .collect();
+
+    // Notes shorter than half a grid step disappear when snapped
+    for notes in tracks.values_mut() {
+        notes.retain(|note| note.end > note.start);
+        notes.sort_by_key(|note| (note.start, note.key));
+    }
+    tracks.retain(|_, notes| !notes.is_empty());
+
+    let measure_length = (beats * divisions * 4 / beat_type).max(1);
+    // The beams are grouped by beat, or by dotted quarter in compound meters
+    let beat_length = if beat_type == 8 && beats % 3 == 0 && beats > 3 {
+        divisions * 3 / 2
+    } else {
+        (divisions * 4 / beat_type).max(1)
+    };
+
+    let file = File::create(output).map_err(|e| format!("Failed to create MusicXML: {}", e))?;
+    let mut out = BufWriter::new(file);
+    let write_error = |e: std::io::Error| format!("Failed to write MusicXML: {}", e);
+
+    writeln!(
+        out,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#
+    )
+    .map_err(write_error)?;
+    writeln!(
+        out,
+        r#"<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">"#
+    )
+    .map_err(write_error)?;
+    writeln!(out, r#"<score-partwise version="4.0">"#).map_err(write_error)?;
+
+    writeln!(out, "  <part-list>").map_err(write_error)?;
+    for track in tracks.keys() {
+        writeln!(out, r#"    <score-part id="P{}">"#, track + 1).map_err(write_error)?;
+        writeln!(out, "      <part-name>Track {}</part-name>", track + 1).map_err(write_error)?;
+        writeln!(out, "    </score-part>").map_err(write_error)?;
+    }
+    writeln!(out, "  </part-list>").map_err(write_error)?;
+
+    // Every part gets the same number of measures, so they line up
+    let end = tracks
+        .values()
+        .flat_map(|notes| notes.iter().map(|note| note.end))
+        .max()
+        .unwrap_or(0);
+    let measures = end.div_ceil(measure_length).max(1);
+
+    for (track, notes) in tracks.iter() {
+        let elements =
+            split_into_measures(&chords(notes), measures * measure_length, measure_length);
+
+        // Most of the notes of a track are usually on one side of middle C
+        let average_key =
+            notes.iter().map(|note| note.key as u64).sum::<u64>() / notes.len() as u64;
+        let clef = if average_key >= 60 {
+            ("G", 2)
+        } else {
+            ("F", 4)
+        };
+
+        writeln!(out, r#"  <part id="P{}">"#, track + 1).map_err(write_error)?;
+        let mut elements = elements.iter().peekable();
+        for measure in 0..measures {
+            writeln!(out, r#"    <measure number="{}">"#, measure + 1).map_err(write_error)?;
+            if measure == 0 {
+                write_attributes(&mut out, divisions, (beats, beat_type), clef)
+                    .map_err(write_error)?;
+                if let Some(tempo) = tempo {
+                    writeln!(out, r#"      <sound tempo="{:.2}"/>"#, tempo).map_err(write_error)?;
+                }
+            }
+
+            let measure_end = (measure + 1) * measure_length;
+            let mut pieces = Vec::new();
+            while let Some(element) = elements.next_if(|element| element.start < measure_end) {
+                for piece in split_into_pieces(element.start, element.duration, divisions) {
+                    pieces.push((element, piece));
+                }
+            }
+
+            let beams = beams(&pieces, beat_length);
+            for (i, (element, piece)) in pieces.iter().enumerate() {
+                // Pieces of a chord after the first are tied to the one before, and all
+                // but the last are tied to the one after
+                let first = i == 0 || !std::ptr::eq(pieces[i - 1].0, *element);
+                let last = i + 1 == pieces.len() || !std::ptr::eq(pieces[i + 1].0, *element);
+                let is_chord = !element.keys.is_empty();
+                let tie_stop = is_chord && (!first || element.tie_stop);
+                let tie_start = is_chord && (!last || element.tie_start);
+
+                write_note(&mut out, element, piece, tie_start, tie_stop, beams[i])
+                    .map_err(write_error)?;
+            }
+
+            writeln!(out, "    </measure>").map_err(write_error)?;
+        }
+        writeln!(out, "  </part>").map_err(write_error)?;
+    }
+
+    writeln!(out, "</score-partwise>").map_err(write_error)?;
+    out.flush().map_err(write_error)?;
+
+    Ok(())
+}
+
+/// Groups the notes that start together into chords, each lasting until its longest
+/// note ends or the next chord starts. The gaps between them are filled with rests.
+fn chords(notes: &[GridNote]) -> Vec<(u64, u64, Vec<u8>)> {
+    let mut chords: Vec<(u64, u64, Vec<u8>)> = Vec::new();
+    for note in notes {
+        match chords.last_mut() {
+            Some((start, end, keys)) if *start == note.start => {
+                *end = (*end).max(note.end);
+                if !keys.contains(&note.key) {
+                    keys.push(note.key);
+                }
+            }
+            _ => chords.push((note.start, note.end, vec![note.key])),
+        }
+    }
+
+    let mut filled = Vec::with_capacity(chords.len() * 2);
+    let mut time = 0;
+    for (i, (start, end, keys)) in chords.iter().enumerate() {
+        if *start > time {
+            filled.push((time, *start, Vec::new()));
+        }
+        let end = match chords.get(i + 1) {
+            Some((next, _, _)) => (*end).min(*next),
+            None => *end,
+        };
+        filled.push((*start, end, keys.clone()));
+        time = end;
+    }
+    filled
+}
+
+/// Splits the chords and rests at the bar lines, tying the chords across them, and
+/// fills the rest of the last measure with a rest
+fn split_into_measures(
+    chords: &[(u64, u64, Vec<u8>)],
+    end: u64,
+    measure_length: u64,
+) -> Vec<Element> {
+    let mut elements = Vec::new();
+    let last_end = chords.last().map_or(0, |(_, end, _)| *end);
+    let rest = (last_end < end).then_some((last_end, end, Vec::new()));
+
+    for (start, end, keys) in chords.iter().chain(rest.iter()) {
+        let mut time = *start;
+        while time < *end {
+            let bar_line = (time / measure_length + 1) * measure_length;
+            let piece_end = bar_line.min(*end);
+            elements.push(Element {
+                start: time,
+                duration: piece_end - time,
+                keys: keys.clone(),
+                tie_start: !keys.is_empty() && piece_end < *end,
+                tie_stop: !keys.is_empty() && time > *start,
+            });
+            time = piece_end;
+        }
+    }
+    elements
+}
+
+/// Splits a duration into note types that are written out one after another, using the
+/// longest type that fits first and a dot where it fits too
+fn split_into_pieces(start: u64, duration: u64, divisions: u64) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut time = start;
+    let mut left = duration;
+
+    while left > 0 {
+        let (note_type, length) = (0..NOTE_TYPES.len())
+            .map(|i| (i, divisions * 4 >> i))
+            .find(|(_, length)| *length > 0 && *length <= left)
+            .unwrap_or((NOTE_TYPES.len() - 1, left));
+
+        let dotted = length % 2 == 0 && length + length / 2 <= left;
+        let length = if dotted { length + length / 2 } else { length };
+
+        pieces.push(Piece {
+            start: time,
+            duration: length,
+            note_type,
+            dotted,
+        });
+        time += length;
+        left -= length;
+    }
+    pieces
+}
+
+/// The beam of every piece: chords of eighth notes and shorter that follow each other
+/// inside of the same beat are beamed together
+fn beams(pieces: &[(&Element, Piece)], beat_length: u64) -> Vec<Option<&'static str>> {
+    let beamable =
+        |(element, piece): &(&Element, Piece)| !element.keys.is_empty() && piece.note_type >= 3;
+
+    let mut beams = vec![None; pieces.len()];
+    let mut i = 0;
+    while i < pieces.len() {
+        let beat = pieces[i].1.start / beat_length;
+        let mut j = i;
+        while j < pieces.len()
+            && beamable(&pieces[j])
+            && pieces[j].1.start / beat_length == beat
+            && (pieces[j].1.start + pieces[j].1.duration - 1) / beat_length == beat
+        {
+            j += 1;
+        }
+
+        if j - i >= 2 {
+            beams[i] = Some("begin");
+            for beam in beams.iter_mut().take(j - 1).skip(i + 1) {
+                *beam = Some("continue");
+            }
+            beams[j - 1] = Some("end");
+        }
+        i = j.max(i + 1);
+    }
+    beams
+}
+
+fn write_attributes(
+    out: &mut impl Write,
+    divisions: u64,
+    (beats, beat_type): (u64, u64),
+    (sign, line): (&str, u8),
+) -> std::io::Result<()> {
+    writeln!(out, "      <attributes>")?;
+    writeln!(out, "        <divisions>{}</divisions>", divisions)?;
+    writeln!(out, "        <key><fifths>0</fifths></key>")?;
+    writeln!(
+        out,
+        "        <time><beats>{}</beats><beat-type>{}</beat-type></time>",
+        beats, beat_type
+    )?;
+    writeln!(
+        out,
+        "        <clef><sign>{}</sign><line>{}</line></clef>",
+        sign, line
+    )?;
+    writeln!(out, "      </attributes>")
+}
+
+fn write_note(
+    out: &mut impl Write,
+    element: &Element,
+    piece: &Piece,
+    tie_start: bool,
+    tie_stop: bool,
+    beam: Option<&str>,
+) -> std::io::Result<()> {
+    let write_common = |out: &mut dyn Write| -> std::io::Result<()> {
+        writeln!(out, "        <duration>{}</duration>", piece.duration)?;
+        if tie_stop {
+            writeln!(out, r#"        <tie type="stop"/>"#)?;
+        }
+        if tie_start {
+            writeln!(out, r#"        <tie type="start"/>"#)?;
+        }
+        writeln!(out, "        <type>{}</type>", NOTE_TYPES[piece.note_type])?;
+        if piece.dotted {
+            writeln!(out, "        <dot/>")?;
+        }
+        Ok(())
+    };
+
+    if element.keys.is_empty() {
+        writeln!(out, "      <note>")?;
+        writeln!(out, "        <rest/>")?;
+        write_common(out)?;
+        return writeln!(out, "      </note>");
+    }
+
+    for (i, key) in element.keys.iter().enumerate() {
+        let (step, alter) = STEPS[*key as usize % 12];
+        let octave = *key as i32 / 12 - 1;
+
+        writeln!(out, "      <note>")?;
+        if i > 0 {
+            writeln!(out, "        <chord/>")?;
+        }
+        writeln!(out, "        <pitch>")?;
+        writeln!(out, "          <step>{}</step>", step)?;
+        if alter != 0 {
+            writeln!(out, "          <alter>{}</alter>", alter)?;
+        }
+        writeln!(out, "          <octave>{}</octave>", octave)?;
+        writeln!(out, "        </pitch>")?;
+        write_common(out)?;
+        if alter != 0 {
+            writeln!(out, "        <accidental>sharp</accidental>")?;
+        }
+        if let (0, Some(beam)) = (i, beam) {
+            writeln!(out, r#"        <beam number="1">{}</beam>"#, beam)?;
+        }
+        if tie_start || tie_stop {
+            writeln!(out, "        <notations>")?;
+            if tie_stop {
+                writeln!(out, r#"          <tied type="stop"/>"#)?;
+            }
+            if tie_start {
+                writeln!(out, r#"          <tied type="start"/>"#)?;
+            }
+            writeln!(out, "        </notations>")?;
+        }
+        writeln!(out, "      </note>")?;
+    }
+    Ok(())
+}
@@ -10,21 +10,26 @@ pub struct InRamNoteBlock {
 pub struct BasicMIDINote {
     pub len: f32,
     pub track_chan: TrackAndChannel,
+    pub vel: u8,
+    /// The last program change on the channel of the note, if there was one
+    pub program: Option<u8>,
 }
 
 impl InRamNoteBlock {
-    /// Creates a new block from an iterator of Track/Channel values.
+    /// Creates a new block from an iterator of Track/Channel, velocity and program values.
     /// This assumes that the lengths will be added in the future.
     pub fn new_from_trackchans(
         time: f64,
-        track_chans_iter: impl ExactSizeIterator<Item = TrackAndChannel>,
+        track_chans_iter: impl ExactSizeIterator<Item = (TrackAndChannel, u8, Option<u8>)>,
     ) -> Self {
         let mut notes: Vec<BasicMIDINote> = Vec::with_capacity(track_chans_iter.len());
 
-        for track_chan in track_chans_iter {
+        for (track_chan, vel, program) in track_chans_iter {
             notes.push(BasicMIDINote {
                 len: 0.0,
                 track_chan,
+                vel,
+                program,
             });
         }
 
@@ -35,6 +40,17 @@ impl InRamNoteBlock {
         }
     }
 
+    /// Creates a new block from notes that already have their lengths set.
+    pub fn new_from_notes(time: f64, notes: Vec<BasicMIDINote>) -> Self {
+        let max_length = notes.iter().map(|note| note.len).fold(0.0, f32::max);
+
+        InRamNoteBlock {
+            start: time,
+            notes: notes.into_boxed_slice(),
+            max_length,
+        }
+    }
+
     pub fn set_note_end_time(&mut self, note_index: usize, end_time: f64) {
         let note = &mut self.notes[note_index];
         note.len = (end_time - self.start) as f32;
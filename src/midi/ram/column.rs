@@ -1,5 +1,7 @@
 use std::ops::Range;
 
+use crate::midi::edit::ColumnEdits;
+
 use super::block::InRamNoteBlock;
 
 pub struct InRamNoteColumnViewData {
@@ -21,6 +23,7 @@ impl InRamNoteColumnViewData {
 pub struct InRamNoteColumn {
     pub data: InRamNoteColumnViewData,
     pub blocks: Vec<InRamNoteBlock>,
+    pub edits: ColumnEdits,
 }
 
 impl InRamNoteColumn {
@@ -28,6 +31,7 @@ impl InRamNoteColumn {
         InRamNoteColumn {
             blocks,
             data: InRamNoteColumnViewData::new(),
+            edits: ColumnEdits::default(),
         }
     }
 }
@@ -1,7 +1,12 @@
-use self::view::{InRamCurrentNoteViews, InRamNoteViewData};
+use std::sync::Arc;
+
+use self::view::{EditableNote, InRamCurrentNoteViews, InRamNoteViewData};
 
 use super::{
-    shared::timer::TimeKeeper, MIDIFile, MIDIFileBase, MIDIFileUniqueSignature, MIDIViewRange,
+    edit::{edits_per_key, NoteEdit},
+    shared::timer::TimeKeeper,
+    Beat, ControlChanges, LyricLine, MIDIFile, MIDIFileBase, MIDIFileMetadata,
+    MIDIFileUniqueSignature, MIDIViewRange, ProgramChange, SharedMetadata,
 };
 
 pub mod block;
@@ -29,9 +34,36 @@ pub struct InRamMIDIFile {
     length: f64,
     note_count: u64,
     signature: MIDIFileUniqueSignature,
+    reversed: bool,
+    note_edits: Vec<NoteEdit>,
+    metadata: SharedMetadata,
 }
 
-impl InRamMIDIFile {}
+impl InRamMIDIFile {
+    /// Turns the colors of every track and program around the color wheel
+    pub fn rotate_hues(&mut self, degrees: f64) {
+        self.view_data.rotate_hues(degrees);
+    }
+
+    pub fn note_edits(&self) -> &[NoteEdit] {
+        &self.note_edits
+    }
+
+    pub fn set_note_edits(&mut self, edits: Vec<NoteEdit>) {
+        self.view_data.set_column_edits(edits_per_key(&edits));
+        self.note_edits = edits;
+    }
+
+    pub fn push_note_edit(&mut self, edit: NoteEdit) {
+        let mut edits = std::mem::take(&mut self.note_edits);
+        edits.push(edit);
+        self.set_note_edits(edits);
+    }
+
+    pub fn find_note_at(&self, key: u8, time: f64) -> Option<EditableNote> {
+        self.view_data.find_note_at(key, time)
+    }
+}
 
 impl MIDIFileBase for InRamMIDIFile {
     fn midi_length(&self) -> Option<f64> {
@@ -61,14 +93,52 @@ impl MIDIFileBase for InRamMIDIFile {
     fn signature(&self) -> &MIDIFileUniqueSignature {
         &self.signature
     }
+
+    fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    fn track_count(&self) -> usize {
+        self.view_data.track_count()
+    }
+
+    fn metadata(&self) -> Arc<MIDIFileMetadata> {
+        self.metadata.read().unwrap().clone()
+    }
+
+    fn control_changes(&self) -> Arc<ControlChanges> {
+        self.metadata.read().unwrap().control_changes.clone()
+    }
+
+    fn program_changes(&self) -> Arc<Vec<ProgramChange>> {
+        self.metadata.read().unwrap().program_changes.clone()
+    }
+
+    fn lyrics(&self) -> Arc<Vec<LyricLine>> {
+        self.metadata.read().unwrap().lyrics.clone()
+    }
+
+    fn bpm_at(&self, time: f64) -> f64 {
+        self.metadata.read().unwrap().bpm_at(time)
+    }
+
+    fn beats_between(&self, start: f64, end: f64) -> Vec<Beat> {
+        // The beats are found after the lock is let go of, the scan may be waiting on it
+        let metadata = self.metadata.read().unwrap().clone();
+        metadata.beats_between(start, end)
+    }
+
+    fn ppq(&self) -> u16 {
+        self.metadata.read().unwrap().ppq
+    }
 }
 
 impl MIDIFile for InRamMIDIFile {
     type ColumnsViews<'a> = InRamCurrentNoteViews<'a> where Self: 'a;
 
-    fn get_current_column_views(&mut self, range: f64) -> Self::ColumnsViews<'_> {
+    fn get_current_column_views(&mut self, range: f64, lookback: f64) -> Self::ColumnsViews<'_> {
         let time = self.timer.get_time().as_secs_f64();
-        let new_range = MIDIViewRange::new(time, time + range);
+        let new_range = MIDIViewRange::new(time - lookback, time + range);
         self.view_data.shift_view_range(new_range);
 
         InRamCurrentNoteViews::new(&self.view_data)
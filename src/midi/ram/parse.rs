@@ -1,5 +1,6 @@
 use std::{
     collections::VecDeque,
+    path::Path,
     sync::{Arc, RwLock},
     thread,
 };
@@ -14,6 +15,7 @@ use midi_toolkit::{
     },
 };
 use rustc_hash::FxHashMap;
+use triggered::Listener;
 
 use crate::{
     audio_playback::SimpleTemporaryPlayer,
@@ -21,11 +23,19 @@ use crate::{
         audio::ram::InRamAudioPlayer,
         open_file_and_signature,
         ram::{column::InRamNoteColumn, view::InRamNoteViewData},
-        shared::{audio::CompressedAudio, timer::TimeKeeper, track_channel::TrackAndChannel},
+        shared::{
+            audio::CompressedAudio, repair::MIDIRepair, timer::TimeKeeper,
+            track_channel::TrackAndChannel,
+        },
+        MIDIColor, MIDIFileMetadata, MIDIOpenError,
     },
+    settings::MidiRepairMode,
 };
 
-use super::{block::InRamNoteBlock, InRamMIDIFile};
+use super::{
+    block::{BasicMIDINote, InRamNoteBlock},
+    InRamMIDIFile,
+};
 
 struct UnendedNote {
     column_index: usize,
@@ -34,7 +44,7 @@ struct UnendedNote {
 
 struct Key {
     column: Vec<InRamNoteBlock>,
-    block_builder: Vec<TrackAndChannel>,
+    block_builder: Vec<(TrackAndChannel, u8, Option<u8>)>,
     unended_notes: FxHashMap<TrackAndChannel, VecDeque<UnendedNote>>,
 }
 
@@ -47,10 +57,10 @@ impl Key {
         }
     }
 
-    fn add_note(&mut self, track_chan: TrackAndChannel) {
+    fn add_note(&mut self, track_chan: TrackAndChannel, vel: u8, program: Option<u8>) {
         let block_index = self.block_builder.len();
         let column_index = self.column.len();
-        self.block_builder.push(track_chan);
+        self.block_builder.push((track_chan, vel, program));
         let unended_queue = self
             .unended_notes
             .entry(track_chan)
@@ -98,14 +108,56 @@ impl Key {
     }
 }
 
+/// Mirrors all the notes in a column around the end of the MIDI, so that
+/// the notes that ended last start first.
+fn reverse_column(column: Vec<InRamNoteBlock>, length: f64) -> Vec<InRamNoteBlock> {
+    let mut notes: Vec<(f64, BasicMIDINote)> = column
+        .into_iter()
+        .flat_map(|block| {
+            let start = block.start;
+            block
+                .notes
+                .into_vec()
+                .into_iter()
+                .map(move |note| (length - start - note.len as f64, note))
+        })
+        .collect();
+    notes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut reversed: Vec<InRamNoteBlock> = Vec::new();
+    let mut block_notes = Vec::new();
+    let mut block_start = 0.0;
+
+    for (start, note) in notes {
+        if start != block_start && !block_notes.is_empty() {
+            reversed.push(InRamNoteBlock::new_from_notes(
+                block_start,
+                std::mem::take(&mut block_notes),
+            ));
+        }
+        block_start = start;
+        block_notes.push(note);
+    }
+
+    if !block_notes.is_empty() {
+        reversed.push(InRamNoteBlock::new_from_notes(block_start, block_notes));
+    }
+
+    reversed
+}
+
 impl InRamMIDIFile {
     pub fn load_from_file(
-        path: &str,
+        path: &Path,
         player: Arc<RwLock<SimpleTemporaryPlayer>>,
         random_colors: bool,
-    ) -> Self {
-        let (file, signature) = open_file_and_signature(path);
-        let midi = TKMIDIFile::open_from_stream(file, None).unwrap();
+        program_colors: Option<Vec<MIDIColor>>,
+        reverse: bool,
+        repair_mode: MidiRepairMode,
+        cancel: &Listener,
+    ) -> Result<Self, MIDIOpenError> {
+        let (file, signature) = open_file_and_signature(path)?;
+        let midi = TKMIDIFile::open_from_stream(file, None).map_err(MIDIOpenError::Parse)?;
 
         let ppq = midi.ppq();
         let merged = pipe!(
@@ -120,12 +172,16 @@ impl InRamMIDIFile {
         let (key_snd, key_rcv) = crossbeam_channel::bounded::<Arc<Ev>>(1000);
         let (audio_snd, audio_rcv) = crossbeam_channel::bounded::<Arc<Ev>>(1000);
 
-        let key_join_handle = thread::spawn(|| {
+        let key_join_handle = thread::spawn(move || {
             let mut keys: Vec<Key> = (0..256).map(|_| Key::new()).collect();
 
             let mut time = 0.0;
 
             let mut notes = 0;
+            let mut repair = MIDIRepair::new(repair_mode);
+
+            // Program changes apply to a channel across all of the tracks
+            let mut programs = [None; 16];
 
             fn flush_keys(time: f64, keys: &mut [Key]) {
                 for key in keys.iter_mut() {
@@ -141,16 +197,27 @@ impl InRamMIDIFile {
 
                 for event in batch.iter_events() {
                     let track = event.track;
+                    if !repair.keep_event(track, event.as_event()) {
+                        continue;
+                    }
+
                     match event.as_event() {
                         Event::NoteOn(e) => {
                             let track_chan = TrackAndChannel::new(track, e.channel);
-                            keys[e.key as usize].add_note(track_chan);
+                            keys[e.key as usize].add_note(
+                                track_chan,
+                                e.velocity,
+                                programs[e.channel as usize],
+                            );
                             notes += 1;
                         }
                         Event::NoteOff(e) => {
                             let track_chan = TrackAndChannel::new(track, e.channel);
                             keys[e.key as usize].end_note(track_chan, time);
                         }
+                        Event::ProgramChange(e) => {
+                            programs[e.channel as usize] = Some(e.program);
+                        }
                         _ => {}
                     }
                 }
@@ -158,15 +225,29 @@ impl InRamMIDIFile {
 
             flush_keys(time, &mut keys);
 
+            // The notes that never ended are cut off at the end either way,
+            // this only counts them and adds the note offs for the audio
+            repair.end_hanging_notes();
             for key in keys.iter_mut() {
                 key.end_all(time);
             }
 
-            (keys, notes)
+            if reverse {
+                for key in keys.iter_mut() {
+                    key.column = reverse_column(std::mem::take(&mut key.column), time);
+                }
+            }
+
+            (keys, notes, repair.stats())
         });
 
-        let audio_join_handle = thread::spawn(|| {
-            let vec: Vec<_> = CompressedAudio::build_blocks(audio_rcv.into_iter()).collect();
+        let audio_join_handle = thread::spawn(move || {
+            let repair = MIDIRepair::new(repair_mode);
+            let vec: Vec<_> = if reverse {
+                CompressedAudio::build_reversed_blocks(audio_rcv.into_iter(), repair)
+            } else {
+                CompressedAudio::build_repaired_blocks(audio_rcv.into_iter(), repair).collect()
+            };
             vec
         });
 
@@ -174,6 +255,9 @@ impl InRamMIDIFile {
 
         // Write events to the threads
         for batch in merged {
+            if cancel.is_triggered() {
+                return Err(MIDIOpenError::Cancelled);
+            }
             length += batch.delta;
             let batch = Arc::new(batch);
             key_snd.send(batch.clone()).unwrap();
@@ -183,7 +267,7 @@ impl InRamMIDIFile {
         drop(key_snd);
         drop(audio_snd);
 
-        let (keys, note_count) = key_join_handle.join().unwrap();
+        let (keys, note_count, repair_stats) = key_join_handle.join().unwrap();
         let audio = audio_join_handle.join().unwrap();
 
         let mut timer = TimeKeeper::new();
@@ -195,12 +279,25 @@ impl InRamMIDIFile {
             .map(|key| InRamNoteColumn::new(key.column))
             .collect();
 
-        InRamMIDIFile {
-            view_data: InRamNoteViewData::new(columns, midi.track_count(), random_colors),
+        let metadata = MIDIFileMetadata::load(path, cancel);
+        if repair_mode != MidiRepairMode::Off {
+            Arc::make_mut(&mut metadata.write().unwrap()).repairs = Some(repair_stats);
+        }
+
+        Ok(InRamMIDIFile {
+            view_data: InRamNoteViewData::new(
+                columns,
+                midi.track_count(),
+                random_colors,
+                program_colors,
+            ),
             timer,
             length,
             note_count,
             signature,
-        }
+            reversed: reverse,
+            note_edits: Vec::new(),
+            metadata,
+        })
     }
 }
@@ -5,13 +5,17 @@ use std::ops::Range;
 use gen_iter::GenIter;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 
-use crate::midi::{DisplacedMIDINote, MIDIColor, MIDINoteColumnView, MIDINoteViews, MIDIViewRange};
+use crate::midi::{
+    edit::{ColumnEdits, NoteEdit, NoteId},
+    DisplacedMIDINote, MIDIColor, MIDINoteColumnView, MIDINoteViews, MIDIViewRange,
+};
 
 use super::column::InRamNoteColumn;
 
 pub struct InRamNoteViewData {
     columns: Vec<InRamNoteColumn>,
     default_track_colors: Vec<MIDIColor>,
+    program_colors: Option<Vec<MIDIColor>>,
     view_range: MIDIViewRange,
 }
 
@@ -26,7 +30,12 @@ impl<'a> InRamCurrentNoteViews<'a> {
 }
 
 impl InRamNoteViewData {
-    pub fn new(columns: Vec<InRamNoteColumn>, track_count: usize, random_colors: bool) -> Self {
+    pub fn new(
+        columns: Vec<InRamNoteColumn>,
+        track_count: usize,
+        random_colors: bool,
+        program_colors: Option<Vec<MIDIColor>>,
+    ) -> Self {
         InRamNoteViewData {
             columns,
             view_range: MIDIViewRange {
@@ -38,6 +47,7 @@ impl InRamNoteViewData {
             } else {
                 MIDIColor::new_vec_for_tracks(track_count)
             },
+            program_colors,
         }
     }
 }
@@ -58,7 +68,69 @@ impl InRamNoteColumnViewData {
     }
 }
 
+/// A note found under the cursor in the note editor, with its edits applied
+#[derive(Debug, Clone, Copy)]
+pub struct EditableNote {
+    pub id: NoteId,
+    pub start: f64,
+    pub end: f64,
+    pub original_start: f64,
+    pub original_end: f64,
+}
+
 impl InRamNoteViewData {
+    pub fn track_count(&self) -> usize {
+        self.default_track_colors.len() / 16
+    }
+
+    pub fn rotate_hues(&mut self, degrees: f64) {
+        let program_colors = self.program_colors.iter_mut().flatten();
+        for color in self.default_track_colors.iter_mut().chain(program_colors) {
+            *color = color.rotate_hue(degrees);
+        }
+    }
+
+    pub fn set_column_edits(&mut self, edits: Vec<ColumnEdits>) {
+        for (column, edits) in self.columns.iter_mut().zip(edits) {
+            column.edits = edits;
+        }
+    }
+
+    /// Finds the visible note in a key that is playing at the given time
+    pub fn find_note_at(&self, key: u8, time: f64) -> Option<EditableNote> {
+        let column = self.columns.get(key as usize)?;
+
+        for block_index in column.data.block_range.clone().rev() {
+            let block = &column.blocks[block_index];
+            for (index, note) in block.notes.iter().enumerate().rev() {
+                let id = NoteId {
+                    key,
+                    start: block.start,
+                    index: index as u32,
+                };
+                let original_end = block.start + note.len as f64;
+
+                let (start, end) = match column.edits.get(&id.column_key()) {
+                    Some(NoteEdit::Delete { .. }) => continue,
+                    Some(NoteEdit::Trim { start, end, .. }) => (*start, *end),
+                    None => (block.start, original_end),
+                };
+
+                if start <= time && time <= end {
+                    return Some(EditableNote {
+                        id,
+                        start,
+                        end,
+                        original_start: block.start,
+                        original_end,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
     pub fn shift_view_range(&mut self, new_view_range: MIDIViewRange) {
         let old_view_range = self.view_range;
         self.view_range = new_view_range;
@@ -158,17 +230,61 @@ impl<'a> MIDINoteColumnView for InRamNoteColumnView<'a> {
 
     fn iterate_displaced_notes(&self) -> Self::Iter<'_> {
         let colors = &self.view.default_track_colors;
+        let program_colors = self.view.program_colors.as_deref();
 
         let iter = GenIter(move || {
             for block_index in self.column.data.block_range.clone().rev() {
                 let block = &self.column.blocks[block_index];
                 let start = (block.start - self.view_range.start) as f32;
+                let edits = &self.column.edits;
+
+                for (index, note) in block.notes.iter().enumerate().rev() {
+                    let color =
+                        MIDIColor::for_note(colors, program_colors, note.track_chan, note.program);
+                    let track = note.track_chan.track();
+                    let channel = note.track_chan.channel();
+
+                    if !edits.is_empty() {
+                        match edits.get(&(block.start.to_bits(), index as u32)) {
+                            // Deleted notes still have to be yielded to keep the length exact,
+                            // a negative length makes the renderer cull them
+                            Some(NoteEdit::Delete { .. }) => {
+                                yield DisplacedMIDINote {
+                                    start,
+                                    len: -1.0,
+                                    color,
+                                    vel: note.vel,
+                                    track,
+                                    channel,
+                                };
+                                continue;
+                            }
+                            Some(NoteEdit::Trim {
+                                start: trim_start,
+                                end,
+                                ..
+                            }) => {
+                                yield DisplacedMIDINote {
+                                    start: (trim_start - self.view_range.start) as f32,
+                                    len: (end - trim_start) as f32,
+                                    color,
+                                    vel: note.vel,
+                                    track,
+                                    channel,
+                                };
+                                continue;
+                            }
+                            None => {}
+                        }
+                    }
 
-                for note in block.notes.iter().rev() {
                     yield DisplacedMIDINote {
                         start,
                         len: note.len,
-                        color: colors[note.track_chan.as_usize()],
+                        color,
+                        vel: note.vel,
+                        track,
+                        channel,
                     };
                 }
             }
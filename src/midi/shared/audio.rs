@@ -1,10 +1,15 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 
 use gen_iter::GenIter;
 use midi_toolkit::{
     events::{Event, MIDIEventEnum},
     sequence::event::{Delta, EventBatch, Track},
 };
+use rustc_hash::FxHashMap;
+
+use crate::settings::MidiRepairMode;
+
+use super::repair::MIDIRepair;
 
 pub struct CompressedAudio {
     pub time: f64,
@@ -26,6 +31,18 @@ impl CompressedAudio {
         E: MIDIEventEnum,
     >(
         iter: Iter,
+    ) -> impl Iterator<Item = CompressedAudio> {
+        CompressedAudio::build_repaired_blocks(iter, MIDIRepair::new(MidiRepairMode::Off))
+    }
+
+    /// Builds the audio blocks with the events that the repair pass keeps,
+    /// followed by a block that ends the hanging notes if there are any
+    pub fn build_repaired_blocks<
+        Iter: Iterator<Item = Arc<Delta<f64, Track<EventBatch<E>>>>>,
+        E: MIDIEventEnum,
+    >(
+        iter: Iter,
+        mut repair: MIDIRepair,
     ) -> impl Iterator<Item = CompressedAudio> {
         let mut builder_vec: Vec<u8> = Vec::new();
         let mut control_builder_vec: Vec<u8> = Vec::new();
@@ -41,6 +58,10 @@ impl CompressedAudio {
                 builder_vec.clear();
 
                 for event in block.iter_events() {
+                    if !repair.keep_event(event.track, event.as_event()) {
+                        continue;
+                    }
+
                     match event.as_event() {
                         Event::NoteOn(e) => {
                             let head = EV_ON | e.channel;
@@ -103,9 +124,185 @@ impl CompressedAudio {
                     time,
                 };
             }
+
+            let hanging = repair.end_hanging_notes();
+            if !hanging.is_empty() {
+                yield CompressedAudio {
+                    data: hanging
+                        .into_iter()
+                        .flat_map(|(channel, key)| [EV_OFF | channel, key])
+                        .collect(),
+                    control_only_data: None,
+                    time,
+                };
+            }
         })
     }
 
+    /// Builds the audio blocks for playing the MIDI backwards. Note on and note off
+    /// events swap places, with the velocity being taken from the matching note on.
+    pub fn build_reversed_blocks<
+        Iter: Iterator<Item = Arc<Delta<f64, Track<EventBatch<E>>>>>,
+        E: MIDIEventEnum,
+    >(
+        iter: Iter,
+        mut repair: MIDIRepair,
+    ) -> Vec<CompressedAudio> {
+        struct TimedEvent {
+            time: f64,
+            data: [u8; 3],
+            len: usize,
+            control: bool,
+        }
+
+        let mut time = 0.0;
+        let mut events = Vec::new();
+        let mut unended_velocities: FxHashMap<(u32, u8, u8), VecDeque<u8>> = FxHashMap::default();
+
+        for block in iter {
+            time += block.delta;
+
+            for event in block.iter_events() {
+                let track = event.track;
+                if !repair.keep_event(track, event.as_event()) {
+                    continue;
+                }
+
+                let (data, len, control) = match event.as_event() {
+                    Event::NoteOn(e) => {
+                        unended_velocities
+                            .entry((track, e.channel, e.key))
+                            .or_insert_with(VecDeque::new)
+                            .push_back(e.velocity);
+                        ([EV_OFF | e.channel, e.key, 0], 2, false)
+                    }
+                    Event::NoteOff(e) => {
+                        let velocity = unended_velocities
+                            .get_mut(&(track, e.channel, e.key))
+                            .and_then(|queue| queue.pop_front());
+                        if let Some(velocity) = velocity {
+                            ([EV_ON | e.channel, e.key, velocity], 3, false)
+                        } else {
+                            continue;
+                        }
+                    }
+                    Event::PolyphonicKeyPressure(e) => {
+                        ([EV_POLYPHONIC | e.channel, e.key, e.velocity], 3, false)
+                    }
+                    Event::ControlChange(e) => {
+                        ([EV_CONTROL | e.channel, e.controller, e.value], 3, true)
+                    }
+                    Event::ProgramChange(e) => ([EV_PROGRAM | e.channel, e.program, 0], 2, true),
+                    Event::ChannelPressure(e) => {
+                        ([EV_CHAN_PRESSURE | e.channel, e.pressure, 0], 2, true)
+                    }
+                    Event::PitchWheelChange(e) => {
+                        let value = e.pitch + 8192;
+                        (
+                            [
+                                EV_PITCH_BEND | e.channel,
+                                (value & 0x7F) as u8,
+                                ((value >> 7) & 0x7F) as u8,
+                            ],
+                            3,
+                            true,
+                        )
+                    }
+                    _ => continue,
+                };
+
+                events.push(TimedEvent {
+                    time,
+                    data,
+                    len,
+                    control,
+                });
+            }
+        }
+
+        // Notes that never ended start at the very beginning of the reversed MIDI
+        for ((_, channel, key), velocities) in unended_velocities {
+            for velocity in velocities {
+                events.push(TimedEvent {
+                    time,
+                    data: [EV_ON | channel, key, velocity],
+                    len: 3,
+                    control: false,
+                });
+            }
+        }
+
+        // Within a moment, the notes that end go first so a key that is played again at
+        // the same time isn't cut off by the end of the note before it, and the notes
+        // start after the controllers they were played with. The sort is stable, so the
+        // rest stay in reverse.
+        fn order(event: &TimedEvent) -> u8 {
+            match event.data[0] & 0xF0 {
+                EV_OFF => 0,
+                EV_ON => 2,
+                _ => 1,
+            }
+        }
+        events.reverse();
+        events.sort_by(|a, b| b.time.total_cmp(&a.time).then(order(a).cmp(&order(b))));
+
+        let length = time;
+        let mut blocks: Vec<CompressedAudio> = Vec::new();
+        let mut held: FxHashMap<(u8, u8), u32> = FxHashMap::default();
+
+        for event in events.iter() {
+            let time = length - event.time;
+
+            match event.data[0] & 0xF0 {
+                EV_ON => *held.entry((event.data[0], event.data[1])).or_default() += 1,
+                EV_OFF => {
+                    let on = (EV_ON | (event.data[0] & 0xF), event.data[1]);
+                    if let Some(count) = held.get_mut(&on) {
+                        *count -= 1;
+                        if *count == 0 {
+                            held.remove(&on);
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if blocks.last().map(|block| block.time) != Some(time) {
+                blocks.push(CompressedAudio {
+                    time,
+                    data: Vec::new(),
+                    control_only_data: None,
+                });
+            }
+            let block = blocks.last_mut().unwrap();
+
+            let data = &event.data[..event.len];
+            block.data.extend_from_slice(data);
+            if event.control {
+                block
+                    .control_only_data
+                    .get_or_insert_with(Vec::new)
+                    .extend_from_slice(data);
+            }
+        }
+
+        // Notes that are still held when the reversed MIDI ends are let go in a last block
+        if !held.is_empty() {
+            blocks.push(CompressedAudio {
+                time: length,
+                data: held
+                    .into_iter()
+                    .flat_map(|((head, key), count)| {
+                        (0..count).flat_map(move |_| [EV_OFF | (head & 0xF), key])
+                    })
+                    .collect(),
+                control_only_data: None,
+            });
+        }
+
+        blocks
+    }
+
     pub fn iter_events(&self) -> impl '_ + Iterator<Item = u32> {
         CompressedAudio::iter_events_from_vec(self.data.iter().cloned())
     }
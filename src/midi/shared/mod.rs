@@ -1,3 +1,4 @@
 pub mod audio;
+pub mod repair;
 pub mod timer;
 pub mod track_channel;
@@ -0,0 +1,89 @@
+use midi_toolkit::events::Event;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::settings::MidiRepairMode;
+
+/// What the repair pass changed while loading a MIDI
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MIDIRepairStats {
+    pub duplicate_notes: u64,
+    pub hanging_notes: u64,
+}
+
+/// Fixes broken note events while a MIDI is loaded. The events are already read
+/// in order of their absolute tick, since the tracks are merged by time.
+///
+/// The notes and the audio are built on separate threads, so each of them runs its
+/// own repair, which makes the same decisions because it sees the same events.
+pub struct MIDIRepair {
+    mode: MidiRepairMode,
+    /// The keys that are held, by track, channel and key
+    held: FxHashSet<(u32, u8, u8)>,
+    /// How many note offs still belong to note ons that were removed
+    removed: FxHashMap<(u32, u8, u8), u32>,
+    stats: MIDIRepairStats,
+}
+
+impl MIDIRepair {
+    pub fn new(mode: MidiRepairMode) -> Self {
+        Self {
+            mode,
+            held: FxHashSet::default(),
+            removed: FxHashMap::default(),
+            stats: MIDIRepairStats::default(),
+        }
+    }
+
+    /// Whether an event should be kept. A note on for a key that is already held on the
+    /// same track and channel is removed, along with the note off that would have ended
+    /// it, so the held note lasts until the last of the note offs.
+    pub fn keep_event(&mut self, track: u32, event: &Event) -> bool {
+        if self.mode == MidiRepairMode::Off {
+            return true;
+        }
+
+        match event {
+            Event::NoteOn(e) => {
+                let key = (track, e.channel, e.key);
+                if self.held.insert(key) {
+                    true
+                } else {
+                    *self.removed.entry(key).or_default() += 1;
+                    self.stats.duplicate_notes += 1;
+                    false
+                }
+            }
+            Event::NoteOff(e) => {
+                let key = (track, e.channel, e.key);
+                if let Some(removed) = self.removed.get_mut(&key).filter(|removed| **removed > 0) {
+                    *removed -= 1;
+                    false
+                } else {
+                    self.held.remove(&key);
+                    true
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// The channel and key of every note that never got a note off, for adding
+    /// the missing note offs at the end of the MIDI
+    pub fn end_hanging_notes(&mut self) -> Vec<(u8, u8)> {
+        if self.mode != MidiRepairMode::Full {
+            return Vec::new();
+        }
+
+        let hanging: Vec<_> = self
+            .held
+            .drain()
+            .map(|(_, channel, key)| (channel, key))
+            .collect();
+        self.stats.hanging_notes += hanging.len() as u64;
+        hanging
+    }
+
+    pub fn stats(&self) -> MIDIRepairStats {
+        self.stats
+    }
+}
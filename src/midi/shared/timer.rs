@@ -5,6 +5,7 @@ use std::time::{Duration, Instant};
 struct NotifySignal {
     new_state: TimerState,
     has_seeked: bool,
+    audio_lead: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -12,6 +13,7 @@ enum TimerState {
     Running {
         continue_time: Instant,
         time_offset: Duration,
+        speed: f64,
     },
     Paused {
         time_offset: Duration,
@@ -24,7 +26,8 @@ impl TimerState {
             TimerState::Running {
                 continue_time,
                 time_offset,
-            } => continue_time.elapsed() + *time_offset,
+                speed,
+            } => continue_time.elapsed().mul_f64(*speed) + *time_offset,
             TimerState::Paused { time_offset } => *time_offset,
         }
     }
@@ -32,12 +35,23 @@ impl TimerState {
     fn is_paused(&self) -> bool {
         matches!(self, TimerState::Paused { .. })
     }
+
+    /// Converts a duration of playback time into the real time it takes to pass
+    fn to_real_duration(&self, time: Duration) -> Duration {
+        match self {
+            TimerState::Running { speed, .. } => time.div_f64(*speed),
+            TimerState::Paused { .. } => time,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct TimeKeeper {
     current_state: TimerState,
     listeners: Vec<crossbeam_channel::Sender<NotifySignal>>,
+    speed: f64,
+    /// How far ahead of the displayed time the listeners are, in real time
+    audio_lead: Duration,
 }
 
 impl TimeKeeper {
@@ -47,6 +61,8 @@ impl TimeKeeper {
                 time_offset: Duration::new(0, 0),
             },
             listeners: Vec::new(),
+            speed: 1.0,
+            audio_lead: Duration::ZERO,
         }
     }
 
@@ -64,6 +80,7 @@ impl TimeKeeper {
         TimeListener {
             reciever: rcv,
             current: self.current_state.clone(),
+            audio_lead: self.audio_lead,
         }
     }
 
@@ -75,6 +92,7 @@ impl TimeKeeper {
             let signal = NotifySignal {
                 new_state: self.current_state.clone(),
                 has_seeked: seeked,
+                audio_lead: self.audio_lead,
             };
 
             match listener.send(signal) {
@@ -94,6 +112,7 @@ impl TimeKeeper {
                 self.current_state = TimerState::Running {
                     continue_time: Instant::now(),
                     time_offset: now,
+                    speed: self.speed,
                 };
             }
             TimerState::Running { .. } => {
@@ -115,6 +134,7 @@ impl TimeKeeper {
         self.current_state = TimerState::Running {
             continue_time: Instant::now(),
             time_offset: now,
+            speed: self.speed,
         };
         self.notify_listeners(false);
     }
@@ -123,14 +143,50 @@ impl TimeKeeper {
         self.current_state = TimerState::Running {
             continue_time: Instant::now(),
             time_offset: time,
+            speed: self.speed,
         };
         self.notify_listeners(true);
     }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Changes how fast playback time passes relative to real time,
+    /// without seeking
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed == self.speed || speed <= 0.0 {
+            return;
+        }
+
+        let now = self.get_time();
+        self.speed = speed;
+        if let TimerState::Running { .. } = self.current_state {
+            self.current_state = TimerState::Running {
+                continue_time: Instant::now(),
+                time_offset: now,
+                speed,
+            };
+            self.notify_listeners(false);
+        }
+    }
+
+    /// Runs the listeners, which play the audio, ahead of the displayed time, so the
+    /// audio that the synth holds back for its buffer is heard as the notes hit the keys
+    pub fn set_audio_lead(&mut self, lead: Duration) {
+        if lead == self.audio_lead {
+            return;
+        }
+
+        self.audio_lead = lead;
+        self.notify_listeners(false);
+    }
 }
 
 pub struct TimeListener {
     reciever: crossbeam_channel::Receiver<NotifySignal>,
     current: TimerState,
+    audio_lead: Duration,
 }
 
 #[must_use]
@@ -160,23 +216,26 @@ impl TimeListener {
     }
 
     pub fn wait_until(&mut self, time: Duration) -> WaitResult {
-        let curr_time = self.current.get_time();
+        let curr_time = self.get_time();
         if curr_time >= time {
             return WaitResult::Ok;
         }
 
         // TODO: Maybe find a more reliable way to wait while still reading?
-        let result = self.reciever.recv_timeout(time - curr_time);
+        let result = self
+            .reciever
+            .recv_timeout(self.current.to_real_duration(time - curr_time));
 
         match result {
             Ok(signal) => {
-                self.current = signal.new_state;
+                self.receive(signal.new_state, signal.audio_lead);
                 if signal.has_seeked {
-                    WaitResult::Seeked(self.current.get_time())
+                    WaitResult::Seeked(self.get_time())
                 } else if self.current.is_paused() {
                     WaitResult::Paused
                 } else {
-                    WaitResult::Ok
+                    // Only the speed or the lead changed, so wait again with the new state
+                    self.wait_until(time)
                 }
             }
             Err(error) => match error {
@@ -198,9 +257,9 @@ impl TimeListener {
 
             match result {
                 Ok(signal) => {
-                    self.current = signal.new_state;
+                    self.receive(signal.new_state, signal.audio_lead);
                     if signal.has_seeked {
-                        seeked = Some(self.current.get_time());
+                        seeked = Some(self.get_time());
                     }
 
                     if !self.current.is_paused() {
@@ -223,13 +282,13 @@ impl TimeListener {
 
             match result {
                 Ok(signal) => {
-                    self.current = signal.new_state;
+                    self.receive(signal.new_state, signal.audio_lead);
                     if signal.has_seeked {
                         seeked = true;
                     }
 
                     if seeked && !self.current.is_paused() {
-                        return SeekWaitResult::UnpausedAndSeeked(self.current.get_time());
+                        return SeekWaitResult::UnpausedAndSeeked(self.get_time());
                     }
                 }
                 Err(_) => return SeekWaitResult::Killed,
@@ -237,7 +296,18 @@ impl TimeListener {
         }
     }
 
+    /// The time of the timer plus the lead, which is in real time and so is scaled
+    /// by the speed of the playback
     pub fn get_time(&self) -> Duration {
-        self.current.get_time()
+        let lead = match self.current {
+            TimerState::Running { speed, .. } => self.audio_lead.mul_f64(speed),
+            TimerState::Paused { .. } => self.audio_lead,
+        };
+        self.current.get_time() + lead
+    }
+
+    fn receive(&mut self, state: TimerState, audio_lead: Duration) {
+        self.current = state;
+        self.audio_lead = audio_lead;
     }
 }
@@ -0,0 +1,184 @@
+use std::{collections::VecDeque, fs::File, path::Path};
+
+use midi_toolkit::{
+    events::{Event, MIDIEventEnum},
+    io::MIDIFile as TKMIDIFile,
+    pipe,
+    sequence::{unwrap_items, TimeCaster},
+};
+use rustc_hash::FxHashMap;
+
+/// Broken MIDIs can have a violation for nearly every note, so the list stops here
+pub const MAX_SOP_VIOLATIONS: usize = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SopViolationKind {
+    /// The file couldn't be opened or parsed
+    Unreadable,
+    /// A note on that no note off ever ends
+    MissingNoteOff,
+    /// A note on for a key that is still held on the same channel
+    OverlappingNoteOn,
+    /// A tempo change outside of the first track of a format 1 file
+    TempoOutsideFirstTrack,
+    /// A note that plays before any time signature was set
+    NoTimeSignature,
+}
+
+impl SopViolationKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            SopViolationKind::Unreadable => "Unreadable",
+            SopViolationKind::MissingNoteOff => "Missing Note Off",
+            SopViolationKind::OverlappingNoteOn => "Overlapping Note On",
+            SopViolationKind::TempoOutsideFirstTrack => "Tempo Outside Track 0",
+            SopViolationKind::NoTimeSignature => "No Time Signature",
+        }
+    }
+}
+
+/// Something in a MIDI file that goes against the usual rules for a well formed file
+#[derive(Debug, Clone)]
+pub struct SopViolation {
+    pub kind: SopViolationKind,
+    pub track: u32,
+    /// The time of the event in ticks
+    pub tick: u64,
+    pub message: String,
+}
+
+/// Checks that every note on has a note off, that no key is turned on while it is
+/// still held on the same channel, that a format 1 file only changes the tempo in
+/// its first track and that a time signature is set before the first note. At most
+/// [`MAX_SOP_VIOLATIONS`] are returned in order of time, with the notes that are
+/// never turned off at the end.
+pub fn validate_sop(midi_path: &Path, format: u16) -> Vec<SopViolation> {
+    let unreadable = |message: String| {
+        vec![SopViolation {
+            kind: SopViolationKind::Unreadable,
+            track: 0,
+            tick: 0,
+            message,
+        }]
+    };
+
+    let file = match File::open(midi_path) {
+        Ok(file) => file,
+        Err(e) => return unreadable(format!("Failed to open MIDI: {}", e)),
+    };
+    let midi = match TKMIDIFile::open_from_stream(file, None) {
+        Ok(midi) => midi,
+        Err(e) => return unreadable(format!("Failed to parse MIDI: {:?}", e)),
+    };
+
+    let merged = pipe!(
+        midi.iter_all_track_events_merged_batches()
+        |>TimeCaster::<f64>::cast_event_delta()
+        |>unwrap_items()
+    );
+
+    let mut violations = Vec::new();
+    let mut push = |violation: SopViolation| {
+        if violations.len() < MAX_SOP_VIOLATIONS {
+            violations.push(violation);
+        }
+    };
+
+    // The ticks of the held notes by track, channel and key, ended first in first out
+    let mut held: FxHashMap<(u32, u8, u8), VecDeque<u64>> = FxHashMap::default();
+    // How many notes hold each key of each channel, over all of the tracks
+    let mut holding = [[0u32; 128]; 16];
+    let mut time_signature_set = false;
+    let mut tick = 0.0;
+
+    for batch in merged {
+        tick += batch.delta;
+        let tick = tick as u64;
+
+        for event in batch.iter_events() {
+            let track = event.track;
+            match event.as_event() {
+                Event::NoteOn(e) if e.velocity > 0 => {
+                    let (channel, key) = (e.channel & 0xF, e.key & 0x7F);
+                    if !time_signature_set {
+                        time_signature_set = true;
+                        push(SopViolation {
+                            kind: SopViolationKind::NoTimeSignature,
+                            track,
+                            tick,
+                            message: "The first note plays before any time signature is set"
+                                .to_owned(),
+                        });
+                    }
+                    if holding[channel as usize][key as usize] > 0 {
+                        push(SopViolation {
+                            kind: SopViolationKind::OverlappingNoteOn,
+                            track,
+                            tick,
+                            message: format!(
+                                "Key {} on channel {} is turned on while it is still held",
+                                key,
+                                channel + 1
+                            ),
+                        });
+                    }
+                    holding[channel as usize][key as usize] += 1;
+                    held.entry((track, channel, key))
+                        .or_default()
+                        .push_back(tick);
+                }
+                // A note on with a velocity of 0 ends the note like a note off
+                Event::NoteOn(e) => end_note(&mut held, &mut holding, track, e.channel, e.key),
+                Event::NoteOff(e) => end_note(&mut held, &mut holding, track, e.channel, e.key),
+                Event::Tempo(_) if format == 1 && track != 0 => push(SopViolation {
+                    kind: SopViolationKind::TempoOutsideFirstTrack,
+                    track,
+                    tick,
+                    message: format!(
+                        "Track {} changes the tempo, which format 1 files only do in track 0",
+                        track
+                    ),
+                }),
+                Event::TimeSignature(_) => time_signature_set = true,
+                _ => {}
+            }
+        }
+    }
+
+    let mut hanging: Vec<_> = held
+        .into_iter()
+        .flat_map(|((track, channel, key), ticks)| {
+            ticks
+                .into_iter()
+                .map(move |tick| (tick, track, channel, key))
+        })
+        .collect();
+    hanging.sort_unstable();
+    for (tick, track, channel, key) in hanging {
+        push(SopViolation {
+            kind: SopViolationKind::MissingNoteOff,
+            track,
+            tick,
+            message: format!("Key {} on channel {} is never turned off", key, channel + 1),
+        });
+    }
+
+    violations
+}
+
+fn end_note(
+    held: &mut FxHashMap<(u32, u8, u8), VecDeque<u64>>,
+    holding: &mut [[u32; 128]; 16],
+    track: u32,
+    channel: u8,
+    key: u8,
+) {
+    let (channel, key) = (channel & 0xF, key & 0x7F);
+    let ended = held
+        .get_mut(&(track, channel, key))
+        .and_then(|ticks| ticks.pop_front())
+        .is_some();
+    if ended {
+        holding[channel as usize][key as usize] -= 1;
+    }
+}
@@ -0,0 +1,65 @@
+/// The tempos that can be detected. Faster or slower tempos are halved or doubled until
+/// they fit, since the notes alone can't tell a beat apart from half or double of it.
+const MIN_BPM: f64 = 80.0;
+const MAX_BPM: f64 = 160.0;
+
+/// Onsets closer together than this in seconds are played together, like a chord
+pub const CHORD_WINDOW: f64 = 0.03;
+
+/// How many of the following onsets each onset is compared with
+const NEIGHBORS: usize = 4;
+
+/// Fewer intervals than this don't say enough about the tempo
+const MIN_INTERVALS: usize = 16;
+
+/// Guesses the tempo of music from the times in seconds that its notes start at, in
+/// order. The times between each onset and the few after it are counted as tempos in a
+/// histogram with a bin per BPM, and the fullest bin is taken.
+pub fn detect_bpm(onsets: &[f64]) -> Option<f64> {
+    // The notes of a chord count as a single onset
+    let mut beats: Vec<f64> = Vec::with_capacity(onsets.len());
+    for &onset in onsets {
+        if beats
+            .last()
+            .map_or(true, |last| onset - last > CHORD_WINDOW)
+        {
+            beats.push(onset);
+        }
+    }
+
+    let bins = (MAX_BPM - MIN_BPM) as usize;
+    let mut histogram = vec![0.0; bins];
+    let mut intervals = 0;
+    for (i, start) in beats.iter().enumerate() {
+        for (n, end) in beats[i + 1..].iter().take(NEIGHBORS).enumerate() {
+            let mut bpm = 60.0 / (end - start);
+            while bpm < MIN_BPM {
+                bpm *= 2.0;
+            }
+            while bpm >= MAX_BPM {
+                bpm /= 2.0;
+            }
+
+            // The next onset says more about the beat than the ones further away
+            let bin = ((bpm - MIN_BPM) as usize).min(bins - 1);
+            histogram[bin] += 1.0 / (n + 1) as f64;
+            intervals += 1;
+        }
+    }
+    if intervals < MIN_INTERVALS {
+        return None;
+    }
+
+    let (peak, _) = histogram
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+    // Averaging with the bins next to the peak gets closer than a whole BPM
+    let neighborhood = peak.saturating_sub(1)..(peak + 2).min(bins);
+    let weight: f64 = histogram[neighborhood.clone()].iter().sum();
+    let center: f64 = neighborhood
+        .map(|bin| (MIN_BPM + bin as f64 + 0.5) * histogram[bin])
+        .sum();
+    Some(center / weight)
+}
@@ -0,0 +1,45 @@
+use std::sync::Mutex;
+
+/// How many notifications are kept until the window shows them, so they don't pile up
+/// when there is no window, like when exporting from the command line
+const MAX_PENDING: usize = 32;
+
+static PENDING: Mutex<Vec<Notification>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationLevel {
+    Info,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub level: NotificationLevel,
+    pub message: String,
+}
+
+/// Tells the user that something failed. The message is written to the standard error
+/// right away, and shown in the window the next time it is drawn.
+pub fn error(message: impl Into<String>) {
+    push(NotificationLevel::Error, message.into());
+}
+
+/// Tells the user about something that finished, like an export
+pub fn info(message: impl Into<String>) {
+    push(NotificationLevel::Info, message.into());
+}
+
+fn push(level: NotificationLevel, message: String) {
+    eprintln!("{}", message);
+
+    let mut pending = PENDING.lock().unwrap();
+    if pending.len() == MAX_PENDING {
+        pending.remove(0);
+    }
+    pending.push(Notification { level, message });
+}
+
+/// Takes the notifications that weren't shown yet, oldest first
+pub fn take_pending() -> Vec<Notification> {
+    std::mem::take(&mut *PENDING.lock().unwrap())
+}
@@ -4,11 +4,13 @@ use std::sync::Arc;
 
 use vulkano::{
     device::{
-        physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, Features, Queue,
-        QueueCreateInfo, QueueFlags,
+        physical::{PhysicalDevice, PhysicalDeviceType},
+        Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
     },
     format::Format,
     instance::{Instance, InstanceCreateInfo, InstanceExtensions},
+    memory::MemoryHeapFlags,
+    pipeline::cache::PipelineCache,
     swapchain::Surface,
     sync::GpuFuture,
     Version, VulkanLibrary,
@@ -26,6 +28,11 @@ use winit::{
     window::{Fullscreen, Icon, Window, WindowBuilder},
 };
 
+use crate::{
+    notifications,
+    settings::{ColorSpace, WasabiSettings},
+};
+
 use self::swapchain::{ManagedSwapchain, SwapchainFrame};
 
 const ICON: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/icon.bitmap"));
@@ -37,15 +44,25 @@ pub struct Renderer {
     window: Arc<Window>,
     queue: Arc<Queue>,
     swap_chain: ManagedSwapchain,
+    pipeline_cache: Arc<PipelineCache>,
 }
 
 impl Renderer {
-    pub fn new(event_loop: &EventLoop<()>, name: &str, fullscreen: bool, mode: VideoMode) -> Self {
+    pub fn new(
+        event_loop: &EventLoop<()>,
+        name: &str,
+        fullscreen: bool,
+        mode: VideoMode,
+        color_space: ColorSpace,
+        gpu_device_index: usize,
+    ) -> Self {
         // Why
         let library = VulkanLibrary::new().unwrap();
 
         // Add instance extensions based on needs
+        // The HDR color spaces are only listed for the surface with this extension
         let instance_extensions = InstanceExtensions {
+            ext_swapchain_colorspace: library.supported_extensions().ext_swapchain_colorspace,
             ..vulkano_win::required_extensions(&library)
         };
 
@@ -82,12 +99,14 @@ impl Renderer {
             .with_title(name)
             .build(event_loop)
             .expect("Failed to create vulkan surface & window");
+        // Without this winit doesn't send the IME events, which egui needs for composing
+        // text like Japanese or Chinese in the text fields
+        window.set_ime_allowed(true);
         let window = Arc::new(window);
 
         let surface = create_surface_from_winit(window.clone(), instance.clone())
             .expect("Failed to create surface");
 
-        // Get most performant physical device (device with most memory)
         let device_extensions = DeviceExtensions {
             khr_swapchain: true,
             ..DeviceExtensions::empty()
@@ -97,11 +116,13 @@ impl Renderer {
             ..Features::empty()
         };
 
-        let (physical_device, queue_family_index) = instance
+        // The devices that can draw to the window, with the queue family that draws
+        let usable_devices = instance
             .enumerate_physical_devices()
             .unwrap()
-            .filter(|p| p.supported_extensions().contains(&device_extensions))
-            .filter_map(|p| {
+            .enumerate()
+            .filter(|(_, p)| p.supported_extensions().contains(&device_extensions))
+            .filter_map(|(index, p)| {
                 p.queue_family_properties()
                     .iter()
                     .enumerate()
@@ -109,16 +130,35 @@ impl Renderer {
                         q.queue_flags.contains(QueueFlags::GRAPHICS)
                             && p.surface_support(i as u32, &surface).unwrap_or(false)
                     })
-                    .map(|i| (p, i as u32))
+                    .map(|i| (index, p, i as u32))
             })
-            .min_by_key(|(p, _)| match p.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-                PhysicalDeviceType::Other => 4,
-                _ => 5,
+            .collect::<Vec<_>>();
+
+        // The index counts the devices from 1, 0 picks the most performant one
+        let chosen = gpu_device_index.checked_sub(1).and_then(|index| {
+            let device = usable_devices.iter().find(|(i, _, _)| *i == index);
+            if device.is_none() {
+                notifications::error(format!(
+                    "GPU {} can't be used, picking one automatically",
+                    gpu_device_index
+                ));
+            }
+            device
+        });
+        let (_, physical_device, queue_family_index) = chosen
+            .or_else(|| {
+                usable_devices
+                    .iter()
+                    .min_by_key(|(_, p, _)| match p.properties().device_type {
+                        PhysicalDeviceType::DiscreteGpu => 0,
+                        PhysicalDeviceType::IntegratedGpu => 1,
+                        PhysicalDeviceType::VirtualGpu => 2,
+                        PhysicalDeviceType::Cpu => 3,
+                        PhysicalDeviceType::Other => 4,
+                        _ => 5,
+                    })
             })
+            .cloned()
             .unwrap();
 
         println!(
@@ -158,10 +198,13 @@ impl Renderer {
             },
             #[cfg(not(target_os = "linux"))]
             crate::PRESENT_MODE,
+            color_space,
         );
 
         let queue = queues.next().unwrap();
 
+        let pipeline_cache = load_pipeline_cache(device.clone());
+
         Self {
             _instance: instance,
             device,
@@ -169,6 +212,7 @@ impl Renderer {
             queue,
             swap_chain,
             window,
+            pipeline_cache,
         }
     }
 
@@ -180,6 +224,26 @@ impl Renderer {
         self.device.clone()
     }
 
+    pub fn pipeline_cache(&self) -> Arc<PipelineCache> {
+        self.pipeline_cache.clone()
+    }
+
+    /// Writes the compiled pipelines to the config folder, so the next start doesn't
+    /// have to compile the shaders again
+    pub fn save_pipeline_cache(&self) {
+        let result = self
+            .pipeline_cache
+            .get_data()
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                std::fs::write(WasabiSettings::pipeline_cache_path(), data)
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            notifications::error(format!("Failed to save the pipeline cache: {}", e));
+        }
+    }
+
     pub fn surface(&self) -> Arc<Surface> {
         self.surface.clone()
     }
@@ -233,3 +297,39 @@ impl Renderer {
         frame.present(&self.queue, after_future);
     }
 }
+
+/// The name and VRAM of every GPU, in the order that the GPU setting counts them from 1
+pub fn describe_gpu_devices(instance: &Arc<Instance>) -> Vec<String> {
+    match instance.enumerate_physical_devices() {
+        Ok(devices) => devices.map(|device| describe_gpu_device(&device)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn describe_gpu_device(device: &PhysicalDevice) -> String {
+    let vram: u64 = device
+        .memory_properties()
+        .memory_heaps
+        .iter()
+        .filter(|heap| heap.flags.contains(MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+    format!(
+        "{} ({} MB)",
+        device.properties().device_name,
+        vram / (1024 * 1024)
+    )
+}
+
+/// Loads the pipelines that were compiled in an earlier run, or starts an empty
+/// cache if there are none yet
+fn load_pipeline_cache(device: Arc<Device>) -> Arc<PipelineCache> {
+    if let Ok(data) = std::fs::read(WasabiSettings::pipeline_cache_path()) {
+        // Safety: the data was written by `get_data` in an earlier run. The driver checks
+        // its header and ignores it when it was made by another device or driver version.
+        if let Ok(cache) = unsafe { PipelineCache::with_data(device.clone(), &data) } {
+            return cache;
+        }
+    }
+    PipelineCache::empty(device).expect("Failed to create pipeline cache")
+}
@@ -5,13 +5,15 @@ use vulkano::{
     format::Format,
     image::{view::ImageView, ImageUsage, SwapchainImage},
     swapchain::{
-        AcquireError, PresentMode, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo,
-        SwapchainCreationError, SwapchainPresentInfo,
+        AcquireError, ColorSpace as SurfaceColorSpace, PresentMode, Surface, Swapchain,
+        SwapchainAcquireFuture, SwapchainCreateInfo, SwapchainCreationError, SwapchainPresentInfo,
     },
     sync::{self, FlushError, GpuFuture},
 };
 use winit::{dpi::PhysicalSize, window::Window};
 
+use crate::{notifications, settings::ColorSpace};
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct ImagesState {
     pub count: usize,
@@ -32,6 +34,42 @@ pub struct ManagedSwapchain {
     recreate_on_next_frame: bool,
 }
 
+/// Picks the surface format for the color space in the settings, falling
+/// back to sRGB if the display doesn't support it
+fn choose_surface_format(
+    surface_formats: &[(Format, SurfaceColorSpace)],
+    color_space: ColorSpace,
+) -> (Format, SurfaceColorSpace) {
+    let wanted: &[(Format, SurfaceColorSpace)] = match color_space {
+        ColorSpace::Srgb => &[],
+        ColorSpace::LinearHdr => &[(
+            Format::R16G16B16A16_SFLOAT,
+            SurfaceColorSpace::ExtendedSrgbLinear,
+        )],
+    };
+
+    if let Some(format) = wanted
+        .iter()
+        .find(|format| surface_formats.contains(format))
+    {
+        println!("Color Space: {:?} ({:?})", format.1, format.0);
+        return *format;
+    }
+
+    if color_space != ColorSpace::Srgb {
+        notifications::error(format!(
+            "The {} color space isn't supported by the display, using sRGB instead",
+            color_space.as_str()
+        ));
+    }
+
+    surface_formats
+        .iter()
+        .find(|(_, space)| *space == SurfaceColorSpace::SrgbNonLinear)
+        .copied()
+        .unwrap_or(surface_formats[0])
+}
+
 impl ManagedSwapchain {
     pub fn create(
         surface: Arc<Surface>,
@@ -39,16 +77,17 @@ impl ManagedSwapchain {
         physical: Arc<PhysicalDevice>,
         device: Arc<Device>,
         present_mode: PresentMode,
+        color_space: ColorSpace,
     ) -> Self {
         let surface_capabilities = physical
             .surface_capabilities(&surface, Default::default())
             .unwrap();
-        let image_format = Some(
-            physical
-                .surface_formats(&surface, Default::default())
-                .unwrap()[0]
-                .0,
-        );
+        let surface_formats = physical
+            .surface_formats(&surface, Default::default())
+            .unwrap();
+        let (image_format, image_color_space) =
+            choose_surface_format(&surface_formats, color_space);
+        let image_format = Some(image_format);
         let image_extent = window.inner_size().into();
 
         let (swapchain, images) = Swapchain::new(
@@ -57,6 +96,7 @@ impl ManagedSwapchain {
             SwapchainCreateInfo {
                 min_image_count: surface_capabilities.min_image_count,
                 image_format,
+                image_color_space,
                 image_extent,
                 image_usage: ImageUsage::COLOR_ATTACHMENT,
                 composite_alpha: surface_capabilities
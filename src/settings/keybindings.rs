@@ -0,0 +1,292 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use egui::{Key, Modifiers};
+use serde::{de::Visitor, Deserializer, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+/// Something that can be done with a keyboard shortcut
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WasabiAction {
+    TogglePause,
+    SeekForward,
+    SeekBackward,
+    IncreaseNoteSpeed,
+    DecreaseNoteSpeed,
+    ToggleTopPanel,
+    ToggleStatistics,
+    ToggleFullscreen,
+    UndoMidiLoad,
+}
+
+impl WasabiAction {
+    pub const ALL: [WasabiAction; 9] = [
+        WasabiAction::TogglePause,
+        WasabiAction::SeekForward,
+        WasabiAction::SeekBackward,
+        WasabiAction::IncreaseNoteSpeed,
+        WasabiAction::DecreaseNoteSpeed,
+        WasabiAction::ToggleTopPanel,
+        WasabiAction::ToggleStatistics,
+        WasabiAction::ToggleFullscreen,
+        WasabiAction::UndoMidiLoad,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WasabiAction::TogglePause => "Play/Pause",
+            WasabiAction::SeekForward => "Seek Forward",
+            WasabiAction::SeekBackward => "Seek Backward",
+            WasabiAction::IncreaseNoteSpeed => "Increase Note Speed",
+            WasabiAction::DecreaseNoteSpeed => "Decrease Note Speed",
+            WasabiAction::ToggleTopPanel => "Toggle Top Panel",
+            WasabiAction::ToggleStatistics => "Toggle Statistics",
+            WasabiAction::ToggleFullscreen => "Toggle Fullscreen",
+            WasabiAction::UndoMidiLoad => "Reopen Previous MIDI",
+        }
+    }
+
+    fn default_binding(&self) -> KeyBinding {
+        match self {
+            WasabiAction::TogglePause => KeyBinding::new(Key::Space, Modifiers::NONE),
+            WasabiAction::SeekForward => KeyBinding::new(Key::ArrowRight, Modifiers::NONE),
+            WasabiAction::SeekBackward => KeyBinding::new(Key::ArrowLeft, Modifiers::NONE),
+            WasabiAction::IncreaseNoteSpeed => KeyBinding::new(Key::ArrowUp, Modifiers::NONE),
+            WasabiAction::DecreaseNoteSpeed => KeyBinding::new(Key::ArrowDown, Modifiers::NONE),
+            WasabiAction::ToggleTopPanel => KeyBinding::new(Key::F, Modifiers::CTRL),
+            WasabiAction::ToggleStatistics => KeyBinding::new(Key::G, Modifiers::CTRL),
+            WasabiAction::ToggleFullscreen => KeyBinding::new(Key::Enter, Modifiers::ALT),
+            WasabiAction::UndoMidiLoad => KeyBinding::new(Key::Z, Modifiers::CTRL),
+        }
+    }
+}
+
+/// The names of the keys that can be bound, as they are written in the config
+const KEY_NAMES: [(Key, &str); 73] = [
+    (Key::ArrowDown, "Down"),
+    (Key::ArrowLeft, "Left"),
+    (Key::ArrowRight, "Right"),
+    (Key::ArrowUp, "Up"),
+    (Key::Escape, "Escape"),
+    (Key::Tab, "Tab"),
+    (Key::Backspace, "Backspace"),
+    (Key::Enter, "Enter"),
+    (Key::Space, "Space"),
+    (Key::Insert, "Insert"),
+    (Key::Delete, "Delete"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::Minus, "Minus"),
+    (Key::PlusEquals, "Plus"),
+    (Key::Num0, "0"),
+    (Key::Num1, "1"),
+    (Key::Num2, "2"),
+    (Key::Num3, "3"),
+    (Key::Num4, "4"),
+    (Key::Num5, "5"),
+    (Key::Num6, "6"),
+    (Key::Num7, "7"),
+    (Key::Num8, "8"),
+    (Key::Num9, "9"),
+    (Key::A, "A"),
+    (Key::B, "B"),
+    (Key::C, "C"),
+    (Key::D, "D"),
+    (Key::E, "E"),
+    (Key::F, "F"),
+    (Key::G, "G"),
+    (Key::H, "H"),
+    (Key::I, "I"),
+    (Key::J, "J"),
+    (Key::K, "K"),
+    (Key::L, "L"),
+    (Key::M, "M"),
+    (Key::N, "N"),
+    (Key::O, "O"),
+    (Key::P, "P"),
+    (Key::Q, "Q"),
+    (Key::R, "R"),
+    (Key::S, "S"),
+    (Key::T, "T"),
+    (Key::U, "U"),
+    (Key::V, "V"),
+    (Key::W, "W"),
+    (Key::X, "X"),
+    (Key::Y, "Y"),
+    (Key::Z, "Z"),
+    (Key::F1, "F1"),
+    (Key::F2, "F2"),
+    (Key::F3, "F3"),
+    (Key::F4, "F4"),
+    (Key::F5, "F5"),
+    (Key::F6, "F6"),
+    (Key::F7, "F7"),
+    (Key::F8, "F8"),
+    (Key::F9, "F9"),
+    (Key::F10, "F10"),
+    (Key::F11, "F11"),
+    (Key::F12, "F12"),
+    (Key::F13, "F13"),
+    (Key::F14, "F14"),
+    (Key::F15, "F15"),
+    (Key::F16, "F16"),
+    (Key::F17, "F17"),
+    (Key::F18, "F18"),
+    (Key::F19, "F19"),
+    (Key::F20, "F20"),
+];
+
+/// A key together with the modifiers that have to be held for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub key: Key,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyBinding {
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        Self {
+            key,
+            ctrl: modifiers.ctrl || modifiers.command,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+        }
+    }
+
+    /// Whether the key was pressed with at least the modifiers of the binding
+    fn is_pressed(&self, key: Key, modifiers: &Modifiers) -> bool {
+        self.key == key
+            && (!self.ctrl || modifiers.ctrl || modifiers.command)
+            && (!self.alt || modifiers.alt)
+            && (!self.shift || modifiers.shift)
+    }
+
+    fn modifier_count(&self) -> usize {
+        self.ctrl as usize + self.alt as usize + self.shift as usize
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        let name = KEY_NAMES
+            .iter()
+            .find(|(key, _)| *key == self.key)
+            .map(|(_, name)| *name)
+            .unwrap_or("?");
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut binding = KeyBinding::new(Key::Space, Modifiers::NONE);
+        let mut parts: Vec<&str> = s.split('+').map(|part| part.trim()).collect();
+        let key_name = parts.pop().unwrap_or_default();
+
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => binding.ctrl = true,
+                "alt" => binding.alt = true,
+                "shift" => binding.shift = true,
+                m => {
+                    return Err(format!(
+                        "{} was not expected. Expected one of `ctrl`, `alt`, or `shift`",
+                        m
+                    ))
+                }
+            }
+        }
+
+        binding.key = KEY_NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(key_name))
+            .map(|(key, _)| *key)
+            .ok_or_else(|| format!("{} is not a key that can be bound", key_name))?;
+        Ok(binding)
+    }
+}
+
+impl serde::Serialize for KeyBinding {
+    fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        ser.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for KeyBinding {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BindingVisitor;
+
+        impl<'de> Visitor<'de> for BindingVisitor {
+            type Value = KeyBinding;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("A key binding like `Ctrl+F`")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                v.parse().map_err(|e: String| E::custom(e))
+            }
+        }
+
+        de.deserialize_str(BindingVisitor)
+    }
+}
+
+/// The keyboard shortcut of every action. Actions that aren't in the config
+/// keep their default binding.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct KeyBindings(BTreeMap<WasabiAction, KeyBinding>);
+
+impl KeyBindings {
+    pub fn get(&self, action: WasabiAction) -> KeyBinding {
+        self.0
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+
+    pub fn set(&mut self, action: WasabiAction, binding: KeyBinding) {
+        self.0.insert(action, binding);
+    }
+
+    pub fn reset(&mut self) {
+        self.0.clear();
+    }
+
+    /// The action of a key press. When several bindings use the key, the one with
+    /// the most matching modifiers wins, so `Ctrl+F` isn't also taken as `F`, while
+    /// modifiers that aren't part of a binding can still be held, like Shift to seek further.
+    pub fn action_for(&self, key: Key, modifiers: &Modifiers) -> Option<WasabiAction> {
+        WasabiAction::ALL
+            .iter()
+            .map(|action| (*action, self.get(*action)))
+            .filter(|(_, binding)| binding.is_pressed(key, modifiers))
+            .max_by_key(|(_, binding)| binding.modifier_count())
+            .map(|(action, _)| action)
+    }
+}
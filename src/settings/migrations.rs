@@ -46,12 +46,14 @@ impl WasabiConfigFileV0 {
                     use_effects: cfg.use_effects,
                     sfz_path: cfg.sfz_path,
                     vel_ignore: cfg.vel_ignore_lo..=cfg.vel_ignore_hi,
+                    ..Default::default()
                 },
                 midi: MidiSettings {
                     note_speed: cfg.note_speed,
                     random_colors: cfg.random_colors,
                     key_range: cfg.first_key..=cfg.last_key,
                     midi_loading: MidiLoading::from(cfg.midi_loading),
+                    ..Default::default()
                 },
                 visual: VisualSettings {
                     bg_color: Color32::from_rgb(
@@ -64,11 +66,9 @@ impl WasabiConfigFileV0 {
                         bar.get_green() as u8,
                         bar.get_blue() as u8,
                     ),
-                    show_top_pannel: true,
-                    show_statistics: true,
-                    fullscreen: false,
+                    ..Default::default()
                 },
-                load_midi_file: None,
+                ..Default::default()
             })
         } else {
             Ok(WasabiSettings::default())
@@ -1,3 +1,4 @@
+use bitflags::bitflags;
 use clap::{parser::ValueSource, value_parser, Arg, ArgAction, Command, ValueHint};
 use colors_transform::{Color, Rgb};
 use directories::BaseDirs;
@@ -5,17 +6,26 @@ use egui::Color32;
 use num_enum::FromPrimitive;
 use serde_derive::{Deserialize, Serialize};
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
     fs,
     io::Write,
     ops::RangeInclusive,
     path::{Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 use xsynth_core::{channel::ChannelInitOptions, soundfont::SoundfontInitOptions};
 use xsynth_realtime::config::XSynthRealtimeConfig;
 
+use crate::midi::MUSICXML_GRIDS;
+
+mod keybindings;
 mod migrations;
+mod overrides;
+
+pub use keybindings::{KeyBinding, KeyBindings, WasabiAction};
+pub use overrides::SettingsOverride;
 
 #[inline(always)]
 fn f64_parser(s: &str) -> Result<f64, String> {
@@ -52,6 +62,56 @@ fn range_parser(s: &str) -> Result<RangeInclusive<u8>, String> {
         ..=range.1.parse().map_err(|e| format!("{}", e))?)
 }
 
+#[inline(always)]
+fn event_filter_parser(s: &str) -> Result<EventFilterFlags, String> {
+    let mut filter = EventFilterFlags::all();
+    for name in s
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+    {
+        let (flag, _) = EventFilterFlags::NAMES
+            .iter()
+            .find(|(_, option)| option.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                format!(
+                    "{} was not expected. Expected one of `note-on`, `note-off`, \
+                    `pitch-bend`, `cc` or `program-change`",
+                    name
+                )
+            })?;
+        filter.remove(*flag);
+    }
+    Ok(filter)
+}
+
+#[inline(always)]
+fn cc_list_parser(s: &str) -> Result<Vec<u8>, String> {
+    s.split(',')
+        .filter(|cc| !cc.trim().is_empty())
+        .map(|cc| match cc.trim().parse::<u8>() {
+            Ok(cc) if cc < 128 => Ok(cc),
+            _ => Err(format!(
+                "{} is not a controller number from 0 to 127",
+                cc.trim()
+            )),
+        })
+        .collect()
+}
+
+#[inline(always)]
+fn channel_mask_parser(s: &str) -> Result<u16, String> {
+    s.split(',')
+        .filter(|channel| !channel.trim().is_empty())
+        .try_fold(0, |mask, channel| match channel.trim().parse::<u8>() {
+            Ok(channel @ 1..=16) => Ok(mask | 1 << (channel - 1)),
+            _ => Err(format!(
+                "{} is not a channel number from 1 to 16",
+                channel.trim()
+            )),
+        })
+}
+
 mod color32_serde {
     use colors_transform::Rgb;
     use egui::Color32;
@@ -95,6 +155,34 @@ mod color32_serde {
     }
 }
 
+mod color32_vec_serde {
+    use egui::Color32;
+    use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct HexColor(#[serde(with = "super::color32_serde")] Color32);
+
+    pub fn serialize<S>(colors: &[Color32], ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = ser.serialize_seq(Some(colors.len()))?;
+        for color in colors {
+            seq.serialize_element(&HexColor(*color))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D>(de: D) -> Result<Vec<Color32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let colors = Vec::<HexColor>::deserialize(de)?;
+        Ok(colors.into_iter().map(|color| color.0).collect())
+    }
+}
+
 mod range_serde {
     use std::ops::RangeInclusive;
 
@@ -206,335 +294,2264 @@ impl FromStr for MidiLoading {
     }
 }
 
+/// How broken note events are fixed while a MIDI is loaded
 #[repr(usize)]
-#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
 #[serde(rename_all = "lowercase")]
-pub enum Synth {
+pub enum MidiRepairMode {
     #[default]
-    XSynth = 0,
-    Kdmapi = 1,
+    Off = 0,
+    Duplicates = 1,
+    Full = 2,
 }
 
-impl Synth {
-    #[inline]
+impl MidiRepairMode {
     pub const fn as_str(self) -> &'static str {
         match self {
-            Synth::XSynth => "XSynth",
-            Synth::Kdmapi => "KDMAPI",
+            MidiRepairMode::Off => "Off",
+            MidiRepairMode::Duplicates => "Remove Duplicates",
+            MidiRepairMode::Full => "Full",
         }
     }
 }
 
-impl FromStr for Synth {
+impl FromStr for MidiRepairMode {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "xsynth" => Ok(Synth::XSynth),
-            "kdmapi" => Ok(Synth::Kdmapi),
+            "off" => Ok(MidiRepairMode::Off),
+            "duplicates" => Ok(MidiRepairMode::Duplicates),
+            "full" => Ok(MidiRepairMode::Full),
             s => Err(format!(
-                "{} was not expected. Expected one of `xsynth`, or `kdmapi`",
+                "{} was not expected. Expected one of `off`, `duplicates` or `full`",
                 s
             )),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(default)]
-pub struct VisualSettings {
-    #[serde(with = "color32_serde")]
-    pub bg_color: Color32,
-    #[serde(with = "color32_serde")]
-    pub bar_color: Color32,
-    pub show_top_pannel: bool,
-    pub show_statistics: bool,
-    pub fullscreen: bool,
+bitflags! {
+    /// The kinds of MIDI events that are sent to the synth, the others are dropped
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(transparent)]
+    pub struct EventFilterFlags: u8 {
+        const NOTE_ON = 1 << 0;
+        const NOTE_OFF = 1 << 1;
+        const PITCH_BEND = 1 << 2;
+        const CONTROL_CHANGE = 1 << 3;
+        const PROGRAM_CHANGE = 1 << 4;
+    }
 }
 
-impl Default for VisualSettings {
+impl EventFilterFlags {
+    /// Every flag with the name it has on the command line
+    pub const NAMES: [(EventFilterFlags, &'static str); 5] = [
+        (EventFilterFlags::NOTE_ON, "note-on"),
+        (EventFilterFlags::NOTE_OFF, "note-off"),
+        (EventFilterFlags::PITCH_BEND, "pitch-bend"),
+        (EventFilterFlags::CONTROL_CHANGE, "cc"),
+        (EventFilterFlags::PROGRAM_CHANGE, "program-change"),
+    ];
+
+    /// Whether an event, packed the way the synth takes it, is let through.
+    /// Note ons without velocity count as note offs, like they do for the synth.
+    pub fn allows(self, data: u32) -> bool {
+        let flag = match data & 0xF0 {
+            0x80 => EventFilterFlags::NOTE_OFF,
+            0x90 if (data >> 16) & 0xFF == 0 => EventFilterFlags::NOTE_OFF,
+            0x90 => EventFilterFlags::NOTE_ON,
+            0xB0 => EventFilterFlags::CONTROL_CHANGE,
+            0xC0 => EventFilterFlags::PROGRAM_CHANGE,
+            0xE0 => EventFilterFlags::PITCH_BEND,
+            _ => return true,
+        };
+        self.contains(flag)
+    }
+}
+
+impl Default for EventFilterFlags {
     fn default() -> Self {
-        VisualSettings {
-            bg_color: Color32::from_rgb(30, 30, 30),
-            bar_color: Color32::from_rgb(145, 0, 0),
-            show_top_pannel: true,
-            show_statistics: true,
-            fullscreen: false,
+        EventFilterFlags::all()
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteShape {
+    #[default]
+    Rectangle = 0,
+    Circle = 1,
+    Diamond = 2,
+}
+
+impl NoteShape {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            NoteShape::Rectangle => "Rectangle",
+            NoteShape::Circle => "Circle",
+            NoteShape::Diamond => "Diamond",
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(default)]
-pub struct MidiSettings {
-    pub note_speed: f64,
-    pub random_colors: bool,
-    #[serde(with = "range_serde")]
-    pub key_range: RangeInclusive<u8>,
-    pub midi_loading: MidiLoading,
+impl FromStr for NoteShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rectangle" => Ok(NoteShape::Rectangle),
+            "circle" => Ok(NoteShape::Circle),
+            "diamond" => Ok(NoteShape::Diamond),
+            s => Err(format!(
+                "{} was not expected. Expected one of `rectangle`, `circle` or `diamond`",
+                s
+            )),
+        }
+    }
 }
 
-impl Default for MidiSettings {
-    fn default() -> Self {
-        MidiSettings {
-            note_speed: 0.25,
-            random_colors: false,
-            key_range: 0..=127,
-            midi_loading: MidiLoading::Ram,
+/// The order the notes of a key are drawn in. Notes at the same depth keep whichever was
+/// drawn first, so the notes early in the order end up on top where they overlap.
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteRenderOrder {
+    #[default]
+    FileOrder = 0,
+    EarliestFirst = 1,
+    LatestFirst = 2,
+    ByTrackDescending = 3,
+}
+
+impl NoteRenderOrder {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            NoteRenderOrder::FileOrder => "File Order",
+            NoteRenderOrder::EarliestFirst => "Earliest First",
+            NoteRenderOrder::LatestFirst => "Latest First",
+            NoteRenderOrder::ByTrackDescending => "By Track (Descending)",
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(default)]
-pub struct SynthSettings {
-    pub synth: Synth,
-    pub buffer_ms: f64,
-    pub sfz_path: String,
-    pub limit_layers: bool,
-    pub layer_count: usize,
-    #[serde(with = "range_serde")]
-    pub vel_ignore: RangeInclusive<u8>,
-    pub fade_out_kill: bool,
-    pub linear_envelope: bool,
-    pub use_effects: bool,
+impl FromStr for NoteRenderOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fileorder" => Ok(NoteRenderOrder::FileOrder),
+            "earliestfirst" => Ok(NoteRenderOrder::EarliestFirst),
+            "latestfirst" => Ok(NoteRenderOrder::LatestFirst),
+            "bytrackdescending" => Ok(NoteRenderOrder::ByTrackDescending),
+            s => Err(format!(
+                "{} was not expected. Expected one of `fileorder`, `earliestfirst`, \
+                `latestfirst` or `bytrackdescending`",
+                s
+            )),
+        }
+    }
 }
 
-impl Default for SynthSettings {
-    fn default() -> Self {
-        SynthSettings {
-            synth: Synth::XSynth,
-            buffer_ms: XSynthRealtimeConfig::default().render_window_ms,
-            sfz_path: String::new(),
-            limit_layers: true,
-            layer_count: 4,
-            vel_ignore: 0..=0,
-            fade_out_kill: ChannelInitOptions::default().fade_out_killing,
-            linear_envelope: SoundfontInitOptions::default().linear_release,
-            use_effects: SoundfontInitOptions::default().use_effects,
+/// Which keys of the keyboard get their note name written on them
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyLabelMode {
+    #[default]
+    None = 0,
+    /// Every key, with the black keys named as flats
+    All = 1,
+    /// Only the C of every octave
+    OctaveC = 2,
+    /// Every key, with the black keys named as sharps
+    ChromaticSharps = 3,
+}
+
+impl KeyLabelMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            KeyLabelMode::None => "None",
+            KeyLabelMode::All => "All Keys (Flats)",
+            KeyLabelMode::OctaveC => "Octave C",
+            KeyLabelMode::ChromaticSharps => "All Keys (Sharps)",
+        }
+    }
+
+    /// Whether a key gets a label in this mode
+    pub fn labels(self, key: usize) -> bool {
+        match self {
+            KeyLabelMode::None => false,
+            KeyLabelMode::All | KeyLabelMode::ChromaticSharps => true,
+            KeyLabelMode::OctaveC => key % 12 == 0,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
-#[serde(default)]
-pub struct WasabiSettings {
-    pub synth: SynthSettings,
-    pub midi: MidiSettings,
-    pub visual: VisualSettings,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub load_midi_file: Option<String>,
+impl FromStr for KeyLabelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(KeyLabelMode::None),
+            "all" => Ok(KeyLabelMode::All),
+            "octavec" => Ok(KeyLabelMode::OctaveC),
+            "chromaticsharps" => Ok(KeyLabelMode::ChromaticSharps),
+            s => Err(format!(
+                "{} was not expected. Expected one of `none`, `all`, `octavec` or \
+                `chromaticsharps`",
+                s
+            )),
+        }
+    }
 }
 
-static CONFIG_PATH: &str = "wasabi-config.toml";
+/// What the time ruler next to the notes counts in
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeRulerMode {
+    /// Minutes and seconds from the start
+    #[default]
+    Seconds = 0,
+    /// Bars and beats, following the tempo and the time signatures
+    Musical = 1,
+}
 
-impl WasabiSettings {
-    pub fn new_or_load() -> Self {
-        let config_path = Self::get_config_path();
-        let mut config = if !Path::new(&config_path).exists() {
-            Self::load_and_save_defaults()
-        } else {
-            let config = fs::read_to_string(&config_path).unwrap();
-            if config.starts_with('#') {
-                if let Ok(config) = toml::from_str(&config) {
-                    config
-                } else {
-                    Self::load_and_save_defaults()
-                }
-            } else {
-                let config = migrations::WasabiConfigFileV0::migrate().unwrap_or_default();
-                config.save_to_file();
-                config
-            }
-        };
+impl TimeRulerMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            TimeRulerMode::Seconds => "Seconds",
+            TimeRulerMode::Musical => "Bars and Beats",
+        }
+    }
+}
 
-        config.augment_from_args();
-        config
+impl FromStr for TimeRulerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "seconds" => Ok(TimeRulerMode::Seconds),
+            "musical" => Ok(TimeRulerMode::Musical),
+            s => Err(format!(
+                "{} was not expected. Expected one of `seconds` or `musical`",
+                s
+            )),
+        }
     }
+}
 
-    pub fn save_to_file(&self) {
-        let config_path = Self::get_config_path();
-        let toml: String = toml::to_string(&self).unwrap();
-        if Path::new(&config_path).exists() {
-            fs::remove_file(&config_path).expect("Error deleting old config");
+/// Where on the notes the lyrics are shown
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum LyricsPosition {
+    Top = 0,
+    Center = 1,
+    #[default]
+    Bottom = 2,
+}
+
+impl LyricsPosition {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            LyricsPosition::Top => "Top",
+            LyricsPosition::Center => "Center",
+            LyricsPosition::Bottom => "Bottom",
         }
-        let mut file = fs::File::create(&config_path).unwrap();
-        file.write_all(b"# DON'T EDIT THIS LINE; Version: 1\n\n")
-            .unwrap();
-        file.write_all(toml.as_bytes())
-            .expect("Error creating config");
     }
+}
 
-    fn augment_from_args(&mut self) {
-        let matches = Command::new("wasabi")
-            .version(env!("CARGO_PKG_VERSION"))
-            .author(env!("CARGO_PKG_AUTHORS"))
-            .about(env!("CARGO_PKG_DESCRIPTION"))
-            .arg(
-                Arg::new("synth")
-                    .help("The synthesizer to use")
-                    .long_help(
-                        "The synthesizer that is used to play the MIDI. \
-                        This can either be XSynth (recommended) or KDMAPI. KDMAPI \
-                        only works if you have OmniMIDI installed, and are using Windows",
-                    )
-                    .short('S')
-                    .long("synth")
-                    .value_parser(Synth::from_str),
-            )
-            .arg(
-                Arg::new("buffer-ms")
-                    .help("The amount of time events are held in the buffer")
-                    .long_help(
-                        "The amount of time that events are held in the \
-                        buffer before being played. Higher numbers may increase \
-                        performance but also increase latency.",
-                    )
-                    .short('b')
-                    .long("buffer-ms")
-                    .value_parser(f64_parser),
-            )
-            .arg(
-                Arg::new("sfz-path")
+impl FromStr for LyricsPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "top" => Ok(LyricsPosition::Top),
+            "center" => Ok(LyricsPosition::Center),
+            "bottom" => Ok(LyricsPosition::Bottom),
+            s => Err(format!(
+                "{} was not expected. Expected one of `top`, `center` or `bottom`",
+                s
+            )),
+        }
+    }
+}
+
+/// How the notes of the General MIDI percussion channel are shown. Drum pads take
+/// them out of the key columns and light up a labeled pad for each drum instead.
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum PercussionDisplayMode {
+    #[default]
+    Piano = 0,
+    DrumPad = 1,
+}
+
+impl PercussionDisplayMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            PercussionDisplayMode::Piano => "Piano",
+            PercussionDisplayMode::DrumPad => "Drum Pads",
+        }
+    }
+}
+
+impl FromStr for PercussionDisplayMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "piano" => Ok(PercussionDisplayMode::Piano),
+            "drumpad" => Ok(PercussionDisplayMode::DrumPad),
+            s => Err(format!(
+                "{} was not expected. Expected one of `piano` or `drumpad`",
+                s
+            )),
+        }
+    }
+}
+
+/// Which notes are played by the right hand and which by the left, for piano MIDIs.
+/// The two hands get their own colors over every other way of coloring the notes.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum HandSplitMode {
+    #[default]
+    None,
+    /// The channels of the hands, counted from 0. Notes on other channels keep their color.
+    ByChannel { right: u8, left: u8 },
+    /// The right hand plays the keys from `split_key` up
+    ByPitch { split_key: u8 },
+}
+
+impl HandSplitMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            HandSplitMode::None => "None",
+            HandSplitMode::ByChannel { .. } => "By Channel",
+            HandSplitMode::ByPitch { .. } => "By Pitch",
+        }
+    }
+
+    /// Whether a note is played by the right hand, or `None` if it isn't split
+    pub fn is_right_hand(self, channel: u8, key: u8) -> Option<bool> {
+        match self {
+            HandSplitMode::None => None,
+            HandSplitMode::ByChannel { right, .. } if channel == right => Some(true),
+            HandSplitMode::ByChannel { left, .. } if channel == left => Some(false),
+            HandSplitMode::ByChannel { .. } => None,
+            HandSplitMode::ByPitch { split_key } => Some(key >= split_key),
+        }
+    }
+}
+
+impl FromStr for HandSplitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let (mode, args) = s.split_once(':').unwrap_or((s.as_str(), ""));
+        let expected = "Expected one of `none`, `channel:<right>,<left>` or `pitch:<key>`";
+        match mode {
+            "none" => Ok(HandSplitMode::None),
+            "channel" => {
+                let channel = |c: Option<&str>| {
+                    c.and_then(|c| c.trim().parse::<u8>().ok())
+                        .filter(|c| (1..=16).contains(c))
+                        .map(|c| c - 1)
+                        .ok_or_else(|| format!("{} doesn't have two channels from 1 to 16", s))
+                };
+                let mut channels = args.split(',');
+                Ok(HandSplitMode::ByChannel {
+                    right: channel(channels.next())?,
+                    left: channel(channels.next())?,
+                })
+            }
+            "pitch" => match args.trim().parse::<u8>() {
+                Ok(split_key) if split_key < 128 => Ok(HandSplitMode::ByPitch { split_key }),
+                _ => Err(format!("{} doesn't have a key from 0 to 127", s)),
+            },
+            _ => Err(format!("{} was not expected. {}", s, expected)),
+        }
+    }
+}
+
+/// How much every note is shortened, so notes that follow each other on a key don't merge
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteGap {
+    /// A gap of this many pixels, from 0 to 5. Notes keep at least a pixel of height.
+    Pixels { pixels: f32 },
+    /// Every note ends this many ticks earlier, counted with the tempo where it ends.
+    /// Notes keep at least half of their length.
+    Ticks { ticks: u32 },
+}
+
+impl Default for NoteGap {
+    fn default() -> Self {
+        NoteGap::Pixels { pixels: 0.0 }
+    }
+}
+
+impl NoteGap {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            NoteGap::Pixels { .. } => "Pixels",
+            NoteGap::Ticks { .. } => "Ticks",
+        }
+    }
+}
+
+impl FromStr for NoteGap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        if let Some(ticks) = s.strip_suffix("ticks") {
+            match ticks.trim().parse::<u32>() {
+                Ok(ticks) => Ok(NoteGap::Ticks {
+                    ticks: ticks.min(960),
+                }),
+                Err(_) => Err(format!("{} doesn't have a whole number of ticks", s)),
+            }
+        } else {
+            let pixels = s.strip_suffix("px").unwrap_or(&s);
+            match pixels.trim().parse::<f32>() {
+                Ok(pixels) => Ok(NoteGap::Pixels {
+                    pixels: pixels.clamp(0.0, 5.0),
+                }),
+                Err(_) => Err(format!(
+                    "{} was not expected. Expected pixels like `2px` or ticks like `10ticks`",
+                    s
+                )),
+            }
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum WatermarkCorner {
+    TopLeft = 0,
+    TopRight = 1,
+    BottomLeft = 2,
+    #[default]
+    BottomRight = 3,
+}
+
+impl WatermarkCorner {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            WatermarkCorner::TopLeft => "Top Left",
+            WatermarkCorner::TopRight => "Top Right",
+            WatermarkCorner::BottomLeft => "Bottom Left",
+            WatermarkCorner::BottomRight => "Bottom Right",
+        }
+    }
+}
+
+impl FromStr for WatermarkCorner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "topleft" => Ok(WatermarkCorner::TopLeft),
+            "topright" => Ok(WatermarkCorner::TopRight),
+            "bottomleft" => Ok(WatermarkCorner::BottomLeft),
+            "bottomright" => Ok(WatermarkCorner::BottomRight),
+            s => Err(format!(
+                "{} was not expected. Expected one of `topleft`, `topright`, `bottomleft` \
+                or `bottomright`",
+                s
+            )),
+        }
+    }
+}
+
+/// A line of text drawn in a corner of the notes, like a credit for the MIDI
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WatermarkConfig {
+    pub text: String,
+    pub font_size: f32,
+    pub opacity: f32,
+    pub corner: WatermarkCorner,
+    /// Slowly pulses the opacity of the text
+    pub animated: bool,
+}
+
+impl Default for WatermarkConfig {
+    fn default() -> Self {
+        WatermarkConfig {
+            text: String::new(),
+            font_size: 18.0,
+            opacity: 0.5,
+            corner: WatermarkCorner::BottomRight,
+            animated: false,
+        }
+    }
+}
+
+/// What is drawn behind the notes, on top of the background color
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundMode {
+    #[default]
+    SolidColor,
+    Image(PathBuf),
+    Video(PathBuf),
+}
+
+impl BackgroundMode {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            BackgroundMode::SolidColor => "Solid Color",
+            BackgroundMode::Image(..) => "Image",
+            BackgroundMode::Video(..) => "Video",
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            BackgroundMode::SolidColor => None,
+            BackgroundMode::Image(path) | BackgroundMode::Video(path) => Some(path),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    #[default]
+    Srgb = 0,
+    /// Extended sRGB with linear values. BT.2020 isn't offered, since the colors would
+    /// need to be encoded with the PQ curve, so configs that asked for it get this.
+    #[serde(alias = "bt2020")]
+    LinearHdr = 1,
+}
+
+impl ColorSpace {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::LinearHdr => "Linear HDR",
+        }
+    }
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "srgb" => Ok(ColorSpace::Srgb),
+            "linearhdr" => Ok(ColorSpace::LinearHdr),
+            s => Err(format!(
+                "{} was not expected. Expected one of `srgb` or `linearhdr`",
+                s
+            )),
+        }
+    }
+}
+
+/// The colors the loudness of a frequency is drawn with in the spectrogram
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum SpectrogramColorMap {
+    #[default]
+    Magma = 0,
+    Rainbow = 1,
+    Grayscale = 2,
+}
+
+impl SpectrogramColorMap {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            SpectrogramColorMap::Magma => "Magma",
+            SpectrogramColorMap::Rainbow => "Rainbow",
+            SpectrogramColorMap::Grayscale => "Grayscale",
+        }
+    }
+}
+
+impl FromStr for SpectrogramColorMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "magma" => Ok(SpectrogramColorMap::Magma),
+            "rainbow" => Ok(SpectrogramColorMap::Rainbow),
+            "grayscale" => Ok(SpectrogramColorMap::Grayscale),
+            s => Err(format!(
+                "{} was not expected. Expected one of `magma`, `rainbow` or `grayscale`",
+                s
+            )),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[serde(rename_all = "lowercase")]
+pub enum Synth {
+    #[default]
+    XSynth = 0,
+    Kdmapi = 1,
+    Jack = 2,
+}
+
+impl Synth {
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Synth::XSynth => "XSynth",
+            Synth::Kdmapi => "KDMAPI",
+            Synth::Jack => "JACK",
+        }
+    }
+}
+
+impl FromStr for Synth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "xsynth" => Ok(Synth::XSynth),
+            "kdmapi" => Ok(Synth::Kdmapi),
+            "jack" => Ok(Synth::Jack),
+            s => Err(format!(
+                "{} was not expected. Expected one of `xsynth`, `kdmapi`, or `jack`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VisualSettings {
+    #[serde(with = "color32_serde")]
+    pub bg_color: Color32,
+    #[serde(with = "color32_serde")]
+    pub bar_color: Color32,
+    #[serde(with = "color32_serde")]
+    pub keyboard_white_key_color: Color32,
+    #[serde(with = "color32_serde")]
+    pub keyboard_black_key_color: Color32,
+    #[serde(with = "color32_serde")]
+    pub keyboard_white_key_pressed_color: Color32,
+    #[serde(with = "color32_serde")]
+    pub keyboard_black_key_pressed_color: Color32,
+    pub keyboard_heatmap: bool,
+    pub keyboard_key_labels: KeyLabelMode,
+    /// The width of the black keys relative to the white keys
+    pub black_key_width_ratio: f32,
+    /// How far down the keyboard the black keys reach
+    pub black_key_height_ratio: f32,
+    #[serde(with = "color32_vec_serde")]
+    pub heatmap_gradient: Vec<Color32>,
+    pub show_top_pannel: bool,
+    pub show_statistics: bool,
+    pub safe_zone_margin_px: u32,
+    pub fullscreen: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ui_font_path: Option<PathBuf>,
+    pub ui_font_size: f32,
+    pub bloom_enabled: bool,
+    pub bloom_radius: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dim_after_secs: Option<u64>,
+    pub show_mini_keyboard: bool,
+    pub waterfall_mode: bool,
+    /// Puts the low keys where the high keys usually are, at the top in waterfall mode
+    pub invert_pitch_axis: bool,
+    pub inner_glow_intensity: f32,
+    pub inner_glow_color: [f32; 4],
+    pub note_shape: NoteShape,
+    pub note_render_order: NoteRenderOrder,
+    pub percussion_display_mode: PercussionDisplayMode,
+    pub hand_split_mode: HandSplitMode,
+    pub note_gap: NoteGap,
+    pub min_note_height_px: f32,
+    pub spotlight_mode: bool,
+    pub length_opacity_mode: bool,
+    pub length_opacity_min_ms: f32,
+    pub length_opacity_max_ms: f32,
+    pub msaa_samples: u32,
+    /// The size of the depth buffer of the notes relative to the frame, 0.5 or 1.0
+    pub depth_buffer_scale: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub echo_beats: Option<f32>,
+    pub particles_per_note: u32,
+    pub particle_lifetime_ms: f32,
+    pub inactive_channel_alpha: f32,
+    pub normalize_percussion_length: bool,
+    pub normalized_length_ms: f32,
+    pub chord_grouping: bool,
+    pub chord_tolerance_ms: f32,
+    pub arpeggio_view: bool,
+    pub arpeggio_offset_ms: f32,
+    pub color_space: ColorSpace,
+    pub background: BackgroundMode,
+    pub background_opacity: f32,
+    pub show_spectrogram: bool,
+    pub spectrogram_fft_size: usize,
+    pub spectrogram_color_map: SpectrogramColorMap,
+    pub spectrogram_height: f32,
+    pub use_color_script: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub twitch_chat_channel: Option<String>,
+    pub cc_lanes: Vec<u8>,
+    pub cc_lane_height: f32,
+    pub show_instrument_labels: bool,
+    pub show_channel_activity: bool,
+    pub show_lyrics: bool,
+    pub lyrics_font_size: f32,
+    #[serde(with = "color32_serde")]
+    pub lyrics_color: Color32,
+    #[serde(with = "color32_serde")]
+    pub lyrics_highlight_color: Color32,
+    pub lyrics_position: LyricsPosition,
+    pub show_time_ruler: bool,
+    pub time_ruler_mode: TimeRulerMode,
+    /// The least space between two labels of the ruler in pixels
+    pub time_ruler_spacing_px: f32,
+    pub show_chord_names: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<WatermarkConfig>,
+}
+
+impl VisualSettings {
+    /// From cold colors for the keys with the fewest notes to warm ones for the most
+    pub fn default_heatmap_gradient() -> Vec<Color32> {
+        vec![
+            Color32::from_rgb(30, 50, 160),
+            Color32::from_rgb(30, 160, 160),
+            Color32::from_rgb(220, 200, 40),
+            Color32::from_rgb(210, 40, 30),
+        ]
+    }
+}
+
+impl Default for VisualSettings {
+    fn default() -> Self {
+        VisualSettings {
+            bg_color: Color32::from_rgb(30, 30, 30),
+            bar_color: Color32::from_rgb(145, 0, 0),
+            keyboard_white_key_color: Color32::WHITE,
+            keyboard_black_key_color: Color32::from_rgb(20, 20, 20),
+            keyboard_white_key_pressed_color: Color32::WHITE,
+            keyboard_black_key_pressed_color: Color32::WHITE,
+            keyboard_heatmap: false,
+            keyboard_key_labels: KeyLabelMode::None,
+            // 13.7 mm black keys next to 23.5 mm white keys, like on a real piano
+            black_key_width_ratio: 0.583,
+            black_key_height_ratio: 0.66,
+            heatmap_gradient: VisualSettings::default_heatmap_gradient(),
+            show_top_pannel: true,
+            show_statistics: true,
+            safe_zone_margin_px: 0,
+            fullscreen: false,
+            ui_font_path: None,
+            ui_font_size: 12.5,
+            bloom_enabled: false,
+            bloom_radius: 8.0,
+            dim_after_secs: None,
+            show_mini_keyboard: false,
+            waterfall_mode: false,
+            invert_pitch_axis: false,
+            inner_glow_intensity: 0.0,
+            inner_glow_color: [1.0, 1.0, 1.0, 1.0],
+            note_shape: NoteShape::Rectangle,
+            note_render_order: NoteRenderOrder::FileOrder,
+            percussion_display_mode: PercussionDisplayMode::Piano,
+            hand_split_mode: HandSplitMode::None,
+            note_gap: NoteGap::default(),
+            min_note_height_px: 2.0,
+            spotlight_mode: false,
+            length_opacity_mode: false,
+            length_opacity_min_ms: 20.0,
+            length_opacity_max_ms: 500.0,
+            msaa_samples: 1,
+            depth_buffer_scale: 1.0,
+            echo_beats: None,
+            particles_per_note: 0,
+            particle_lifetime_ms: 300.0,
+            inactive_channel_alpha: 0.3,
+            normalize_percussion_length: false,
+            normalized_length_ms: 100.0,
+            chord_grouping: false,
+            chord_tolerance_ms: 10.0,
+            arpeggio_view: false,
+            arpeggio_offset_ms: 15.0,
+            color_space: ColorSpace::Srgb,
+            background: BackgroundMode::SolidColor,
+            background_opacity: 1.0,
+            show_spectrogram: false,
+            spectrogram_fft_size: 2048,
+            spectrogram_color_map: SpectrogramColorMap::Magma,
+            spectrogram_height: 120.0,
+            use_color_script: false,
+            twitch_chat_channel: None,
+            cc_lanes: Vec::new(),
+            cc_lane_height: 32.0,
+            show_instrument_labels: false,
+            show_channel_activity: false,
+            show_lyrics: false,
+            lyrics_font_size: 28.0,
+            lyrics_color: Color32::WHITE,
+            lyrics_highlight_color: Color32::from_rgb(255, 200, 40),
+            lyrics_position: LyricsPosition::Bottom,
+            show_time_ruler: false,
+            time_ruler_mode: TimeRulerMode::Seconds,
+            time_ruler_spacing_px: 60.0,
+            show_chord_names: false,
+            watermark: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InputSettings {
+    pub touch_enabled: bool,
+    pub touch_zoom_sensitivity: f32,
+    pub touch_swipe_sensitivity: f32,
+    pub key_bindings: KeyBindings,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        InputSettings {
+            touch_enabled: true,
+            touch_zoom_sensitivity: 1.0,
+            touch_swipe_sensitivity: 1.0,
+            key_bindings: KeyBindings::default(),
+        }
+    }
+}
+
+/// The HTTP API for controlling the playback from other programs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RemoteSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// The token that every request has to send, made up when the API first starts
+    /// without one
+    pub token: String,
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        RemoteSettings {
+            enabled: false,
+            port: 8787,
+            token: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MidiSettings {
+    pub note_speed: f64,
+    pub auto_note_speed: bool,
+    pub auto_note_speed_target: u64,
+    pub auto_note_speed_smoothing: f64,
+    pub random_colors: bool,
+    pub program_colors: bool,
+    #[serde(with = "color32_vec_serde")]
+    pub program_color_map: Vec<Color32>,
+    /// Colors the programs by how bright the SFZ sounds in the register of their
+    /// family, instead of with the program color map
+    pub smart_colors: bool,
+    #[serde(with = "range_serde")]
+    pub key_range: RangeInclusive<u8>,
+    pub midi_loading: MidiLoading,
+    /// Maps the MIDI into memory instead of reading it, with the live loading mode
+    pub use_mmap: bool,
+    pub repair_mode: MidiRepairMode,
+    pub reverse_playback: bool,
+    pub transpose_semitones: i8,
+    pub follow_mode: bool,
+    pub keyboard_clickable: bool,
+    pub pause_on_focus_loss: bool,
+    pub seek_step_secs: f64,
+    pub seek_fast_multiplier: f64,
+    pub seek_fine_multiplier: f64,
+    pub event_filter: EventFilterFlags,
+    /// A bitmask of the channels whose notes aren't sent to the synth
+    pub muted_channels: u16,
+    pub musicxml_grid: u32,
+}
+
+impl Default for MidiSettings {
+    fn default() -> Self {
+        MidiSettings {
+            note_speed: 0.25,
+            auto_note_speed: false,
+            auto_note_speed_target: 2000,
+            auto_note_speed_smoothing: 0.05,
+            random_colors: false,
+            program_colors: false,
+            program_color_map: MidiSettings::default_program_colors(),
+            smart_colors: false,
+            key_range: 0..=127,
+            midi_loading: MidiLoading::Ram,
+            use_mmap: false,
+            repair_mode: MidiRepairMode::Off,
+            reverse_playback: false,
+            transpose_semitones: 0,
+            follow_mode: false,
+            keyboard_clickable: false,
+            pause_on_focus_loss: false,
+            seek_step_secs: 1.0,
+            seek_fast_multiplier: 10.0,
+            event_filter: EventFilterFlags::all(),
+            muted_channels: 0,
+            seek_fine_multiplier: 0.1,
+            musicxml_grid: 16,
+        }
+    }
+}
+
+impl MidiSettings {
+    /// Gives each of the 16 General MIDI instrument families its own hue,
+    /// with the programs inside a family getting darker one by one
+    pub fn default_program_colors() -> Vec<Color32> {
+        (0..128)
+            .map(|program| {
+                let family = program / 8;
+                let index = program % 8;
+                egui::ecolor::Hsva::new(family as f32 / 16.0, 0.8, 1.0 - index as f32 * 0.06, 1.0)
+                    .into()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SynthSettings {
+    pub synth: Synth,
+    pub buffer_ms: f64,
+    /// How far ahead of the notes the audio is played, to make up for the synth's buffer.
+    /// Follows the buffer when it isn't set.
+    pub audio_latency_compensation_ms: Option<f32>,
+    pub sfz_path: String,
+    pub limit_layers: bool,
+    pub layer_count: usize,
+    #[serde(with = "range_serde")]
+    pub vel_ignore: RangeInclusive<u8>,
+    pub fade_out_kill: bool,
+    pub linear_envelope: bool,
+    pub use_effects: bool,
+    pub release_multiplier: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_cutoff_ms: Option<f32>,
+    /// The shortest attack of the voices, which the notes also fade in over
+    pub note_fadein_ms: f32,
+    pub jack_client_name: String,
+    pub jack_port_left: String,
+    pub jack_port_right: String,
+    pub midi_clock_sync: bool,
+    pub midi_clock_port: String,
+    pub loop_input_port: String,
+    pub loop_measures: u32,
+    pub loop_bpm: f64,
+    pub channel_tuning: [f32; 16],
+    /// The SFZ regions that are left out on each channel, by channel number and region index
+    pub disabled_regions: BTreeMap<String, BTreeSet<usize>>,
+}
+
+impl Default for SynthSettings {
+    fn default() -> Self {
+        SynthSettings {
+            synth: Synth::XSynth,
+            buffer_ms: XSynthRealtimeConfig::default().render_window_ms,
+            audio_latency_compensation_ms: None,
+            sfz_path: String::new(),
+            limit_layers: true,
+            layer_count: 4,
+            vel_ignore: 0..=0,
+            fade_out_kill: ChannelInitOptions::default().fade_out_killing,
+            linear_envelope: SoundfontInitOptions::default().linear_release,
+            use_effects: SoundfontInitOptions::default().use_effects,
+            release_multiplier: 1.0,
+            release_cutoff_ms: None,
+            note_fadein_ms: 0.0,
+            jack_client_name: String::from("wasabi"),
+            jack_port_left: String::new(),
+            jack_port_right: String::new(),
+            midi_clock_sync: false,
+            midi_clock_port: String::new(),
+            loop_input_port: String::new(),
+            loop_measures: 4,
+            loop_bpm: 120.0,
+            channel_tuning: [0.0; 16],
+            disabled_regions: BTreeMap::new(),
+        }
+    }
+}
+
+impl SynthSettings {
+    /// How far ahead of the notes the audio is played, which is the buffer unless it
+    /// was set
+    pub fn latency_compensation_ms(&self) -> f32 {
+        self.audio_latency_compensation_ms
+            .unwrap_or(self.buffer_ms as f32)
+            .clamp(0.0, 1000.0)
+    }
+}
+
+/// The MIDI that was playing when Wasabi was last closed, and where it was
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SavedSession {
+    pub midi_file: PathBuf,
+    pub position: Duration,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct RenderingSettings {
+    /// The GPU counted from 1 in the order Vulkan lists them, 0 picks the most performant one
+    pub gpu_device_index: usize,
+    /// The size of the memory blocks for the notes in MB, 0 lets vulkano pick
+    pub memory_block_size_mb: u64,
+    /// Skip the notes outside of the view, by key and by time, with a compute shader, which
+    /// also counts the notes that were drawn
+    pub gpu_culling: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct WasabiSettings {
+    pub synth: SynthSettings,
+    pub midi: MidiSettings,
+    pub visual: VisualSettings,
+    pub rendering: RenderingSettings,
+    pub input: InputSettings,
+    pub remote: RemoteSettings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_midi_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_session: Option<SavedSession>,
+    #[serde(skip)]
+    pub no_audio: bool,
+    #[serde(skip)]
+    pub export_audio: Option<PathBuf>,
+    #[serde(skip)]
+    pub analyze_midi: Option<PathBuf>,
+    #[serde(skip)]
+    pub analyze_output: Option<PathBuf>,
+    #[serde(skip)]
+    pub export_musicxml: Option<PathBuf>,
+}
+
+static CONFIG_PATH: &str = "wasabi-config.toml";
+
+impl WasabiSettings {
+    pub fn new_or_load() -> Self {
+        let config_path = Self::get_config_path();
+        let mut config = if !Path::new(&config_path).exists() {
+            Self::load_and_save_defaults()
+        } else {
+            let config = fs::read_to_string(&config_path).unwrap();
+            if config.starts_with('#') {
+                if let Ok(config) = toml::from_str(&config) {
+                    config
+                } else {
+                    Self::load_and_save_defaults()
+                }
+            } else {
+                let config = migrations::WasabiConfigFileV0::migrate().unwrap_or_default();
+                config.save_to_file();
+                config
+            }
+        };
+
+        config.augment_from_args();
+        config
+    }
+
+    pub fn save_to_file(&self) {
+        let config_path = Self::get_config_path();
+        let toml: String = toml::to_string(&self).unwrap();
+        if Path::new(&config_path).exists() {
+            fs::remove_file(&config_path).expect("Error deleting old config");
+        }
+        let mut file = fs::File::create(&config_path).unwrap();
+        file.write_all(b"# DON'T EDIT THIS LINE; Version: 1\n\n")
+            .unwrap();
+        file.write_all(toml.as_bytes())
+            .expect("Error creating config");
+    }
+
+    /// Writes the session into the config file, without saving any of the other
+    /// settings that were changed since they were loaded or passed as arguments
+    pub fn save_session(session: SavedSession) {
+        let config_path = Self::get_config_path();
+        let Some(mut config) = fs::read_to_string(config_path)
+            .ok()
+            .and_then(|config| toml::from_str::<WasabiSettings>(&config).ok())
+        else {
+            return;
+        };
+
+        config.last_session = Some(session);
+        config.save_to_file();
+    }
+
+    fn augment_from_args(&mut self) {
+        let matches = Command::new("wasabi")
+            .version(env!("CARGO_PKG_VERSION"))
+            .author(env!("CARGO_PKG_AUTHORS"))
+            .about(env!("CARGO_PKG_DESCRIPTION"))
+            .arg(
+                Arg::new("synth")
+                    .help("The synthesizer to use")
+                    .long_help(
+                        "The synthesizer that is used to play the MIDI. \
+                        This can either be XSynth (recommended), KDMAPI or JACK. KDMAPI \
+                        only works if you have OmniMIDI installed, and are using Windows. \
+                        JACK renders with XSynth and requires a running JACK server, and \
+                        Wasabi to be built with the `jack` feature",
+                    )
+                    .short('S')
+                    .long("synth")
+                    .value_parser(Synth::from_str),
+            )
+            .arg(
+                Arg::new("buffer-ms")
+                    .help("The amount of time events are held in the buffer")
+                    .long_help(
+                        "The amount of time that events are held in the \
+                        buffer before being played. Higher numbers may increase \
+                        performance but also increase latency.",
+                    )
+                    .short('b')
+                    .long("buffer-ms")
+                    .value_parser(f64_parser),
+            )
+            .arg(
+                Arg::new("latency-compensation")
+                    .help("Plays the audio ahead of the notes by the given amount of ms")
+                    .long_help(
+                        "Plays the audio the given amount of milliseconds ahead of the \
+                        notes, up to 1000, so the notes hit the keys when they are heard. \
+                        Follows the synth render buffer when it isn't given, which delays \
+                        the audio by about as much",
+                    )
+                    .long("latency-compensation")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("sfz-path")
                     .help("The path to an SFZ SoundFont")
                     .long_help(
-                        "The path to any SFZ soundfont. In audio only mode \
-                        a soundfont must be passed either via the config file, or
-                        this command line option. In the GUI you can set this under \
-                        `Open Synth Settings > SFZ Path`",
+                        "The path to any SFZ soundfont. In audio only mode \
+                        a soundfont must be passed either via the config file, or
+                        this command line option. In the GUI you can set this under \
+                        `Open Synth Settings > SFZ Path`",
+                    )
+                    .short('s')
+                    .long("sfz-path")
+                    .visible_alias("soundfont")
+                    .value_hint(ValueHint::FilePath),
+            )
+            .arg(
+                Arg::new("dont-limit-layers")
+                    .help("Do not apply the layer limit to the synth")
+                    .long_help(
+                        "This allows the synth to create as many layers as it \
+                        needs to play the MIDI file faithfully. Only turn this on if your \
+                        MIDI sounds bad, or your computer is running on GFuel",
+                    )
+                    .long("dont-limit-layers")
+                    .action(ArgAction::SetFalse),
+            )
+            .arg(
+                Arg::new("layer-count")
+                    .help("The amount of layers that the synthesizer can create")
+                    .long_help(
+                        "The maximum amount of voices the synth is \
+                        allowed to create per key per channel",
+                    )
+                    .short('l')
+                    .long("layer-count")
+                    .value_parser(value_parser!(usize)),
+            )
+            .arg(
+                Arg::new("vel-ignore")
+                    .help("The range of note velocities that the synth will discard")
+                    .long_help(
+                        "Two numbers, comma seperated, that represent a range of velocities \
+                        that the synth will discard, making notes in the range inaudible.",
+                    )
+                    .short('v')
+                    .long("vel-ignore")
+                    .value_parser(range_parser),
+            )
+            .arg(
+                Arg::new("fade-out-kill")
+                    .help("Once a voice is killed, fade it out")
+                    .long_help(
+                        "Once the synthesizer kills one of it's voices, it will fade it \
+                        out as opposed to simply cutting it off",
+                    )
+                    .short('F')
+                    .long("fade-out-kill")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("linear-envelope")
+                    .help("Adjust the rate of decay on the synth's voices")
+                    .long_help(
+                        "Switch the synth's voice's rate of decay from \
+                        exponential to linear. This may be bring a performance \
+                        improvement on some systems.",
+                    )
+                    .short('L')
+                    .long("linear-envelope")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("release-multiplier")
+                    .help("Scales the release time of the soundfont's voices")
+                    .long_help(
+                        "Multiplies the release time of every region of the SFZ, from 0.1 \
+                        to 4. Shorter releases keep the voice count down with soundfonts \
+                        that have very long release tails",
+                    )
+                    .long("release-multiplier")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("release-cutoff")
+                    .help("The longest release time of the soundfont's voices in ms")
+                    .long_help(
+                        "Caps the release time of every region of the SFZ to the given \
+                        amount of milliseconds, after the release multiplier",
+                    )
+                    .long("release-cutoff")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("note-fadein")
+                    .help("Fades in the notes over the given amount of ms")
+                    .long_help(
+                        "Makes the attack of every region of the SFZ at least the given \
+                        amount of milliseconds, up to 1000, which softens abrupt note starts. \
+                        The notes on screen fade in over the same time at their start",
+                    )
+                    .long("note-fadein")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("no-effects")
+                    .help("Disables the soundfont's effects")
+                    .long_help(
+                        "Disables soundfont audio effects. \
+                        This may improve the performance.",
+                    )
+                    .short('N')
+                    .long("no-effects")
+                    .action(ArgAction::SetFalse),
+            )
+            .arg(
+                Arg::new("midi-clock-sync")
+                    .help("Follow the tempo of an external MIDI clock")
+                    .long_help(
+                        "Reads MIDI timing clock messages from a MIDI input port and \
+                        speeds up or slows down playback to match their tempo, so `wasabi` \
+                        can be synced to a DAW or a hardware sequencer",
+                    )
+                    .long("midi-clock-sync")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("midi-clock-port")
+                    .help("The MIDI input port to read the clock from")
+                    .long_help(
+                        "The name of the MIDI input port that sends the timing clock. \
+                        The first available port is used if this isn't set",
+                    )
+                    .long("midi-clock-port"),
+            )
+            .arg(
+                Arg::new("loop-input-port")
+                    .help("The MIDI input port that loops are recorded from")
+                    .long_help(
+                        "The name of the MIDI input port that is recorded in loop record \
+                        mode. The first available port is used if this isn't set",
+                    )
+                    .long("loop-input-port"),
+            )
+            .arg(
+                Arg::new("loop-measures")
+                    .help("How many measures a recorded loop is long")
+                    .long("loop-measures")
+                    .value_parser(value_parser!(u32)),
+            )
+            .arg(
+                Arg::new("loop-bpm")
+                    .help("The tempo that loops are recorded at")
+                    .long("loop-bpm")
+                    .value_parser(value_parser!(f64)),
+            )
+            .arg(
+                Arg::new("note-speed")
+                    .help("The speed that the notes travel on-screen")
+                    .long_help(
+                        "The speed at which the notes will move across the screen. This makes \
+                        the notes physically longer, causing them to move faster on-screen",
+                    )
+                    .short('n')
+                    .long("note-speed")
+                    .value_parser(note_speed),
+            )
+            .arg(
+                Arg::new("auto-note-speed")
+                    .help("Adjust the note speed to the density of the MIDI")
+                    .long_help(
+                        "Continuously changes the note speed so that about the same amount \
+                        of notes is on screen at once, set with --auto-note-speed-target",
+                    )
+                    .long("auto-note-speed")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("auto-note-speed-target")
+                    .help("How many notes the automatic note speed keeps on screen")
+                    .long("auto-note-speed-target")
+                    .value_parser(value_parser!(u64).range(1..)),
+            )
+            .arg(
+                Arg::new("auto-note-speed-smoothing")
+                    .help("How quickly the automatic note speed follows the density, from 0 to 1")
+                    .long_help(
+                        "How much of the way to the wanted note speed the automatic note speed \
+                        moves every frame, from 0.001 to 1. Lower values change the speed more \
+                        smoothly, 1 jumps to it right away",
+                    )
+                    .long("auto-note-speed-smoothing")
+                    .value_parser(value_parser!(f64)),
+            )
+            .arg(
+                Arg::new("random-colors")
+                    .help("Make each channel a random color")
+                    .long_help("This causes each of the note channels to become a random color")
+                    .short('r')
+                    .long("random-colors")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("program-colors")
+                    .help("Color notes by the program of their channel")
+                    .long_help(
+                        "After a program change, the notes of that channel get the color of \
+                        the new program from the program color map in the config. \
+                        Not supported with the cake MIDI loading mode",
+                    )
+                    .long("program-colors")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("smart-colors")
+                    .help("Color notes by how bright the SFZ sounds for their program")
+                    .long_help(
+                        "After a program change, the notes of that channel get a color from \
+                        how bright the SFZ sounds in the register the program's General MIDI \
+                        family usually plays in, warm for bright ones and cool for dull ones. \
+                        The same SFZ plays every program, so the programs of a family share \
+                        a hue. The SFZ is analyzed when the MIDI is loaded. \
+                        Not supported with the cake MIDI loading mode",
+                    )
+                    .long("smart-colors")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("key-range")
+                    .help("The key range of the on-screen piano keyboard")
+                    .long_help(
+                        "Two numbers, comma seperated, that describe the range \
+                        of keys to be shown on the on-screen piano keyboard, the range must \
+                        be less than 255 and more than 0",
+                    )
+                    .short('k')
+                    .long("key-range")
+                    .value_parser(range_parser),
+            )
+            .arg(
+                Arg::new("midi-loading")
+                    .help("How the MIDI is loaded into `wasabi`")
+                    .long_help(
+                        "The method in which the MIDI file is loaded into `wasabi`, the \
+                        possible options are `ram`, which loads the MIDI file entirely into \
+                        RAM before beginning playback; `live` which will read the MIDI file \
+                        as it's being played back, and `cake` which loads the notes into \
+                        binary trees on the GPU. The `live` method is for using with systems \
+                        with low memory",
+                    )
+                    .short('m')
+                    .long("midi-loading")
+                    .visible_alias("loading-mode")
+                    .value_parser(MidiLoading::from_str),
+            )
+            .arg(
+                Arg::new("mmap")
+                    .help("Map the MIDI into memory instead of reading it")
+                    .long_help(
+                        "Maps the whole MIDI file into memory, so the parser reads it in place \
+                        instead of copying it into buffers with a system call each, which \
+                        helps with big files on fast drives. Slow drives are as slow either \
+                        way. The file must not be changed or shortened while it plays. Only \
+                        used by the `live` MIDI loading method",
+                    )
+                    .long("mmap")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("repair-mode")
+                    .help("How broken note events are fixed when the MIDI is loaded")
+                    .long_help(
+                        "Fixes broken note events while the MIDI is loaded, the possible \
+                        options are `off`; `duplicates`, which removes note ons for keys that \
+                        are already held on the same track and channel, and `full`, which also \
+                        adds note offs for the notes that are never ended. The amount of \
+                        repairs is shown in the MIDI info dialog. Only supported by the `ram` \
+                        MIDI loading method",
+                    )
+                    .long("repair-mode")
+                    .value_parser(MidiRepairMode::from_str),
+            )
+            .arg(
+                Arg::new("drop-events")
+                    .help("The kinds of events that aren't sent to the synth")
+                    .long_help(
+                        "A comma separated list of the kinds of MIDI events that are dropped \
+                        before they reach the synth, for example `cc,pitch-bend` to play \
+                        without the sustain pedal and pitch bends. The possible options are \
+                        `note-on`, `note-off`, `pitch-bend`, `cc` and `program-change`. \
+                        Dropping note offs leaves the notes playing",
+                    )
+                    .long("drop-events")
+                    .value_parser(event_filter_parser),
+            )
+            .arg(
+                Arg::new("mute-channels")
+                    .help("The channels whose notes aren't played")
+                    .long_help(
+                        "A comma separated list of the channels, from 1 to 16, whose notes \
+                        aren't sent to the synth, for example `10` to play without drums. \
+                        The notes of the muted channels are drawn with the opacity given by \
+                        `--inactive-channel-alpha`",
+                    )
+                    .long("mute-channels")
+                    .value_parser(channel_mask_parser),
+            )
+            .arg(
+                Arg::new("reverse-playback")
+                    .help("Play the MIDI backwards")
+                    .long_help(
+                        "Plays the MIDI file backwards, from the end to the start. \
+                        This only works with the `ram` MIDI loading method",
+                    )
+                    .long("reverse-playback")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("transpose")
+                    .help("Transpose the MIDI by a number of semitones")
+                    .long_help(
+                        "Shifts every note up or down by the given number of semitones, \
+                        between -24 and 24. This affects both the rendered notes and the \
+                        audio, notes shifted outside of the 0-127 key range are dropped",
+                    )
+                    .short('t')
+                    .long("transpose")
+                    .allow_negative_numbers(true)
+                    .value_parser(value_parser!(i8).range(-24..=24)),
+            )
+            .arg(
+                Arg::new("follow-mode")
+                    .help("Keep the most played key centered on the keyboard")
+                    .long_help(
+                        "Automatically scrolls the visible key range so that the key \
+                        with the most notes on screen stays at the center of the view",
+                    )
+                    .long("follow-mode")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("keyboard-clickable")
+                    .help("Play the keys of the keyboard by clicking them")
+                    .long_help(
+                        "Plays a note on the synth while a key of the on-screen keyboard is \
+                        held down with the mouse. Several keys can be held at once with a \
+                        touch screen",
+                    )
+                    .long("keyboard-clickable")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("pause-on-focus-loss")
+                    .help("Pause the playback while the window isn't focused")
+                    .long_help(
+                        "Pauses the playback when switching to another application, \
+                        and resumes it when the window is focused again",
+                    )
+                    .long("pause-on-focus-loss")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("seek-step")
+                    .help("How many seconds the arrow keys seek by")
+                    .long_help(
+                        "The amount of seconds that the left and right arrow keys seek by. \
+                        Holding Shift or Ctrl multiplies it by the fast and fine seek \
+                        multipliers from the settings",
+                    )
+                    .long("seek-step")
+                    .value_parser(value_parser!(f64)),
+            )
+            .arg(
+                Arg::new("bg-color")
+                    .help("The window background")
+                    .long_help("A hex color string describing the background color of the window")
+                    .long("bg-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("bar-color")
+                    .help("The color of the bar just above the piano")
+                    .long_help(
+                        "A hex color string describing the color of the bar just above \
+                         the on-screen piano keyboard",
+                    )
+                    .long("bar-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("white-key-color")
+                    .help("The color of the white keys of the piano")
+                    .long("white-key-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("black-key-color")
+                    .help("The color of the black keys of the piano")
+                    .long("black-key-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("white-key-pressed-color")
+                    .help("The tint of the white keys of the piano while they are pressed")
+                    .long_help(
+                        "A hex color string that is multiplied with the color of the note \
+                        that presses a white key, white keeps the note color as it is",
+                    )
+                    .long("white-key-pressed-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("black-key-pressed-color")
+                    .help("The tint of the black keys of the piano while they are pressed")
+                    .long_help(
+                        "A hex color string that is multiplied with the color of the note \
+                        that presses a black key, white keeps the note color as it is",
+                    )
+                    .long("black-key-pressed-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("ui-font")
+                    .help("A custom font for the user interface")
+                    .long_help(
+                        "The path to a TTF or OTF font file which is used for all of the \
+                        text in the user interface. The built-in font is used if the file \
+                        can't be loaded",
+                    )
+                    .long("ui-font")
+                    .value_hint(ValueHint::FilePath)
+                    .value_parser(value_parser!(PathBuf)),
+            )
+            .arg(
+                Arg::new("background-image")
+                    .help("An image that is shown behind the notes")
+                    .long_help(
+                        "The path to a PNG or JPEG image which is stretched to cover the \
+                        note area, keeping its aspect ratio",
+                    )
+                    .long("background-image")
+                    .value_hint(ValueHint::FilePath)
+                    .value_parser(value_parser!(PathBuf))
+                    .conflicts_with("background-video"),
+            )
+            .arg(
+                Arg::new("background-video")
+                    .help("A video that is played behind the notes")
+                    .long_help(
+                        "The path to a video which is played in a loop behind the notes. \
+                        Videos need Wasabi to be built with the `video-background` feature",
+                    )
+                    .long("background-video")
+                    .value_hint(ValueHint::FilePath)
+                    .value_parser(value_parser!(PathBuf)),
+            )
+            .arg(
+                Arg::new("background-opacity")
+                    .help("The opacity of the background image or video, from 0 to 1")
+                    .long("background-opacity")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("safe-zone-margin")
+                    .help("Leave an empty border of this many pixels around everything")
+                    .long_help(
+                        "Moves the notes, keyboard and panels inwards by this many pixels, \
+                        leaving a transparent border around the edges of the window. Useful \
+                        when the window is captured as a streaming overlay that cuts off \
+                        the edges",
+                    )
+                    .long("safe-zone-margin")
+                    .value_parser(value_parser!(u32)),
+            )
+            .arg(
+                Arg::new("ui-font-size")
+                    .help("The size of the user interface text")
+                    .long("ui-font-size")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("bloom")
+                    .help("Make the notes glow")
+                    .long_help(
+                        "Adds a bloom effect on top of the notes, notes with a higher \
+                        velocity glow brighter",
+                    )
+                    .long("bloom")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("bloom-radius")
+                    .help("The radius of the bloom effect in pixels")
+                    .long("bloom-radius")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("inner-glow")
+                    .help("The intensity of the glow inside the note borders, from 0 to 1")
+                    .long("inner-glow")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("inner-glow-color")
+                    .help("The color of the glow inside the note borders")
+                    .long_help(
+                        "A hex color string describing the color that the note borders blend \
+                        toward, set the intensity with --inner-glow",
+                    )
+                    .long("inner-glow-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("dim-after")
+                    .help("Dim the notes after this many seconds without input")
+                    .long_help(
+                        "Slowly dims the notes once the MIDI has been playing for the given \
+                        amount of seconds without any mouse or keyboard input. Moving the \
+                        mouse or pressing a key restores the full brightness",
+                    )
+                    .long("dim-after")
+                    .value_parser(value_parser!(u64)),
+            )
+            .arg(
+                Arg::new("echo-beats")
+                    .help("Show a faded echo of the notes from this many beats ago")
+                    .long_help(
+                        "Draws the notes a second time, moved forward by the given amount of \
+                        beats at the current tempo and faded out, which makes repeating \
+                        phrases stand out. Only supported by the `ram` and `live` MIDI \
+                        loading methods",
+                    )
+                    .long("echo-beats")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("particles-per-note")
+                    .help("How many particles burst out of each note at the playhead")
+                    .long_help(
+                        "Spawns the given amount of particles in the color of a note when it \
+                        reaches the playhead, from 0 to 64. Only supported by the `ram` and \
+                        `live` MIDI loading methods",
+                    )
+                    .long("particles-per-note")
+                    .value_parser(value_parser!(u32)),
+            )
+            .arg(
+                Arg::new("particle-lifetime")
+                    .help("How long the note particles take to fade out in ms")
+                    .long("particle-lifetime")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("watermark")
+                    .help("Text to show in a corner of the notes")
+                    .long_help(
+                        "Shows the given text in a corner of the notes, like a credit for \
+                        the MIDI. The size, opacity and animation can be changed in the settings",
+                    )
+                    .long("watermark")
+                    .value_parser(value_parser!(String)),
+            )
+            .arg(
+                Arg::new("watermark-corner")
+                    .help("The corner the watermark is shown in")
+                    .long_help(
+                        "The corner of the notes the watermark is shown in, the possible \
+                        options are `topleft`, `topright`, `bottomleft` and `bottomright`",
+                    )
+                    .long("watermark-corner")
+                    .value_parser(WatermarkCorner::from_str),
+            )
+            .arg(
+                Arg::new("mini-keyboard")
+                    .help("Show a small keyboard above the notes")
+                    .long_help(
+                        "Shows a miniature keyboard at the top of the notes that mirrors \
+                        the pressed keys, which is useful when the main keyboard is out of view",
+                    )
+                    .long("mini-keyboard")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("waterfall")
+                    .help("Scroll the notes from right to left")
+                    .long_help(
+                        "Rotates the view so the keyboard is on the left edge of the window \
+                        and the notes scroll from the right to the left",
+                    )
+                    .long("waterfall")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("invert-pitch")
+                    .help("Show the low keys where the high keys usually are")
+                    .long_help(
+                        "Flips the keyboard and the notes along the keys, so the low keys are \
+                        on the right, or at the top in waterfall mode",
+                    )
+                    .long("invert-pitch")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("keyboard-heatmap")
+                    .help("Color the keys by how many notes they have")
+                    .long_help(
+                        "Colors the keys of the keyboard that aren't pressed by how many \
+                        notes they have in the whole MIDI, from cold to warm colors. The \
+                        gradient can be changed in the settings",
+                    )
+                    .long("keyboard-heatmap")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("key-labels")
+                    .help("Which keys of the keyboard show their note name")
+                    .long_help(
+                        "Writes the note name, like `C4`, on the keys of the keyboard. The \
+                        possible options are `none`, `all` for every key with the black keys \
+                        named as flats, `octavec` for only the C of every octave and \
+                        `chromaticsharps` for every key with the black keys named as sharps. \
+                        Labels are hidden on keys that are too narrow for them",
+                    )
+                    .long("key-labels")
+                    .value_parser(KeyLabelMode::from_str),
+            )
+            .arg(
+                Arg::new("black-key-width")
+                    .help("The width of the black keys relative to the white keys")
+                    .long_help(
+                        "The width of the black keys and their notes relative to the white \
+                        keys, from 0.1 to 1. A real piano has black keys about 0.583 as wide \
+                        as its white keys",
+                    )
+                    .long("black-key-width")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("black-key-height")
+                    .help("How far down the keyboard the black keys reach, from 0.1 to 1")
+                    .long("black-key-height")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("note-shape")
+                    .help("The shape the notes are drawn as")
+                    .long_help(
+                        "The shape of the notes, the possible options are `rectangle`, \
+                        `circle` and `diamond`. Circles and diamonds are stretched to fill \
+                        the note, and are only supported by the `ram` and `live` MIDI loading \
+                        methods",
+                    )
+                    .long("note-shape")
+                    .value_parser(NoteShape::from_str),
+            )
+            .arg(
+                Arg::new("note-render-order")
+                    .help("The order the notes of a key are drawn in")
+                    .long_help(
+                        "Which of the overlapping notes of a key are drawn on top, the \
+                        possible options are `fileorder`, `earliestfirst`, `latestfirst` \
+                        and `bytrackdescending`. Notes earlier in the order end up on top. \
+                        Only supported by the `ram` and `live` MIDI loading methods",
+                    )
+                    .long("note-render-order")
+                    .value_parser(NoteRenderOrder::from_str),
+            )
+            .arg(
+                Arg::new("percussion-display")
+                    .help("How the notes of the percussion channel are shown")
+                    .long_help(
+                        "Either `piano` to show the notes of channel 10 on the keys like the \
+                        other notes, or `drumpad` to light up a labeled pad for each General \
+                        MIDI drum instead. Only supported by the `ram` and `live` MIDI \
+                        loading methods",
+                    )
+                    .long("percussion-display")
+                    .value_parser(PercussionDisplayMode::from_str),
+            )
+            .arg(
+                Arg::new("hand-split")
+                    .help("Color the notes of the right and left hand apart")
+                    .long_help(
+                        "Either `none`, `channel:<right>,<left>` for the channels the hands \
+                        are on, like `channel:1,2`, or `pitch:<key>` to give the keys from \
+                        that key up to the right hand, like `pitch:60`. The hands get their \
+                        own colors over every other color setting. Only supported by the \
+                        `ram` and `live` MIDI loading methods",
+                    )
+                    .long("hand-split")
+                    .value_parser(HandSplitMode::from_str),
+            )
+            .arg(
+                Arg::new("note-gap")
+                    .help("The gap between notes that follow each other on a key")
+                    .long_help(
+                        "Shortens every note, so back to back notes on the same key don't \
+                        merge. Either in pixels from 0 to 5, like `2px`, where notes keep at \
+                        least a pixel of height, or in ticks up to 960, like `10ticks`, which \
+                        are counted with the tempo where each note ends and keep at least half \
+                        of the note. Only supported by the `ram` and `live` MIDI loading methods",
+                    )
+                    .long("note-gap")
+                    .value_parser(NoteGap::from_str),
+            )
+            .arg(
+                Arg::new("min-note-height")
+                    .help("The smallest height in pixels a note is drawn with")
+                    .long_help(
+                        "Notes shorter than this many pixels, from 0 to 10, are stretched to \
+                        it, so very short notes stay visible at any note speed. Only \
+                        supported by the `ram` and `live` MIDI loading methods",
+                    )
+                    .long("min-note-height")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("spotlight")
+                    .help("Dim every note except the ones that are playing")
+                    .long_help(
+                        "Draws the notes that aren't playing at the current time faded out, \
+                        so the ones that are playing stand out. Only supported by the `ram` \
+                        and `live` MIDI loading methods",
+                    )
+                    .long("spotlight")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("length-opacity")
+                    .help("Make shorter notes more transparent")
+                    .long_help(
+                        "Fades notes by their length, from fully transparent at the length of \
+                        --length-opacity-min to fully opaque at the length of \
+                        --length-opacity-max, so sustained notes stand out from short ones. \
+                        Only supported by the `ram` and `live` MIDI loading methods",
                     )
-                    .short('s')
-                    .long("sfz-path")
-                    .value_hint(ValueHint::FilePath),
+                    .long("length-opacity")
+                    .action(ArgAction::SetTrue),
             )
             .arg(
-                Arg::new("dont-limit-layers")
-                    .help("Do not apply the layer limit to the synth")
+                Arg::new("length-opacity-min")
+                    .help("The note length in milliseconds at which notes are fully transparent")
+                    .long("length-opacity-min")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("length-opacity-max")
+                    .help("The note length in milliseconds at which notes are fully opaque")
+                    .long("length-opacity-max")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("msaa")
+                    .help("The amount of samples per pixel the notes are drawn with")
                     .long_help(
-                        "This allows the synth to create as many layers as it \
-                        needs to play the MIDI file faithfully. Only turn this on if your \
-                        MIDI sounds bad, or your computer is running on GFuel",
+                        "Draws the notes with multisample anti-aliasing, with 1, 2, 4 or 8 \
+                        samples per pixel, which smooths the edges where notes overlap. The \
+                        highest count the GPU supports up to this one is used. Only \
+                        supported by the `ram` and `live` MIDI loading methods",
                     )
-                    .long("dont-limit-layers")
-                    .action(ArgAction::SetFalse),
+                    .long("msaa")
+                    .value_parser(value_parser!(u32)),
             )
             .arg(
-                Arg::new("layer-count")
-                    .help("The amount of layers that the synthesizer can create")
+                Arg::new("depth-buffer-scale")
+                    .help("The size of the depth buffer relative to the frame, 0.5 or 1")
                     .long_help(
-                        "The maximum amount of voices the synth is \
-                        allowed to create per key per channel",
+                        "With 0.5, the notes are drawn with a depth buffer of half the frame \
+                        size, which saves memory and bandwidth on big frames. The notes are \
+                        drawn into an image of the same size and stretched onto the frame, \
+                        so they get softer edges, and the pixel sizes of the notes like the \
+                        gap and the minimum height count pixels of that smaller image",
                     )
-                    .short('l')
-                    .long("layer-count")
-                    .value_parser(value_parser!(usize)),
+                    .long("depth-buffer-scale")
+                    .value_parser(value_parser!(f32)),
             )
             .arg(
-                Arg::new("vel-ignore")
-                    .help("The range of note velocities that the synth will discard")
+                Arg::new("inactive-channel-alpha")
+                    .help("The opacity of notes on channels that aren't playing, from 0 to 1")
                     .long_help(
-                        "Two numbers, comma seperated, that represent a range of velocities \
-                        that the synth will discard, making notes in the range inaudible.",
+                        "Notes on channels that haven't played a note in the last few seconds, \
+                        or that are muted, are drawn with this opacity, from 0 to 1, so the \
+                        active parts stand out. 1 draws every note fully opaque. Only supported by the `ram` and \
+                        `live` MIDI loading methods",
                     )
-                    .short('v')
-                    .long("vel-ignore")
-                    .value_parser(range_parser),
+                    .long("inactive-channel-alpha")
+                    .value_parser(value_parser!(f32)),
             )
             .arg(
-                Arg::new("fade-out-kill")
-                    .help("Once a voice is killed, fade it out")
+                Arg::new("normalize-percussion")
+                    .help("Draw every percussion note with the same length")
                     .long_help(
-                        "Once the synthesizer kills one of it's voices, it will fade it \
-                        out as opposed to simply cutting it off",
+                        "Draws the notes of channel 10, which is used for percussion, with the \
+                        length given by `--percussion-length` instead of their real length, \
+                        so drum rolls are easier to read. Only supported by the `ram` and \
+                        `live` MIDI loading methods",
                     )
-                    .short('F')
-                    .long("fade-out-kill")
+                    .long("normalize-percussion")
                     .action(ArgAction::SetTrue),
             )
             .arg(
-                Arg::new("linear-envelope")
-                    .help("Adjust the rate of decay on the synth's voices")
+                Arg::new("percussion-length")
+                    .help("The length in milliseconds of percussion notes when they are normalized")
+                    .long("percussion-length")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("chord-grouping")
+                    .help("Draw the notes of a chord as one block")
                     .long_help(
-                        "Switch the synth's voice's rate of decay from \
-                        exponential to linear. This may be bring a performance \
-                        improvement on some systems.",
+                        "Notes on the same channel that start within `--chord-tolerance` of \
+                        each other are drawn slightly wider, with a border around the whole \
+                        chord instead of around every note. Only supported by the `ram` and \
+                        `live` MIDI loading methods",
                     )
-                    .short('L')
-                    .long("linear-envelope")
+                    .long("chord-grouping")
                     .action(ArgAction::SetTrue),
             )
             .arg(
-                Arg::new("no-effects")
-                    .help("Disables the soundfont's effects")
+                Arg::new("chord-tolerance")
+                    .help("How far apart in milliseconds the notes of a chord can start")
+                    .long("chord-tolerance")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("arpeggio-view")
+                    .help("Spread the notes of chords apart in time")
                     .long_help(
-                        "Disables soundfont audio effects. \
-                        This may improve the performance.",
+                        "Draws the notes of a chord one after the other from the lowest key \
+                        up, each --arpeggio-offset milliseconds after the one below it, so \
+                        every note of the chord can be seen on its own. The notes that start \
+                        within --chord-tolerance of each other are a chord. The audio isn't \
+                        changed. Only supported by the `ram` and `live` MIDI loading methods",
                     )
-                    .short('N')
-                    .long("no-effects")
-                    .action(ArgAction::SetFalse),
+                    .long("arpeggio-view")
+                    .action(ArgAction::SetTrue),
             )
             .arg(
-                Arg::new("note-speed")
-                    .help("The speed that the notes travel on-screen")
+                Arg::new("arpeggio-offset")
+                    .help("How far apart in milliseconds the notes of a chord are drawn")
+                    .long("arpeggio-offset")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("cc-lanes")
+                    .help("The controllers to show in lanes below the notes, comma separated")
                     .long_help(
-                        "The speed at which the notes will move across the screen. This makes \
-                        the notes physically longer, causing them to move faster on-screen",
+                        "Shows a lane at the bottom of every key column for each of the \
+                        given control change numbers, like `1,11,74`, with the value of \
+                        the channel playing on the key as a ribbon that moves with the notes",
                     )
-                    .short('n')
-                    .long("note-speed")
-                    .value_parser(note_speed),
+                    .long("cc-lanes")
+                    .value_parser(cc_list_parser),
             )
             .arg(
-                Arg::new("random-colors")
-                    .help("Make each channel a random color")
-                    .long_help("This causes each of the note channels to become a random color")
-                    .short('r')
-                    .long("random-colors")
+                Arg::new("instrument-labels")
+                    .help("Label the program changes with their General MIDI instrument")
+                    .long_help(
+                        "Shows the General MIDI name of the instrument that every program \
+                        change picks, at the left edge of the notes where its notes start",
+                    )
+                    .long("instrument-labels")
                     .action(ArgAction::SetTrue),
             )
             .arg(
-                Arg::new("key-range")
-                    .help("The key range of the on-screen piano keyboard")
+                Arg::new("channel-activity")
+                    .help("Show which channels have notes on screen")
                     .long_help(
-                        "Two numbers, comma seperated, that describe the range \
-                        of keys to be shown on the on-screen piano keyboard, the range must \
-                        be less than 255 and more than 0",
+                        "Shows a thin bar above the keyboard with a segment in the color of \
+                        each of the 16 channels, which is bright while the channel has notes \
+                        on screen. Only supported by the `ram` and `live` MIDI loading methods",
                     )
-                    .short('k')
-                    .long("key-range")
-                    .value_parser(range_parser),
+                    .long("channel-activity")
+                    .action(ArgAction::SetTrue),
             )
             .arg(
-                Arg::new("midi-loading")
-                    .help("How the MIDI is loaded into `wasabi`")
+                Arg::new("lyrics")
+                    .help("Show the lyrics of karaoke files while they are sung")
                     .long_help(
-                        "The method in which the MIDI file is loaded into `wasabi`, the \
-                        two possible options are `ram`, which loads the MIDI file entirely into \
-                        RAM before beginning playback; and `live` which will read the MIDI file \
-                        as it's being played back. The latter method is for using with systems \
-                        with low memory",
+                        "Shows the line of the lyrics that is being sung, with the next line \
+                        below it. The syllables fill up with the highlight color while they \
+                        are sung. The lyrics are read from the lyric events, or from the text \
+                        events of `.kar` karaoke files",
                     )
-                    .short('m')
-                    .long("midi-loading")
-                    .value_parser(MidiLoading::from_str),
+                    .long("lyrics")
+                    .action(ArgAction::SetTrue),
             )
             .arg(
-                Arg::new("bg-color")
-                    .help("The window background")
-                    .long_help("A hex color string describing the background color of the window")
-                    .long("bg-color")
+                Arg::new("lyrics-font-size")
+                    .help("The size of the lyrics in points")
+                    .long("lyrics-font-size")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("lyrics-color")
+                    .help("The color of the lyrics")
+                    .long("lyrics-color")
                     .value_parser(color_parser),
             )
             .arg(
-                Arg::new("bar-color")
-                    .help("The color of the bar just above the piano")
+                Arg::new("lyrics-highlight-color")
+                    .help("The color of the lyrics that were sung")
+                    .long("lyrics-highlight-color")
+                    .value_parser(color_parser),
+            )
+            .arg(
+                Arg::new("lyrics-position")
+                    .help("Where the lyrics are shown")
                     .long_help(
-                        "A hex color string describing the color of the bar just above \
-                         the on-screen piano keyboard",
+                        "Where on the notes the lyrics are shown, the possible options are \
+                        `top`, `center` and `bottom`",
                     )
-                    .long("bar-color")
-                    .value_parser(color_parser),
+                    .long("lyrics-position")
+                    .value_parser(LyricsPosition::from_str),
+            )
+            .arg(
+                Arg::new("time-ruler")
+                    .help("Show a ruler with the time next to the notes")
+                    .long_help(
+                        "Shows a ruler along the left edge of the notes, or the top edge in \
+                        waterfall mode, with the time at the height of the notes. The labels \
+                        are spread further apart when the notes are zoomed out",
+                    )
+                    .long("time-ruler")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("time-ruler-mode")
+                    .help("What the time ruler counts in")
+                    .long_help(
+                        "What the time ruler counts in, the possible options are `seconds` \
+                        and `musical` for bars and beats, which follow the tempo and time \
+                        signature events of the MIDI",
+                    )
+                    .long("time-ruler-mode")
+                    .value_parser(TimeRulerMode::from_str),
+            )
+            .arg(
+                Arg::new("time-ruler-spacing")
+                    .help("The least space between two labels of the time ruler in pixels")
+                    .long("time-ruler-spacing")
+                    .value_parser(value_parser!(f32)),
+            )
+            .arg(
+                Arg::new("chord-names")
+                    .help("Show the name of the chord being played above the keyboard")
+                    .long_help(
+                        "Shows the name of the chord that the pressed keys make up above the \
+                        keyboard, like `Am7` or `Cmaj/E`, with the notes of every channel. \
+                        Nothing is shown when the keys don't make up a known chord",
+                    )
+                    .long("chord-names")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("color-space")
+                    .help("The color space of the display")
+                    .long_help(
+                        "The color space the window is presented in, the possible options are \
+                        `srgb` and `linearhdr`. The HDR option needs a display and driver that \
+                        support extended sRGB, otherwise sRGB is used. Notes are tone-mapped \
+                        when `linearhdr` is used",
+                    )
+                    .long("color-space")
+                    .value_parser(ColorSpace::from_str),
+            )
+            .arg(
+                Arg::new("gpu")
+                    .help("The GPU to render with, counted from 1")
+                    .long_help(
+                        "The GPU to render with, counted from 1 in the order Vulkan lists \
+                        them, which is the order of the GPU setting in the settings window. \
+                        0 picks the most performant one. A GPU that can't draw to the window \
+                        is replaced by the most performant one",
+                    )
+                    .long("gpu")
+                    .value_parser(value_parser!(usize)),
+            )
+            .arg(
+                Arg::new("memory-block-size")
+                    .help("The size of the GPU memory blocks for the notes in MB")
+                    .long_help(
+                        "The size of the blocks of GPU memory that the buffers for the notes \
+                        are taken from, in MB. Bigger blocks need fewer allocations for MIDIs \
+                        with many notes. 0 picks a size from the memory of the GPU. Only \
+                        used by the `ram` and `live` MIDI loading methods",
+                    )
+                    .long("memory-block-size")
+                    .value_parser(value_parser!(u64)),
+            )
+            .arg(
+                Arg::new("gpu-culling")
+                    .help("Skip the notes outside of the view on the GPU")
+                    .long_help(
+                        "Skips the notes outside of the view, by key and by time, with a \
+                        compute shader, which leaves only the visible notes to draw, and counts \
+                        the notes that were drawn on the GPU for the stats. Needs a GPU that \
+                        can bind the whole note buffer as a storage buffer. Only used by the \
+                        `ram` and `live` MIDI loading methods",
+                    )
+                    .long("gpu-culling")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("color-script")
+                    .help("Color the notes with the Lua script in the config folder")
+                    .long_help(
+                        "Colors the notes with `color.lua` in the wasabi config folder. The \
+                        script can define a function `color(key, velocity, channel, track)` \
+                        that returns the red, green, blue and optionally alpha of the note from \
+                        0 to 255, an alpha of 0 hides the note, and a function `layout(key, \
+                        velocity, channel, track)` that returns the key the note is drawn on. \
+                        The script is loaded again when it changes. Only supported by the `ram` and `live` MIDI loading methods",
+                    )
+                    .long("color-script")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("twitch-chat")
+                    .help("Let the viewers of a Twitch channel pick the note colors")
+                    .long_help(
+                        "Reads the chat of the given Twitch channel, where viewers pick a color \
+                        with `!color <hex>`. Every viewer gets a channel of the MIDI in the \
+                        order they first picked a color, counting 16 channels per track. \
+                        Needs Wasabi to be built with the `live-chat` feature",
+                    )
+                    .long("twitch-chat"),
+            )
+            .arg(
+                Arg::new("spectrogram")
+                    .help("Show a spectrogram of the audio below the keyboard")
+                    .long_help(
+                        "Shows the frequencies of the rendered audio over time below the \
+                        keyboard. Only the JACK synth renders its audio inside of Wasabi, so \
+                        the spectrogram is only shown with it",
+                    )
+                    .long("spectrogram")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("spectrogram-fft-size")
+                    .help("The amount of samples of each spectrogram column")
+                    .long_help(
+                        "The amount of samples each column of the spectrogram is calculated \
+                        from, rounded up to a power of two between 256 and 16384. Larger sizes \
+                        tell frequencies apart better, but react slower",
+                    )
+                    .long("spectrogram-fft-size")
+                    .value_parser(value_parser!(usize)),
+            )
+            .arg(
+                Arg::new("spectrogram-color-map")
+                    .help("The colors of the spectrogram")
+                    .long_help(
+                        "The colors the loudness of each frequency is drawn with, the possible \
+                        options are `magma`, `rainbow` and `grayscale`",
+                    )
+                    .long("spectrogram-color-map")
+                    .value_parser(SpectrogramColorMap::from_str),
+            )
+            .arg(
+                Arg::new("no-touch")
+                    .help("Ignore touchscreen gestures")
+                    .long_help(
+                        "Turns off the touchscreen gestures. Pinching zooms the note speed \
+                        vertically and the key range horizontally, swiping with two fingers \
+                        seeks horizontally and scrolls the keys vertically",
+                    )
+                    .long("no-touch")
+                    .action(ArgAction::SetFalse),
+            )
+            .arg(
+                Arg::new("remote-control")
+                    .help("Start the HTTP API for controlling the playback")
+                    .long_help(
+                        "Listens for HTTP requests on localhost, so other programs can \
+                        control the playback. The commands `/play`, `/pause`, \
+                        `/seek?t=<seconds>` and `/load?path=<path>` are sent with POST, and \
+                        `/status` with GET. Every request needs the token from the settings \
+                        in an `Authorization: Bearer <token>` header, and requests from web \
+                        pages are turned away. Needs Wasabi to be built with the \
+                        `remote-control` feature",
+                    )
+                    .long("remote-control")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("remote-port")
+                    .help("The port of the HTTP API for controlling the playback")
+                    .long("remote-port")
+                    .value_parser(value_parser!(u16).range(1..)),
+            )
+            .arg(
+                Arg::new("remote-token")
+                    .help("The token that the requests to the HTTP API have to send")
+                    .long_help(
+                        "The token that the requests to the HTTP API have to send in an \
+                        `Authorization: Bearer <token>` header. A random one is made up \
+                        when there is none",
+                    )
+                    .long("remote-token"),
             )
             .arg(
                 Arg::new("hide-top-pannel")
@@ -568,6 +2585,83 @@ impl WasabiSettings {
                     .long("fullscreen")
                     .action(ArgAction::SetTrue),
             )
+            .arg(
+                Arg::new("no-audio")
+                    .help("Start without any audio output")
+                    .long_help(
+                        "Starts `wasabi` with a silent synthesizer, so the MIDI is only \
+                        displayed and not played. The synth can be re-enabled in the settings",
+                    )
+                    .long("no-audio")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("export-audio")
+                    .value_hint(ValueHint::FilePath)
+                    .value_parser(value_parser!(PathBuf))
+                    .help("Render the MIDI file to a WAV file and exit")
+                    .long_help(
+                        "Renders the audio of the MIDI file with XSynth into the given WAV file \
+                        as fast as possible, without opening a window. The soundfont and \
+                        synth options are taken from the settings",
+                    )
+                    .long("export-audio"),
+            )
+            .arg(
+                Arg::new("analyze")
+                    .value_hint(ValueHint::FilePath)
+                    .value_parser(value_parser!(PathBuf))
+                    .help("Write the note statistics of a MIDI file to a CSV file and exit")
+                    .long_help(
+                        "Reads every event of the given MIDI file and writes the note count, \
+                        velocities, durations and peak polyphony of every track and channel \
+                        to the CSV file given with `--output`, without opening a window or \
+                        playing any audio",
+                    )
+                    .long("analyze")
+                    .requires("output"),
+            )
+            .arg(
+                Arg::new("export-musicxml")
+                    .value_hint(ValueHint::FilePath)
+                    .value_parser(value_parser!(PathBuf))
+                    .help("Convert the MIDI file to MusicXML sheet music and exit")
+                    .long_help(
+                        "Writes the notes of the MIDI file into the given MusicXML file, with \
+                        one part for every track, without opening a window. The notes are \
+                        snapped to the grid set with `--musicxml-grid`",
+                    )
+                    .long("export-musicxml"),
+            )
+            .arg(
+                Arg::new("musicxml-grid")
+                    .help("The shortest note value of exported sheet music")
+                    .long_help(
+                        "The notes of exported sheet music are snapped to this note value, \
+                        given as a fraction of a whole note: 4, 8, 16, 32 or 64",
+                    )
+                    .long("musicxml-grid")
+                    .value_parser(value_parser!(u32)),
+            )
+            .arg(
+                Arg::new("output")
+                    .value_hint(ValueHint::FilePath)
+                    .value_parser(value_parser!(PathBuf))
+                    .help("The CSV file the statistics of `--analyze` are written to")
+                    .long("output")
+                    .requires("analyze"),
+            )
+            .arg(
+                Arg::new("midi")
+                    .value_hint(ValueHint::FilePath)
+                    .help("The MIDI file to immediately begin playing")
+                    .long_help(
+                        "This MIDI file is played immediately after the app's launch. \
+                        This is the same as passing the MIDI file as the last argument",
+                    )
+                    .long("midi")
+                    .conflicts_with("midi-file"),
+            )
             .arg(
                 Arg::new("midi-file")
                     .value_hint(ValueHint::FilePath)
@@ -605,11 +2699,27 @@ impl WasabiSettings {
             };
         }
 
-        self.load_midi_file = matches.get_one::<String>("midi-file").map(|f| f.to_owned());
+        self.load_midi_file = matches
+            .get_one::<String>("midi")
+            .or_else(|| matches.get_one::<String>("midi-file"))
+            .map(|f| f.to_owned());
+        self.no_audio = matches.get_flag("no-audio");
+        self.export_audio = matches.get_one::<PathBuf>("export-audio").cloned();
+        self.analyze_midi = matches.get_one::<PathBuf>("analyze").cloned();
+        self.analyze_output = matches.get_one::<PathBuf>("output").cloned();
+        self.export_musicxml = matches.get_one::<PathBuf>("export-musicxml").cloned();
+        if let Some(grid) = matches.get_one::<u32>("musicxml-grid") {
+            if MUSICXML_GRIDS.contains(grid) {
+                self.midi.musicxml_grid = *grid;
+            }
+        }
 
         // Synth settings
         set!(synth.synth, "synth");
         set!(synth.buffer_ms, "buffer-ms");
+        if let Some(latency) = matches.get_one::<f32>("latency-compensation") {
+            self.synth.audio_latency_compensation_ms = Some(latency.clamp(0.0, 1000.0));
+        }
         set_owned!(synth.sfz_path, "sfz-path", String);
         set_flag!(synth.limit_layers, "dont-limit-layers");
         set!(synth.layer_count, "layer-count");
@@ -617,16 +2727,202 @@ impl WasabiSettings {
         set_flag!(synth.fade_out_kill, "fade-out-kill");
         set_flag!(synth.linear_envelope, "linear-envelope");
         set_flag!(synth.use_effects, "no-effects");
+        if let Some(multiplier) = matches.get_one::<f32>("release-multiplier") {
+            self.synth.release_multiplier = multiplier.clamp(0.1, 4.0);
+        }
+        if let Some(cutoff) = matches.get_one::<f32>("release-cutoff") {
+            self.synth.release_cutoff_ms = Some(cutoff.clamp(1.0, 10000.0));
+        }
+        if let Some(fadein) = matches.get_one::<f32>("note-fadein") {
+            self.synth.note_fadein_ms = fadein.clamp(0.0, 1000.0);
+        }
+        set_flag!(synth.midi_clock_sync, "midi-clock-sync");
+        set_owned!(synth.midi_clock_port, "midi-clock-port", String);
+        set_owned!(synth.loop_input_port, "loop-input-port", String);
+        if let Some(measures) = matches.get_one::<u32>("loop-measures") {
+            self.synth.loop_measures = (*measures).clamp(1, 64);
+        }
+        if let Some(bpm) = matches.get_one::<f64>("loop-bpm") {
+            self.synth.loop_bpm = bpm.clamp(20.0, 999.0);
+        }
 
         // MIDI settings
         set!(midi.note_speed, "note-speed");
+        set_flag!(midi.auto_note_speed, "auto-note-speed");
+        set!(midi.auto_note_speed_target, "auto-note-speed-target");
+        if let Some(smoothing) = matches.get_one::<f64>("auto-note-speed-smoothing") {
+            self.midi.auto_note_speed_smoothing = smoothing.clamp(0.001, 1.0);
+        }
         set_flag!(midi.random_colors, "random-colors");
+        set_flag!(midi.program_colors, "program-colors");
+        set_flag!(midi.smart_colors, "smart-colors");
         set_owned!(midi.key_range, "key-range", RangeInclusive<u8>);
+        set!(midi.event_filter, "drop-events");
+        set!(midi.muted_channels, "mute-channels");
         set!(midi.midi_loading, "midi-loading");
+        set_flag!(midi.use_mmap, "mmap");
+        set!(midi.repair_mode, "repair-mode");
+        set_flag!(midi.reverse_playback, "reverse-playback");
+        set!(midi.transpose_semitones, "transpose");
+        set_flag!(midi.follow_mode, "follow-mode");
+        set_flag!(midi.keyboard_clickable, "keyboard-clickable");
+        set_flag!(midi.pause_on_focus_loss, "pause-on-focus-loss");
+        set!(midi.seek_step_secs, "seek-step");
 
         // Visual settings
         set!(visual.bg_color, "bg-color");
         set!(visual.bar_color, "bar-color");
+        set!(visual.keyboard_white_key_color, "white-key-color");
+        set!(visual.keyboard_black_key_color, "black-key-color");
+        set!(
+            visual.keyboard_white_key_pressed_color,
+            "white-key-pressed-color"
+        );
+        set!(
+            visual.keyboard_black_key_pressed_color,
+            "black-key-pressed-color"
+        );
+        if let Some(path) = matches.get_one::<PathBuf>("ui-font") {
+            self.visual.ui_font_path = Some(path.to_owned());
+        }
+        if let Some(path) = matches.get_one::<PathBuf>("background-image") {
+            self.visual.background = BackgroundMode::Image(path.to_owned());
+        }
+        if let Some(path) = matches.get_one::<PathBuf>("background-video") {
+            self.visual.background = BackgroundMode::Video(path.to_owned());
+        }
+        if let Some(opacity) = matches.get_one::<f32>("background-opacity") {
+            self.visual.background_opacity = opacity.clamp(0.0, 1.0);
+        }
+        set!(visual.ui_font_size, "ui-font-size");
+        if let Some(margin) = matches.get_one::<u32>("safe-zone-margin") {
+            self.visual.safe_zone_margin_px = (*margin).min(500);
+        }
+        set_flag!(visual.bloom_enabled, "bloom");
+        set!(visual.bloom_radius, "bloom-radius");
+        if let Some(intensity) = matches.get_one::<f32>("inner-glow") {
+            self.visual.inner_glow_intensity = intensity.clamp(0.0, 1.0);
+        }
+        if let Some(color) = matches.get_one::<Color32>("inner-glow-color") {
+            self.visual.inner_glow_color = [
+                color.r() as f32 / 255.0,
+                color.g() as f32 / 255.0,
+                color.b() as f32 / 255.0,
+                1.0,
+            ];
+        }
+        if let Some(secs) = matches.get_one::<u64>("dim-after") {
+            self.visual.dim_after_secs = Some(*secs);
+        }
+        if let Some(beats) = matches.get_one::<f32>("echo-beats") {
+            self.visual.echo_beats = Some(beats.clamp(0.25, 16.0));
+        }
+        if let Some(count) = matches.get_one::<u32>("particles-per-note") {
+            self.visual.particles_per_note = (*count).min(64);
+        }
+        if let Some(lifetime) = matches.get_one::<f32>("particle-lifetime") {
+            self.visual.particle_lifetime_ms = lifetime.clamp(50.0, 5000.0);
+        }
+        if let Some(text) = matches.get_one::<String>("watermark") {
+            self.visual
+                .watermark
+                .get_or_insert_with(Default::default)
+                .text = text.clone();
+        }
+        if let Some(corner) = matches.get_one::<WatermarkCorner>("watermark-corner") {
+            self.visual
+                .watermark
+                .get_or_insert_with(Default::default)
+                .corner = *corner;
+        }
+        set_flag!(visual.show_mini_keyboard, "mini-keyboard");
+        set_flag!(visual.waterfall_mode, "waterfall");
+        set_flag!(visual.invert_pitch_axis, "invert-pitch");
+        set_flag!(visual.keyboard_heatmap, "keyboard-heatmap");
+        set!(visual.keyboard_key_labels, "key-labels");
+        if let Some(ratio) = matches.get_one::<f32>("black-key-width") {
+            self.visual.black_key_width_ratio = ratio.clamp(0.1, 1.0);
+        }
+        if let Some(ratio) = matches.get_one::<f32>("black-key-height") {
+            self.visual.black_key_height_ratio = ratio.clamp(0.1, 1.0);
+        }
+        set!(visual.note_shape, "note-shape");
+        set!(visual.note_render_order, "note-render-order");
+        set!(visual.percussion_display_mode, "percussion-display");
+        set!(visual.hand_split_mode, "hand-split");
+        set!(visual.note_gap, "note-gap");
+        if let Some(height) = matches.get_one::<f32>("min-note-height") {
+            self.visual.min_note_height_px = height.clamp(0.0, 10.0);
+        }
+        set_flag!(visual.spotlight_mode, "spotlight");
+        set_flag!(visual.length_opacity_mode, "length-opacity");
+        if let Some(length) = matches.get_one::<f32>("length-opacity-min") {
+            self.visual.length_opacity_min_ms = length.clamp(0.0, 10000.0);
+        }
+        if let Some(length) = matches.get_one::<f32>("length-opacity-max") {
+            self.visual.length_opacity_max_ms = length.clamp(0.0, 10000.0);
+        }
+        if let Some(samples) = matches.get_one::<u32>("msaa") {
+            // Sample counts are powers of two, so anything in between is rounded down
+            let samples = (*samples).clamp(1, 8);
+            self.visual.msaa_samples = 1 << samples.ilog2();
+        }
+        if let Some(scale) = matches.get_one::<f32>("depth-buffer-scale") {
+            // Only half and full size are offered, anything below full is half
+            self.visual.depth_buffer_scale = if *scale < 1.0 { 0.5 } else { 1.0 };
+        }
+        if let Some(alpha) = matches.get_one::<f32>("inactive-channel-alpha") {
+            self.visual.inactive_channel_alpha = alpha.clamp(0.0, 1.0);
+        }
+        set_flag!(visual.normalize_percussion_length, "normalize-percussion");
+        if let Some(length) = matches.get_one::<f32>("percussion-length") {
+            self.visual.normalized_length_ms = length.clamp(1.0, 2000.0);
+        }
+        set_flag!(visual.chord_grouping, "chord-grouping");
+        if let Some(tolerance) = matches.get_one::<f32>("chord-tolerance") {
+            self.visual.chord_tolerance_ms = tolerance.clamp(0.0, 200.0);
+        }
+        set_flag!(visual.arpeggio_view, "arpeggio-view");
+        if let Some(offset) = matches.get_one::<f32>("arpeggio-offset") {
+            self.visual.arpeggio_offset_ms = offset.clamp(0.0, 200.0);
+        }
+        if let Some(lanes) = matches.get_one::<Vec<u8>>("cc-lanes") {
+            self.visual.cc_lanes = lanes.clone();
+        }
+        set_flag!(visual.show_instrument_labels, "instrument-labels");
+        set_flag!(visual.show_channel_activity, "channel-activity");
+        set_flag!(visual.show_lyrics, "lyrics");
+        if let Some(size) = matches.get_one::<f32>("lyrics-font-size") {
+            self.visual.lyrics_font_size = size.clamp(8.0, 96.0);
+        }
+        set!(visual.lyrics_color, "lyrics-color");
+        set!(visual.lyrics_highlight_color, "lyrics-highlight-color");
+        set!(visual.lyrics_position, "lyrics-position");
+        set_flag!(visual.show_time_ruler, "time-ruler");
+        set!(visual.time_ruler_mode, "time-ruler-mode");
+        if let Some(spacing) = matches.get_one::<f32>("time-ruler-spacing") {
+            self.visual.time_ruler_spacing_px = spacing.clamp(20.0, 400.0);
+        }
+        set_flag!(visual.show_chord_names, "chord-names");
+        set!(visual.color_space, "color-space");
+        set!(rendering.gpu_device_index, "gpu");
+        if let Some(size) = matches.get_one::<u64>("memory-block-size") {
+            self.rendering.memory_block_size_mb = (*size).min(4096);
+        }
+        set_flag!(rendering.gpu_culling, "gpu-culling");
+        set_flag!(visual.use_color_script, "color-script");
+        if let Some(channel) = matches.get_one::<String>("twitch-chat") {
+            self.visual.twitch_chat_channel = Some(channel.clone());
+        }
+        set_flag!(visual.show_spectrogram, "spectrogram");
+        if let Some(size) = matches.get_one::<usize>("spectrogram-fft-size") {
+            self.visual.spectrogram_fft_size = size.clamp(256, 16384).next_power_of_two();
+        }
+        set!(visual.spectrogram_color_map, "spectrogram-color-map");
+        set_flag!(input.touch_enabled, "no-touch");
+        set_flag!(remote.enabled, "remote-control");
+        set!(remote.port, "remote-port");
+        set_owned!(remote.token, "remote-token", String);
         set_flag!(visual.show_top_pannel, "hide-top-pannel");
         set_flag!(visual.show_statistics, "hide-statistics");
         set_flag!(visual.fullscreen, "fullscreen");
@@ -639,6 +2935,16 @@ impl WasabiSettings {
         cfg
     }
 
+    /// The Lua script that colors the notes, `color.lua` next to the config file
+    pub fn color_script_path() -> PathBuf {
+        Path::new(&Self::get_config_path()).with_file_name("color.lua")
+    }
+
+    /// The compiled Vulkan pipelines, `pipeline_cache.bin` next to the config file
+    pub fn pipeline_cache_path() -> PathBuf {
+        Path::new(&Self::get_config_path()).with_file_name("pipeline_cache.bin")
+    }
+
     fn get_config_path() -> String {
         if let Some(base_dirs) = BaseDirs::new() {
             let mut path: PathBuf = base_dirs.config_dir().to_path_buf();
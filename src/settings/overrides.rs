@@ -0,0 +1,117 @@
+use serde_json::Value;
+
+use crate::notifications;
+
+use super::WasabiSettings;
+
+/// Settings from the sidecar file of a MIDI that override the global settings
+/// while the MIDI is loaded, along with the values they replaced
+#[derive(Debug, Clone)]
+pub struct SettingsOverride {
+    overrides: Value,
+    replaced: Value,
+}
+
+impl SettingsOverride {
+    /// Whether the override changes anything in a section, like `synth` or `visual`
+    pub fn changes_section(&self, section: &str) -> bool {
+        self.overrides.get(section).is_some()
+    }
+}
+
+impl WasabiSettings {
+    /// Applies only the fields that are present in the overrides, returning
+    /// what's needed to restore them, or `None` if they couldn't be applied
+    pub fn apply_override(&mut self, overrides: Value) -> Option<SettingsOverride> {
+        let current = serde_json::to_value(&*self).ok()?;
+        let replaced = pick_fields(&current, &overrides);
+
+        if self.merge_fields(&overrides) {
+            Some(SettingsOverride {
+                overrides,
+                replaced,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Puts back the values that an override replaced, other changes are kept
+    pub fn restore_override(&mut self, settings_override: SettingsOverride) {
+        self.merge_fields(&settings_override.replaced);
+    }
+
+    /// Saves the settings with the values that an override replaced, so the
+    /// settings of the loaded MIDI don't end up in the global config
+    pub fn save_without_override(&self, settings_override: Option<&SettingsOverride>) {
+        let Some(settings_override) = settings_override else {
+            self.save_to_file();
+            return;
+        };
+
+        let mut value = match serde_json::to_value(self) {
+            Ok(value) => value,
+            Err(e) => {
+                notifications::error(format!("Failed to save the settings: {}", e));
+                return;
+            }
+        };
+        merge(&mut value, &settings_override.replaced);
+
+        match serde_json::from_value::<WasabiSettings>(value) {
+            Ok(settings) => settings.save_to_file(),
+            Err(e) => notifications::error(format!("Failed to save the settings: {}", e)),
+        }
+    }
+
+    fn merge_fields(&mut self, fields: &Value) -> bool {
+        let mut value = match serde_json::to_value(&*self) {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+        merge(&mut value, fields);
+
+        match serde_json::from_value::<WasabiSettings>(value) {
+            Ok(mut settings) => {
+                // These only come from the command line, so they aren't serialized
+                settings.no_audio = self.no_audio;
+                settings.export_audio = self.export_audio.take();
+                *self = settings;
+                true
+            }
+            Err(e) => {
+                notifications::error(format!("Failed to apply the settings override: {}", e));
+                false
+            }
+        }
+    }
+}
+
+/// Recursively copies the fields of `patch` into `target`
+fn merge(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+/// Takes the fields out of `source` that are also present in `shape`
+fn pick_fields(source: &Value, shape: &Value) -> Value {
+    match (source, shape) {
+        (Value::Object(source), Value::Object(shape)) => Value::Object(
+            shape
+                .iter()
+                .filter_map(|(key, shape)| {
+                    source
+                        .get(key)
+                        .map(|source| (key.clone(), pick_fields(source, shape)))
+                })
+                .collect(),
+        ),
+        (source, _) => source.clone(),
+    }
+}
@@ -1,10 +1,21 @@
 use std::path::PathBuf;
 
+use crate::settings::WasabiAction;
+
 #[derive(Clone, Default)]
 pub struct WasabiState {
     pub fullscreen: bool,
     pub settings_visible: bool,
     pub xsynth_settings_visible: bool,
+    pub batch_export_visible: bool,
     pub last_midi_file: Option<PathBuf>,
     pub last_sfz_file: Option<PathBuf>,
+    pub view_key_offset: i32,
+    pub note_editor: bool,
+    pub idle_playback_secs: f64,
+    pub tracks_visible: bool,
+    pub midi_info_visible: bool,
+    pub track_order: Vec<usize>,
+    /// The action whose key binding is replaced by the next key press in the settings
+    pub recording_binding: Option<WasabiAction>,
 }